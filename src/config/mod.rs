@@ -1,3 +1,10 @@
 mod types;
+mod watch;
 
-pub use types::{ConfigError, GatewayConfig, NodeData, SfuConfig};
+pub use types::{
+    ApiKeyConfig, ClientIdentity, ConfigError, DEFAULT_CHANNEL_LIFETIME, DEFAULT_FORWARD_HEADERS,
+    DEFAULT_MAX_BODY_BYTES, DEFAULT_MAX_QUERY_BYTES, DEFAULT_SFU_URL_SCHEMES, GatewayConfig,
+    GeoConfig, HealthCheckMode, NodeData, SelectionStrategy, SfuConfig, decode_key,
+    load_gateway_keys,
+};
+pub use watch::{NodesWatcher, watch_nodes};
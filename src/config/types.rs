@@ -1,20 +1,213 @@
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use base64::Engine;
+use jsonwebtoken::Algorithm;
 use serde::Deserialize;
 
+use crate::http::constant_time_eq;
+use crate::routing::GeoTable;
+
 const EXPECTED_KEY_LENGTH: usize = 32;
 
+/// Headers set by the gateway itself, which static headers must not override
+const RESERVED_HEADERS: &[&str] = &[
+    "authorization",
+    "x-forwarded-for",
+    "x-request-id",
+    "traceparent",
+    "host",
+    "content-length",
+    "content-type",
+];
+
+/// Schemes accepted for the URL returned by SFUs when `SFU_GATEWAY_SFU_URL_SCHEMES` is not set
+pub const DEFAULT_SFU_URL_SCHEMES: &[&str] = &["wss", "https"];
+
+/// Longest query string accepted when `SFU_GATEWAY_MAX_QUERY_BYTES` is not set
+pub const DEFAULT_MAX_QUERY_BYTES: usize = 8 * 1024;
+
+/// Largest request body accepted and SFU response body read when `SFU_GATEWAY_MAX_BODY_BYTES` is
+/// not set
+pub const DEFAULT_MAX_BODY_BYTES: usize = 64 * 1024;
+
+/// How long an assigned channel counts towards its SFU's load when
+/// `SFU_GATEWAY_CHANNEL_LIFETIME_MS` is not set
+pub const DEFAULT_CHANNEL_LIFETIME: Duration = Duration::from_hours(1);
+
+/// Client headers forwarded to the SFU when `SFU_GATEWAY_FORWARD_HEADERS` is not set
+pub const DEFAULT_FORWARD_HEADERS: &[&str] = &["user-agent", "accept-language", "x-odoo-*"];
+
 /// Gateway configuration from environment variables
 #[derive(Debug, Clone)]
+#[allow(clippy::struct_excessive_bools)] // independent switches, each from its own variable
 pub struct GatewayConfig {
     pub bind: String,
     pub port: u16,
-    pub key: Vec<u8>,
+    /// Keys tokens from Odoo may be signed with, the current one first during a rotation
+    pub keys: Vec<Vec<u8>>,
+    /// File the keys were read from, re-read on key reload (`None` when they come from
+    /// `SFU_GATEWAY_KEY`)
+    pub key_file: Option<PathBuf>,
     pub nodes: Option<String>,
     /// When true, trust X-Forwarded-For header from upstream proxy to determine client IP
     pub trust_proxy: bool,
+    /// Seed for the balancer's RNG, random (from entropy) when not set
+    pub seed: Option<u64>,
+    /// How SFUs are probed for health, unless overridden per SFU
+    pub health_check_mode: HealthCheckMode,
+    /// Consecutive failed probes before an SFU is marked down
+    pub health_failure_threshold: u32,
+    /// Consecutive successful probes before an SFU is marked up again
+    pub health_success_threshold: u32,
+    /// Delay between two rounds of health probes (health checks disabled when `None`)
+    pub health_check_interval: Option<Duration>,
+    /// Maximum duration of a single health probe
+    pub health_check_timeout: Duration,
+    /// Longest delay between two probes of an SFU marked down, probes back off up to it
+    pub health_check_max_interval: Duration,
+    /// Static API key accepted as an alternative to JWT (disabled when `None`)
+    pub api_key: Option<ApiKeyConfig>,
+    /// Region the gateway itself is deployed in
+    pub region: Option<String>,
+    /// When true, requests without region hint prefer SFUs in the gateway's own region
+    pub prefer_local_region: bool,
+    /// Region hint of the requests without one, takes precedence over `prefer_local_region`
+    pub default_region: Option<String>,
+    /// How long an issuer sticks to the SFU it was last given (disabled when `None`)
+    pub affinity_ttl: Option<Duration>,
+    /// Maximum number of issuers remembered for affinity
+    pub affinity_max_entries: usize,
+    /// Upper bound on the whole handling of a channel request (none when `None`)
+    pub request_deadline: Option<Duration>,
+    /// Query parameter read for the JWT when the Authorization header is absent (opt-in)
+    pub token_query_param: Option<String>,
+    /// Traffic ramp-up duration for SFUs added at runtime (disabled when `None`)
+    pub slow_start: Option<Duration>,
+    /// How long an assigned channel counts towards its SFU's `max_channels` and load
+    pub channel_lifetime: Duration,
+    /// JWKS endpoint publishing the public keys of RS256 tokens (disabled when `None`)
+    pub jwks_url: Option<String>,
+    /// How long the fetched JWKS is trusted before being fetched again
+    pub jwks_ttl: Duration,
+    /// SFUs tried for a channel request before giving up
+    pub max_attempts: u32,
+    /// How an SFU is picked among the candidates of a request
+    pub strategy: SelectionStrategy,
+    /// Furthest region a request falls back to from the hinted one, in km (unbounded when `None`)
+    pub max_fallback_km: Option<f64>,
+    /// Maximum duration of a request to an SFU, response body included
+    pub sfu_timeout: Duration,
+    /// Maximum duration of the connection to an SFU, DNS resolution included
+    pub connect_timeout: Duration,
+    /// Time given to in-flight forwards to finish on shutdown
+    pub shutdown_grace: Duration,
+    /// Idle connections kept open to each SFU
+    pub pool_max_idle: usize,
+    /// How long an idle connection to an SFU is kept open
+    pub pool_idle_timeout: Duration,
+    /// TCP keep-alive of the connections to the SFUs (disabled when `None`)
+    pub tcp_keepalive: Option<Duration>,
+    /// Reject expired JWTs, and JWTs without `exp`
+    pub validate_exp: bool,
+    /// Tolerated clock skew when checking `exp`, in seconds
+    pub leeway_secs: u64,
+    /// Reject JWTs issued more than that many seconds in the future, or not valid yet (`nbf`),
+    /// unchecked when `None`
+    pub max_iat_skew_secs: Option<u64>,
+    /// Reject JWTs issued more than that many seconds ago whatever their `exp`, and JWTs
+    /// without `iat`, unchecked when `None`
+    pub max_token_age_secs: Option<u64>,
+    /// MaxMind country database used to guess the region of requests without hint (opt-in)
+    pub geoip_db: Option<String>,
+    /// Client headers forwarded to the SFU, lowercase names or prefixes ending with `*`
+    pub forward_headers: Vec<String>,
+    /// Requests per minute allowed for each issuer (disabled when `None`)
+    pub rate_limit: Option<u32>,
+    /// Delay between two sweeps of the idle per-issuer entries (sweeps disabled when `None`)
+    pub sweep_interval: Option<Duration>,
+    /// How long a per-issuer entry may stay untouched before being swept
+    pub idle_ttl: Duration,
+    /// Schemes the channel URL returned by an SFU may use, lowercase
+    pub sfu_url_schemes: Vec<String>,
+    /// Schemes accepted in the Authorization header, lowercase, any when empty
+    pub auth_schemes: Vec<String>,
+    /// Serve the unauthenticated debugging endpoints, such as `/v1/select`
+    pub debug_endpoints: bool,
+    /// Longest query string accepted on the forwarding endpoints, in bytes
+    pub max_query_bytes: usize,
+    /// Largest request body accepted and SFU response body read, in bytes
+    pub max_body_bytes: usize,
+    /// Send a hash of the SFU address in `X-SFU-Address` rather than the address itself
+    pub redact_sfu_address: bool,
+    /// Reject channel requests with an unknown `region` or `country` rather than ignoring it
+    pub strict_region: bool,
+    /// Refuse to start when `Balancer::region_issues` finds a problem, rather than warn
+    pub strict_region_check: bool,
+    /// Refuse to start when an SFU key is also a gateway key, rather than warn
+    pub strict_key_check: bool,
+    /// How long `/v1/status` reuses the last probe of an SFU, zero probes on every request
+    pub status_probe_ttl: Duration,
+    /// Scheme given to SFU addresses without one, which are rejected when `None`
+    pub default_scheme: Option<String>,
+    /// Origins allowed to call the channel routes from a browser, `*` for any (CORS disabled
+    /// when empty)
+    pub cors_origins: Vec<String>,
+    /// Key of the `/admin` maintenance endpoints (disabled when `None`)
+    pub admin_key: Option<String>,
+    /// Client certificate presented to the SFUs, for mutual TLS (none when `None`)
+    pub client_identity: Option<ClientIdentity>,
+    /// Oldest TLS version accepted from the SFUs
+    pub min_tls_version: reqwest::tls::Version,
+    /// Ignore `HTTP_PROXY`, `HTTPS_PROXY` and the like, connecting to the SFUs directly
+    pub disable_proxy: bool,
+}
+
+/// Static API key authentication, for integrations that cannot produce JWTs
+#[derive(Debug, Clone)]
+pub struct ApiKeyConfig {
+    /// Expected value of the `X-Api-Key` header
+    pub key: String,
+    /// Issuer used for the claims of requests authenticated with this key
+    pub iss: String,
+    /// When set, requests authenticated with this key are always routed to this region
+    pub region: Option<String>,
+}
+
+/// Client certificate the gateway presents to SFUs requiring mutual TLS
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientIdentity {
+    /// PEM file with the certificate chain, and the private key unless `key` is set
+    pub cert: String,
+    /// PEM file with the private key
+    pub key: Option<String>,
+}
+
+impl ClientIdentity {
+    /// Read the PEM files into the identity given to the HTTP client.
+    ///
+    /// # Errors
+    /// Returns `ConfigError::Io` when a file can't be read, `ConfigError::Env` when they don't
+    /// hold a certificate and a private key.
+    pub fn load(&self) -> Result<reqwest::Identity, ConfigError> {
+        let read = |path: &str| {
+            fs::read(path).map_err(|source| ConfigError::Io {
+                path: path.to_string(),
+                source,
+            })
+        };
+        let mut pem = read(&self.cert)?;
+        if let Some(key) = &self.key {
+            pem.push(b'\n');
+            pem.extend(read(key)?);
+        }
+        reqwest::Identity::from_pem(&pem).map_err(|e| ConfigError::Env {
+            var: "SFU_GATEWAY_CLIENT_CERT".to_string(),
+            message: format!("invalid client identity: {e}"),
+        })
+    }
 }
 
 impl GatewayConfig {
@@ -23,11 +216,70 @@ impl GatewayConfig {
     /// Environment variables:
     /// - `SFU_GATEWAY_BIND` - Address to bind (default: "0.0.0.0")
     /// - `SFU_GATEWAY_PORT` - Port to listen on (default: 8071)
-    /// - `SFU_GATEWAY_KEY` - Base64-encoded JWT secret key (required)
+    /// - `SFU_GATEWAY_KEY` - Base64-encoded JWT secret key, or a comma-separated list while rotating (required unless `SFU_GATEWAY_KEY_FILE` is set)
+    /// - `SFU_GATEWAY_KEY_FILE` - File holding the keys instead, comma or newline-separated, re-read on SIGUSR1 (optional)
     /// - `SFU_GATEWAY_NODES` - JSON string of SFU nodes (optional)
+    /// - `SFU_GATEWAY_TRUST_PROXY` - Trust `Forwarded` and X-Forwarded-For from upstream proxy, `Forwarded` first (default: false)
+    /// - `SFU_GATEWAY_SEED` - Seed for randomized selection, for reproducible runs (optional)
+    /// - `SFU_GATEWAY_HEALTH_CHECK_MODE` - `http` or `tcp` (default: http)
+    /// - `SFU_GATEWAY_HEALTH_FAILURE_THRESHOLD` - Failed probes before marking down (default: 3)
+    /// - `SFU_GATEWAY_HEALTH_SUCCESS_THRESHOLD` - Successful probes before marking up (default: 2)
+    /// - `SFU_GATEWAY_HEALTH_CHECK_INTERVAL_MS` - Delay between health probes, 0 disables (default: 10000)
+    /// - `SFU_GATEWAY_HEALTH_CHECK_TIMEOUT_MS` - Timeout of a health probe (default: 2000)
+    /// - `SFU_GATEWAY_HEALTH_CHECK_MAX_INTERVAL_MS` - Longest delay between probes of an SFU marked down (default: 300000)
+    /// - `SFU_GATEWAY_API_KEY` - Static API key accepted in `X-Api-Key` (optional)
+    /// - `SFU_GATEWAY_API_KEY_ISS` - Issuer for API key requests (required with the API key)
+    /// - `SFU_GATEWAY_API_KEY_REGION` - Region API key requests are routed to (optional)
+    /// - `SFU_GATEWAY_REGION` - Region the gateway is deployed in (optional)
+    /// - `SFU_GATEWAY_PREFER_LOCAL_REGION` - Use `SFU_GATEWAY_REGION` as default hint (default: false)
+    /// - `SFU_GATEWAY_DEFAULT_REGION` - Region hint of requests without one, whatever the gateway's region (optional)
+    /// - `SFU_GATEWAY_AFFINITY_TTL_MS` - Issuer → SFU affinity window, 0 disables (default: 0)
+    /// - `SFU_GATEWAY_AFFINITY_MAX_ENTRIES` - Issuers remembered for affinity (default: 10000)
+    /// - `SFU_GATEWAY_REQUEST_DEADLINE_MS` - Overall channel request deadline, 0 disables (default: 0)
+    /// - `SFU_GATEWAY_TOKEN_QUERY_PARAM` - Query parameter holding the JWT when there is no header (optional)
+    /// - `SFU_GATEWAY_SLOW_START_MS` - Traffic ramp-up of SFUs added at runtime, 0 disables (default: 0)
+    /// - `SFU_GATEWAY_CHANNEL_LIFETIME_MS` - How long an assigned channel counts towards its SFU's load, at least 1 (default: 3600000)
+    /// - `SFU_GATEWAY_JWKS_URL` - JWKS endpoint for verifying RS256 tokens (optional)
+    /// - `SFU_GATEWAY_JWKS_TTL_MS` - JWKS cache duration (default: 300000)
+    /// - `SFU_GATEWAY_MAX_ATTEMPTS` - SFUs tried per channel request, at least 1 (default: 2)
+    /// - `SFU_GATEWAY_STRATEGY` - `round-robin`, `sticky`, `least-connections`, `lowest-latency` or `weighted-random` (default: round-robin)
+    /// - `SFU_GATEWAY_MAX_FALLBACK_KM` - Furthest region a hinted request falls back to, in km (optional)
+    /// - `SFU_GATEWAY_SFU_TIMEOUT_MS` - Timeout of a request to an SFU (default: 5000)
+    /// - `SFU_GATEWAY_CONNECT_TIMEOUT_MS` - Timeout of the connection to an SFU, within the request timeout (default: 2000)
+    /// - `SFU_GATEWAY_SHUTDOWN_GRACE_MS` - Time in-flight forwards get to finish on shutdown, the server's workers round it up to the second (default: 30000)
+    /// - `SFU_GATEWAY_POOL_MAX_IDLE` - Idle connections kept open to each SFU (default: 32)
+    /// - `SFU_GATEWAY_POOL_IDLE_TIMEOUT_MS` - How long an idle connection to an SFU is kept open (default: 90000)
+    /// - `SFU_GATEWAY_TCP_KEEPALIVE_MS` - TCP keep-alive interval of the connections to SFUs, 0 disables (default: 60000)
+    /// - `SFU_GATEWAY_VALIDATE_EXP` - `true` or `false`, reject expired JWTs (default: true)
+    /// - `SFU_GATEWAY_LEEWAY` - Clock skew tolerated on `exp`, in seconds (default: 60)
+    /// - `SFU_GATEWAY_MAX_IAT_SKEW` - Reject JWTs with `iat` further in the future, in seconds, and honor `nbf` (optional)
+    /// - `SFU_GATEWAY_MAX_TOKEN_AGE_SECS` - Reject JWTs issued more than that many seconds ago, or without `iat`, whatever their `exp` (optional)
+    /// - `SFU_GATEWAY_GEOIP_DB` - Path to a MaxMind country database (optional)
+    /// - `SFU_GATEWAY_RATE_LIMIT` - Requests per minute allowed per issuer, 0 disables (default: 0)
+    /// - `SFU_GATEWAY_SWEEP_INTERVAL_MS` - Delay between sweeps of the idle per-issuer entries, 0 disables (default: 60000)
+    /// - `SFU_GATEWAY_IDLE_TTL_MS` - How long a per-issuer entry may stay untouched before being swept (default: 600000)
+    /// - `SFU_GATEWAY_SFU_URL_SCHEMES` - Comma-separated schemes allowed for the channel URL returned by SFUs (default: `wss,https`)
+    /// - `SFU_GATEWAY_AUTH_SCHEME` - Comma-separated schemes accepted in the Authorization header, such as `Bearer` (default: any)
+    /// - `SFU_GATEWAY_DEBUG_ENDPOINTS` - Serve the unauthenticated `/v1/select` routing preview (default: false)
+    /// - `SFU_GATEWAY_MAX_QUERY_BYTES` - Longest query string accepted, longer ones get a 400 (default: 8192)
+    /// - `SFU_GATEWAY_MAX_BODY_BYTES` - Largest request body accepted (413 over it) and SFU response body read (502 over it) (default: 65536)
+    /// - `SFU_GATEWAY_REDACT_SFU_ADDRESS` - Send a hash of the SFU address in `X-SFU-Address` instead of the address (default: false)
+    /// - `SFU_GATEWAY_STRICT_REGION` - Reject an unknown `region` or `country` with a 400 instead of ignoring it (default: false)
+    /// - `SFU_GATEWAY_STRICT_REGION_CHECK` - Refuse to start when a region has no reachable SFU or an SFU region is unknown, instead of warning (default: false)
+    /// - `SFU_GATEWAY_STRICT_KEY_CHECK` - Refuse to start when an SFU key is also a gateway key, instead of warning (default: false)
+    /// - `SFU_GATEWAY_STATUS_PROBE_TTL_MS` - How long `/v1/status` reuses the last probe of an SFU instead of probing it again, 0 always probes (default: 1000)
+    /// - `SFU_GATEWAY_DEFAULT_SCHEME` - `http` or `https`, given to SFU addresses without a scheme instead of rejecting them (optional)
+    /// - `SFU_GATEWAY_CORS_ORIGINS` - Comma-separated origins allowed to call the channel routes from a browser, `*` for any (default: none, CORS disabled)
+    /// - `SFU_GATEWAY_ADMIN_KEY` - Key expected in `X-Admin-Key` by the `/admin` endpoints, unset disables them (optional)
+    /// - `SFU_GATEWAY_CLIENT_CERT` - PEM file with the client certificate presented to SFUs, and its key unless `SFU_GATEWAY_CLIENT_KEY` is set (optional)
+    /// - `SFU_GATEWAY_CLIENT_KEY` - PEM file with the private key of `SFU_GATEWAY_CLIENT_CERT` (optional)
+    /// - `SFU_GATEWAY_MIN_TLS_VERSION` - `1.2` or `1.3`, oldest TLS version accepted from SFUs (default: 1.2)
+    /// - `SFU_GATEWAY_DISABLE_PROXY` - Connect to SFUs directly whatever `HTTP_PROXY`/`HTTPS_PROXY` say (default: false)
+    /// - `SFU_GATEWAY_FORWARD_HEADERS` - Comma-separated client headers forwarded to the SFU, `x-odoo-*` style prefixes allowed, empty forwards none (default: `DEFAULT_FORWARD_HEADERS`)
     ///
     /// # Errors
     /// Returns `ConfigError::Env` if required variables are missing or invalid.
+    #[allow(clippy::too_many_lines)] // one field per variable
     pub fn from_env() -> Result<Self, ConfigError> {
         let bind = std::env::var("SFU_GATEWAY_BIND").unwrap_or_else(|_| "0.0.0.0".to_string());
 
@@ -39,28 +291,460 @@ impl GatewayConfig {
                 message: format!("invalid port: {e}"),
             })?;
 
-        let key_str = std::env::var("SFU_GATEWAY_KEY").map_err(|_| ConfigError::Env {
-            var: "SFU_GATEWAY_KEY".to_string(),
-            message: "required but not set".to_string(),
-        })?;
-        let key = decode_and_validate_key(&key_str).map_err(|message| ConfigError::Env {
-            var: "SFU_GATEWAY_KEY".to_string(),
-            message,
-        })?;
+        let health_check_mode =
+            env_opt::<HealthCheckMode>("SFU_GATEWAY_HEALTH_CHECK_MODE")?.unwrap_or_default();
+        let health_check_interval =
+            Some(env_millis("SFU_GATEWAY_HEALTH_CHECK_INTERVAL_MS", 10_000)?)
+                .filter(|interval| !interval.is_zero());
+        let health_check_max_interval =
+            env_millis("SFU_GATEWAY_HEALTH_CHECK_MAX_INTERVAL_MS", 300_000)?;
+
+        let region = std::env::var("SFU_GATEWAY_REGION").ok();
+        let prefer_local_region = env_flag("SFU_GATEWAY_PREFER_LOCAL_REGION");
+        if prefer_local_region && region.is_none() {
+            return Err(ConfigError::Env {
+                var: "SFU_GATEWAY_REGION".to_string(),
+                message: "required when SFU_GATEWAY_PREFER_LOCAL_REGION is set".to_string(),
+            });
+        }
+
+        let affinity_max_entries =
+            env_opt::<usize>("SFU_GATEWAY_AFFINITY_MAX_ENTRIES")?.unwrap_or(10_000);
 
-        let nodes = std::env::var("SFU_GATEWAY_NODES").ok();
+        let token_query_param = std::env::var("SFU_GATEWAY_TOKEN_QUERY_PARAM")
+            .ok()
+            .filter(|name| !name.is_empty());
 
-        let trust_proxy = std::env::var("SFU_GATEWAY_TRUST_PROXY")
-            .map(|v| v.eq_ignore_ascii_case("true") || v == "1")
-            .unwrap_or(false);
+        let tcp_keepalive = Some(env_millis("SFU_GATEWAY_TCP_KEEPALIVE_MS", 60_000)?)
+            .filter(|keepalive| !keepalive.is_zero());
+
+        let geoip_db = std::env::var("SFU_GATEWAY_GEOIP_DB")
+            .ok()
+            .filter(|path| !path.is_empty());
+
+        let key_file = gateway_key_file_from_env()?;
+
+        let max_query_bytes =
+            env_opt::<usize>("SFU_GATEWAY_MAX_QUERY_BYTES")?.unwrap_or(DEFAULT_MAX_QUERY_BYTES);
 
         Ok(Self {
             bind,
             port,
-            key,
-            nodes,
-            trust_proxy,
+            keys: match &key_file {
+                Some(path) => load_gateway_keys(path)?,
+                None => gateway_keys_from_env()?,
+            },
+            key_file,
+            nodes: std::env::var("SFU_GATEWAY_NODES").ok(),
+            trust_proxy: env_flag("SFU_GATEWAY_TRUST_PROXY"),
+            seed: env_opt::<u64>("SFU_GATEWAY_SEED")?,
+            health_check_mode,
+            health_failure_threshold: env_threshold("SFU_GATEWAY_HEALTH_FAILURE_THRESHOLD", 3)?,
+            health_success_threshold: env_threshold("SFU_GATEWAY_HEALTH_SUCCESS_THRESHOLD", 2)?,
+            health_check_interval,
+            health_check_timeout: env_millis("SFU_GATEWAY_HEALTH_CHECK_TIMEOUT_MS", 2_000)?,
+            health_check_max_interval,
+            api_key: api_key_from_env()?,
+            region,
+            prefer_local_region,
+            default_region: std::env::var("SFU_GATEWAY_DEFAULT_REGION")
+                .ok()
+                .filter(|region| !region.is_empty()),
+            affinity_ttl: env_opt_millis("SFU_GATEWAY_AFFINITY_TTL_MS")?,
+            affinity_max_entries,
+            request_deadline: env_opt_millis("SFU_GATEWAY_REQUEST_DEADLINE_MS")?,
+            token_query_param,
+            slow_start: env_opt_millis("SFU_GATEWAY_SLOW_START_MS")?,
+            channel_lifetime: channel_lifetime_from_env()?,
+            jwks_url: std::env::var("SFU_GATEWAY_JWKS_URL").ok(),
+            jwks_ttl: env_millis("SFU_GATEWAY_JWKS_TTL_MS", 300_000)?,
+            max_attempts: env_threshold("SFU_GATEWAY_MAX_ATTEMPTS", 2)?,
+            strategy: env_opt::<SelectionStrategy>("SFU_GATEWAY_STRATEGY")?.unwrap_or_default(),
+            max_fallback_km: max_fallback_km_from_env()?,
+            sfu_timeout: env_millis("SFU_GATEWAY_SFU_TIMEOUT_MS", 5_000)?,
+            connect_timeout: env_millis("SFU_GATEWAY_CONNECT_TIMEOUT_MS", 2_000)?,
+            shutdown_grace: env_millis("SFU_GATEWAY_SHUTDOWN_GRACE_MS", 30_000)?,
+            pool_max_idle: env_opt::<usize>("SFU_GATEWAY_POOL_MAX_IDLE")?.unwrap_or(32),
+            pool_idle_timeout: env_millis("SFU_GATEWAY_POOL_IDLE_TIMEOUT_MS", 90_000)?,
+            tcp_keepalive,
+            validate_exp: env_opt::<bool>("SFU_GATEWAY_VALIDATE_EXP")?.unwrap_or(true),
+            leeway_secs: env_opt::<u64>("SFU_GATEWAY_LEEWAY")?.unwrap_or(60),
+            max_iat_skew_secs: env_opt::<u64>("SFU_GATEWAY_MAX_IAT_SKEW")?,
+            max_token_age_secs: env_opt::<u64>("SFU_GATEWAY_MAX_TOKEN_AGE_SECS")?,
+            geoip_db,
+            forward_headers: forward_headers_from_env()?,
+            rate_limit: env_opt::<u32>("SFU_GATEWAY_RATE_LIMIT")?.filter(|limit| *limit > 0),
+            sweep_interval: Some(env_millis("SFU_GATEWAY_SWEEP_INTERVAL_MS", 60_000)?)
+                .filter(|interval| !interval.is_zero()),
+            idle_ttl: env_millis("SFU_GATEWAY_IDLE_TTL_MS", 600_000)?,
+            sfu_url_schemes: sfu_url_schemes_from_env()?,
+            auth_schemes: auth_schemes_from_env()?,
+            debug_endpoints: env_flag("SFU_GATEWAY_DEBUG_ENDPOINTS"),
+            max_query_bytes,
+            max_body_bytes: env_opt::<usize>("SFU_GATEWAY_MAX_BODY_BYTES")?
+                .unwrap_or(DEFAULT_MAX_BODY_BYTES),
+            redact_sfu_address: env_flag("SFU_GATEWAY_REDACT_SFU_ADDRESS"),
+            strict_region: env_flag("SFU_GATEWAY_STRICT_REGION"),
+            strict_region_check: env_flag("SFU_GATEWAY_STRICT_REGION_CHECK"),
+            strict_key_check: env_flag("SFU_GATEWAY_STRICT_KEY_CHECK"),
+            status_probe_ttl: env_millis("SFU_GATEWAY_STATUS_PROBE_TTL_MS", 1_000)?,
+            default_scheme: default_scheme_from_env()?,
+            cors_origins: cors_origins_from_env()?,
+            admin_key: std::env::var("SFU_GATEWAY_ADMIN_KEY")
+                .ok()
+                .filter(|key| !key.is_empty()),
+            client_identity: client_identity_from_env()?,
+            min_tls_version: min_tls_version_from_env()?,
+            disable_proxy: env_flag("SFU_GATEWAY_DISABLE_PROXY"),
+        })
+    }
+
+    /// Region hint of the requests without one: `default_region`, else the gateway's own
+    /// region when `prefer_local_region` is set.
+    #[must_use]
+    pub fn hintless_region(&self) -> Option<&str> {
+        self.default_region
+            .as_deref()
+            .or(self.region.as_deref().filter(|_| self.prefer_local_region))
+    }
+}
+
+/// Gateway keys from the comma-separated `SFU_GATEWAY_KEY`, at least one.
+fn gateway_keys_from_env() -> Result<Vec<Vec<u8>>, ConfigError> {
+    let env_error = |message: &str| ConfigError::Env {
+        var: "SFU_GATEWAY_KEY".to_string(),
+        message: message.to_string(),
+    };
+    let list = std::env::var("SFU_GATEWAY_KEY")
+        .map_err(|_| env_error("required unless SFU_GATEWAY_KEY_FILE is set"))?;
+    parse_gateway_keys(&list).map_err(|message| env_error(&message))
+}
+
+/// Path of `SFU_GATEWAY_KEY_FILE`, which excludes `SFU_GATEWAY_KEY`.
+fn gateway_key_file_from_env() -> Result<Option<PathBuf>, ConfigError> {
+    let Some(path) = std::env::var("SFU_GATEWAY_KEY_FILE")
+        .ok()
+        .filter(|path| !path.is_empty())
+    else {
+        return Ok(None);
+    };
+    if std::env::var_os("SFU_GATEWAY_KEY").is_some() {
+        return Err(ConfigError::Env {
+            var: "SFU_GATEWAY_KEY_FILE".to_string(),
+            message: "can't be set along with SFU_GATEWAY_KEY".to_string(),
+        });
+    }
+    Ok(Some(PathBuf::from(path)))
+}
+
+/// Read the gateway keys from `path`, separated by commas or newlines, the current one first.
+///
+/// # Errors
+/// Returns `ConfigError::Io` when the file can't be read, `ConfigError::Env` when it doesn't
+/// hold at least one valid key.
+pub fn load_gateway_keys(path: &Path) -> Result<Vec<Vec<u8>>, ConfigError> {
+    let list = fs::read_to_string(path).map_err(|source| ConfigError::Io {
+        path: path.display().to_string(),
+        source,
+    })?;
+    parse_gateway_keys(&list.replace('\n', ",")).map_err(|message| ConfigError::Env {
+        var: "SFU_GATEWAY_KEY_FILE".to_string(),
+        message,
+    })
+}
+
+/// Decode a comma-separated list of keys, at least one.
+fn parse_gateway_keys(list: &str) -> Result<Vec<Vec<u8>>, String> {
+    let keys = list
+        .split(',')
+        .map(str::trim)
+        .filter(|key| !key.is_empty())
+        .map(decode_and_validate_key)
+        .collect::<Result<Vec<_>, _>>()?;
+    if keys.is_empty() {
+        return Err("required but empty".to_string());
+    }
+    Ok(keys)
+}
+
+/// Allowlist of `SFU_GATEWAY_FORWARD_HEADERS`, lowercased, `DEFAULT_FORWARD_HEADERS` when unset.
+fn forward_headers_from_env() -> Result<Vec<String>, ConfigError> {
+    let Ok(list) = std::env::var("SFU_GATEWAY_FORWARD_HEADERS") else {
+        return Ok(DEFAULT_FORWARD_HEADERS
+            .iter()
+            .map(|h| (*h).to_string())
+            .collect());
+    };
+    list.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let name = entry.strip_suffix('*').unwrap_or(entry);
+            // a lone `*` forwards every header but the ones never forwarded
+            if !name.is_empty() {
+                reqwest::header::HeaderName::from_bytes(name.as_bytes()).map_err(|e| {
+                    ConfigError::Env {
+                        var: "SFU_GATEWAY_FORWARD_HEADERS".to_string(),
+                        message: format!("invalid header name '{entry}': {e}"),
+                    }
+                })?;
+            }
+            Ok(entry.to_ascii_lowercase())
+        })
+        .collect()
+}
+
+/// `SFU_GATEWAY_DEFAULT_SCHEME`, `http` or `https`, `None` when unset or empty.
+fn default_scheme_from_env() -> Result<Option<String>, ConfigError> {
+    let Some(scheme) = std::env::var("SFU_GATEWAY_DEFAULT_SCHEME")
+        .ok()
+        .filter(|scheme| !scheme.is_empty())
+    else {
+        return Ok(None);
+    };
+    let scheme = scheme.to_ascii_lowercase();
+    if matches!(scheme.as_str(), "http" | "https") {
+        Ok(Some(scheme))
+    } else {
+        Err(ConfigError::Env {
+            var: "SFU_GATEWAY_DEFAULT_SCHEME".to_string(),
+            message: format!("unsupported scheme '{scheme}', expected http or https"),
+        })
+    }
+}
+
+/// Schemes of `SFU_GATEWAY_AUTH_SCHEME`, lowercased, empty (any scheme) when unset.
+fn auth_schemes_from_env() -> Result<Vec<String>, ConfigError> {
+    let list = std::env::var("SFU_GATEWAY_AUTH_SCHEME").unwrap_or_default();
+    list.split(',')
+        .map(str::trim)
+        .filter(|scheme| !scheme.is_empty())
+        .map(|scheme| {
+            if scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+            {
+                Ok(scheme.to_ascii_lowercase())
+            } else {
+                Err(ConfigError::Env {
+                    var: "SFU_GATEWAY_AUTH_SCHEME".to_string(),
+                    message: format!("invalid scheme '{scheme}'"),
+                })
+            }
+        })
+        .collect()
+}
+
+/// Schemes of `SFU_GATEWAY_SFU_URL_SCHEMES`, lowercased, `DEFAULT_SFU_URL_SCHEMES` when unset.
+fn sfu_url_schemes_from_env() -> Result<Vec<String>, ConfigError> {
+    let Ok(list) = std::env::var("SFU_GATEWAY_SFU_URL_SCHEMES") else {
+        return Ok(DEFAULT_SFU_URL_SCHEMES
+            .iter()
+            .map(|s| (*s).to_string())
+            .collect());
+    };
+    let env_error = |message: String| ConfigError::Env {
+        var: "SFU_GATEWAY_SFU_URL_SCHEMES".to_string(),
+        message,
+    };
+    let schemes: Vec<String> = list
+        .split(',')
+        .map(str::trim)
+        .filter(|scheme| !scheme.is_empty())
+        .map(|scheme| {
+            // RFC 3986: a letter followed by letters, digits, `+`, `-` or `.`
+            let valid = scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+            if valid {
+                Ok(scheme.to_ascii_lowercase())
+            } else {
+                Err(env_error(format!("invalid scheme '{scheme}'")))
+            }
+        })
+        .collect::<Result<_, _>>()?;
+    if schemes.is_empty() {
+        return Err(env_error("at least one scheme is required".to_string()));
+    }
+    Ok(schemes)
+}
+
+/// Origins of `SFU_GATEWAY_CORS_ORIGINS`, each `*` or a `scheme://host[:port]` origin, none when
+/// unset.
+fn cors_origins_from_env() -> Result<Vec<String>, ConfigError> {
+    let list = std::env::var("SFU_GATEWAY_CORS_ORIGINS").unwrap_or_default();
+    list.split(',')
+        .map(str::trim)
+        .filter(|origin| !origin.is_empty())
+        .map(|origin| {
+            let is_origin = origin == "*"
+                || url::Url::parse(origin)
+                    .is_ok_and(|url| url.origin().ascii_serialization() == origin);
+            if is_origin {
+                Ok(origin.to_string())
+            } else {
+                Err(ConfigError::Env {
+                    var: "SFU_GATEWAY_CORS_ORIGINS".to_string(),
+                    message: format!("invalid origin '{origin}', expected scheme://host[:port]"),
+                })
+            }
+        })
+        .collect()
+}
+
+/// Static API key settings, `None` when `SFU_GATEWAY_API_KEY` is unset or empty.
+fn api_key_from_env() -> Result<Option<ApiKeyConfig>, ConfigError> {
+    match std::env::var("SFU_GATEWAY_API_KEY") {
+        Ok(key) if !key.is_empty() => {
+            let iss = std::env::var("SFU_GATEWAY_API_KEY_ISS").map_err(|_| ConfigError::Env {
+                var: "SFU_GATEWAY_API_KEY_ISS".to_string(),
+                message: "required when SFU_GATEWAY_API_KEY is set".to_string(),
+            })?;
+            Ok(Some(ApiKeyConfig {
+                key,
+                iss,
+                region: std::env::var("SFU_GATEWAY_API_KEY_REGION").ok(),
+            }))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// `SFU_GATEWAY_MIN_TLS_VERSION`, TLS 1.2 when unset or empty.
+///
+/// Older versions aren't offered, rustls doesn't implement them.
+fn min_tls_version_from_env() -> Result<reqwest::tls::Version, ConfigError> {
+    match std::env::var("SFU_GATEWAY_MIN_TLS_VERSION")
+        .unwrap_or_default()
+        .as_str()
+    {
+        "" | "1.2" => Ok(reqwest::tls::Version::TLS_1_2),
+        "1.3" => Ok(reqwest::tls::Version::TLS_1_3),
+        other => Err(ConfigError::Env {
+            var: "SFU_GATEWAY_MIN_TLS_VERSION".to_string(),
+            message: format!("unsupported TLS version '{other}', expected 1.2 or 1.3"),
+        }),
+    }
+}
+
+/// Client certificate files, `None` when `SFU_GATEWAY_CLIENT_CERT` is unset or empty.
+fn client_identity_from_env() -> Result<Option<ClientIdentity>, ConfigError> {
+    let path = |var: &str| std::env::var(var).ok().filter(|path| !path.is_empty());
+    match (
+        path("SFU_GATEWAY_CLIENT_CERT"),
+        path("SFU_GATEWAY_CLIENT_KEY"),
+    ) {
+        (Some(cert), key) => Ok(Some(ClientIdentity { cert, key })),
+        (None, Some(_)) => Err(ConfigError::Env {
+            var: "SFU_GATEWAY_CLIENT_KEY".to_string(),
+            message: "requires SFU_GATEWAY_CLIENT_CERT".to_string(),
+        }),
+        (None, None) => Ok(None),
+    }
+}
+
+/// Read a boolean flag, set when the variable is `true` (any case) or `1`.
+fn env_flag(var: &str) -> bool {
+    std::env::var(var).is_ok_and(|v| v.eq_ignore_ascii_case("true") || v == "1")
+}
+
+/// Parse an optional environment variable.
+fn env_opt<T>(var: &str) -> Result<Option<T>, ConfigError>
+where
+    T: std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    std::env::var(var)
+        .ok()
+        .map(|v| v.parse::<T>())
+        .transpose()
+        .map_err(|e| ConfigError::Env {
+            var: var.to_string(),
+            message: format!("invalid value: {e}"),
         })
+}
+
+/// Parse a duration in milliseconds.
+fn env_millis(var: &str, default: u64) -> Result<Duration, ConfigError> {
+    Ok(Duration::from_millis(
+        env_opt::<u64>(var)?.unwrap_or(default),
+    ))
+}
+
+/// Distance of `SFU_GATEWAY_MAX_FALLBACK_KM`, which must be a positive number of km.
+fn max_fallback_km_from_env() -> Result<Option<f64>, ConfigError> {
+    const VAR: &str = "SFU_GATEWAY_MAX_FALLBACK_KM";
+    match env_opt::<f64>(VAR)? {
+        Some(km) if !km.is_finite() || km <= 0.0 => Err(ConfigError::Env {
+            var: VAR.to_string(),
+            message: "must be a positive number of km".to_string(),
+        }),
+        km => Ok(km),
+    }
+}
+
+/// Duration of `SFU_GATEWAY_CHANNEL_LIFETIME_MS`, 0 would never count any channel.
+fn channel_lifetime_from_env() -> Result<Duration, ConfigError> {
+    const VAR: &str = "SFU_GATEWAY_CHANNEL_LIFETIME_MS";
+    match env_opt::<u64>(VAR)? {
+        Some(0) => Err(ConfigError::Env {
+            var: VAR.to_string(),
+            message: "must be at least 1".to_string(),
+        }),
+        ms => Ok(ms.map_or(DEFAULT_CHANNEL_LIFETIME, Duration::from_millis)),
+    }
+}
+
+/// Parse an optional duration in milliseconds, `None` when unset or 0.
+fn env_opt_millis(var: &str) -> Result<Option<Duration>, ConfigError> {
+    Ok(env_opt::<u64>(var)?
+        .filter(|ms| *ms > 0)
+        .map(Duration::from_millis))
+}
+
+/// Parse a consecutive-results threshold, which must be at least 1.
+fn env_threshold(var: &str, default: u32) -> Result<u32, ConfigError> {
+    match env_opt::<u32>(var)? {
+        Some(0) => Err(ConfigError::Env {
+            var: var.to_string(),
+            message: "must be at least 1".to_string(),
+        }),
+        value => Ok(value.unwrap_or(default)),
+    }
+}
+
+/// How the balancer picks an SFU among the candidates of a request
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SelectionStrategy {
+    /// Rotate over the candidates, weighted when their weights differ
+    #[default]
+    RoundRobin,
+    /// Consistent hashing of the channel (JWT issuer), reconnects reach the same SFU
+    Sticky,
+    /// The candidate with the fewest channels assigned within the channel lifetime for its
+    /// weight, ties rotate
+    LeastConnections,
+    /// The candidate with the lowest probe round-trip time, close ones rotate
+    LowestLatency,
+    /// A random candidate, weighted, so that gateway replicas don't pick in step
+    WeightedRandom,
+}
+
+impl std::str::FromStr for SelectionStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "round-robin" => Ok(Self::RoundRobin),
+            "sticky" => Ok(Self::Sticky),
+            "least-connections" => Ok(Self::LeastConnections),
+            "lowest-latency" => Ok(Self::LowestLatency),
+            "weighted-random" => Ok(Self::WeightedRandom),
+            _ => Err(format!(
+                "unknown strategy '{s}', expected 'round-robin', 'sticky', 'least-connections', 'lowest-latency' or 'weighted-random'"
+            )),
+        }
     }
 }
 
@@ -69,40 +753,219 @@ impl GatewayConfig {
 struct RawNodeData {
     #[serde(default)]
     sfu: Vec<RawSfuConfig>,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    #[serde(default)]
+    geo: RawGeoConfig,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+struct RawGeoConfig {
+    #[serde(default)]
+    regions: BTreeMap<String, RawRegionCoords>,
+    #[serde(default)]
+    countries: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+struct RawRegionCoords {
+    lat: f64,
+    lon: f64,
 }
 
 #[derive(Debug, Clone, Deserialize)]
 struct RawSfuConfig {
     address: String,
     #[serde(default)]
-    region: Option<String>, // TODO: region should be a well defined type, not all strings can be a region.
-    key: String,
+    region: Option<RawRegions>, // checked against the known regions, see `warn_unknown_regions`
+    #[serde(default)]
+    key: Option<String>,
+    /// `key` while the SFU rotates its secret, the current key first
+    #[serde(default)]
+    keys: Vec<String>,
+    #[serde(default)]
+    health_check: Option<HealthCheckMode>,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    #[serde(default = "default_weight")]
+    weight: u32,
+    #[serde(default)]
+    max_channels: Option<u32>,
+    #[serde(default = "default_accepts_recording_key")]
+    accepts_recording_key: bool,
+    #[serde(default)]
+    path_prefix: String,
+    #[serde(default)]
+    algorithm: Option<String>,
+    #[serde(default)]
+    kid: Option<String>,
+}
+
+const fn default_weight() -> u32 {
+    1
+}
+
+const fn default_accepts_recording_key() -> bool {
+    true
+}
+
+/// `region = "eu-west"` or `region = ["eu-west", "eu-central"]`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum RawRegions {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl From<RawRegions> for Vec<String> {
+    fn from(raw: RawRegions) -> Self {
+        match raw {
+            RawRegions::One(region) => vec![region],
+            RawRegions::Many(regions) => regions,
+        }
+    }
+}
+
+/// How an SFU is probed to decide whether it is healthy
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthCheckMode {
+    /// `GET /noop` must succeed
+    #[default]
+    Http,
+    /// A TCP connection to the SFU's host and port must succeed, cheaper for large fleets
+    Tcp,
+}
+
+impl std::str::FromStr for HealthCheckMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "http" => Ok(Self::Http),
+            "tcp" => Ok(Self::Tcp),
+            _ => Err(format!(
+                "unknown health check mode '{s}', expected 'http' or 'tcp'"
+            )),
+        }
+    }
 }
 
 /// Node data containing SFU entries
 #[derive(Debug, Clone)]
 pub struct NodeData {
     pub sfu: Vec<SfuConfig>,
+    /// Static headers sent on every request to any SFU (values already interpolated)
+    pub headers: Vec<(String, String)>,
+    /// Additions and corrections to the built-in regions and country mapping
+    pub geo: GeoConfig,
 }
 
-#[derive(Debug, Clone)]
+/// The `[geo]` section, merged over the built-in geo data at startup
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GeoConfig {
+    /// Regions to add, or to move when the name is a built-in one: (name, latitude, longitude)
+    pub regions: Vec<(String, f64, f64)>,
+    /// Country codes (upper case ISO 3166-1 alpha-2) to map to a region, over the built-in mapping
+    pub countries: Vec<(String, String)>,
+}
+
+impl GeoConfig {
+    fn from_raw(raw: RawGeoConfig) -> Result<Self, ConfigError> {
+        let geo_error = |message: String| ConfigError::Geo { message };
+        let regions = raw
+            .regions
+            .into_iter()
+            .map(|(name, RawRegionCoords { lat, lon })| {
+                if !(-90.0..=90.0).contains(&lat) || !(-180.0..=180.0).contains(&lon) {
+                    return Err(geo_error(format!(
+                        "region '{name}': coordinates ({lat}, {lon}) out of range"
+                    )));
+                }
+                Ok((name, lat, lon))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let countries = raw
+            .countries
+            .into_iter()
+            .map(|(country, region)| {
+                if country.len() != 2 || !country.bytes().all(|b| b.is_ascii_alphabetic()) {
+                    return Err(geo_error(format!(
+                        "'{country}' is not an ISO 3166-1 alpha-2 country code"
+                    )));
+                }
+                Ok((country.to_ascii_uppercase(), region))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { regions, countries })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct SfuConfig {
     /// The base URL of the SFU (e.g., `http://sfu1.example.com:3000`)
     pub address: String,
-    /// Geographic region identifier (e.g., "eu-west", "us-east")
-    pub region: Option<String>,
-    /// The decoded JWT secret key for this SFU (32 bytes)
+    /// Geographic regions served by this SFU (e.g., "eu-west", "us-east"), usually one
+    pub regions: Vec<String>,
+    /// The decoded JWT secret key for this SFU (32 bytes), the tokens are signed with it
     pub key: Vec<u8>,
+    /// The other keys listed in `keys` after the current one, decoded but not used for signing
+    pub other_keys: Vec<Vec<u8>>,
+    /// Overrides the gateway-wide health check mode for this SFU
+    pub health_check: Option<HealthCheckMode>,
+    /// Static headers sent on every request to this SFU, on top of the global ones
+    pub headers: Vec<(String, String)>,
+    /// Relative capacity, an SFU of weight 3 gets three times the traffic of one of weight 1
+    pub weight: u32,
+    /// Channels this SFU is given at most, unlimited when `None`
+    pub max_channels: Option<u32>,
+    /// Whether the recording encryption key (`key` claim) is forwarded to this SFU
+    pub accepts_recording_key: bool,
+    /// Prepended to the path of the forwarded requests (e.g. `/sfu` for `/sfu/v1/channel`),
+    /// empty when the SFU serves `/v1` at its root
+    pub path_prefix: String,
+    /// HMAC algorithm of the tokens signed for this SFU
+    pub algorithm: Algorithm,
+    /// `kid` header of the tokens signed for this SFU, for SFUs that pick their key by it
+    pub kid: Option<String>,
+}
+
+impl Default for SfuConfig {
+    fn default() -> Self {
+        Self {
+            address: String::new(),
+            regions: Vec::new(),
+            key: Vec::new(),
+            other_keys: Vec::new(),
+            health_check: None,
+            headers: Vec::new(),
+            weight: default_weight(),
+            max_channels: None,
+            accepts_recording_key: default_accepts_recording_key(),
+            path_prefix: String::new(),
+            algorithm: Algorithm::HS256,
+            kid: None,
+        }
+    }
 }
 
-fn decode_base64(key: &str) -> Result<Vec<u8>, base64::DecodeError> {
-    base64::engine::general_purpose::URL_SAFE_NO_PAD
-        .decode(key.trim_end_matches('='))
-        .or_else(|_| base64::engine::general_purpose::STANDARD.decode(key))
+/// Decode a base64 key whatever the tooling that produced it: URL-safe or standard alphabet,
+/// with or without padding, tried in that order.
+///
+/// # Errors
+/// Returns the error of the last alphabet tried when none decodes the key.
+pub fn decode_key(key: &str) -> Result<Vec<u8>, base64::DecodeError> {
+    use base64::engine::general_purpose::{STANDARD, STANDARD_NO_PAD, URL_SAFE, URL_SAFE_NO_PAD};
+
+    URL_SAFE
+        .decode(key)
+        .or_else(|_| URL_SAFE_NO_PAD.decode(key))
+        .or_else(|_| STANDARD.decode(key))
+        .or_else(|_| STANDARD_NO_PAD.decode(key))
 }
 
 fn decode_and_validate_key(key: &str) -> Result<Vec<u8>, String> {
-    let bytes = decode_base64(key).map_err(|e| format!("invalid base64: {e}"))?;
+    let bytes = decode_key(key).map_err(|e| format!("invalid base64: {e}"))?;
     if bytes.len() < EXPECTED_KEY_LENGTH {
         tracing::warn!(
             key_length = bytes.len(),
@@ -113,55 +976,375 @@ fn decode_and_validate_key(key: &str) -> Result<Vec<u8>, String> {
     Ok(bytes)
 }
 
+/// Decode the key of an SFU, given either as `key` or as a `keys` list starting with the
+/// current one, returns the current key and the others.
+fn decode_sfu_keys(key: Option<&str>, keys: &[String]) -> Result<(Vec<u8>, Vec<Vec<u8>>), String> {
+    match (key, keys) {
+        (Some(key), []) => Ok((decode_and_validate_key(key)?, Vec::new())),
+        (None, [current, others @ ..]) => {
+            let decode = |(i, key): (usize, &String)| {
+                decode_and_validate_key(key).map_err(|message| format!("keys[{i}]: {message}"))
+            };
+            let current = decode((0, current))?;
+            let others = others
+                .iter()
+                .enumerate()
+                .map(|(i, key)| decode((i + 1, key)))
+                .collect::<Result<_, _>>()?;
+            Ok((current, others))
+        }
+        (Some(_), [_, ..]) => Err("set either key or keys, not both".to_string()),
+        (None, []) => Err("key (or a non-empty keys list) is required".to_string()),
+    }
+}
+
+/// Check that an SFU address is an http(s) base URL and drop its trailing slash,
+/// so that appending `/v1/channel` always yields a clean path.
+///
+/// An address without `://` gets `default_scheme` prepended when set, it is rejected otherwise.
+fn normalize_address(address: &str, default_scheme: Option<&str>) -> Result<String, String> {
+    let address = match default_scheme {
+        Some(scheme) if !address.contains("://") => format!("{scheme}://{address}"),
+        _ => address.to_string(),
+    };
+    let url = url::Url::parse(&address).map_err(|e| format!("invalid URL: {e}"))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(format!(
+            "unsupported scheme '{}', expected http or https",
+            url.scheme()
+        ));
+    }
+    if url.host().is_none() {
+        return Err("missing host".to_string());
+    }
+    if url.query().is_some() || url.fragment().is_some() {
+        return Err("must not have a query or fragment".to_string());
+    }
+    Ok(url.as_str().trim_end_matches('/').to_string())
+}
+
+/// Reject an SFU listed twice, which would silently double its share of the traffic.
+///
+/// Addresses are compared once normalized, see `normalize_address`.
+fn check_duplicate_addresses(sfus: &[SfuConfig]) -> Result<(), ConfigError> {
+    let mut seen = HashMap::new();
+    for (index, sfu) in sfus.iter().enumerate() {
+        if let Some(first) = seen.insert(sfu.address.as_str(), index) {
+            return Err(ConfigError::Sfu {
+                index,
+                address: sfu.address.clone(),
+                message: format!("duplicate address, already used by SFU[{first}]"),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Algorithm named by `algorithm`, only HMAC ones fit the shared SFU keys.
+fn parse_signing_algorithm(name: &str) -> Result<Algorithm, String> {
+    match name.parse() {
+        Ok(algorithm @ (Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512)) => Ok(algorithm),
+        _ => Err(format!(
+            "algorithm '{name}' is not supported, expected HS256, HS384 or HS512"
+        )),
+    }
+}
+
+/// Check that a non-empty path prefix starts with `/` and has no trailing slash, so that it can
+/// be put right before `/v1/...`.
+fn check_path_prefix(prefix: &str) -> Result<(), String> {
+    if prefix.is_empty() {
+        return Ok(());
+    }
+    if !prefix.starts_with('/') {
+        return Err(format!("path_prefix '{prefix}' must start with '/'"));
+    }
+    if prefix.ends_with('/') {
+        return Err(format!("path_prefix '{prefix}' must not end with '/'"));
+    }
+    if prefix.contains(['?', '#']) {
+        return Err(format!("path_prefix '{prefix}' must be a path only"));
+    }
+    Ok(())
+}
+
+/// Replace `${VAR}` references with the value of the environment variable `VAR`.
+fn interpolate_env(value: &str) -> Result<String, String> {
+    let mut result = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        result.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let end = after
+            .find('}')
+            .ok_or_else(|| format!("unterminated '${{' in '{value}'"))?;
+        let var = &after[..end];
+        let var_value =
+            std::env::var(var).map_err(|_| format!("environment variable {var} is not set"))?;
+        result.push_str(&var_value);
+        rest = &after[end + 1..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+/// Interpolate and validate static headers.
+fn parse_static_headers(
+    raw: BTreeMap<String, String>,
+) -> Result<Vec<(String, String)>, ConfigError> {
+    raw.into_iter()
+        .map(|(name, value)| {
+            let error = |message: String| ConfigError::Header {
+                name: name.clone(),
+                message,
+            };
+            if RESERVED_HEADERS.contains(&name.to_ascii_lowercase().as_str()) {
+                return Err(error(
+                    "set by the gateway, cannot be overridden".to_string(),
+                ));
+            }
+            reqwest::header::HeaderName::from_bytes(name.as_bytes())
+                .map_err(|e| error(e.to_string()))?;
+            let value = interpolate_env(&value).map_err(error)?;
+            reqwest::header::HeaderValue::from_str(&value).map_err(|e| error(e.to_string()))?;
+            Ok((name, value))
+        })
+        .collect()
+}
+
+/// Warn when SFUs of a same region have keys of different lengths.
+///
+/// Keys of a deployment are usually generated the same way, so a mismatch often
+/// reveals a copy-paste or encoding mistake. Heuristic only, never fails.
+fn warn_inconsistent_key_lengths(sfus: &[SfuConfig]) {
+    let mut lengths: BTreeMap<&str, (usize, usize)> = BTreeMap::new();
+    for sfu in sfus {
+        for region in &sfu.regions {
+            let (min, max) = lengths
+                .entry(region)
+                .or_insert((sfu.key.len(), sfu.key.len()));
+            *min = (*min).min(sfu.key.len());
+            *max = (*max).max(sfu.key.len());
+        }
+    }
+    for (region, (min, max)) in lengths {
+        if min != max {
+            tracing::warn!(
+                region,
+                min_key_length = min,
+                max_key_length = max,
+                "SFUs of the same region have keys of different lengths, check for encoding mistakes"
+            );
+        }
+    }
+}
+
+/// Warn about SFU regions that are neither built in nor in the `[geo]` section.
+///
+/// Such a region, a typo most of the time, has no coordinates: region hints never match it
+/// and its SFUs are only picked when requests fall back to all SFUs.
+fn warn_unknown_regions(sfus: &[SfuConfig], geo: &GeoConfig) {
+    let table = GeoTable::default().with_overrides(geo);
+    for sfu in sfus {
+        for region in sfu.regions.iter().filter(|r| !table.is_known_region(r)) {
+            tracing::warn!(
+                address = %sfu.address,
+                region,
+                "Unknown region, region hints will never route to this SFU; add it to [geo.regions] if intended"
+            );
+        }
+    }
+}
+
 impl NodeData {
-    /// Load node data from a TOML file.
+    /// Load node data from a file, YAML for a `.yaml` or `.yml` extension, JSON for `.json`,
+    /// TOML otherwise.
     ///
     /// # Errors
-    /// Returns `ConfigError::Io` on file read failure, `ConfigError::Toml` on parse failure.
+    /// Returns `ConfigError::Io` on file read failure, `ConfigError::Toml`, `ConfigError::Yaml`
+    /// or `ConfigError::Json` on parse failure, `ConfigError::Key`, `ConfigError::Address` and
+    /// `ConfigError::Sfu` on invalid SFU entries, `ConfigError::Geo` on an invalid `geo` section.
     pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
-        let content = fs::read_to_string(path.as_ref()).map_err(|e| ConfigError::Io {
-            path: path.as_ref().display().to_string(),
+        Self::load_with_default_scheme(path, None)
+    }
+
+    /// Same as `load`, addresses without a scheme get `default_scheme` (`http` or `https`)
+    /// rather than being rejected.
+    ///
+    /// # Errors
+    /// Same as `load`.
+    pub fn load_with_default_scheme<P: AsRef<Path>>(
+        path: P,
+        default_scheme: Option<&str>,
+    ) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path).map_err(|e| ConfigError::Io {
+            path: path.display().to_string(),
             source: e,
         })?;
-        let raw: RawNodeData = toml::from_str(&content).map_err(ConfigError::Toml)?;
-        Self::from_raw(raw)
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_ascii_lowercase);
+        let raw: RawNodeData = match extension.as_deref() {
+            Some("yaml" | "yml") => serde_yaml::from_str(&content).map_err(ConfigError::Yaml)?,
+            Some("json") => serde_json::from_str(&content).map_err(ConfigError::Json)?,
+            _ => toml::from_str(&content).map_err(ConfigError::Toml)?,
+        };
+        Self::from_raw(raw, default_scheme)
+    }
+
+    /// Parse node data from a YAML string, same shape as the TOML file.
+    ///
+    /// # Errors
+    /// Returns `ConfigError::Yaml` on parse failure, and the errors of `from_json` on invalid
+    /// entries.
+    pub fn from_yaml(yaml: &str) -> Result<Self, ConfigError> {
+        let raw: RawNodeData = serde_yaml::from_str(yaml).map_err(ConfigError::Yaml)?;
+        Self::from_raw(raw, None)
     }
 
     /// Parse node data from a JSON string.
     ///
     /// # Errors
-    /// Returns `ConfigError::Json` on parse failure, `ConfigError::Key` on invalid keys.
+    /// Returns `ConfigError::Json` on parse failure, `ConfigError::Key` on invalid keys,
+    /// `ConfigError::Address` on invalid addresses, `ConfigError::Sfu` on other invalid SFU entries,
+    /// `ConfigError::Geo` on an invalid `geo` object.
     pub fn from_json(json: &str) -> Result<Self, ConfigError> {
+        Self::from_json_with_default_scheme(json, None)
+    }
+
+    /// Same as `from_json`, with the default scheme of `load_with_default_scheme`.
+    ///
+    /// # Errors
+    /// Same as `from_json`.
+    pub fn from_json_with_default_scheme(
+        json: &str,
+        default_scheme: Option<&str>,
+    ) -> Result<Self, ConfigError> {
         let raw: RawNodeData = serde_json::from_str(json).map_err(ConfigError::Json)?;
-        Self::from_raw(raw)
+        Self::from_raw(raw, default_scheme)
     }
 
+    /// Parse node data from a TOML string.
+    ///
+    /// # Errors
+    /// Returns `ConfigError::Toml` on parse failure, otherwise the errors of `from_json`.
     #[cfg(test)]
     pub fn load_from_toml(toml_str: &str) -> Result<Self, ConfigError> {
         let raw: RawNodeData = toml::from_str(toml_str).map_err(ConfigError::Toml)?;
-        Self::from_raw(raw)
+        Self::from_raw(raw, None)
+    }
+
+    /// Warn about every SFU whose key, current or not, is also one of `gateway_keys`.
+    ///
+    /// Tokens are re-signed so that whoever holds a gateway key can't talk to the SFUs
+    /// directly, an SFU sharing a gateway key defeats that.
+    ///
+    /// # Errors
+    /// Returns `ConfigError::Key` for the first such SFU.
+    pub fn check_key_isolation(&self, gateway_keys: &[Vec<u8>]) -> Result<(), ConfigError> {
+        let mut first = None;
+        for (index, sfu) in self.sfu.iter().enumerate() {
+            let shared = std::iter::once(&sfu.key).chain(&sfu.other_keys).any(|key| {
+                gateway_keys
+                    .iter()
+                    .any(|gateway_key| constant_time_eq(key, gateway_key))
+            });
+            if shared {
+                tracing::warn!(
+                    address = %sfu.address,
+                    "SFU key is also a gateway key, re-signing tokens for this SFU protects nothing"
+                );
+                first.get_or_insert_with(|| ConfigError::Key {
+                    index,
+                    address: sfu.address.clone(),
+                    message: "same as a gateway key".to_string(),
+                });
+            }
+        }
+        first.map_or(Ok(()), Err)
     }
 
-    fn from_raw(raw: RawNodeData) -> Result<Self, ConfigError> {
+    fn from_raw(raw: RawNodeData, default_scheme: Option<&str>) -> Result<Self, ConfigError> {
         let sfu = raw
             .sfu
             .into_iter()
             .enumerate()
             .map(|(i, raw_sfu)| {
-                let key =
-                    decode_and_validate_key(&raw_sfu.key).map_err(|message| ConfigError::Key {
+                let address =
+                    normalize_address(&raw_sfu.address, default_scheme).map_err(|message| {
+                        ConfigError::Address {
+                            index: i,
+                            address: raw_sfu.address.clone(),
+                            message,
+                        }
+                    })?;
+                let (key, other_keys) = decode_sfu_keys(raw_sfu.key.as_deref(), &raw_sfu.keys)
+                    .map_err(|message| ConfigError::Key {
+                        index: i,
+                        address: raw_sfu.address.clone(),
+                        message,
+                    })?;
+                if raw_sfu.weight == 0 {
+                    return Err(ConfigError::Sfu {
+                        index: i,
+                        address: raw_sfu.address,
+                        message: "weight must be at least 1".to_string(),
+                    });
+                }
+                if raw_sfu.max_channels == Some(0) {
+                    return Err(ConfigError::Sfu {
+                        index: i,
+                        address: raw_sfu.address,
+                        message: "max_channels must be at least 1".to_string(),
+                    });
+                }
+                if let Err(message) = check_path_prefix(&raw_sfu.path_prefix) {
+                    return Err(ConfigError::Sfu {
+                        index: i,
+                        address: raw_sfu.address,
+                        message,
+                    });
+                }
+                let algorithm = raw_sfu
+                    .algorithm
+                    .as_deref()
+                    .map_or(Ok(Algorithm::HS256), parse_signing_algorithm)
+                    .map_err(|message| ConfigError::Sfu {
                         index: i,
                         address: raw_sfu.address.clone(),
                         message,
                     })?;
+                if raw_sfu.kid.as_deref() == Some("") {
+                    return Err(ConfigError::Sfu {
+                        index: i,
+                        address: raw_sfu.address,
+                        message: "kid must not be empty".to_string(),
+                    });
+                }
                 Ok(SfuConfig {
-                    address: raw_sfu.address,
-                    region: raw_sfu.region,
+                    address,
+                    regions: raw_sfu.region.map(Vec::from).unwrap_or_default(),
                     key,
+                    other_keys,
+                    health_check: raw_sfu.health_check,
+                    headers: parse_static_headers(raw_sfu.headers)?,
+                    weight: raw_sfu.weight,
+                    max_channels: raw_sfu.max_channels,
+                    accepts_recording_key: raw_sfu.accepts_recording_key,
+                    path_prefix: raw_sfu.path_prefix,
+                    algorithm,
+                    kid: raw_sfu.kid,
                 })
             })
             .collect::<Result<Vec<_>, ConfigError>>()?;
-        Ok(Self { sfu })
+        check_duplicate_addresses(&sfu)?;
+        warn_inconsistent_key_lengths(&sfu);
+        let headers = parse_static_headers(raw.headers)?;
+        let geo = GeoConfig::from_raw(raw.geo)?;
+        warn_unknown_regions(&sfu, &geo);
+        Ok(Self { sfu, headers, geo })
     }
 }
 
@@ -172,6 +1355,7 @@ pub enum ConfigError {
         source: std::io::Error,
     },
     Toml(toml::de::Error),
+    Yaml(serde_yaml::Error),
     Json(serde_json::Error),
     Env {
         var: String,
@@ -182,6 +1366,36 @@ pub enum ConfigError {
         address: String,
         message: String,
     },
+    Header {
+        name: String,
+        message: String,
+    },
+    Sfu {
+        index: usize,
+        address: String,
+        message: String,
+    },
+    Address {
+        index: usize,
+        address: String,
+        message: String,
+    },
+    Geo {
+        message: String,
+    },
+    /// Region setup refused by `SFU_GATEWAY_STRICT_REGION_CHECK`
+    Region {
+        message: String,
+    },
+    /// Database file that can be read but isn't one, such as the GeoIP database
+    Database {
+        path: String,
+        message: String,
+    },
+    /// TLS settings the HTTP client to the SFUs can't be built with
+    Tls {
+        message: String,
+    },
 }
 
 impl std::fmt::Display for ConfigError {
@@ -191,6 +1405,7 @@ impl std::fmt::Display for ConfigError {
                 write!(f, "failed to read file '{path}': {source}")
             }
             Self::Toml(e) => write!(f, "failed to parse TOML config: {e}"),
+            Self::Yaml(e) => write!(f, "failed to parse YAML config: {e}"),
             Self::Json(e) => write!(f, "failed to parse JSON config: {e}"),
             Self::Env { var, message } => {
                 write!(f, "environment variable {var}: {message}")
@@ -202,13 +1417,66 @@ impl std::fmt::Display for ConfigError {
             } => {
                 write!(f, "invalid key for SFU[{index}] at '{address}': {message}")
             }
+            Self::Header { name, message } => {
+                write!(f, "invalid static header '{name}': {message}")
+            }
+            Self::Sfu {
+                index,
+                address,
+                message,
+            } => {
+                write!(f, "invalid SFU[{index}] at '{address}': {message}")
+            }
+            Self::Address {
+                index,
+                address,
+                message,
+            } => {
+                write!(f, "invalid address for SFU[{index}] '{address}': {message}")
+            }
+            Self::Geo { message } => write!(f, "invalid geo section: {message}"),
+            Self::Region { message } => write!(f, "region check failed: {message}"),
+            Self::Database { path, message } => {
+                write!(f, "invalid database '{path}': {message}")
+            }
+            Self::Tls { message } => write!(f, "invalid TLS settings: {message}"),
         }
     }
 }
 
 impl std::error::Error for ConfigError {}
 
-#[cfg(test)]
+impl ConfigError {
+    /// Process exit code for this error, so that a deployment can tell failures apart.
+    ///
+    /// 2 for an invalid environment variable, 3 for an unreadable file, 4 for a file that
+    /// doesn't parse, 5 for an invalid SFU key, 6 for other invalid SFU, header, geo or region
+    /// settings and 7 for unusable TLS settings. 1 is left to the failures that aren't
+    /// configuration errors, 64 to command line usage errors.
+    #[must_use]
+    pub const fn exit_code(&self) -> u8 {
+        match self {
+            Self::Env { .. } => 2,
+            Self::Io { .. } => 3,
+            Self::Toml(_) | Self::Yaml(_) | Self::Json(_) | Self::Database { .. } => 4,
+            Self::Key { .. } => 5,
+            Self::Header { .. }
+            | Self::Sfu { .. }
+            | Self::Address { .. }
+            | Self::Geo { .. }
+            | Self::Region { .. } => 6,
+            Self::Tls { .. } => 7,
+        }
+    }
+}
+
+impl From<&ConfigError> for std::process::ExitCode {
+    fn from(error: &ConfigError) -> Self {
+        Self::from(error.exit_code())
+    }
+}
+
+#[cfg(test)]
 mod tests {
     use super::*;
 
@@ -218,6 +1486,101 @@ mod tests {
     // 32 bytes: "other-secret-key-123456789012345" in base64
     const VALID_KEY_2: &str = "b3RoZXItc2VjcmV0LWtleS0xMjM0NTY3ODkwMTIzNDU=";
     const VALID_KEY_2_BYTES: &[u8] = b"other-secret-key-123456789012345";
+    const VALID_KEY_3: &str = "dGhpcmQtdGVzdC1rZXktcGFkZGVkLTMyLWJ5dGVzISE=";
+    const VALID_KEY_4: &str = "Z2F0ZXdheS10ZXN0LWtleS1wYWRkZWQtMzJieXRlcyE=";
+
+    #[test]
+    fn test_exit_codes() {
+        let index = 0;
+        let address = "http://sfu.example.com".to_string();
+        let message = "invalid".to_string();
+        let cases = [
+            (
+                ConfigError::Env {
+                    var: "SFU_GATEWAY_PORT".to_string(),
+                    message: message.clone(),
+                },
+                2,
+            ),
+            (
+                ConfigError::Io {
+                    path: "secrets.toml".to_string(),
+                    source: std::io::Error::from(std::io::ErrorKind::NotFound),
+                },
+                3,
+            ),
+            (
+                ConfigError::Toml(toml::from_str::<RawNodeData>("[[sfu").unwrap_err()),
+                4,
+            ),
+            (
+                ConfigError::Yaml(serde_yaml::from_str::<RawNodeData>("sfu: [").unwrap_err()),
+                4,
+            ),
+            (
+                ConfigError::Json(serde_json::from_str::<RawNodeData>("{").unwrap_err()),
+                4,
+            ),
+            (
+                ConfigError::Key {
+                    index,
+                    address: address.clone(),
+                    message: message.clone(),
+                },
+                5,
+            ),
+            (
+                ConfigError::Header {
+                    name: "X-Test".to_string(),
+                    message: message.clone(),
+                },
+                6,
+            ),
+            (
+                ConfigError::Sfu {
+                    index,
+                    address: address.clone(),
+                    message: message.clone(),
+                },
+                6,
+            ),
+            (
+                ConfigError::Address {
+                    index,
+                    address,
+                    message: message.clone(),
+                },
+                6,
+            ),
+            (
+                ConfigError::Geo {
+                    message: message.clone(),
+                },
+                6,
+            ),
+            (
+                ConfigError::Region {
+                    message: message.clone(),
+                },
+                6,
+            ),
+            (
+                ConfigError::Database {
+                    path: "GeoLite2-Country.mmdb".to_string(),
+                    message: message.clone(),
+                },
+                4,
+            ),
+            (ConfigError::Tls { message }, 7),
+        ];
+        for (error, code) in cases {
+            assert_eq!(error.exit_code(), code, "{error}");
+            assert_eq!(
+                std::process::ExitCode::from(&error),
+                std::process::ExitCode::from(code)
+            );
+        }
+    }
 
     #[test]
     fn test_parse_secrets_file() {
@@ -237,96 +1600,1419 @@ mod tests {
         let secrets = NodeData::load_from_toml(&config_str).unwrap();
         assert_eq!(secrets.sfu.len(), 2);
         assert_eq!(secrets.sfu[0].address, "http://sfu1.example.com:3000");
-        assert_eq!(secrets.sfu[0].region, Some("eu-west".to_string()));
+        assert_eq!(secrets.sfu[0].regions, vec!["eu-west".to_string()]);
         assert_eq!(secrets.sfu[0].key, VALID_KEY_1_BYTES);
-        assert_eq!(secrets.sfu[1].region, None);
+        assert!(secrets.sfu[1].regions.is_empty());
         assert_eq!(secrets.sfu[1].key, VALID_KEY_2_BYTES);
     }
 
     #[test]
-    fn test_empty_secrets_file() {
-        let config_str = "";
-        let secrets = NodeData::load_from_toml(config_str).unwrap();
-        assert!(secrets.sfu.is_empty());
-    }
+    fn test_parse_yaml_same_as_toml() {
+        let toml_str = format!(
+            r#"
+            [[sfu]]
+            address = "http://sfu1.example.com:3000"
+            region = ["eu-west", "eu-central"]
+            key = "{VALID_KEY_1}"
+            weight = 3
+            headers = {{ X-Tenant = "acme" }}
 
-    #[test]
-    fn test_parse_json_nodes() {
-        let json_str = format!(
-            r#"{{
-            "sfu": [
-                {{
-                    "address": "http://sfu1.example.com:3000",
-                    "region": "eu-west",
-                    "key": "{VALID_KEY_1}"
-                }}
-            ]
-        }}"#
+            [[sfu]]
+            address = "http://sfu2.example.com:3000"
+            key = "{VALID_KEY_2}"
+            health_check = "tcp"
+        "#
+        );
+        let yaml_str = format!(
+            r#"
+sfu:
+  - address: http://sfu1.example.com:3000
+    region: [eu-west, eu-central]
+    key: "{VALID_KEY_1}"
+    weight: 3
+    headers:
+      X-Tenant: acme
+  - address: http://sfu2.example.com:3000
+    key: "{VALID_KEY_2}"
+    health_check: tcp
+"#
         );
 
-        let secrets = NodeData::from_json(&json_str).unwrap();
-        assert_eq!(secrets.sfu.len(), 1);
-        assert_eq!(secrets.sfu[0].address, "http://sfu1.example.com:3000");
-        assert_eq!(secrets.sfu[0].key, VALID_KEY_1_BYTES);
+        let from_toml = NodeData::load_from_toml(&toml_str).unwrap();
+        let from_yaml = NodeData::from_yaml(&yaml_str).unwrap();
+        assert_eq!(from_yaml.sfu, from_toml.sfu);
+        assert_eq!(from_yaml.sfu.len(), 2);
+
+        assert!(matches!(
+            NodeData::from_yaml("sfu: [{ address: 42"),
+            Err(ConfigError::Yaml(_))
+        ));
     }
 
     #[test]
-    fn test_parse_invalid_json() {
-        let json_str = r#"{ "sfu": [ { "address": "incomplete" "#;
-        let result = NodeData::from_json(json_str);
-        assert!(result.is_err());
-        if let Err(ConfigError::Json(e)) = result {
-            assert!(e.to_string().contains("EOF"));
-        } else {
-            panic!("Expected JSON error");
-        }
+    fn test_load_detects_format_by_extension() {
+        let dir = tempfile::tempdir().unwrap();
+        let yaml = dir.path().join("secrets.YML");
+        fs::write(
+            &yaml,
+            format!(
+                "sfu:\n  - address: http://sfu1.example.com:3000\n    key: \"{VALID_KEY_1}\"\n"
+            ),
+        )
+        .unwrap();
+        let toml = dir.path().join("secrets.toml");
+        fs::write(
+            &toml,
+            format!(
+                "[[sfu]]\naddress = \"http://sfu1.example.com:3000\"\nkey = \"{VALID_KEY_1}\"\n"
+            ),
+        )
+        .unwrap();
+
+        assert_eq!(
+            NodeData::load(&yaml).unwrap().sfu,
+            NodeData::load(&toml).unwrap().sfu
+        );
+        // YAML content read as TOML
+        let misnamed = dir.path().join("secrets.conf");
+        fs::copy(&yaml, &misnamed).unwrap();
+        assert!(matches!(
+            NodeData::load(&misnamed),
+            Err(ConfigError::Toml(_))
+        ));
     }
 
     #[test]
-    fn test_invalid_key_not_base64() {
-        let json_str = r#"{
-            "sfu": [{ "address": "http://sfu.example.com", "key": "not-valid-base64!!!" }]
-        }"#;
-        let result = NodeData::from_json(json_str);
-        assert!(matches!(result, Err(ConfigError::Key { .. })));
+    fn test_parse_region_list() {
+        let config_str = format!(
+            r#"
+            [[sfu]]
+            address = "http://sfu1.example.com:3000"
+            region = ["eu-west", "eu-central"]
+            key = "{VALID_KEY_1}"
+        "#
+        );
+
+        let secrets = NodeData::load_from_toml(&config_str).unwrap();
+        assert_eq!(
+            secrets.sfu[0].regions,
+            vec!["eu-west".to_string(), "eu-central".to_string()]
+        );
     }
 
     #[test]
-    fn test_short_key_accepted_with_warning() {
-        // "short-key" is only 9 bytes - should succeed but would log a warning
-        let short_key = "c2hvcnQta2V5";
-        let json_str = format!(
-            r#"{{ "sfu": [{{ "address": "http://sfu.example.com", "key": "{short_key}" }}] }}"#
+    fn test_address_validation() {
+        let parse = |address: &str| {
+            NodeData::from_json(&format!(
+                r#"{{"sfu": [{{"address": "{address}", "key": "{VALID_KEY_1}"}}]}}"#
+            ))
+        };
+
+        let missing_scheme = parse("sfu1.example.com:3000");
+        assert!(matches!(
+            missing_scheme,
+            Err(ConfigError::Address { index: 0, .. })
+        ));
+        assert!(matches!(
+            parse("ftp://sfu1.example.com"),
+            Err(ConfigError::Address { .. })
+        ));
+        assert!(matches!(
+            parse("http://sfu1.example.com/?a=b"),
+            Err(ConfigError::Address { .. })
+        ));
+
+        let trailing_slash = parse("http://sfu1.example.com:3000/").unwrap();
+        assert_eq!(
+            trailing_slash.sfu[0].address,
+            "http://sfu1.example.com:3000"
         );
-        let result = NodeData::from_json(&json_str);
-        assert!(result.is_ok());
-        assert_eq!(result.unwrap().sfu[0].key, b"short-key");
+        let with_path = parse("http://sfu1.example.com/sfu/").unwrap();
+        assert_eq!(with_path.sfu[0].address, "http://sfu1.example.com/sfu");
+
+        let https = parse("https://sfu1.example.com").unwrap();
+        assert_eq!(https.sfu[0].address, "https://sfu1.example.com");
+    }
+
+    #[test]
+    fn test_default_scheme() {
+        let parse = |address: &str| {
+            NodeData::from_json_with_default_scheme(
+                &format!(r#"{{"sfu": [{{"address": "{address}", "key": "{VALID_KEY_1}"}}]}}"#),
+                Some("https"),
+            )
+        };
+
+        let upgraded = parse("sfu1.internal:3000").unwrap();
+        assert_eq!(upgraded.sfu[0].address, "https://sfu1.internal:3000");
+        let upgraded = parse("10.0.0.1:3000/sfu/").unwrap();
+        assert_eq!(upgraded.sfu[0].address, "https://10.0.0.1:3000/sfu");
+
+        let explicit = parse("http://sfu1.internal:3000").unwrap();
+        assert_eq!(explicit.sfu[0].address, "http://sfu1.internal:3000");
+        assert!(matches!(
+            parse("ftp://sfu1.internal"),
+            Err(ConfigError::Address { .. })
+        ));
     }
 
     #[test]
     #[serial_test::serial]
-    fn test_gateway_config_from_env() {
+    fn test_gateway_config_default_scheme() {
         // SAFETY: test runs serially
         #[allow(unsafe_code)]
         unsafe {
             std::env::set_var("SFU_GATEWAY_KEY", VALID_KEY_1);
-            std::env::set_var("SFU_GATEWAY_NODES", "{\"sfu\":[]}");
+            std::env::set_var("SFU_GATEWAY_DEFAULT_SCHEME", "HTTPS");
         }
+        let config = GatewayConfig::from_env();
 
-        let config = GatewayConfig::from_env().unwrap();
-        assert_eq!(config.key, VALID_KEY_1_BYTES);
-        assert_eq!(config.nodes, Some("{\"sfu\":[]}".to_string()));
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::set_var("SFU_GATEWAY_DEFAULT_SCHEME", "ws");
+        }
+        let unsupported = GatewayConfig::from_env();
+
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::remove_var("SFU_GATEWAY_DEFAULT_SCHEME");
+        }
+        assert_eq!(config.unwrap().default_scheme.as_deref(), Some("https"));
+        assert!(
+            matches!(unsupported, Err(ConfigError::Env { var, .. }) if var == "SFU_GATEWAY_DEFAULT_SCHEME")
+        );
+    }
+
+    #[test]
+    fn test_parse_weight() {
+        let config_str = format!(
+            r#"
+            [[sfu]]
+            address = "http://sfu1.example.com:3000"
+            key = "{VALID_KEY_1}"
+            weight = 3
+
+            [[sfu]]
+            address = "http://sfu2.example.com:3000"
+            key = "{VALID_KEY_2}"
+        "#
+        );
+
+        let secrets = NodeData::load_from_toml(&config_str).unwrap();
+        assert_eq!(secrets.sfu[0].weight, 3);
+        assert_eq!(secrets.sfu[1].weight, 1);
+
+        let json = format!(
+            r#"{{"sfu": [{{"address": "http://sfu1:3000", "key": "{VALID_KEY_1}", "weight": 0}}]}}"#
+        );
+        let result = NodeData::from_json(&json);
+        assert!(matches!(result, Err(ConfigError::Sfu { index: 0, .. })));
+    }
+
+    #[test]
+    fn test_duplicate_addresses_rejected() {
+        for (first, second) in [
+            (
+                "http://sfu1.example.com:3000",
+                "http://sfu1.example.com:3000",
+            ),
+            (
+                "http://sfu1.example.com:3000",
+                "http://sfu1.example.com:3000/",
+            ),
+            ("https://SFU1.example.com", "https://sfu1.example.com:443/"),
+        ] {
+            let config_str = format!(
+                r#"
+                [[sfu]]
+                address = "{first}"
+                key = "{VALID_KEY_1}"
+
+                [[sfu]]
+                address = "{second}"
+                key = "{VALID_KEY_2}"
+            "#
+            );
+            let result = NodeData::load_from_toml(&config_str);
+            assert!(
+                matches!(&result, Err(ConfigError::Sfu { index: 1, message, .. }) if message.contains("SFU[0]")),
+                "{second}: {result:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_max_channels() {
+        let config_str = format!(
+            r#"
+            [[sfu]]
+            address = "http://sfu1.example.com:3000"
+            key = "{VALID_KEY_1}"
+            max_channels = 500
+
+            [[sfu]]
+            address = "http://sfu2.example.com:3000"
+            key = "{VALID_KEY_2}"
+        "#
+        );
+
+        let secrets = NodeData::load_from_toml(&config_str).unwrap();
+        assert_eq!(secrets.sfu[0].max_channels, Some(500));
+        assert_eq!(secrets.sfu[1].max_channels, None);
+        assert!(secrets.sfu[1].accepts_recording_key);
+
+        let json = format!(
+            r#"{{"sfu": [{{"address": "http://sfu1:3000", "key": "{VALID_KEY_1}", "max_channels": 0}}]}}"#
+        );
+        let result = NodeData::from_json(&json);
+        assert!(matches!(result, Err(ConfigError::Sfu { index: 0, .. })));
+    }
+
+    #[test]
+    fn test_parse_signing_algorithm_and_kid() {
+        let config_str = format!(
+            r#"
+            [[sfu]]
+            address = "http://sfu1.example.com:3000"
+            key = "{VALID_KEY_1}"
+            algorithm = "HS384"
+            kid = "sfu1-2024"
+
+            [[sfu]]
+            address = "http://sfu2.example.com:3000"
+            key = "{VALID_KEY_2}"
+        "#
+        );
+
+        let secrets = NodeData::load_from_toml(&config_str).unwrap();
+        assert_eq!(secrets.sfu[0].algorithm, Algorithm::HS384);
+        assert_eq!(secrets.sfu[0].kid.as_deref(), Some("sfu1-2024"));
+        assert_eq!(secrets.sfu[1].algorithm, Algorithm::HS256);
+        assert_eq!(secrets.sfu[1].kid, None);
+
+        for field in [
+            r#""algorithm": "RS256""#,
+            r#""algorithm": "hs256""#,
+            r#""kid": """#,
+        ] {
+            let json = format!(
+                r#"{{"sfu": [{{"address": "http://sfu1:3000", "key": "{VALID_KEY_1}", {field}}}]}}"#
+            );
+            let result = NodeData::from_json(&json);
+            assert!(
+                matches!(result, Err(ConfigError::Sfu { index: 0, .. })),
+                "{field}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_accepts_recording_key() {
+        let config_str = format!(
+            r#"
+            [[sfu]]
+            address = "http://sfu1.example.com:3000"
+            key = "{VALID_KEY_1}"
+            accepts_recording_key = false
+
+            [[sfu]]
+            address = "http://sfu2.example.com:3000"
+            key = "{VALID_KEY_2}"
+        "#
+        );
+
+        let secrets = NodeData::load_from_toml(&config_str).unwrap();
+        assert!(!secrets.sfu[0].accepts_recording_key);
+        assert!(secrets.sfu[1].accepts_recording_key);
+    }
+
+    #[test]
+    fn test_parse_path_prefix() {
+        let config_str = format!(
+            r#"
+            [[sfu]]
+            address = "http://sfu1.example.com:3000"
+            key = "{VALID_KEY_1}"
+            path_prefix = "/sfu"
+
+            [[sfu]]
+            address = "http://sfu2.example.com:3000"
+            key = "{VALID_KEY_2}"
+        "#
+        );
+
+        let secrets = NodeData::load_from_toml(&config_str).unwrap();
+        assert_eq!(secrets.sfu[0].path_prefix, "/sfu");
+        assert_eq!(secrets.sfu[1].path_prefix, "");
+
+        for prefix in ["sfu", "/sfu/", "/", "/sfu?x=1"] {
+            let json = format!(
+                r#"{{"sfu": [{{"address": "http://sfu1:3000", "key": "{VALID_KEY_1}", "path_prefix": "{prefix}"}}]}}"#
+            );
+            let result = NodeData::from_json(&json);
+            assert!(
+                matches!(result, Err(ConfigError::Sfu { index: 0, .. })),
+                "{prefix}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_geo_section() {
+        let config_str = format!(
+            r#"
+            [[sfu]]
+            address = "http://sfu1.example.com:3000"
+            key = "{VALID_KEY_1}"
+
+            [geo.regions]
+            ap-east-2 = {{ lat = 22.3, lon = 114.2 }}
+
+            [geo.countries]
+            hk = "ap-east-2"
+        "#
+        );
+
+        let secrets = NodeData::load_from_toml(&config_str).unwrap();
+        assert_eq!(
+            secrets.geo,
+            GeoConfig {
+                regions: vec![("ap-east-2".to_string(), 22.3, 114.2)],
+                countries: vec![("HK".to_string(), "ap-east-2".to_string())],
+            }
+        );
+
+        let without =
+            format!(r#"{{"sfu": [{{"address": "http://sfu1:3000", "key": "{VALID_KEY_1}"}}]}}"#);
+        assert_eq!(
+            NodeData::from_json(&without).unwrap().geo,
+            GeoConfig::default()
+        );
+
+        for geo in [
+            r#"{"regions": {"north": {"lat": 91.0, "lon": 0.0}}}"#,
+            r#"{"countries": {"HKG": "ap-east"}}"#,
+        ] {
+            let json = format!(
+                r#"{{"sfu": [{{"address": "http://sfu1:3000", "key": "{VALID_KEY_1}"}}], "geo": {geo}}}"#
+            );
+            let result = NodeData::from_json(&json);
+            assert!(matches!(result, Err(ConfigError::Geo { .. })), "{geo}");
+        }
+    }
+
+    #[test]
+    fn test_parse_health_check_mode() {
+        let config_str = format!(
+            r#"
+            [[sfu]]
+            address = "http://sfu1.example.com:3000"
+            key = "{VALID_KEY_1}"
+            health_check = "tcp"
+
+            [[sfu]]
+            address = "http://sfu2.example.com:3000"
+            key = "{VALID_KEY_2}"
+        "#
+        );
+
+        let secrets = NodeData::load_from_toml(&config_str).unwrap();
+        assert_eq!(secrets.sfu[0].health_check, Some(HealthCheckMode::Tcp));
+        assert_eq!(secrets.sfu[1].health_check, None);
+
+        assert_eq!("TCP".parse::<HealthCheckMode>(), Ok(HealthCheckMode::Tcp));
+        assert_eq!("http".parse::<HealthCheckMode>(), Ok(HealthCheckMode::Http));
+        assert!("icmp".parse::<HealthCheckMode>().is_err());
+    }
+
+    #[test]
+    fn test_parse_selection_strategy() {
+        assert_eq!(
+            "Round-Robin".parse::<SelectionStrategy>(),
+            Ok(SelectionStrategy::RoundRobin)
+        );
+        assert_eq!(
+            "sticky".parse::<SelectionStrategy>(),
+            Ok(SelectionStrategy::Sticky)
+        );
+        assert_eq!(
+            "least-connections".parse::<SelectionStrategy>(),
+            Ok(SelectionStrategy::LeastConnections)
+        );
+        assert_eq!(
+            "lowest-latency".parse::<SelectionStrategy>(),
+            Ok(SelectionStrategy::LowestLatency)
+        );
+        assert_eq!(
+            "weighted-random".parse::<SelectionStrategy>(),
+            Ok(SelectionStrategy::WeightedRandom)
+        );
+        assert!("random".parse::<SelectionStrategy>().is_err());
     }
 
     #[test]
     #[serial_test::serial]
-    fn test_gateway_config_invalid_key() {
+    fn test_parse_static_headers() {
         // SAFETY: test runs serially
         #[allow(unsafe_code)]
         unsafe {
-            std::env::set_var("SFU_GATEWAY_KEY", "invalid-key");
+            std::env::set_var("TEST_SFU_INTERNAL_TOKEN", "secret-token");
+        }
+        let config_str = format!(
+            r#"
+            [headers]
+            X-Internal-Token = "${{TEST_SFU_INTERNAL_TOKEN}}"
+
+            [[sfu]]
+            address = "http://sfu1.example.com:3000"
+            key = "{VALID_KEY_1}"
+            headers = {{ X-Tenant = "tenant-${{TEST_SFU_INTERNAL_TOKEN}}-1" }}
+        "#
+        );
+
+        let secrets = NodeData::load_from_toml(&config_str).unwrap();
+        assert_eq!(
+            secrets.headers,
+            vec![("X-Internal-Token".to_string(), "secret-token".to_string())]
+        );
+        assert_eq!(
+            secrets.sfu[0].headers,
+            vec![("X-Tenant".to_string(), "tenant-secret-token-1".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_static_headers_rejected() {
+        let missing_var = r#"
+            [headers]
+            X-Internal-Token = "${TEST_SFU_UNSET_VARIABLE}"
+        "#;
+        assert!(matches!(
+            NodeData::load_from_toml(missing_var),
+            Err(ConfigError::Header { .. })
+        ));
+
+        for name in [
+            "Authorization",
+            "X-Request-Id",
+            "traceparent",
+            "Content-Type",
+        ] {
+            let reserved = format!("[headers]\n{name} = \"static\"\n");
+            assert!(
+                matches!(
+                    NodeData::load_from_toml(&reserved),
+                    Err(ConfigError::Header { .. })
+                ),
+                "{name} should be reserved"
+            );
+        }
+    }
+
+    /// Run `f` and return what it logged.
+    fn capture_logs(f: impl FnOnce()) -> String {
+        use std::sync::{Arc, Mutex};
+
+        #[derive(Clone, Default)]
+        struct Buffer(Arc<Mutex<Vec<u8>>>);
+        impl std::io::Write for Buffer {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .finish();
+        tracing::subscriber::with_default(subscriber, f);
+        let logs = buffer.0.lock().unwrap().clone();
+        String::from_utf8(logs).unwrap()
+    }
+
+    #[test]
+    fn test_key_length_mismatch_in_region_warns() {
+        // "short-key" is only 9 bytes
+        let config_str = format!(
+            r#"
+            [[sfu]]
+            address = "http://sfu1.example.com:3000"
+            region = "eu-west"
+            key = "{VALID_KEY_1}"
+
+            [[sfu]]
+            address = "http://sfu2.example.com:3000"
+            region = "eu-west"
+            key = "c2hvcnQta2V5"
+
+            [[sfu]]
+            address = "http://sfu3.example.com:3000"
+            region = "us-east"
+            key = "{VALID_KEY_2}"
+        "#
+        );
+
+        let mut result = None;
+        let logs = capture_logs(|| result = Some(NodeData::load_from_toml(&config_str)));
+
+        assert_eq!(result.unwrap().unwrap().sfu.len(), 3);
+        assert!(logs.contains("keys of different lengths"), "logs: {logs}");
+        assert!(logs.contains("eu-west"));
+        assert!(!logs.contains("us-east"));
+    }
+
+    #[test]
+    fn test_unknown_region_warns() {
+        let config_str = format!(
+            r#"
+            [geo.regions]
+            ap-east-2 = {{ lat = 22.3, lon = 114.2 }}
+
+            [[sfu]]
+            address = "http://sfu1.example.com:3000"
+            region = ["eu-west", "eu-wset"]
+            key = "{VALID_KEY_1}"
+
+            [[sfu]]
+            address = "http://sfu2.example.com:3000"
+            region = "ap-east-2"
+            key = "{VALID_KEY_2}"
+        "#
+        );
+
+        let mut result = None;
+        let logs = capture_logs(|| result = Some(NodeData::load_from_toml(&config_str)));
+
+        assert_eq!(result.unwrap().unwrap().sfu.len(), 2);
+        assert!(logs.contains("Unknown region"), "logs: {logs}");
+        assert!(logs.contains("eu-wset"));
+        assert!(!logs.contains("region=eu-west"));
+        assert!(!logs.contains("ap-east-2"));
+    }
+
+    #[test]
+    fn test_consistent_key_lengths_stay_quiet() {
+        let config_str = format!(
+            r#"
+            [[sfu]]
+            address = "http://sfu1.example.com:3000"
+            region = "eu-west"
+            key = "{VALID_KEY_1}"
+
+            [[sfu]]
+            address = "http://sfu2.example.com:3000"
+            region = "eu-west"
+            key = "{VALID_KEY_2}"
+        "#
+        );
+
+        let logs = capture_logs(|| {
+            NodeData::load_from_toml(&config_str).unwrap();
+        });
+        assert!(!logs.contains("keys of different lengths"), "logs: {logs}");
+    }
+
+    #[test]
+    fn test_empty_secrets_file() {
+        let config_str = "";
+        let secrets = NodeData::load_from_toml(config_str).unwrap();
+        assert!(secrets.sfu.is_empty());
+    }
+
+    #[test]
+    fn test_parse_json_nodes() {
+        let json_str = format!(
+            r#"{{
+            "sfu": [
+                {{
+                    "address": "http://sfu1.example.com:3000",
+                    "region": "eu-west",
+                    "key": "{VALID_KEY_1}"
+                }}
+            ]
+        }}"#
+        );
+
+        let secrets = NodeData::from_json(&json_str).unwrap();
+        assert_eq!(secrets.sfu.len(), 1);
+        assert_eq!(secrets.sfu[0].address, "http://sfu1.example.com:3000");
+        assert_eq!(secrets.sfu[0].key, VALID_KEY_1_BYTES);
+    }
+
+    #[test]
+    fn test_parse_invalid_json() {
+        let json_str = r#"{ "sfu": [ { "address": "incomplete" "#;
+        let result = NodeData::from_json(json_str);
+        assert!(result.is_err());
+        if let Err(ConfigError::Json(e)) = result {
+            assert!(e.to_string().contains("EOF"));
+        } else {
+            panic!("Expected JSON error");
+        }
+    }
+
+    #[test]
+    fn test_key_shared_with_gateway_warns() {
+        let config_str = format!(
+            r#"
+            [[sfu]]
+            address = "http://sfu1.example.com:3000"
+            key = "{VALID_KEY_1}"
+
+            [[sfu]]
+            address = "http://sfu2.example.com:3000"
+            keys = ["{VALID_KEY_2}", "{VALID_KEY_3}"]
+        "#
+        );
+        let nodes = NodeData::load_from_toml(&config_str).unwrap();
+        let key = |key: &str| decode_key(key).unwrap();
+
+        let mut distinct = None;
+        let logs = capture_logs(|| distinct = Some(nodes.check_key_isolation(&[key(VALID_KEY_4)])));
+        assert!(distinct.unwrap().is_ok());
+        assert!(!logs.contains("gateway key"), "logs: {logs}");
+
+        let mut shared = None;
+        let logs = capture_logs(|| {
+            shared = Some(nodes.check_key_isolation(&[key(VALID_KEY_4), key(VALID_KEY_3)]));
+        });
+        assert!(
+            matches!(shared.unwrap(), Err(ConfigError::Key { index: 1, address, .. }) if address == "http://sfu2.example.com:3000")
+        );
+        assert!(logs.contains("also a gateway key"), "logs: {logs}");
+        assert!(logs.contains("sfu2.example.com"));
+        assert!(!logs.contains("sfu1.example.com"));
+    }
+
+    #[test]
+    fn test_decode_key_any_alphabet_and_padding() {
+        use base64::engine::general_purpose::{
+            STANDARD, STANDARD_NO_PAD, URL_SAFE, URL_SAFE_NO_PAD,
+        };
+
+        // the alphabets differ on 62 and 63, and 4 bytes need padding
+        let bytes = [0xfb, 0xff, 0xbf, 0x01];
+        for encoded in [
+            URL_SAFE.encode(bytes),
+            URL_SAFE_NO_PAD.encode(bytes),
+            STANDARD.encode(bytes),
+            STANDARD_NO_PAD.encode(bytes),
+        ] {
+            assert_eq!(decode_key(&encoded).unwrap(), bytes, "{encoded}");
+        }
+        assert_eq!(URL_SAFE.encode(bytes), "-_-_AQ==");
+        assert!(decode_key("-_+/AQ==").is_err());
+        assert!(decode_key("not-valid-base64!!!").is_err());
+    }
+
+    #[test]
+    fn test_invalid_key_not_base64() {
+        let json_str = r#"{
+            "sfu": [{ "address": "http://sfu.example.com", "key": "not-valid-base64!!!" }]
+        }"#;
+        let result = NodeData::from_json(json_str);
+        assert!(matches!(result, Err(ConfigError::Key { .. })));
+    }
+
+    #[test]
+    fn test_parse_keys() {
+        let config_str = format!(
+            r#"
+            [[sfu]]
+            address = "http://sfu1.example.com:3000"
+            key = "{VALID_KEY_1}"
+
+            [[sfu]]
+            address = "http://sfu2.example.com:3000"
+            keys = ["{VALID_KEY_2}", "{VALID_KEY_1}"]
+        "#
+        );
+        let config = NodeData::load_from_toml(&config_str).unwrap();
+        assert_eq!(config.sfu[0].key, decode_key(VALID_KEY_1).unwrap());
+        assert!(config.sfu[0].other_keys.is_empty());
+        assert_eq!(config.sfu[1].key, decode_key(VALID_KEY_2).unwrap());
+        assert_eq!(config.sfu[1].other_keys, [decode_key(VALID_KEY_1).unwrap()]);
+
+        let sfu = |keys: &str| {
+            NodeData::from_json(&format!(
+                r#"{{ "sfu": [{{ "address": "http://sfu.example.com", {keys} }}] }}"#
+            ))
+        };
+        let key_error = |result: Result<NodeData, ConfigError>| match result {
+            Err(ConfigError::Key { message, .. }) => message,
+            other => panic!("expected a key error, got {other:?}"),
+        };
+        assert!(key_error(sfu(r#""region": "eu-west""#)).contains("required"));
+        assert!(key_error(sfu(r#""keys": []"#)).contains("required"));
+        assert!(
+            key_error(sfu(&format!(
+                r#""key": "{VALID_KEY_1}", "keys": ["{VALID_KEY_2}"]"#
+            )))
+            .contains("not both")
+        );
+        assert!(
+            key_error(sfu(&format!(
+                r#""keys": ["{VALID_KEY_1}", "not-valid-base64!!!"]"#
+            )))
+            .starts_with("keys[1]: invalid base64")
+        );
+    }
+
+    #[test]
+    fn test_short_key_accepted_with_warning() {
+        // "short-key" is only 9 bytes - should succeed but would log a warning
+        let short_key = "c2hvcnQta2V5";
+        let json_str = format!(
+            r#"{{ "sfu": [{{ "address": "http://sfu.example.com", "key": "{short_key}" }}] }}"#
+        );
+        let result = NodeData::from_json(&json_str);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap().sfu[0].key, b"short-key");
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_gateway_config_from_env() {
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::set_var("SFU_GATEWAY_KEY", VALID_KEY_1);
+            std::env::set_var("SFU_GATEWAY_NODES", "{\"sfu\":[]}");
+        }
+
+        let config = GatewayConfig::from_env().unwrap();
+        assert_eq!(config.keys, [VALID_KEY_1_BYTES]);
+        assert_eq!(config.nodes, Some("{\"sfu\":[]}".to_string()));
+        assert_eq!(config.sfu_timeout, Duration::from_secs(5));
+        assert_eq!(config.connect_timeout, Duration::from_secs(2));
+        assert_eq!(config.shutdown_grace, Duration::from_secs(30));
+        assert_eq!(config.channel_lifetime, DEFAULT_CHANNEL_LIFETIME);
+        assert_eq!(config.forward_headers, DEFAULT_FORWARD_HEADERS);
+        assert_eq!(config.rate_limit, None);
+        assert_eq!(config.sweep_interval, Some(Duration::from_mins(1)));
+        assert_eq!(config.idle_ttl, Duration::from_mins(10));
+        assert_eq!(config.sfu_url_schemes, DEFAULT_SFU_URL_SCHEMES);
+        assert!(config.auth_schemes.is_empty());
+        assert_eq!(config.max_query_bytes, DEFAULT_MAX_QUERY_BYTES);
+        assert_eq!(config.max_body_bytes, DEFAULT_MAX_BODY_BYTES);
+        assert_eq!(config.status_probe_ttl, Duration::from_secs(1));
+        assert_eq!(config.default_scheme, None);
+        assert_eq!(config.max_fallback_km, None);
+        assert_eq!(config.health_check_max_interval, Duration::from_mins(5));
+        assert_eq!(config.pool_max_idle, 32);
+        assert_eq!(config.pool_idle_timeout, Duration::from_secs(90));
+        assert_eq!(config.tcp_keepalive, Some(Duration::from_mins(1)));
+    }
+
+    #[test]
+    fn test_load_gateway_keys() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        fs::write(file.path(), format!("{VALID_KEY_2}\n{VALID_KEY_1},\n")).unwrap();
+        assert_eq!(
+            load_gateway_keys(file.path()).unwrap(),
+            [VALID_KEY_2_BYTES, VALID_KEY_1_BYTES]
+        );
+
+        let empty = tempfile::NamedTempFile::new().unwrap();
+        assert!(matches!(
+            load_gateway_keys(empty.path()),
+            Err(ConfigError::Env { .. })
+        ));
+        assert!(matches!(
+            load_gateway_keys(Path::new("/nonexistent/gateway.key")),
+            Err(ConfigError::Io { .. })
+        ));
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_gateway_key_file_excludes_key() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        fs::write(file.path(), VALID_KEY_2).unwrap();
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::remove_var("SFU_GATEWAY_KEY");
+            std::env::set_var("SFU_GATEWAY_KEY_FILE", file.path());
+        }
+        let from_file = GatewayConfig::from_env();
+
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::set_var("SFU_GATEWAY_KEY", VALID_KEY_1);
+        }
+        let both = GatewayConfig::from_env();
+
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::remove_var("SFU_GATEWAY_KEY_FILE");
+        }
+
+        let from_file = from_file.unwrap();
+        assert_eq!(from_file.keys, [VALID_KEY_2_BYTES]);
+        assert_eq!(from_file.key_file.as_deref(), Some(file.path()));
+        assert!(matches!(both, Err(ConfigError::Env { var, .. }) if var == "SFU_GATEWAY_KEY_FILE"));
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_gateway_config_max_fallback_km() {
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::set_var("SFU_GATEWAY_KEY", VALID_KEY_1);
+            std::env::set_var("SFU_GATEWAY_MAX_FALLBACK_KM", "2500");
+        }
+        let custom = GatewayConfig::from_env();
+
+        let mut invalid = Vec::new();
+        for value in ["0", "-10", "inf", "far"] {
+            // SAFETY: test runs serially
+            #[allow(unsafe_code)]
+            unsafe {
+                std::env::set_var("SFU_GATEWAY_MAX_FALLBACK_KM", value);
+            }
+            invalid.push(GatewayConfig::from_env());
+        }
+
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::remove_var("SFU_GATEWAY_MAX_FALLBACK_KM");
+        }
+        assert_eq!(custom.unwrap().max_fallback_km, Some(2500.0));
+        for result in invalid {
+            assert!(matches!(result, Err(ConfigError::Env { .. })));
+        }
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_gateway_config_connection_pool() {
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::set_var("SFU_GATEWAY_KEY", VALID_KEY_1);
+            std::env::set_var("SFU_GATEWAY_POOL_MAX_IDLE", "4");
+            std::env::set_var("SFU_GATEWAY_POOL_IDLE_TIMEOUT_MS", "15000");
+            std::env::set_var("SFU_GATEWAY_TCP_KEEPALIVE_MS", "0");
+        }
+        let custom = GatewayConfig::from_env();
+
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::set_var("SFU_GATEWAY_POOL_MAX_IDLE", "-1");
+        }
+        let invalid = GatewayConfig::from_env();
+
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::remove_var("SFU_GATEWAY_POOL_MAX_IDLE");
+            std::env::remove_var("SFU_GATEWAY_POOL_IDLE_TIMEOUT_MS");
+            std::env::remove_var("SFU_GATEWAY_TCP_KEEPALIVE_MS");
+        }
+        let custom = custom.unwrap();
+        assert_eq!(custom.pool_max_idle, 4);
+        assert_eq!(custom.pool_idle_timeout, Duration::from_secs(15));
+        assert_eq!(custom.tcp_keepalive, None);
+        assert!(matches!(invalid, Err(ConfigError::Env { .. })));
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_gateway_config_sfu_url_schemes() {
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::set_var("SFU_GATEWAY_KEY", VALID_KEY_1);
+            std::env::set_var("SFU_GATEWAY_SFU_URL_SCHEMES", "WSS, wss+unix");
+        }
+        let custom = GatewayConfig::from_env();
+
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::set_var("SFU_GATEWAY_SFU_URL_SCHEMES", "wss,1ws");
+        }
+        let invalid = GatewayConfig::from_env();
+
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::set_var("SFU_GATEWAY_SFU_URL_SCHEMES", " , ");
+        }
+        let empty = GatewayConfig::from_env();
+
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::remove_var("SFU_GATEWAY_SFU_URL_SCHEMES");
+        }
+        assert_eq!(custom.unwrap().sfu_url_schemes, ["wss", "wss+unix"]);
+        assert!(matches!(invalid, Err(ConfigError::Env { .. })));
+        assert!(matches!(empty, Err(ConfigError::Env { .. })));
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_gateway_config_auth_schemes() {
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::set_var("SFU_GATEWAY_KEY", VALID_KEY_1);
+            std::env::set_var("SFU_GATEWAY_AUTH_SCHEME", "Bearer");
+        }
+        let bearer = GatewayConfig::from_env();
+
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::set_var("SFU_GATEWAY_AUTH_SCHEME", "Bearer,Bear er");
+        }
+        let invalid = GatewayConfig::from_env();
+
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::remove_var("SFU_GATEWAY_AUTH_SCHEME");
+        }
+        assert_eq!(bearer.unwrap().auth_schemes, ["bearer"]);
+        assert!(matches!(invalid, Err(ConfigError::Env { .. })));
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_gateway_config_cors_origins() {
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::set_var("SFU_GATEWAY_KEY", VALID_KEY_1);
+            std::env::remove_var("SFU_GATEWAY_CORS_ORIGINS");
+        }
+        let disabled = GatewayConfig::from_env();
+
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::set_var(
+                "SFU_GATEWAY_CORS_ORIGINS",
+                "https://odoo.example.com, http://localhost:8069",
+            );
+        }
+        let custom = GatewayConfig::from_env();
+
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::set_var("SFU_GATEWAY_CORS_ORIGINS", "https://odoo.example.com/web");
+        }
+        let with_path = GatewayConfig::from_env();
+
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::remove_var("SFU_GATEWAY_CORS_ORIGINS");
+        }
+        assert!(disabled.unwrap().cors_origins.is_empty());
+        assert_eq!(
+            custom.unwrap().cors_origins,
+            ["https://odoo.example.com", "http://localhost:8069"]
+        );
+        assert!(matches!(with_path, Err(ConfigError::Env { .. })));
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_gateway_config_forward_headers() {
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::set_var("SFU_GATEWAY_KEY", VALID_KEY_1);
+            std::env::set_var("SFU_GATEWAY_FORWARD_HEADERS", " User-Agent, X-Tenant-*,,");
+        }
+        let custom = GatewayConfig::from_env();
+
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::set_var("SFU_GATEWAY_FORWARD_HEADERS", "");
+        }
+        let none = GatewayConfig::from_env();
+
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::set_var("SFU_GATEWAY_FORWARD_HEADERS", "user agent");
+        }
+        let invalid = GatewayConfig::from_env();
+
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::remove_var("SFU_GATEWAY_FORWARD_HEADERS");
+        }
+        assert_eq!(
+            custom.unwrap().forward_headers,
+            ["user-agent", "x-tenant-*"]
+        );
+        assert!(none.unwrap().forward_headers.is_empty());
+        assert!(matches!(invalid, Err(ConfigError::Env { .. })));
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_gateway_config_seed() {
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::set_var("SFU_GATEWAY_KEY", VALID_KEY_1);
+            std::env::set_var("SFU_GATEWAY_SEED", "42");
+        }
+        let config = GatewayConfig::from_env().unwrap();
+        assert_eq!(config.seed, Some(42));
+
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::set_var("SFU_GATEWAY_SEED", "not-a-number");
+        }
+        let result = GatewayConfig::from_env();
+
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::remove_var("SFU_GATEWAY_SEED");
+        }
+        assert!(matches!(result, Err(ConfigError::Env { .. })));
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_gateway_config_api_key() {
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::set_var("SFU_GATEWAY_KEY", VALID_KEY_1);
+            std::env::set_var("SFU_GATEWAY_API_KEY", "static-api-key");
+            std::env::remove_var("SFU_GATEWAY_API_KEY_ISS");
+        }
+        let missing_iss = GatewayConfig::from_env();
+
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::set_var("SFU_GATEWAY_API_KEY_ISS", "integration");
+        }
+        let config = GatewayConfig::from_env();
+
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::remove_var("SFU_GATEWAY_API_KEY");
+            std::env::remove_var("SFU_GATEWAY_API_KEY_ISS");
+        }
+        assert!(matches!(missing_iss, Err(ConfigError::Env { .. })));
+        let api_key = config.unwrap().api_key.unwrap();
+        assert_eq!(api_key.key, "static-api-key");
+        assert_eq!(api_key.iss, "integration");
+        assert_eq!(api_key.region, None);
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_gateway_config_client_identity() {
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::set_var("SFU_GATEWAY_KEY", VALID_KEY_1);
+        }
+        let defaults = GatewayConfig::from_env().unwrap();
+
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::set_var("SFU_GATEWAY_CLIENT_KEY", "/etc/gateway/client.key");
+        }
+        let key_alone = GatewayConfig::from_env();
+
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::set_var("SFU_GATEWAY_CLIENT_CERT", "/etc/gateway/client.pem");
+        }
+        let config = GatewayConfig::from_env();
+
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::remove_var("SFU_GATEWAY_CLIENT_CERT");
+            std::env::remove_var("SFU_GATEWAY_CLIENT_KEY");
+        }
+        assert_eq!(defaults.client_identity, None);
+        assert!(
+            matches!(key_alone, Err(ConfigError::Env { var, .. }) if var == "SFU_GATEWAY_CLIENT_KEY")
+        );
+        assert_eq!(
+            config.unwrap().client_identity,
+            Some(ClientIdentity {
+                cert: "/etc/gateway/client.pem".to_string(),
+                key: Some("/etc/gateway/client.key".to_string()),
+            })
+        );
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_gateway_config_default_region() {
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::set_var("SFU_GATEWAY_KEY", VALID_KEY_1);
+            std::env::set_var("SFU_GATEWAY_REGION", "us-east");
+        }
+        let unset = GatewayConfig::from_env().unwrap();
+
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::set_var("SFU_GATEWAY_PREFER_LOCAL_REGION", "true");
+        }
+        let local = GatewayConfig::from_env().unwrap();
+
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::set_var("SFU_GATEWAY_DEFAULT_REGION", "eu-west");
+        }
+        let default = GatewayConfig::from_env().unwrap();
+
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::remove_var("SFU_GATEWAY_REGION");
+            std::env::remove_var("SFU_GATEWAY_PREFER_LOCAL_REGION");
+            std::env::remove_var("SFU_GATEWAY_DEFAULT_REGION");
+        }
+        assert_eq!(unset.default_region, None);
+        assert_eq!(unset.hintless_region(), None);
+        assert_eq!(local.hintless_region(), Some("us-east"));
+        assert_eq!(default.default_region.as_deref(), Some("eu-west"));
+        assert_eq!(default.hintless_region(), Some("eu-west"));
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_gateway_config_tls_and_proxy() {
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::set_var("SFU_GATEWAY_KEY", VALID_KEY_1);
+        }
+        let defaults = GatewayConfig::from_env().unwrap();
+
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::set_var("SFU_GATEWAY_MIN_TLS_VERSION", "1.3");
+            std::env::set_var("SFU_GATEWAY_DISABLE_PROXY", "true");
+        }
+        let config = GatewayConfig::from_env();
+
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::set_var("SFU_GATEWAY_MIN_TLS_VERSION", "1.0");
+        }
+        let unsupported = GatewayConfig::from_env();
+
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::remove_var("SFU_GATEWAY_MIN_TLS_VERSION");
+            std::env::remove_var("SFU_GATEWAY_DISABLE_PROXY");
+        }
+        assert_eq!(defaults.min_tls_version, reqwest::tls::Version::TLS_1_2);
+        assert!(!defaults.disable_proxy);
+        let config = config.unwrap();
+        assert_eq!(config.min_tls_version, reqwest::tls::Version::TLS_1_3);
+        assert!(config.disable_proxy);
+        assert!(
+            matches!(unsupported, Err(ConfigError::Env { var, .. }) if var == "SFU_GATEWAY_MIN_TLS_VERSION")
+        );
+    }
+
+    #[test]
+    fn test_client_identity_load() {
+        let fixture = |name: &str| format!("{}/tests/fixtures/{name}", env!("CARGO_MANIFEST_DIR"));
+        let separate = ClientIdentity {
+            cert: fixture("client_identity_cert.pem"),
+            key: Some(fixture("client_identity_key.pem")),
+        };
+        let identity = separate.load().unwrap();
+        assert!(
+            reqwest::Client::builder()
+                .identity(identity)
+                .build()
+                .is_ok()
+        );
+
+        // certificate and key in a single file
+        let mut combined = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(
+            &mut combined,
+            &[
+                fs::read(&separate.cert).unwrap(),
+                fs::read(separate.key.as_ref().unwrap()).unwrap(),
+            ]
+            .concat(),
+        )
+        .unwrap();
+        let single = ClientIdentity {
+            cert: combined.path().to_string_lossy().into_owned(),
+            key: None,
+        };
+        assert!(single.load().is_ok());
+
+        let missing = ClientIdentity {
+            cert: fixture("does_not_exist.pem"),
+            key: None,
+        };
+        assert!(matches!(missing.load(), Err(ConfigError::Io { .. })));
+        // a certificate without its key
+        let no_key = ClientIdentity {
+            key: None,
+            ..separate
+        };
+        assert!(matches!(no_key.load(), Err(ConfigError::Env { .. })));
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_gateway_config_health_thresholds() {
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::set_var("SFU_GATEWAY_KEY", VALID_KEY_1);
+        }
+        let defaults = GatewayConfig::from_env().unwrap();
+        assert_eq!(defaults.health_failure_threshold, 3);
+        assert_eq!(defaults.health_success_threshold, 2);
+
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::set_var("SFU_GATEWAY_HEALTH_FAILURE_THRESHOLD", "5");
+        }
+        let custom = GatewayConfig::from_env();
+
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::set_var("SFU_GATEWAY_HEALTH_FAILURE_THRESHOLD", "0");
+        }
+        let zero = GatewayConfig::from_env();
+
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::remove_var("SFU_GATEWAY_HEALTH_FAILURE_THRESHOLD");
+        }
+        assert_eq!(custom.unwrap().health_failure_threshold, 5);
+        assert!(matches!(zero, Err(ConfigError::Env { .. })));
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_gateway_config_exp_validation() {
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::set_var("SFU_GATEWAY_KEY", VALID_KEY_1);
+        }
+        let defaults = GatewayConfig::from_env().unwrap();
+        assert!(defaults.validate_exp);
+        assert_eq!(defaults.leeway_secs, 60);
+        assert_eq!(defaults.max_iat_skew_secs, None);
+        assert_eq!(defaults.max_token_age_secs, None);
+
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::set_var("SFU_GATEWAY_VALIDATE_EXP", "false");
+            std::env::set_var("SFU_GATEWAY_LEEWAY", "5");
+            std::env::set_var("SFU_GATEWAY_MAX_IAT_SKEW", "30");
+            std::env::set_var("SFU_GATEWAY_MAX_TOKEN_AGE_SECS", "900");
+        }
+        let custom = GatewayConfig::from_env();
+
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::set_var("SFU_GATEWAY_VALIDATE_EXP", "nope");
+        }
+        let invalid = GatewayConfig::from_env();
+
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::remove_var("SFU_GATEWAY_VALIDATE_EXP");
+            std::env::remove_var("SFU_GATEWAY_LEEWAY");
+            std::env::remove_var("SFU_GATEWAY_MAX_IAT_SKEW");
+            std::env::remove_var("SFU_GATEWAY_MAX_TOKEN_AGE_SECS");
+        }
+        let custom = custom.unwrap();
+        assert!(!custom.validate_exp);
+        assert_eq!(custom.leeway_secs, 5);
+        assert_eq!(custom.max_iat_skew_secs, Some(30));
+        assert_eq!(custom.max_token_age_secs, Some(900));
+        assert!(matches!(invalid, Err(ConfigError::Env { .. })));
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_gateway_config_invalid_key() {
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::set_var("SFU_GATEWAY_KEY", "invalid-key");
+        }
+        let result = GatewayConfig::from_env();
+        assert!(matches!(result, Err(ConfigError::Env { .. })));
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_gateway_config_rotated_keys() {
+        // SAFETY: test runs serially
+        #[allow(unsafe_code)]
+        unsafe {
+            std::env::set_var("SFU_GATEWAY_KEY", format!("{VALID_KEY_2}, {VALID_KEY_1},"));
+        }
+        let config = GatewayConfig::from_env().unwrap();
+        assert_eq!(config.keys, [VALID_KEY_2_BYTES, VALID_KEY_1_BYTES]);
+
+        for invalid in [format!("{VALID_KEY_1},invalid-key"), " , ".to_string()] {
+            // SAFETY: test runs serially
+            #[allow(unsafe_code)]
+            unsafe {
+                std::env::set_var("SFU_GATEWAY_KEY", invalid);
+            }
+            assert!(matches!(
+                GatewayConfig::from_env(),
+                Err(ConfigError::Env { .. })
+            ));
         }
-        let result = GatewayConfig::from_env();
-        assert!(matches!(result, Err(ConfigError::Env { .. })));
     }
 }
@@ -0,0 +1,109 @@
+//! Secrets file watching, so that SFUs can be added or rotated without restart
+//!
+//! The parent directory is watched rather than the file itself: editors and config
+//! management tools usually replace the file (write elsewhere, rename over), which a watch
+//! on the file would not survive.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use notify::event::ModifyKind;
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+use super::NodeData;
+
+/// Quiet period after a change before the file is read, a write often comes in several events
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Watch on the secrets file, stopped when dropped.
+pub struct NodesWatcher {
+    _watcher: RecommendedWatcher,
+    task: JoinHandle<()>,
+}
+
+impl Drop for NodesWatcher {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Call `on_reload` with the new node data each time the file at `path` changes.
+///
+/// The file is loaded with `NodeData::load_with_default_scheme` and `default_scheme`.
+/// A file that fails to load or has no SFUs is logged and ignored, `on_reload` only ever
+/// sees complete, valid node data. Must be called within a tokio runtime, `on_reload`
+/// runs on it.
+///
+/// # Errors
+/// Returns the watcher error if the file's directory cannot be watched.
+pub fn watch_nodes<F>(
+    path: impl AsRef<Path>,
+    default_scheme: Option<String>,
+    on_reload: F,
+) -> notify::Result<NodesWatcher>
+where
+    F: Fn(NodeData) + Send + 'static,
+{
+    let path = path.as_ref().to_path_buf();
+    let file_name = path.file_name().map(ToOwned::to_owned);
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    let mut watcher =
+        notify::recommended_watcher(move |event: notify::Result<Event>| match event {
+            Ok(event)
+                if is_content_change(event.kind)
+                    && event
+                        .paths
+                        .iter()
+                        .any(|changed| changed.file_name() == file_name.as_deref()) =>
+            {
+                let _ = sender.send(());
+            }
+            Ok(_) => {}
+            Err(e) => warn!("Secrets file watch error: {e}"),
+        })?;
+    watcher.watch(&directory, RecursiveMode::NonRecursive)?;
+    info!(path = %path.display(), "Watching secrets file for changes");
+
+    let task = tokio::spawn(async move {
+        while receiver.recv().await.is_some() {
+            loop {
+                match tokio::time::timeout(DEBOUNCE, receiver.recv()).await {
+                    Ok(Some(())) => {}
+                    Ok(None) => return,
+                    Err(_) => break,
+                }
+            }
+            match NodeData::load_with_default_scheme(&path, default_scheme.as_deref()) {
+                Ok(nodes) if nodes.sfu.is_empty() => {
+                    warn!("Secrets file has no SFUs, keeping the current ones");
+                }
+                Ok(nodes) => on_reload(nodes),
+                Err(e) => error!("Failed to reload secrets file, keeping the current SFUs: {e}"),
+            }
+        }
+    });
+
+    Ok(NodesWatcher {
+        _watcher: watcher,
+        task,
+    })
+}
+
+/// Events that may change the content of the file, reading it must not trigger a reload.
+const fn is_content_change(kind: EventKind) -> bool {
+    matches!(
+        kind,
+        EventKind::Create(_)
+            | EventKind::Modify(
+                ModifyKind::Data(_) | ModifyKind::Name(_) | ModifyKind::Any | ModifyKind::Other
+            )
+    )
+}
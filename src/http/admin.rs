@@ -0,0 +1,120 @@
+//! Maintenance endpoints, only served when an admin key is configured
+//!
+//! The admin key is presented in `X-Admin-Key`. It is distinct from the credentials of Odoo so
+//! that a leaked client token or API key can't reroute traffic.
+
+use std::sync::Arc;
+
+use actix_web::{HttpRequest, HttpResponse, web};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use super::auth::constant_time_eq;
+use super::error::ChannelError;
+use super::server::AppState;
+
+/// Body of `/admin/drain` and `/admin/undrain`
+#[derive(Debug, Deserialize)]
+pub struct DrainRequest {
+    /// Address of the SFU, as configured
+    pub address: String,
+}
+
+/// Drain state of an SFU after an admin request
+#[derive(Debug, Serialize)]
+pub struct DrainResponse {
+    pub address: String,
+    pub draining: bool,
+}
+
+/// SFU set after `/admin/reload`
+#[derive(Debug, Serialize)]
+pub struct ReloadResponse {
+    pub sfu_count: usize,
+}
+
+/// Stop giving new channels to an SFU, which stays configured and reported by `/v1/status`.
+///
+/// The drain lasts until `/admin/undrain`, reloads of the SFU set included. 404 when no admin
+/// key is configured.
+///
+/// # Errors
+/// Returns `ChannelError` when the admin key is missing or wrong, or the SFU is unknown.
+#[allow(clippy::unused_async)] // async required by actix
+pub async fn drain(
+    req: HttpRequest,
+    body: web::Json<DrainRequest>,
+    state: web::Data<Arc<AppState>>,
+) -> Result<HttpResponse, ChannelError> {
+    set_draining(&req, &state, &body.address, true)
+}
+
+/// Give new channels to an SFU drained with `/admin/drain` again.
+///
+/// # Errors
+/// Same as `drain`.
+#[allow(clippy::unused_async)] // async required by actix
+pub async fn undrain(
+    req: HttpRequest,
+    body: web::Json<DrainRequest>,
+    state: web::Data<Arc<AppState>>,
+) -> Result<HttpResponse, ChannelError> {
+    set_draining(&req, &state, &body.address, false)
+}
+
+/// Re-read the secrets file and replace the SFU set, as SIGHUP does.
+///
+/// Answers the new SFU count. A file that fails to load or has no SFUs leaves the current
+/// SFUs in place. 404 when no admin key is configured.
+///
+/// # Errors
+/// Returns `ChannelError` when the admin key is missing or wrong, or the reload failed.
+#[allow(clippy::unused_async)] // async required by actix
+pub async fn reload(
+    req: HttpRequest,
+    state: web::Data<Arc<AppState>>,
+) -> Result<HttpResponse, ChannelError> {
+    if !check_admin_key(&req, &state)? {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    info!("Reload requested on /admin/reload");
+    let sfu_count = state.reload_secrets().map_err(|message| {
+        warn!("Reload failed, keeping the current SFUs: {message}");
+        ChannelError::ReloadFailed { message }
+    })?;
+    Ok(HttpResponse::Ok().json(ReloadResponse { sfu_count }))
+}
+
+/// Whether the admin endpoints are enabled, when they are the request must carry the admin key.
+fn check_admin_key(req: &HttpRequest, state: &AppState) -> Result<bool, ChannelError> {
+    let Some(admin_key) = &state.admin_key else {
+        return Ok(false);
+    };
+    let presented = req
+        .headers()
+        .get("X-Admin-Key")
+        .ok_or(ChannelError::MissingAuth)?;
+    if !constant_time_eq(presented.as_bytes(), admin_key.as_bytes()) {
+        warn!("Invalid admin key");
+        return Err(ChannelError::InvalidAdminKey);
+    }
+    Ok(true)
+}
+
+fn set_draining(
+    req: &HttpRequest,
+    state: &AppState,
+    address: &str,
+    draining: bool,
+) -> Result<HttpResponse, ChannelError> {
+    if !check_admin_key(req, state)? {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    if !state.balancer().set_draining(address, draining) {
+        return Err(ChannelError::UnknownSfu);
+    }
+    Ok(HttpResponse::Ok().json(DrainResponse {
+        address: address.to_string(),
+        draining,
+    }))
+}
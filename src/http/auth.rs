@@ -4,8 +4,12 @@
 //! - Verifying JWTs from Odoo (signed with gateway's key)
 //! - Re-signing JWTs for SFUs (signed with each SFU's key)
 
-use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation, decode, encode};
+use jsonwebtoken::errors::ErrorKind;
+use jsonwebtoken::{
+    Algorithm, DecodingKey, EncodingKey, Header, Validation, decode, decode_header, encode,
+};
 use serde::{Deserialize, Serialize};
+use subtle::ConstantTimeEq;
 
 /// JWT claims structure matching the SFU's expected format
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -21,6 +25,9 @@ pub struct Claims {
     /// Issued at time (Unix timestamp)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub iat: Option<u64>,
+    /// Not before (Unix timestamp)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nbf: Option<u64>,
 }
 
 #[derive(Debug)]
@@ -42,45 +49,254 @@ impl std::fmt::Display for AuthError {
 
 impl std::error::Error for AuthError {}
 
+/// How the time claims of incoming tokens are checked.
+///
+/// The default rejects tokens without `exp` or expired for more than 60 seconds, `iat` and
+/// `nbf` are not checked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VerifyOptions {
+    /// Reject tokens whose `exp` is in the past (beyond the leeway)
+    pub validate_exp: bool,
+    /// Tolerated clock skew between Odoo and the gateway, in seconds
+    pub leeway_secs: u64,
+    /// Reject tokens without an `exp` claim
+    pub require_exp: bool,
+    /// Reject tokens whose `nbf` is in the future (beyond the leeway), when they have one
+    pub validate_nbf: bool,
+    /// Reject tokens whose `iat` is more than that many seconds in the future, unchecked when
+    /// `None`
+    pub max_iat_skew_secs: Option<u64>,
+    /// Reject tokens issued more than that many seconds ago, whatever their `exp`, and tokens
+    /// without `iat`. Unchecked when `None`
+    pub max_age_secs: Option<u64>,
+}
+
+impl VerifyOptions {
+    pub const DEFAULT: Self = Self {
+        validate_exp: true,
+        leeway_secs: 60,
+        require_exp: true,
+        validate_nbf: false,
+        max_iat_skew_secs: None,
+        max_age_secs: None,
+    };
+
+    /// Signature and structure checks only: the time claims are checked by `check_times`,
+    /// against a clock the caller controls.
+    fn validation(algorithm: Algorithm) -> Validation {
+        let mut validation = Validation::new(algorithm);
+        validation.validate_exp = false;
+        validation.validate_nbf = false;
+        validation.set_required_spec_claims::<&str>(&[]);
+        validation
+    }
+
+    /// Check the time claims against `now`, in seconds since the Unix epoch.
+    ///
+    /// `exp` and `nbf` are checked as `jsonwebtoken` would, and with the same error names: a
+    /// token is still valid `leeway_secs` after its `exp` and `leeway_secs` before its `nbf`.
+    fn check_times(&self, claims: &Claims, now: u64) -> Result<(), AuthError> {
+        let invalid = |message: String| Err(AuthError::InvalidToken(message));
+        match claims.exp {
+            None if self.require_exp => return invalid("Missing required claim: exp".to_string()),
+            Some(exp) if self.validate_exp && exp < now.saturating_sub(self.leeway_secs) => {
+                return invalid(format!("ExpiredSignature (exp {exp}, now {now})"));
+            }
+            _ => {}
+        }
+        if let Some(nbf) = claims.nbf.filter(|_| self.validate_nbf)
+            && nbf > now.saturating_add(self.leeway_secs)
+        {
+            return invalid(format!("ImmatureSignature (nbf {nbf}, now {now})"));
+        }
+        if let (Some(skew), Some(iat)) = (self.max_iat_skew_secs, claims.iat)
+            && iat > now.saturating_add(skew)
+        {
+            return invalid(format!("issued in the future (iat {iat}, now {now})"));
+        }
+        if let Some(max_age) = self.max_age_secs {
+            let Some(iat) = claims.iat else {
+                return invalid("Missing required claim: iat".to_string());
+            };
+            if iat < now.saturating_sub(max_age) {
+                return invalid(format!(
+                    "too old (iat {iat}, now {now}, max age {max_age}s)"
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for VerifyOptions {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Current time in seconds since the Unix epoch, the clock of the `verify*` functions.
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
 /// Verify a JWT using the gateway's secret key (raw bytes).
 ///
 /// # Errors
-/// Returns `AuthError::InvalidToken` if the token is malformed or signature verification fails.
-pub fn verify(token: &str, key_bytes: &[u8]) -> Result<Claims, AuthError> {
+/// Returns `AuthError::InvalidToken` if the token is malformed, signature verification fails,
+/// or its time claims are rejected by `options`.
+pub fn verify(token: &str, key_bytes: &[u8], options: &VerifyOptions) -> Result<Claims, AuthError> {
+    verify_at(token, key_bytes, options, unix_now())
+}
+
+/// Same as `verify`, with the time claims checked against `now` (seconds since the Unix
+/// epoch) rather than the system clock.
+///
+/// # Errors
+/// Same as `verify`.
+pub fn verify_at(
+    token: &str,
+    key_bytes: &[u8],
+    options: &VerifyOptions,
+    now: u64,
+) -> Result<Claims, AuthError> {
+    verify_any_at(token, &[key_bytes], options, now)
+}
+
+/// Verify a JWT with the first of the gateway's keys it is signed with, for key rotation.
+///
+/// A token whose signature matches a key but whose claims are rejected is not tried against
+/// the remaining keys.
+///
+/// # Errors
+/// Returns `AuthError::InvalidToken` if the token is malformed, is signed with none of the keys,
+/// or its time claims are rejected by `options`.
+pub fn verify_any<K: AsRef<[u8]>>(
+    token: &str,
+    keys: &[K],
+    options: &VerifyOptions,
+) -> Result<Claims, AuthError> {
+    verify_any_at(token, keys, options, unix_now())
+}
+
+/// Same as `verify_any`, with the time claims checked against `now` (seconds since the Unix
+/// epoch) rather than the system clock.
+///
+/// # Errors
+/// Same as `verify_any`.
+pub fn verify_any_at<K: AsRef<[u8]>>(
+    token: &str,
+    keys: &[K],
+    options: &VerifyOptions,
+    now: u64,
+) -> Result<Claims, AuthError> {
     use tracing::debug;
 
-    let key = DecodingKey::from_secret(key_bytes);
+    let validation = VerifyOptions::validation(Algorithm::HS256);
+    let mut last_error = AuthError::InvalidToken("no verification key".to_string());
+    for (index, key) in keys.iter().enumerate() {
+        match decode::<Claims>(token, &DecodingKey::from_secret(key.as_ref()), &validation) {
+            Ok(token_data) => {
+                options.check_times(&token_data.claims, now)?;
+                debug!(iss = %token_data.claims.iss, key_index = index, "JWT verified successfully");
+                return Ok(token_data.claims);
+            }
+            Err(e) if *e.kind() == ErrorKind::InvalidSignature => {
+                last_error = AuthError::InvalidToken(e.to_string());
+            }
+            Err(e) => {
+                debug!(error = %e, "JWT decode failed");
+                return Err(AuthError::InvalidToken(e.to_string()));
+            }
+        }
+    }
+    debug!("JWT signed with none of the gateway keys");
+    Err(last_error)
+}
 
-    let token_data = decode::<Claims>(token, &key, &Validation::default()).map_err(|e| {
-        debug!(error = %e, "JWT decode failed");
-        AuthError::InvalidToken(e.to_string())
-    })?;
+/// Verify an RS256 JWT with a public key, such as one from the identity provider's JWKS.
+///
+/// # Errors
+/// Returns `AuthError::InvalidToken` if the token is malformed, signature verification fails,
+/// or its time claims are rejected by `options`.
+pub fn verify_rs256(
+    token: &str,
+    key: &DecodingKey,
+    options: &VerifyOptions,
+) -> Result<Claims, AuthError> {
+    let claims = decode::<Claims>(token, key, &VerifyOptions::validation(Algorithm::RS256))
+        .map(|token_data| token_data.claims)
+        .map_err(|e| AuthError::InvalidToken(e.to_string()))?;
+    options.check_times(&claims, unix_now())?;
+    Ok(claims)
+}
 
-    debug!(iss = %token_data.claims.iss, "JWT verified successfully");
-    Ok(token_data.claims)
+/// The `kid` of an RS256 token, `None` for other algorithms or malformed tokens.
+#[must_use]
+pub fn rs256_kid(token: &str) -> Option<String> {
+    decode_header(token)
+        .ok()
+        .filter(|header| header.alg == Algorithm::RS256)
+        .and_then(|header| header.kid)
 }
 
-/// Sign claims with the SFU's secret key (raw bytes).
+/// Sign claims with the SFU's secret key (raw bytes), HS256 without `kid`.
 ///
 /// # Errors
 /// Returns `AuthError::SigningFailed` if JWT encoding fails.
 pub fn sign(claims: &Claims, key_bytes: &[u8]) -> Result<String, AuthError> {
+    sign_with_header(claims, key_bytes, &Header::default())
+}
+
+/// `sign` with the given header, its algorithm must be an HMAC one.
+///
+/// # Errors
+/// Returns `AuthError::SigningFailed` if JWT encoding fails.
+pub fn sign_with_header(
+    claims: &Claims,
+    key_bytes: &[u8],
+    header: &Header,
+) -> Result<String, AuthError> {
     let key = EncodingKey::from_secret(key_bytes);
-    encode(&Header::default(), claims, &key).map_err(|e| AuthError::SigningFailed(e.to_string()))
+    encode(header, claims, &key).map_err(|e| AuthError::SigningFailed(e.to_string()))
 }
 
 /// Extract token from Authorization header (format: "<scheme> <token>")
 ///
+/// The scheme must be one of `allowed_schemes` (lowercase, compared case-insensitively), any
+/// scheme is accepted when it is empty.
+///
 /// # Errors
-/// Returns `AuthError::MissingToken` if header is None, or `AuthError::InvalidToken` if format is wrong.
-pub fn extract_token(auth_header: Option<&str>) -> Result<&str, AuthError> {
+/// Returns `AuthError::MissingToken` if header is None, or `AuthError::InvalidToken` if format is
+/// wrong or the scheme isn't allowed.
+pub fn extract_token<'a>(
+    auth_header: Option<&'a str>,
+    allowed_schemes: &[String],
+) -> Result<&'a str, AuthError> {
     let header = auth_header.ok_or(AuthError::MissingToken)?;
-    header
-        .split_once(' ')
-        .map(|(_, token)| token)
-        .ok_or(AuthError::InvalidToken(
-            "expected '<scheme> <token>' format".to_string(),
-        ))
+    let (scheme, token) = header.split_once(' ').ok_or(AuthError::InvalidToken(
+        "expected '<scheme> <token>' format".to_string(),
+    ))?;
+    if !allowed_schemes.is_empty()
+        && !allowed_schemes
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(scheme))
+    {
+        return Err(AuthError::InvalidToken(format!(
+            "scheme '{scheme}' not allowed"
+        )));
+    }
+    Ok(token)
+}
+
+/// Compare secret material without leaking where the inputs differ through timing.
+///
+/// Any gateway-level comparison of secrets (API keys, admin tokens, keys) must go through this
+/// instead of `==`. Only the length may leak, which is not considered secret.
+#[must_use]
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.ct_eq(b).into()
 }
 
 #[cfg(test)]
@@ -90,46 +306,316 @@ mod tests {
     const TEST_KEY: &[u8] = b"test-secret-key-1234567890123456";
     const WRONG_KEY: &[u8] = b"wrong-test-key-12345678901234567";
 
+    fn now() -> u64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
     fn make_test_claims() -> Claims {
         Claims {
             iss: "test-channel-123".to_string(),
             key: Some("encryption-key".to_string()),
-            exp: Some(
-                std::time::SystemTime::now()
-                    .duration_since(std::time::UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs()
-                    + 3600,
-            ),
+            exp: Some(now() + 3600),
             iat: None,
+            nbf: None,
         }
     }
 
+    fn token_with_exp(exp: Option<u64>) -> String {
+        sign(
+            &Claims {
+                exp,
+                ..make_test_claims()
+            },
+            TEST_KEY,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_verify_exp() {
+        let options = VerifyOptions {
+            leeway_secs: 60,
+            ..VerifyOptions::default()
+        };
+        assert!(verify(&token_with_exp(Some(now() + 10)), TEST_KEY, &options).is_ok());
+        // expired, but within the leeway
+        assert!(verify(&token_with_exp(Some(now() - 30)), TEST_KEY, &options).is_ok());
+        let expired = verify(&token_with_exp(Some(now() - 120)), TEST_KEY, &options);
+        assert!(matches!(expired, Err(AuthError::InvalidToken(_))));
+        let missing = verify(&token_with_exp(None), TEST_KEY, &options);
+        assert!(matches!(missing, Err(AuthError::InvalidToken(_))));
+    }
+
+    #[test]
+    fn test_verify_at_exp_boundaries() {
+        const NOW: u64 = 1_700_000_000;
+        let options = VerifyOptions {
+            leeway_secs: 60,
+            ..VerifyOptions::default()
+        };
+        let at = |exp| verify_at(&token_with_exp(Some(exp)), TEST_KEY, &options, NOW);
+
+        assert!(at(NOW + 1).is_ok());
+        assert!(at(NOW).is_ok());
+        // valid up to the end of the leeway, included
+        assert!(at(NOW - 60).is_ok());
+        let expired = at(NOW - 61);
+        assert!(matches!(expired, Err(AuthError::InvalidToken(e)) if e.contains("Expired")));
+
+        let no_leeway = VerifyOptions {
+            leeway_secs: 0,
+            ..options
+        };
+        assert!(verify_at(&token_with_exp(Some(NOW)), TEST_KEY, &no_leeway, NOW).is_ok());
+        assert!(verify_at(&token_with_exp(Some(NOW - 1)), TEST_KEY, &no_leeway, NOW).is_err());
+        // the injected clock wins over the system one, far in the past for this token
+        assert!(verify_at(&token_with_exp(Some(100)), TEST_KEY, &no_leeway, 100).is_ok());
+    }
+
+    #[test]
+    fn test_verify_at_nbf_and_iat_boundaries() {
+        const NOW: u64 = 1_700_000_000;
+        let options = VerifyOptions {
+            validate_nbf: true,
+            max_iat_skew_secs: Some(30),
+            leeway_secs: 60,
+            ..VerifyOptions::default()
+        };
+        let token = |nbf, iat| {
+            sign(
+                &Claims {
+                    exp: Some(NOW + 3600),
+                    nbf,
+                    iat,
+                    ..make_test_claims()
+                },
+                TEST_KEY,
+            )
+            .unwrap()
+        };
+
+        assert!(verify_at(&token(Some(NOW + 60), None), TEST_KEY, &options, NOW).is_ok());
+        let immature = verify_at(&token(Some(NOW + 61), None), TEST_KEY, &options, NOW);
+        assert!(matches!(immature, Err(AuthError::InvalidToken(e)) if e.contains("Immature")));
+
+        assert!(verify_at(&token(None, Some(NOW + 30)), TEST_KEY, &options, NOW).is_ok());
+        let future = verify_at(&token(None, Some(NOW + 31)), TEST_KEY, &options, NOW);
+        assert!(matches!(future, Err(AuthError::InvalidToken(e)) if e.contains("future")));
+    }
+
+    #[test]
+    fn test_verify_exp_disabled() {
+        let options = VerifyOptions {
+            validate_exp: false,
+            require_exp: false,
+            ..VerifyOptions::default()
+        };
+        assert!(verify(&token_with_exp(Some(now() - 120)), TEST_KEY, &options).is_ok());
+        assert!(verify(&token_with_exp(None), TEST_KEY, &options).is_ok());
+    }
+
+    #[test]
+    fn test_verify_at_max_age() {
+        const NOW: u64 = 1_700_000_000;
+        let options = VerifyOptions {
+            max_age_secs: Some(300),
+            ..VerifyOptions::default()
+        };
+        let token = |iat| {
+            sign(
+                &Claims {
+                    exp: Some(NOW + 86_400),
+                    iat,
+                    ..make_test_claims()
+                },
+                TEST_KEY,
+            )
+            .unwrap()
+        };
+
+        assert!(verify_at(&token(Some(NOW)), TEST_KEY, &options, NOW).is_ok());
+        assert!(verify_at(&token(Some(NOW - 300)), TEST_KEY, &options, NOW).is_ok());
+        // far from expired, but too old
+        let old = verify_at(&token(Some(NOW - 301)), TEST_KEY, &options, NOW);
+        assert!(matches!(old, Err(AuthError::InvalidToken(e)) if e.contains("too old")));
+        let missing = verify_at(&token(None), TEST_KEY, &options, NOW);
+        assert!(matches!(missing, Err(AuthError::InvalidToken(e)) if e.contains("iat")));
+
+        // disabled by default
+        let default = VerifyOptions::default();
+        assert!(verify_at(&token(None), TEST_KEY, &default, NOW).is_ok());
+        assert!(verify_at(&token(Some(NOW - 86_400)), TEST_KEY, &default, NOW).is_ok());
+    }
+
+    #[test]
+    fn test_verify_future_iat() {
+        let future_iat = sign(
+            &Claims {
+                iat: Some(now() + 3600),
+                ..make_test_claims()
+            },
+            TEST_KEY,
+        )
+        .unwrap();
+        let checked = VerifyOptions {
+            max_iat_skew_secs: Some(60),
+            ..VerifyOptions::default()
+        };
+        let result = verify(&future_iat, TEST_KEY, &checked);
+        assert!(matches!(result, Err(AuthError::InvalidToken(e)) if e.contains("future")));
+        // lenient by default
+        assert!(verify(&future_iat, TEST_KEY, &VerifyOptions::default()).is_ok());
+
+        // within the skew
+        let skewed_iat = sign(
+            &Claims {
+                iat: Some(now() + 30),
+                ..make_test_claims()
+            },
+            TEST_KEY,
+        )
+        .unwrap();
+        assert!(verify(&skewed_iat, TEST_KEY, &checked).is_ok());
+    }
+
+    #[test]
+    fn test_verify_nbf() {
+        let not_yet = sign(
+            &Claims {
+                nbf: Some(now() + 3600),
+                ..make_test_claims()
+            },
+            TEST_KEY,
+        )
+        .unwrap();
+        let checked = VerifyOptions {
+            validate_nbf: true,
+            ..VerifyOptions::default()
+        };
+        let result = verify(&not_yet, TEST_KEY, &checked);
+        assert!(matches!(result, Err(AuthError::InvalidToken(e)) if e.contains("Immature")));
+        assert!(verify(&not_yet, TEST_KEY, &VerifyOptions::default()).is_ok());
+
+        let valid = sign(
+            &Claims {
+                nbf: Some(now() - 10),
+                ..make_test_claims()
+            },
+            TEST_KEY,
+        )
+        .unwrap();
+        assert!(verify(&valid, TEST_KEY, &checked).is_ok());
+        // nbf is optional even when checked
+        assert!(verify(&token_with_exp(Some(now() + 10)), TEST_KEY, &checked).is_ok());
+    }
+
     #[test]
     fn test_sign_and_verify() {
         let claims = make_test_claims();
         let token = sign(&claims, TEST_KEY).unwrap();
-        let verified = verify(&token, TEST_KEY).unwrap();
+        let verified = verify(&token, TEST_KEY, &VerifyOptions::default()).unwrap();
 
         assert_eq!(verified.iss, "test-channel-123");
         assert_eq!(verified.key, Some("encryption-key".to_string()));
     }
 
+    #[test]
+    fn test_sign_with_header_sets_algorithm_and_kid() {
+        let header = Header {
+            kid: Some("sfu-2024".to_string()),
+            ..Header::new(Algorithm::HS512)
+        };
+        let token = sign_with_header(&make_test_claims(), TEST_KEY, &header).unwrap();
+        let produced = decode_header(&token).unwrap();
+        assert_eq!(produced.alg, Algorithm::HS512);
+        assert_eq!(produced.kid.as_deref(), Some("sfu-2024"));
+
+        // the default stays HS256 without kid
+        let produced = decode_header(sign(&make_test_claims(), TEST_KEY).unwrap()).unwrap();
+        assert_eq!(produced.alg, Algorithm::HS256);
+        assert_eq!(produced.kid, None);
+    }
+
+    #[test]
+    fn test_keys_used_as_raw_bytes() {
+        use base64::Engine;
+
+        // not valid UTF-8, let alone base64
+        let raw_key: Vec<u8> = (0..32).map(|i| 0xF0 ^ i).collect();
+        let token = sign(&make_test_claims(), &raw_key).unwrap();
+        assert!(verify(&token, &raw_key, &VerifyOptions::default()).is_ok());
+
+        // keys are decoded once when loading the config, the base64 text is a different key
+        let encoded = base64::engine::general_purpose::STANDARD.encode(&raw_key);
+        assert!(verify(&token, encoded.as_bytes(), &VerifyOptions::default()).is_err());
+    }
+
     #[test]
     fn test_verify_with_wrong_key() {
         let claims = make_test_claims();
         let token = sign(&claims, TEST_KEY).unwrap();
-        let result = verify(&token, WRONG_KEY);
+        let result = verify(&token, WRONG_KEY, &VerifyOptions::default());
 
         assert!(matches!(result, Err(AuthError::InvalidToken(_))));
     }
 
+    #[test]
+    fn test_verify_any_key() {
+        let options = VerifyOptions::default();
+        let keys = [WRONG_KEY, TEST_KEY];
+        let token = sign(&make_test_claims(), TEST_KEY).unwrap();
+        assert_eq!(
+            verify_any(&token, &keys, &options).unwrap().iss,
+            "test-channel-123"
+        );
+
+        let other = sign(&make_test_claims(), b"other-test-key-1234567890123456").unwrap();
+        assert!(verify_any(&other, &keys, &options).is_err());
+        assert!(verify_any::<&[u8]>(&token, &[], &options).is_err());
+
+        // the matching key rejects it, the next ones are not tried
+        let expired = token_with_exp(Some(now() - 3600));
+        let result = verify_any(&expired, &[TEST_KEY, TEST_KEY], &options);
+        assert!(matches!(result, Err(AuthError::InvalidToken(e)) if e.contains("Expired")));
+    }
+
     #[test]
     fn test_extract_token() {
-        assert_eq!(extract_token(Some("Bearer abc123")).unwrap(), "abc123");
-        assert_eq!(extract_token(Some("jwt abc123")).unwrap(), "abc123");
-        assert!(extract_token(None).is_err());
-        assert!(extract_token(Some("no-space")).is_err());
+        assert_eq!(extract_token(Some("Bearer abc123"), &[]).unwrap(), "abc123");
+        assert_eq!(extract_token(Some("jwt abc123"), &[]).unwrap(), "abc123");
+        assert!(extract_token(None, &[]).is_err());
+        assert!(extract_token(Some("no-space"), &[]).is_err());
+    }
+
+    #[test]
+    fn test_extract_token_scheme_restricted() {
+        let bearer = ["bearer".to_string()];
+        assert_eq!(
+            extract_token(Some("Bearer abc123"), &bearer).unwrap(),
+            "abc123"
+        );
+        assert_eq!(
+            extract_token(Some("BEARER abc123"), &bearer).unwrap(),
+            "abc123"
+        );
+        let result = extract_token(Some("jwt abc123"), &bearer);
+        assert!(matches!(result, Err(AuthError::InvalidToken(e)) if e.contains("'jwt'")));
+        assert!(matches!(
+            extract_token(None, &bearer),
+            Err(AuthError::MissingToken)
+        ));
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"same-secret", b"same-secret"));
+        assert!(constant_time_eq(b"", b""));
+        assert!(!constant_time_eq(b"same-secret", b"same-secreT"));
+        assert!(!constant_time_eq(b"short", b"longer-secret"));
+        assert!(!constant_time_eq(TEST_KEY, WRONG_KEY));
     }
 
     #[test]
@@ -140,13 +626,14 @@ mod tests {
         let claims = make_test_claims();
         let original_token = sign(&claims, gateway_key).unwrap();
 
-        let verified_claims = verify(&original_token, gateway_key).unwrap();
+        let verified_claims =
+            verify(&original_token, gateway_key, &VerifyOptions::default()).unwrap();
 
         let new_token = sign(&verified_claims, sfu_key).unwrap();
 
-        let sfu_verified = verify(&new_token, sfu_key).unwrap();
+        let sfu_verified = verify(&new_token, sfu_key, &VerifyOptions::default()).unwrap();
         assert_eq!(sfu_verified.iss, "test-channel-123");
 
-        assert!(verify(&new_token, gateway_key).is_err());
+        assert!(verify(&new_token, gateway_key, &VerifyOptions::default()).is_err());
     }
 }
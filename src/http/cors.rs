@@ -0,0 +1,25 @@
+//! CORS for deployments where the browser calls the channel routes directly
+//!
+//! Disabled unless origins are configured, the routes then answer without any
+//! `Access-Control-*` header, as before.
+
+use actix_cors::Cors;
+use actix_web::http::{Method, header};
+
+/// How long a browser may cache a preflight answer, in seconds
+const MAX_AGE_SECS: usize = 3600;
+
+/// CORS policy of the channel routes: GET and POST from `origins`, `*` allowing any origin,
+/// with the `Authorization` header (and `Content-Type` for POST bodies).
+pub fn cors(origins: &[String]) -> Cors {
+    let cors = Cors::default()
+        .allowed_methods([Method::GET, Method::POST])
+        .allowed_headers([header::AUTHORIZATION, header::CONTENT_TYPE])
+        .max_age(MAX_AGE_SECS);
+    if origins.iter().any(|origin| origin == "*") {
+        return cors.allow_any_origin();
+    }
+    origins
+        .iter()
+        .fold(cors, |cors, origin| cors.allowed_origin(origin))
+}
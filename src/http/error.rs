@@ -0,0 +1,468 @@
+//! Errors returned by the HTTP handlers
+//!
+//! Each variant maps to a status code and a `{ "error": { "code": "<CODE>", "message": "<message>" } }`
+//! body, so every failure point of a handler renders the same way. Clients branch on the
+//! code, which is stable, the message is for humans. SFU errors are the exception, they are
+//! passed through as received.
+
+use actix_web::error::PayloadError;
+use actix_web::http::StatusCode;
+use actix_web::http::header::{CONTENT_ENCODING, CONTENT_TYPE, HeaderValue, RETRY_AFTER};
+use actix_web::web::Bytes;
+use actix_web::{HttpResponse, ResponseError};
+use serde::Serialize;
+
+use crate::routing::SelectError;
+
+/// Seconds a client is told to wait before retrying when all SFUs are busy
+const BUSY_RETRY_AFTER_SECS: u32 = 2;
+
+/// Machine-readable code of an error, the `code` of the error body
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    MalformedQuery,
+    InvalidPath,
+    InvalidBody,
+    RequestTooLarge,
+    UnknownRegion,
+    MissingAuth,
+    InvalidToken,
+    InvalidApiKey,
+    InvalidAdminKey,
+    UnknownSfu,
+    UnknownPin,
+    ReloadFailed,
+    NoSfu,
+    AllBusy,
+    RateLimited,
+    Internal,
+    SfuUnreachable,
+    SfuTimeout,
+    BadSfuResponse,
+    SfuResponseTooLarge,
+    /// The SFU's own error, its body is passed through rather than wrapped
+    SfuError,
+    DeadlineExceeded,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChannelError {
+    /// The query string would not be decodable by the SFU
+    MalformedQuery,
+    /// The proxied path isn't a plain SFU sub-path
+    InvalidPath,
+    /// The request body could not be read
+    InvalidBody,
+    /// The named part of the request (Authorization header, query string) is over its size limit
+    TooLarge { part: &'static str },
+    /// The request body is over the body size limit
+    BodyTooLarge,
+    /// The named routing hint (`region`, `country`) isn't known, in strict region mode
+    UnknownRegion { param: &'static str },
+    /// No usable Authorization header (or token query parameter)
+    MissingAuth,
+    /// The JWT failed verification with the gateway key
+    InvalidToken,
+    /// The `X-Api-Key` header doesn't match the configured key
+    InvalidApiKey,
+    /// The `X-Admin-Key` header doesn't match the admin key
+    InvalidAdminKey,
+    /// No configured SFU has the address an admin request names
+    UnknownSfu,
+    /// No configured SFU matches the `__sfu` pin of a debug request
+    UnknownPin,
+    /// An admin reload could not load the new configuration, the current one is kept
+    ReloadFailed { message: String },
+    /// No SFU could be selected
+    NoSfu,
+    /// The SFUs that could serve the request are all at capacity
+    AllBusy,
+    /// The issuer exceeded its request rate, it may retry after that many seconds
+    RateLimited { retry_after_secs: u64 },
+    /// Gateway-side failure, such as re-signing the token
+    Internal,
+    /// The SFU could not be reached
+    UpstreamUnreachable,
+    /// The connection to the SFU failed once the request was sent, the SFU may have processed it
+    UpstreamInterrupted,
+    /// The SFU didn't answer within the SFU timeout
+    UpstreamTimeout,
+    /// The SFU answered with a success status but an unexpected body
+    InvalidUpstreamResponse,
+    /// The SFU's response body is over the body size limit
+    UpstreamTooLarge,
+    /// The SFU answered with an error status, passed through with its body, content type and
+    /// content encoding
+    UpstreamStatus {
+        status: StatusCode,
+        content_type: Option<HeaderValue>,
+        content_encoding: Option<HeaderValue>,
+        body: Bytes,
+    },
+    /// The request deadline expired before the flow completed
+    Timeout,
+}
+
+impl ChannelError {
+    /// Stable code of this error, for clients to branch on.
+    #[must_use]
+    pub const fn code(&self) -> ErrorCode {
+        match self {
+            Self::MalformedQuery => ErrorCode::MalformedQuery,
+            Self::InvalidPath => ErrorCode::InvalidPath,
+            Self::InvalidBody => ErrorCode::InvalidBody,
+            Self::TooLarge { .. } | Self::BodyTooLarge => ErrorCode::RequestTooLarge,
+            Self::UnknownRegion { .. } => ErrorCode::UnknownRegion,
+            Self::MissingAuth => ErrorCode::MissingAuth,
+            Self::InvalidToken => ErrorCode::InvalidToken,
+            Self::InvalidApiKey => ErrorCode::InvalidApiKey,
+            Self::InvalidAdminKey => ErrorCode::InvalidAdminKey,
+            Self::UnknownSfu => ErrorCode::UnknownSfu,
+            Self::UnknownPin => ErrorCode::UnknownPin,
+            Self::ReloadFailed { .. } => ErrorCode::ReloadFailed,
+            Self::NoSfu => ErrorCode::NoSfu,
+            Self::AllBusy => ErrorCode::AllBusy,
+            Self::RateLimited { .. } => ErrorCode::RateLimited,
+            Self::Internal => ErrorCode::Internal,
+            Self::UpstreamUnreachable | Self::UpstreamInterrupted => ErrorCode::SfuUnreachable,
+            Self::UpstreamTimeout => ErrorCode::SfuTimeout,
+            Self::InvalidUpstreamResponse => ErrorCode::BadSfuResponse,
+            Self::UpstreamTooLarge => ErrorCode::SfuResponseTooLarge,
+            Self::UpstreamStatus { .. } => ErrorCode::SfuError,
+            Self::Timeout => ErrorCode::DeadlineExceeded,
+        }
+    }
+
+    /// Error body, `{ "error": { "code": "<CODE>", "message": "<message>" } }`.
+    fn body(&self) -> serde_json::Value {
+        serde_json::json!({ "error": { "code": self.code(), "message": self.to_string() } })
+    }
+
+    /// Error of a request body the `Bytes` extractor refused, over the limit of the
+    /// `PayloadConfig` or unreadable.
+    #[must_use]
+    pub fn from_payload(error: &actix_web::Error) -> Self {
+        match error.as_error::<PayloadError>() {
+            Some(PayloadError::Overflow) => Self::BodyTooLarge,
+            _ => Self::InvalidBody,
+        }
+    }
+
+    /// Whether another SFU may succeed where this one failed.
+    ///
+    /// Only failures where the SFU can't have created the channel are: a timed out or
+    /// interrupted request may have been processed, trying another SFU could create a duplicate.
+    #[must_use]
+    pub const fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            Self::UpstreamUnreachable
+                | Self::UpstreamStatus {
+                    status: StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE,
+                    ..
+                }
+        )
+    }
+}
+
+impl From<SelectError> for ChannelError {
+    fn from(error: SelectError) -> Self {
+        match error {
+            SelectError::NoSfu => Self::NoSfu,
+            SelectError::AllBusy => Self::AllBusy,
+        }
+    }
+}
+
+impl std::fmt::Display for ChannelError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MalformedQuery => write!(f, "malformed query string"),
+            Self::InvalidPath => write!(f, "invalid path"),
+            Self::InvalidBody => write!(f, "invalid request body"),
+            Self::TooLarge { part } => write!(f, "{part} too large"),
+            Self::BodyTooLarge => write!(f, "request body too large"),
+            Self::UnknownRegion { param } => write!(f, "unknown {param}"),
+            Self::MissingAuth => write!(f, "missing authorization"),
+            Self::InvalidToken => write!(f, "invalid token"),
+            Self::InvalidApiKey => write!(f, "invalid api key"),
+            Self::InvalidAdminKey => write!(f, "invalid admin key"),
+            Self::UnknownSfu => write!(f, "unknown SFU"),
+            Self::UnknownPin => write!(f, "unknown pinned SFU"),
+            Self::ReloadFailed { message } => write!(f, "reload failed: {message}"),
+            Self::NoSfu => write!(f, "no SFU instances available"),
+            Self::AllBusy => write!(f, "all SFU instances are busy"),
+            Self::RateLimited { .. } => write!(f, "rate limit exceeded"),
+            Self::Internal => write!(f, "internal error"),
+            Self::UpstreamUnreachable => write!(f, "failed to contact SFU"),
+            Self::UpstreamInterrupted => write!(f, "connection to SFU lost"),
+            Self::UpstreamTimeout => write!(f, "SFU timed out"),
+            Self::InvalidUpstreamResponse => write!(f, "invalid SFU response"),
+            Self::UpstreamTooLarge => write!(f, "SFU response too large"),
+            Self::UpstreamStatus { status, .. } => write!(f, "SFU returned {status}"),
+            Self::Timeout => write!(f, "gateway deadline exceeded"),
+        }
+    }
+}
+
+impl std::error::Error for ChannelError {}
+
+impl ResponseError for ChannelError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::MalformedQuery
+            | Self::InvalidPath
+            | Self::InvalidBody
+            | Self::TooLarge { .. }
+            | Self::UnknownRegion { .. }
+            | Self::UnknownPin => StatusCode::BAD_REQUEST,
+            Self::MissingAuth
+            | Self::InvalidToken
+            | Self::InvalidApiKey
+            | Self::InvalidAdminKey => StatusCode::UNAUTHORIZED,
+            Self::UnknownSfu => StatusCode::NOT_FOUND,
+            Self::BodyTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::NoSfu | Self::AllBusy => StatusCode::SERVICE_UNAVAILABLE,
+            Self::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            Self::Internal | Self::ReloadFailed { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::UpstreamUnreachable
+            | Self::UpstreamInterrupted
+            | Self::InvalidUpstreamResponse
+            | Self::UpstreamTooLarge => StatusCode::BAD_GATEWAY,
+            Self::UpstreamStatus { status, .. } => *status,
+            Self::UpstreamTimeout | Self::Timeout => StatusCode::GATEWAY_TIMEOUT,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            Self::UpstreamStatus {
+                status,
+                content_type,
+                content_encoding,
+                body,
+            } => {
+                let mut response = HttpResponse::build(*status);
+                if let Some(content_type) = content_type {
+                    response.insert_header((CONTENT_TYPE, content_type.clone()));
+                }
+                if let Some(content_encoding) = content_encoding {
+                    response.insert_header((CONTENT_ENCODING, content_encoding.clone()));
+                }
+                response.body(body.clone())
+            }
+            Self::AllBusy => HttpResponse::build(self.status_code())
+                .insert_header((RETRY_AFTER, BUSY_RETRY_AFTER_SECS))
+                .json(self.body()),
+            Self::RateLimited { retry_after_secs } => HttpResponse::build(self.status_code())
+                .insert_header((RETRY_AFTER, *retry_after_secs))
+                .json(self.body()),
+            _ => HttpResponse::build(self.status_code()).json(self.body()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn render(error: &ChannelError) -> (StatusCode, String) {
+        let response = error.error_response();
+        let status = response.status();
+        let body = actix_web::body::to_bytes(response.into_body())
+            .await
+            .unwrap_or_default();
+        (status, String::from_utf8_lossy(&body).into_owned())
+    }
+
+    #[actix_web::test]
+    async fn test_error_responses() {
+        let cases = [
+            (
+                ChannelError::MalformedQuery,
+                400,
+                r#"{"error":{"code":"MALFORMED_QUERY","message":"malformed query string"}}"#,
+            ),
+            (
+                ChannelError::InvalidPath,
+                400,
+                r#"{"error":{"code":"INVALID_PATH","message":"invalid path"}}"#,
+            ),
+            (
+                ChannelError::TooLarge {
+                    part: "query string",
+                },
+                400,
+                r#"{"error":{"code":"REQUEST_TOO_LARGE","message":"query string too large"}}"#,
+            ),
+            (
+                ChannelError::BodyTooLarge,
+                413,
+                r#"{"error":{"code":"REQUEST_TOO_LARGE","message":"request body too large"}}"#,
+            ),
+            (
+                ChannelError::InvalidBody,
+                400,
+                r#"{"error":{"code":"INVALID_BODY","message":"invalid request body"}}"#,
+            ),
+            (
+                ChannelError::UnknownRegion { param: "country" },
+                400,
+                r#"{"error":{"code":"UNKNOWN_REGION","message":"unknown country"}}"#,
+            ),
+            (
+                ChannelError::UnknownPin,
+                400,
+                r#"{"error":{"code":"UNKNOWN_PIN","message":"unknown pinned SFU"}}"#,
+            ),
+            (
+                ChannelError::MissingAuth,
+                401,
+                r#"{"error":{"code":"MISSING_AUTH","message":"missing authorization"}}"#,
+            ),
+            (
+                ChannelError::InvalidToken,
+                401,
+                r#"{"error":{"code":"INVALID_TOKEN","message":"invalid token"}}"#,
+            ),
+            (
+                ChannelError::InvalidApiKey,
+                401,
+                r#"{"error":{"code":"INVALID_API_KEY","message":"invalid api key"}}"#,
+            ),
+            (
+                ChannelError::InvalidAdminKey,
+                401,
+                r#"{"error":{"code":"INVALID_ADMIN_KEY","message":"invalid admin key"}}"#,
+            ),
+            (
+                ChannelError::UnknownSfu,
+                404,
+                r#"{"error":{"code":"UNKNOWN_SFU","message":"unknown SFU"}}"#,
+            ),
+            (
+                ChannelError::ReloadFailed {
+                    message: "no SFU in the secrets file".to_string(),
+                },
+                500,
+                r#"{"error":{"code":"RELOAD_FAILED","message":"reload failed: no SFU in the secrets file"}}"#,
+            ),
+            (
+                ChannelError::NoSfu,
+                503,
+                r#"{"error":{"code":"NO_SFU","message":"no SFU instances available"}}"#,
+            ),
+            (
+                ChannelError::AllBusy,
+                503,
+                r#"{"error":{"code":"ALL_BUSY","message":"all SFU instances are busy"}}"#,
+            ),
+            (
+                ChannelError::RateLimited {
+                    retry_after_secs: 3,
+                },
+                429,
+                r#"{"error":{"code":"RATE_LIMITED","message":"rate limit exceeded"}}"#,
+            ),
+            (
+                ChannelError::Internal,
+                500,
+                r#"{"error":{"code":"INTERNAL","message":"internal error"}}"#,
+            ),
+            (
+                ChannelError::UpstreamUnreachable,
+                502,
+                r#"{"error":{"code":"SFU_UNREACHABLE","message":"failed to contact SFU"}}"#,
+            ),
+            (
+                ChannelError::UpstreamInterrupted,
+                502,
+                r#"{"error":{"code":"SFU_UNREACHABLE","message":"connection to SFU lost"}}"#,
+            ),
+            (
+                ChannelError::UpstreamTimeout,
+                504,
+                r#"{"error":{"code":"SFU_TIMEOUT","message":"SFU timed out"}}"#,
+            ),
+            (
+                ChannelError::InvalidUpstreamResponse,
+                502,
+                r#"{"error":{"code":"BAD_SFU_RESPONSE","message":"invalid SFU response"}}"#,
+            ),
+            (
+                ChannelError::UpstreamTooLarge,
+                502,
+                r#"{"error":{"code":"SFU_RESPONSE_TOO_LARGE","message":"SFU response too large"}}"#,
+            ),
+            (
+                ChannelError::Timeout,
+                504,
+                r#"{"error":{"code":"DEADLINE_EXCEEDED","message":"gateway deadline exceeded"}}"#,
+            ),
+        ];
+        for (error, status, body) in cases {
+            assert_eq!(
+                render(&error).await,
+                (StatusCode::from_u16(status).unwrap(), body.to_string())
+            );
+        }
+    }
+
+    fn upstream_status(status: StatusCode) -> ChannelError {
+        ChannelError::UpstreamStatus {
+            status,
+            content_type: None,
+            content_encoding: None,
+            body: Bytes::new(),
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_upstream_status_passthrough() {
+        let (status, body) = render(&upstream_status(StatusCode::CONFLICT)).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert!(body.is_empty());
+
+        let response = ChannelError::UpstreamStatus {
+            status: StatusCode::FORBIDDEN,
+            content_type: Some(HeaderValue::from_static("text/plain")),
+            content_encoding: None,
+            body: Bytes::from_static(b"quota exceeded"),
+        }
+        .error_response();
+        assert_eq!(response.headers().get(CONTENT_TYPE).unwrap(), "text/plain");
+        let body = actix_web::body::to_bytes(response.into_body())
+            .await
+            .unwrap();
+        assert_eq!(body, "quota exceeded");
+    }
+
+    #[test]
+    fn test_retry_after_when_busy_or_limited() {
+        let busy = ChannelError::AllBusy.error_response();
+        assert_eq!(busy.headers().get(RETRY_AFTER).unwrap(), "2");
+        let empty = ChannelError::NoSfu.error_response();
+        assert!(empty.headers().get(RETRY_AFTER).is_none());
+        let limited = ChannelError::RateLimited {
+            retry_after_secs: 20,
+        }
+        .error_response();
+        assert_eq!(limited.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(limited.headers().get(RETRY_AFTER).unwrap(), "20");
+    }
+
+    #[test]
+    fn test_retryable_errors() {
+        assert!(ChannelError::UpstreamUnreachable.is_retryable());
+        assert!(!ChannelError::UpstreamTimeout.is_retryable());
+        assert!(!ChannelError::UpstreamInterrupted.is_retryable());
+        assert!(upstream_status(StatusCode::BAD_GATEWAY).is_retryable());
+        assert!(upstream_status(StatusCode::SERVICE_UNAVAILABLE).is_retryable());
+        assert!(!upstream_status(StatusCode::INTERNAL_SERVER_ERROR).is_retryable());
+        assert!(!upstream_status(StatusCode::CONFLICT).is_retryable());
+        assert!(!ChannelError::InvalidUpstreamResponse.is_retryable());
+        assert!(!ChannelError::NoSfu.is_retryable());
+        assert!(!ChannelError::AllBusy.is_retryable());
+    }
+}
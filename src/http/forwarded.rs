@@ -0,0 +1,447 @@
+//! `X-Forwarded-For` construction for requests forwarded to the SFUs
+//!
+//! Behind a trusted proxy, the chain received in the standard `Forwarded` header (RFC 7239)
+//! is preferred to the one of `X-Forwarded-For`. The logic is kept in pure functions,
+//! `for_request` only reads what it needs from the actix request.
+
+use std::net::{IpAddr, SocketAddr};
+
+use actix_web::HttpRequest;
+
+/// Placeholder for a peer address that isn't known (e.g. unix socket).
+const UNKNOWN_PEER: &str = "unknown";
+
+/// Build X-Forwarded-For header value by appending new IP to existing chain.
+/// Per RFC 7239, each proxy appends the IP of the immediate client it received from.
+/// Pure function for testability.
+///
+/// The chain is normalized on the way: entries are trimmed and joined with `, `, addresses
+/// lose their port and are written the same way whatever they were received as (see
+/// `normalize_entry`), entries that are neither an IP address nor `unknown` are dropped, and
+/// an entry repeating the one before it is dropped too, so a proxy that already appended the
+/// peer doesn't get it twice.
+pub(crate) fn build_forwarded_for(existing: Option<&str>, new_ip: &str) -> String {
+    let mut entries: Vec<String> = Vec::new();
+    let chain = existing.into_iter().flat_map(|chain| chain.split(','));
+    for entry in chain.chain([new_ip]).filter_map(normalize_entry) {
+        if entries.last() != Some(&entry) {
+            entries.push(entry);
+        }
+    }
+    if entries.is_empty() {
+        return UNKNOWN_PEER.to_string();
+    }
+    entries.join(", ")
+}
+
+/// An X-Forwarded-For entry as sent to the SFU, `None` when it is neither an IP address nor
+/// `unknown`.
+///
+/// The port is dropped, IPv6 addresses are unbracketed and lowercase in their shortest form,
+/// and IPv4-mapped ones (`::ffff:10.0.0.1`, from dual-stack sockets) written as IPv4.
+fn normalize_entry(entry: &str) -> Option<String> {
+    let entry = entry.trim();
+    if entry.eq_ignore_ascii_case(UNKNOWN_PEER) {
+        return Some(UNKNOWN_PEER.to_string());
+    }
+    parse_entry(entry).map(|ip| ip.to_string())
+}
+
+/// IP address of an X-Forwarded-For entry, which may carry a port, IPv4-mapped IPv6
+/// addresses as IPv4.
+fn parse_entry(entry: &str) -> Option<IpAddr> {
+    entry
+        .parse::<IpAddr>()
+        .or_else(|_| entry.parse::<SocketAddr>().map(|addr| addr.ip()))
+        .ok()
+        .map(|ip| ip.to_canonical())
+}
+
+/// The `for` addresses of a `Forwarded` header (RFC 7239) as an X-Forwarded-For chain, `None`
+/// when it has none.
+///
+/// Parameter names are case-insensitive and values may be quoted, as IPv6 ones must be
+/// (`for="[2001:db8::1]:4711"`). Elements without `for`, or with an obfuscated identifier
+/// (`for=_hidden`), stand for a hop that didn't disclose its client: `unknown`.
+/// Pure function for testability.
+pub(crate) fn forwarded_header_chain(header: &str) -> Option<String> {
+    let entries: Vec<String> = split_unquoted(header, ',')
+        .into_iter()
+        .filter(|element| !element.trim().is_empty())
+        .map(|element| {
+            split_unquoted(element, ';')
+                .into_iter()
+                .filter_map(|pair| pair.split_once('='))
+                .find(|(name, _)| name.trim().eq_ignore_ascii_case("for"))
+                .map_or_else(
+                    || UNKNOWN_PEER.to_string(),
+                    |(_, value)| forwarded_node(value),
+                )
+        })
+        .collect();
+    if entries.iter().all(|entry| entry == UNKNOWN_PEER) {
+        return None;
+    }
+    Some(entries.join(", "))
+}
+
+/// A `for` node of a `Forwarded` element in X-Forwarded-For form: unquoted, an IPv6 address
+/// without port unbracketed, `unknown` for an obfuscated identifier.
+fn forwarded_node(value: &str) -> String {
+    let value = value.trim();
+    let value = value
+        .strip_prefix('"')
+        .and_then(|quoted| quoted.strip_suffix('"'))
+        .map_or_else(|| value.to_string(), |quoted| quoted.replace('\\', ""));
+    if value.starts_with('_') {
+        return UNKNOWN_PEER.to_string();
+    }
+    value
+        .strip_prefix('[')
+        .and_then(|bracketed| bracketed.strip_suffix(']'))
+        .map_or(value.clone(), str::to_string)
+}
+
+/// Split `value` on `separator`, except within quoted strings.
+fn split_unquoted(value: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut start, mut quoted, mut escaped) = (0, false, false);
+    for (i, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            _ if c == separator && !quoted => {
+                parts.push(&value[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&value[start..]);
+    parts
+}
+
+/// Chain received from a trusted proxy: the one of `Forwarded` when it has `for` addresses,
+/// otherwise `X-Forwarded-For`.
+/// Pure function for testability.
+pub(crate) fn received_chain(
+    forwarded: Option<&str>,
+    x_forwarded_for: Option<&str>,
+) -> Option<String> {
+    forwarded
+        .and_then(forwarded_header_chain)
+        .or_else(|| x_forwarded_for.map(str::to_string))
+}
+
+/// X-Forwarded-For value for the SFU, from the direct peer and the received header.
+///
+/// When `trust_proxy` is true, the gateway is behind a trusted reverse proxy.
+/// We trust the existing X-Forwarded-For header and append our direct peer IP
+/// (the proxy's IP) to maintain the complete chain.
+///
+/// When `trust_proxy` is false, the gateway is directly exposed to clients.
+/// Any existing X-Forwarded-For header is untrusted (could be spoofed), so we
+/// ignore it and use only our direct peer IP as the client.
+///
+/// A blank existing header is treated as absent.
+pub(crate) fn forwarded_for(
+    peer_ip: Option<IpAddr>,
+    existing: Option<&str>,
+    trust_proxy: bool,
+) -> String {
+    let peer_ip = peer_ip.map_or_else(|| UNKNOWN_PEER.to_string(), |ip| ip.to_string());
+    let existing = existing
+        .map(str::trim)
+        .filter(|chain| trust_proxy && !chain.is_empty());
+    build_forwarded_for(existing, &peer_ip)
+}
+
+/// X-Forwarded-For value for the SFU for this request, see `forwarded_for`, with the chain
+/// received in `Forwarded` or `X-Forwarded-For`, see `received_chain`.
+///
+/// Only the socket peer is used as our client: `ConnectionInfo::realip_remote_addr` would
+/// already read the (possibly spoofed) forwarding headers. A header that isn't valid
+/// UTF-8 is ignored.
+pub(crate) fn for_request(req: &HttpRequest, trust_proxy: bool) -> String {
+    let peer_ip = req.peer_addr().map(|addr| addr.ip());
+    let header = |name| req.headers().get(name).and_then(|h| h.to_str().ok());
+    let existing = received_chain(header("Forwarded"), header("X-Forwarded-For"));
+    forwarded_for(peer_ip, existing.as_deref(), trust_proxy)
+}
+
+/// The original client of an X-Forwarded-For chain, its first entry.
+///
+/// Entries may carry a port (`1.2.3.4:5678`, `[2001:db8::1]:443`), `None` when the first
+/// entry isn't an IP address (e.g. `unknown`).
+pub(crate) fn client_ip(forwarded_for: &str) -> Option<IpAddr> {
+    parse_entry(forwarded_for.split(',').next()?.trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::header::HeaderValue;
+    use actix_web::test::TestRequest;
+
+    #[test]
+    fn test_build_forwarded_for_no_existing() {
+        let result = build_forwarded_for(None, "192.168.1.100");
+        assert_eq!(result, "192.168.1.100");
+    }
+
+    #[test]
+    fn test_build_forwarded_for_with_existing() {
+        let result = build_forwarded_for(Some("10.0.0.1"), "192.168.1.100");
+        assert_eq!(result, "10.0.0.1, 192.168.1.100");
+    }
+
+    #[test]
+    fn test_build_forwarded_for_with_chain() {
+        let result = build_forwarded_for(Some("10.0.0.1, 172.16.0.1"), "192.168.1.100");
+        assert_eq!(result, "10.0.0.1, 172.16.0.1, 192.168.1.100");
+    }
+
+    #[test]
+    fn test_build_forwarded_for_normalizes_whitespace() {
+        let result =
+            build_forwarded_for(Some("10.0.0.1 ,172.16.0.1,,  10.0.0.2  "), "192.168.1.100");
+        assert_eq!(result, "10.0.0.1, 172.16.0.1, 10.0.0.2, 192.168.1.100");
+    }
+
+    #[test]
+    fn test_build_forwarded_for_suppresses_duplicates() {
+        // the upstream proxy already appended the peer
+        let result = build_forwarded_for(Some("10.0.0.1, 192.168.1.100"), "192.168.1.100");
+        assert_eq!(result, "10.0.0.1, 192.168.1.100");
+        // repeated entries collapse, a client seen again further down the chain is kept
+        let result = build_forwarded_for(
+            Some("10.0.0.1, 10.0.0.1, 172.16.0.1, 10.0.0.1"),
+            "192.168.1.100",
+        );
+        assert_eq!(result, "10.0.0.1, 172.16.0.1, 10.0.0.1, 192.168.1.100");
+    }
+
+    #[test]
+    fn test_build_forwarded_for_drops_garbage() {
+        let result = build_forwarded_for(
+            Some("not-an-ip, 10.0.0.1, <script>, unknown, 10.0.0.1:5678"),
+            "192.168.1.100",
+        );
+        assert_eq!(result, "10.0.0.1, unknown, 10.0.0.1, 192.168.1.100");
+        assert_eq!(
+            build_forwarded_for(Some("garbage"), "also garbage"),
+            "unknown"
+        );
+    }
+
+    #[test]
+    fn test_forwarded_for_matrix() {
+        let peer = "192.168.1.100".parse().ok();
+        let cases = [
+            // (existing header, trust_proxy, expected)
+            (None, false, "192.168.1.100"),
+            (None, true, "192.168.1.100"),
+            (Some("10.0.0.1"), false, "192.168.1.100"),
+            (Some("10.0.0.1"), true, "10.0.0.1, 192.168.1.100"),
+            (
+                Some("10.0.0.1, 172.16.0.1"),
+                true,
+                "10.0.0.1, 172.16.0.1, 192.168.1.100",
+            ),
+            (Some("  10.0.0.1 "), true, "10.0.0.1, 192.168.1.100"),
+            (Some(""), true, "192.168.1.100"),
+            (Some("   "), true, "192.168.1.100"),
+            // entries of a trusted chain lose their port, IPv6 ones their brackets
+            (Some("10.0.0.1:5678"), true, "10.0.0.1, 192.168.1.100"),
+            (Some("2001:db8::1"), true, "2001:db8::1, 192.168.1.100"),
+            (
+                Some("[2001:db8::1]:443, 10.0.0.1"),
+                true,
+                "2001:db8::1, 10.0.0.1, 192.168.1.100",
+            ),
+        ];
+        for (existing, trust_proxy, expected) in cases {
+            assert_eq!(
+                forwarded_for(peer, existing, trust_proxy),
+                expected,
+                "existing: {existing:?}, trust_proxy: {trust_proxy}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_forwarded_for_ipv6_peer() {
+        let peer = "2001:db8::1".parse().ok();
+        assert_eq!(forwarded_for(peer, None, false), "2001:db8::1");
+        // a dual-stack socket reports IPv4 clients as IPv4-mapped addresses
+        let mapped = "::ffff:192.168.1.100".parse().ok();
+        assert_eq!(forwarded_for(mapped, None, false), "192.168.1.100");
+    }
+
+    #[test]
+    fn test_build_forwarded_for_bracketed_ipv6_with_port() {
+        assert_eq!(
+            build_forwarded_for(Some("[2001:DB8:0:0::1]:443"), "2001:db8::1"),
+            "2001:db8::1"
+        );
+        assert_eq!(
+            build_forwarded_for(None, "[2001:db8::2]:8443"),
+            "2001:db8::2"
+        );
+        // brackets without port aren't an address
+        assert_eq!(
+            build_forwarded_for(Some("[2001:db8::1]"), "10.0.0.1"),
+            "10.0.0.1"
+        );
+    }
+
+    #[test]
+    fn test_build_forwarded_for_mixed_ipv4_ipv6_chain() {
+        let result = build_forwarded_for(
+            Some("203.0.113.7:1234, [2001:db8::1]:443, ::ffff:10.0.0.1, 2001:db8::1"),
+            "2001:db8:0:0:0:0:0:2",
+        );
+        assert_eq!(
+            result,
+            "203.0.113.7, 2001:db8::1, 10.0.0.1, 2001:db8::1, 2001:db8::2"
+        );
+        assert_eq!(client_ip(&result), "203.0.113.7".parse().ok());
+    }
+
+    #[test]
+    fn test_forwarded_header_chain() {
+        let cases = [
+            (
+                "for=192.0.2.60;proto=https;by=203.0.113.43",
+                Some("192.0.2.60"),
+            ),
+            (
+                "for=192.0.2.43, for=198.51.100.17",
+                Some("192.0.2.43, 198.51.100.17"),
+            ),
+            (r#"For="[2001:db8:cafe::17]""#, Some("2001:db8:cafe::17")),
+            (
+                r#"for="[2001:db8:cafe::17]:4711", for=192.0.2.43"#,
+                Some("[2001:db8:cafe::17]:4711, 192.0.2.43"),
+            ),
+            (r#"for="192.0.2.60:8080""#, Some("192.0.2.60:8080")),
+            // undisclosed hops keep their place in the chain
+            (
+                "for=_hidden, proto=http, for=192.0.2.43",
+                Some("unknown, unknown, 192.0.2.43"),
+            ),
+            // separators within quotes don't split
+            (r#"by="a;b,c";for=192.0.2.60"#, Some("192.0.2.60")),
+            ("for=unknown", None),
+            ("proto=https", None),
+            ("", None),
+        ];
+        for (header, expected) in cases {
+            assert_eq!(
+                forwarded_header_chain(header).as_deref(),
+                expected,
+                "{header}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_forwarded_header_combined_into_chain() {
+        let forwarded = r#"for=192.0.2.43, for="[2001:db8::1]:4711""#;
+        let received = received_chain(Some(forwarded), Some("10.0.0.1"));
+        assert_eq!(
+            forwarded_for("192.168.1.100".parse().ok(), received.as_deref(), true),
+            "192.0.2.43, 2001:db8::1, 192.168.1.100"
+        );
+        // without for addresses, X-Forwarded-For is used
+        let received = received_chain(Some("proto=https"), Some("10.0.0.1"));
+        assert_eq!(received.as_deref(), Some("10.0.0.1"));
+
+        let req = TestRequest::default()
+            .peer_addr("192.168.1.100:54321".parse().unwrap())
+            .insert_header(("X-Forwarded-For", "10.0.0.1"))
+            .insert_header(("Forwarded", "for=192.0.2.60;proto=https"))
+            .to_http_request();
+        assert_eq!(for_request(&req, true), "192.0.2.60, 192.168.1.100");
+        assert_eq!(for_request(&req, false), "192.168.1.100");
+    }
+
+    #[test]
+    fn test_forwarded_for_unknown_peer() {
+        assert_eq!(forwarded_for(None, None, false), "unknown");
+        assert_eq!(
+            forwarded_for(None, Some("10.0.0.1"), true),
+            "10.0.0.1, unknown"
+        );
+    }
+
+    #[test]
+    fn test_client_ip() {
+        let cases = [
+            ("192.168.1.100", Some("192.168.1.100")),
+            ("10.0.0.1, 192.168.1.100", Some("10.0.0.1")),
+            ("10.0.0.1:5678, 192.168.1.100", Some("10.0.0.1")),
+            ("2001:db8::1, 10.0.0.1", Some("2001:db8::1")),
+            ("[2001:db8::1]:443", Some("2001:db8::1")),
+            ("unknown", None),
+            ("", None),
+        ];
+        for (chain, expected) in cases {
+            assert_eq!(
+                client_ip(chain),
+                expected.map(|ip| ip.parse().unwrap()),
+                "{chain}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_for_request_ipv4_peer_without_port() {
+        let req = TestRequest::default()
+            .peer_addr("192.168.1.100:54321".parse().unwrap())
+            .to_http_request();
+        assert_eq!(for_request(&req, false), "192.168.1.100");
+    }
+
+    #[test]
+    fn test_for_request_ipv6_peer_unbracketed() {
+        let req = TestRequest::default()
+            .peer_addr("[2001:db8::1]:443".parse().unwrap())
+            .insert_header(("X-Forwarded-For", "10.0.0.1"))
+            .to_http_request();
+        assert_eq!(for_request(&req, true), "10.0.0.1, 2001:db8::1");
+    }
+
+    #[test]
+    fn test_for_request_ignores_spoofed_header_without_proxy() {
+        // realip_remote_addr would report 6.6.6.6 here
+        let req = TestRequest::default()
+            .peer_addr("192.168.1.100:54321".parse().unwrap())
+            .insert_header(("X-Forwarded-For", "6.6.6.6"))
+            .insert_header(("Forwarded", "for=6.6.6.6"))
+            .to_http_request();
+        assert_eq!(for_request(&req, false), "192.168.1.100");
+    }
+
+    #[test]
+    fn test_for_request_non_utf8_header_ignored() {
+        let req = TestRequest::default()
+            .peer_addr("192.168.1.100:54321".parse().unwrap())
+            .insert_header((
+                "X-Forwarded-For",
+                HeaderValue::from_bytes(b"10.0.0.1\xff").unwrap(),
+            ))
+            .to_http_request();
+        assert_eq!(for_request(&req, true), "192.168.1.100");
+    }
+
+    #[test]
+    fn test_for_request_without_peer() {
+        let req = TestRequest::default()
+            .insert_header(("X-Forwarded-For", "10.0.0.1"))
+            .to_http_request();
+        assert_eq!(for_request(&req, true), "10.0.0.1, unknown");
+        assert_eq!(for_request(&req, false), "unknown");
+    }
+}
@@ -0,0 +1,121 @@
+//! Remote JWKS source for the gateway verification keys
+//!
+//! Tokens signed with an asymmetric algorithm (RS256) are verified against the public key
+//! of the JWKS entry matching their `kid`. The key set is cached for a TTL and refreshed
+//! early when a token names an unknown `kid`, so that key rotations on the identity
+//! provider are picked up without restart. Fetch failures keep the cached keys.
+
+use std::time::{Duration, Instant};
+
+use jsonwebtoken::DecodingKey;
+use jsonwebtoken::jwk::JwkSet;
+use tokio::sync::RwLock;
+use tracing::{debug, info, warn};
+
+/// Minimum time between two refreshes triggered by requests, so that tokens with made-up
+/// `kid`s or an unreachable identity provider cannot make every request fetch the JWKS.
+const DEFAULT_MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+
+struct CachedKeys {
+    keys: JwkSet,
+    fetched_at: Instant,
+}
+
+pub struct JwksCache {
+    url: String,
+    ttl: Duration,
+    min_refresh_interval: Duration,
+    http_client: reqwest::Client,
+    cached: RwLock<Option<CachedKeys>>,
+    /// Last fetch attempt, successful or not
+    last_attempt: RwLock<Option<Instant>>,
+}
+
+impl JwksCache {
+    /// Empty cache, keys are fetched on first use or with `refresh`.
+    #[must_use]
+    pub fn new(url: String, ttl: Duration, http_client: reqwest::Client) -> Self {
+        Self {
+            url,
+            ttl,
+            min_refresh_interval: DEFAULT_MIN_REFRESH_INTERVAL,
+            http_client,
+            cached: RwLock::new(None),
+            last_attempt: RwLock::new(None),
+        }
+    }
+
+    /// Override the minimum delay between refreshes triggered by requests.
+    #[must_use]
+    pub const fn with_min_refresh_interval(mut self, interval: Duration) -> Self {
+        self.min_refresh_interval = interval;
+        self
+    }
+
+    /// Fetch the key set and replace the cached one, the cache is kept on failure.
+    ///
+    /// Returns whether the fetch succeeded.
+    pub async fn refresh(&self) -> bool {
+        *self.last_attempt.write().await = Some(Instant::now());
+        let fetched = async {
+            self.http_client
+                .get(&self.url)
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<JwkSet>()
+                .await
+        }
+        .await;
+        match fetched {
+            Ok(keys) => {
+                info!(url = %self.url, keys = keys.keys.len(), "JWKS fetched");
+                *self.cached.write().await = Some(CachedKeys {
+                    keys,
+                    fetched_at: Instant::now(),
+                });
+                true
+            }
+            Err(e) => {
+                warn!(url = %self.url, "Failed to fetch JWKS, keeping cached keys: {e}");
+                false
+            }
+        }
+    }
+
+    /// Decoding key for `kid`, refreshing the key set if it expired or doesn't know `kid`.
+    pub async fn decoding_key(&self, kid: &str) -> Option<DecodingKey> {
+        let (key, expired) = self.cached_key(kid).await;
+        if key.is_some() && !expired {
+            return key;
+        }
+        if self.may_refresh().await {
+            debug!(kid, expired, "Refreshing JWKS");
+            if self.refresh().await {
+                return self.cached_key(kid).await.0;
+            }
+        }
+        key
+    }
+
+    /// Key for `kid` in the cache, and whether the cache is past its TTL.
+    async fn cached_key(&self, kid: &str) -> (Option<DecodingKey>, bool) {
+        let cached = self.cached.read().await;
+        let Some(cached) = cached.as_ref() else {
+            return (None, true);
+        };
+        let key = cached.keys.find(kid).and_then(|jwk| {
+            DecodingKey::from_jwk(jwk)
+                .map_err(|e| warn!(kid, "Unusable JWKS entry: {e}"))
+                .ok()
+        });
+        (key, cached.fetched_at.elapsed() >= self.ttl)
+    }
+
+    async fn may_refresh(&self) -> bool {
+        self.last_attempt
+            .read()
+            .await
+            .is_none_or(|at| at.elapsed() >= self.min_refresh_interval)
+    }
+}
@@ -0,0 +1,227 @@
+//! Prometheus counters for the channel handler
+//!
+//! Plain atomic counters rendered in the text exposition format, the handful of series
+//! doesn't warrant a metrics library.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, PoisonError};
+
+use super::error::ChannelError;
+
+/// Region label of requests whose SFU serves no region
+const NO_REGION: &str = "none";
+
+#[derive(Debug)]
+pub struct Metrics {
+    channel_requests: AtomicU64,
+    /// Requests by region of the first selected SFU
+    region_requests: Mutex<BTreeMap<String, u64>>,
+    auth_missing: AtomicU64,
+    auth_invalid: AtomicU64,
+    forward_successes: AtomicU64,
+    /// Forward failures by class: `4xx`, `5xx`, or `none` when there was no usable response
+    forward_failures: [AtomicU64; 3],
+}
+
+const FAILURE_CLASSES: [&str; 3] = ["4xx", "5xx", "none"];
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Metrics {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            channel_requests: AtomicU64::new(0),
+            region_requests: Mutex::new(BTreeMap::new()),
+            auth_missing: AtomicU64::new(0),
+            auth_invalid: AtomicU64::new(0),
+            forward_successes: AtomicU64::new(0),
+            forward_failures: [AtomicU64::new(0), AtomicU64::new(0), AtomicU64::new(0)],
+        }
+    }
+
+    pub fn channel_request(&self) {
+        self.channel_requests.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count a request for the region it was routed to, `None` for SFUs without region.
+    pub fn region_request(&self, region: Option<&str>) {
+        let region = region.unwrap_or(NO_REGION);
+        let mut regions = self
+            .region_requests
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        match regions.get_mut(region) {
+            Some(count) => *count += 1,
+            None => {
+                regions.insert(region.to_string(), 1);
+            }
+        }
+    }
+
+    /// Count an authentication failure, errors other than auth ones are ignored.
+    pub fn auth_failure(&self, error: &ChannelError) {
+        let counter = match error {
+            ChannelError::MissingAuth => &self.auth_missing,
+            ChannelError::InvalidToken | ChannelError::InvalidApiKey => &self.auth_invalid,
+            _ => return,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count the outcome of forwarding to one SFU, gateway-side errors are ignored.
+    pub fn forward_result<T>(&self, result: &Result<T, ChannelError>) {
+        let class = match result {
+            Ok(_) => {
+                self.forward_successes.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+            Err(ChannelError::UpstreamStatus { status, .. }) if status.is_client_error() => 0,
+            Err(ChannelError::UpstreamStatus { .. }) => 1,
+            Err(
+                ChannelError::UpstreamUnreachable
+                | ChannelError::UpstreamInterrupted
+                | ChannelError::UpstreamTimeout
+                | ChannelError::InvalidUpstreamResponse,
+            ) => 2,
+            Err(_) => return,
+        };
+        self.forward_failures[class].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counters in the Prometheus text exposition format.
+    #[must_use]
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let mut counter = |name: &str, help: &str, samples: &[(Option<(&str, &str)>, u64)]| {
+            let _ = writeln!(out, "# HELP {name} {help}");
+            let _ = writeln!(out, "# TYPE {name} counter");
+            for (label, value) in samples {
+                match label {
+                    Some((key, label)) => {
+                        let _ = writeln!(out, "{name}{{{key}=\"{}\"}} {value}", escape(label));
+                    }
+                    None => {
+                        let _ = writeln!(out, "{name} {value}");
+                    }
+                }
+            }
+        };
+
+        counter(
+            "sfu_gateway_channel_requests_total",
+            "Channel requests received.",
+            &[(None, load(&self.channel_requests))],
+        );
+        let regions = self
+            .region_requests
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        let region_samples: Vec<_> = regions
+            .iter()
+            .map(|(region, count)| (Some(("region", region.as_str())), *count))
+            .collect();
+        counter(
+            "sfu_gateway_region_requests_total",
+            "Channel requests by region of the selected SFU.",
+            &region_samples,
+        );
+        counter(
+            "sfu_gateway_auth_failures_total",
+            "Channel requests rejected by authentication.",
+            &[
+                (Some(("reason", "missing")), load(&self.auth_missing)),
+                (Some(("reason", "invalid")), load(&self.auth_invalid)),
+            ],
+        );
+        counter(
+            "sfu_gateway_forward_successes_total",
+            "Channel requests successfully forwarded to an SFU.",
+            &[(None, load(&self.forward_successes))],
+        );
+        let failure_samples: Vec<_> = FAILURE_CLASSES
+            .iter()
+            .zip(&self.forward_failures)
+            .map(|(class, count)| (Some(("class", *class)), load(count)))
+            .collect();
+        counter(
+            "sfu_gateway_forward_failures_total",
+            "Failed forwards to an SFU, by response status class.",
+            &failure_samples,
+        );
+        out
+    }
+}
+
+/// Content type of the text exposition format
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+fn load(counter: &AtomicU64) -> u64 {
+    counter.load(Ordering::Relaxed)
+}
+
+/// Escape a label value, regions come from the configuration.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+
+    #[test]
+    fn test_render_counters() {
+        let metrics = Metrics::new();
+        metrics.channel_request();
+        metrics.channel_request();
+        metrics.region_request(Some("eu-west"));
+        metrics.region_request(None);
+        metrics.auth_failure(&ChannelError::MissingAuth);
+        metrics.auth_failure(&ChannelError::InvalidApiKey);
+        metrics.auth_failure(&ChannelError::NoSfu);
+        metrics.forward_result::<()>(&Ok(()));
+        metrics.forward_result::<()>(&Err(ChannelError::UpstreamStatus {
+            status: StatusCode::FORBIDDEN,
+            content_type: None,
+            content_encoding: None,
+            body: actix_web::web::Bytes::new(),
+        }));
+        metrics.forward_result::<()>(&Err(ChannelError::UpstreamUnreachable));
+        metrics.forward_result::<()>(&Err(ChannelError::Internal));
+
+        let rendered = metrics.render();
+        for line in [
+            "# TYPE sfu_gateway_channel_requests_total counter",
+            "sfu_gateway_channel_requests_total 2",
+            "sfu_gateway_region_requests_total{region=\"eu-west\"} 1",
+            "sfu_gateway_region_requests_total{region=\"none\"} 1",
+            "sfu_gateway_auth_failures_total{reason=\"missing\"} 1",
+            "sfu_gateway_auth_failures_total{reason=\"invalid\"} 1",
+            "sfu_gateway_forward_successes_total 1",
+            "sfu_gateway_forward_failures_total{class=\"4xx\"} 1",
+            "sfu_gateway_forward_failures_total{class=\"5xx\"} 0",
+            "sfu_gateway_forward_failures_total{class=\"none\"} 1",
+        ] {
+            assert!(
+                rendered.lines().any(|l| l == line),
+                "missing {line}:\n{rendered}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_escape_label_value() {
+        assert_eq!(escape(r#"a"b\c"#), r#"a\"b\\c"#);
+    }
+}
@@ -1,5 +1,28 @@
+mod admin;
 mod auth;
+mod cors;
+mod error;
+mod forwarded;
+mod jwks;
+mod metrics;
+mod proxy;
+mod rate_limit;
+mod request_id;
 mod server;
 
-pub use auth::{AuthError, Claims, extract_token, sign, verify};
-pub use server::{AppState, ChannelQuery, ChannelResponse, channel, create_server, noop};
+pub use admin::{DrainRequest, DrainResponse, ReloadResponse, drain, reload, undrain};
+pub use auth::{
+    AuthError, Claims, VerifyOptions, constant_time_eq, extract_token, rs256_kid, sign,
+    sign_with_header, verify, verify_any, verify_any_at, verify_at, verify_rs256,
+};
+pub use cors::cors;
+pub use error::{ChannelError, ErrorCode};
+pub use jwks::JwksCache;
+pub use metrics::Metrics;
+pub use proxy::proxy;
+pub use rate_limit::RateLimiter;
+pub use server::{
+    AppState, ChannelQuery, ChannelResponse, GeoRegion, GeoResponse, SelectResponse, SfuStatus,
+    StatusResponse, channel, create_server, geo, healthz, metrics, noop, readyz, select_preview,
+    status,
+};
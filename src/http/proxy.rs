@@ -0,0 +1,140 @@
+//! Proxy of the SFU endpoints other than `/v1/channel`
+//!
+//! `/v1/{tail}` is forwarded to `/v1/{tail}` on an SFU selected like for a channel, with the
+//! method, query string and body of the request, and the JWT re-signed with the SFU's key.
+//! The SFU response is passed through as received.
+//!
+//! Unlike channel requests, proxied requests are sent to a single SFU: they may not be
+//! idempotent, so a failure is not retried on another one.
+
+use std::sync::Arc;
+
+use actix_web::web::Bytes;
+use actix_web::{HttpRequest, HttpResponse, web};
+use tracing::{info, warn};
+
+use super::error::ChannelError;
+use super::forwarded;
+use super::server::{
+    AppState, ChannelQuery, Forward, authenticate, check_input_sizes, check_rate_limit,
+    filter_query_params, handle, is_well_formed_query, read_upstream, record_access, region_hints,
+    send_to_sfu, sfu_request,
+};
+
+/// `/v1` paths served by the gateway itself, never proxied whatever the method
+const GATEWAY_PATHS: &[&str] = &["channel", "geo", "select", "status"];
+
+/// Forward `/v1/{tail}` to the same path on a selected SFU
+///
+/// Registered after the gateway's own `/v1` routes, which take precedence. Those reached
+/// with another method are answered with 405 rather than proxied.
+///
+/// # Errors
+/// Returns a `ChannelError`, rendered by actix, at the first failing step.
+pub async fn proxy(
+    req: HttpRequest,
+    tail: web::Path<String>,
+    query: web::Query<ChannelQuery>,
+    body: Result<Bytes, actix_web::Error>,
+    state: web::Data<Arc<AppState>>,
+) -> Result<HttpResponse, ChannelError> {
+    let (req, tail, query, state) = (&req, tail.as_str(), &*query, &**state);
+    Ok(handle(req, state, "proxy", |request_id| async move {
+        let body = body.map_err(|e| ChannelError::from_payload(&e))?;
+        forward(req, tail, query, body, state, &request_id).await
+    })
+    .await)
+}
+
+async fn forward(
+    req: &HttpRequest,
+    tail: &str,
+    query: &ChannelQuery,
+    body: Bytes,
+    state: &AppState,
+    request_id: &str,
+) -> Result<HttpResponse, ChannelError> {
+    if GATEWAY_PATHS.contains(&tail) {
+        return Ok(HttpResponse::MethodNotAllowed().finish());
+    }
+    if !is_valid_tail(tail) {
+        warn!(path = %req.path(), "Invalid proxied path");
+        return Err(ChannelError::InvalidPath);
+    }
+    check_input_sizes(req.headers(), req.query_string(), state.max_query_bytes)?;
+    if !is_well_formed_query(req.query_string()) {
+        warn!(
+            query = %filter_query_params(req.query_string(), state.token_query_param.as_deref()),
+            "Malformed query string"
+        );
+        return Err(ChannelError::MalformedQuery);
+    }
+    let (claims, authenticated_by_api_key) = authenticate(req, state)
+        .await
+        .inspect_err(|e| state.metrics.auth_failure(e))?;
+    info!(iss = %claims.iss, api_key = authenticated_by_api_key, "Authenticated request");
+    check_rate_limit(state, &claims)?;
+
+    let forwarded_for = forwarded::for_request(req, state.trust_proxy);
+    if let Some(client_ip) = forwarded::client_ip(&forwarded_for) {
+        record_access("client_ip", client_ip);
+    }
+    let region_hints = region_hints(state, query, authenticated_by_api_key, &forwarded_for);
+
+    let balancer = state.balancer();
+    let sfu = balancer
+        .select_for_issuer(&region_hints, &claims.iss)
+        .map_err(ChannelError::from)?;
+    info!(sfu_address = %sfu.address, method = %req.method(), path = %req.path(), "Proxying to SFU");
+    record_access("sfu", &sfu.address);
+
+    let forward = Forward::new(req, state, &claims, forwarded_for, request_id, body)?;
+    let request = sfu_request(state, sfu, &format!("/v1/{tail}"), &forward)?;
+
+    let result = send_to_sfu(sfu, request, state.max_body_bytes).await;
+    state.metrics.forward_result(&result);
+    Ok(read_upstream(result?, state.max_body_bytes)
+        .await?
+        .into_http_response())
+}
+
+/// Whether `tail` is a plain sub-path: non-empty segments of unreserved characters, without
+/// `.` or `..` that could climb out of `/v1` on the SFU.
+/// Pure function for testability.
+fn is_valid_tail(tail: &str) -> bool {
+    tail.split('/').all(|segment| {
+        !matches!(segment, "" | "." | "..")
+            && segment
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b'~'))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_valid_tails() {
+        for tail in ["stats", "rooms/42", "a.b/c-d_e~f"] {
+            assert!(is_valid_tail(tail), "{tail}");
+        }
+    }
+
+    #[test]
+    fn test_invalid_tails() {
+        for tail in [
+            "",
+            "stats/",
+            "a//b",
+            "../secret",
+            "a/./b",
+            "a/..",
+            "a b",
+            "a%2Fb",
+            "a?b",
+        ] {
+            assert!(!is_valid_tail(tail), "{tail}");
+        }
+    }
+}
@@ -0,0 +1,137 @@
+//! Per-issuer rate limiting of the requests forwarded to SFUs
+//!
+//! Each issuer (JWT `iss`, the channel) gets a token bucket holding a minute's worth of
+//! requests, refilled continuously. A full bucket is the same as no bucket, so the sweeper
+//! drops the buckets left idle long enough to refill, see `crate::sweep`.
+
+use std::collections::HashMap;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::sweep::Sweep;
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+#[derive(Debug)]
+pub struct RateLimiter {
+    /// Bucket size, and requests refilled per minute
+    per_minute: u32,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl RateLimiter {
+    /// Limiter allowing `per_minute` requests per issuer, in bursts of up to as many.
+    #[must_use]
+    pub fn new(per_minute: u32) -> Self {
+        Self {
+            per_minute,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Take a request from `issuer`'s bucket at `now`.
+    ///
+    /// # Errors
+    /// When the bucket is empty, returns how long until it holds a request again.
+    pub fn check(&self, issuer: &str, now: Instant) -> Result<(), Duration> {
+        let capacity = f64::from(self.per_minute);
+        let per_sec = capacity / 60.0;
+        let mut buckets = self.buckets.lock().unwrap_or_else(PoisonError::into_inner);
+        let bucket = buckets.entry(issuer.to_string()).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * per_sec).min(capacity);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / per_sec))
+        }
+    }
+
+    /// Issuers with a bucket not refilled yet
+    pub fn len(&self) -> usize {
+        self.buckets
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Sweep for RateLimiter {
+    /// Buckets are only evicted once refilled as well, a bucket still refilling would hand out
+    /// a fresh burst when recreated.
+    fn sweep(&self, now: Instant, ttl: Duration) -> usize {
+        let capacity = f64::from(self.per_minute);
+        let per_sec = capacity / 60.0;
+        let mut buckets = self.buckets.lock().unwrap_or_else(PoisonError::into_inner);
+        let before = buckets.len();
+        buckets.retain(|_, bucket| {
+            let idle = now.saturating_duration_since(bucket.updated);
+            idle < ttl || bucket.tokens + idle.as_secs_f64() * per_sec < capacity
+        });
+        before - buckets.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_over_the_limit_rejected() {
+        let limiter = RateLimiter::new(3);
+        let now = Instant::now();
+        for _ in 0..3 {
+            assert!(limiter.check("channel-1", now).is_ok());
+        }
+        // one request is refilled every 20 seconds
+        let retry_after = limiter.check("channel-1", now).unwrap_err();
+        assert_eq!(retry_after.as_secs(), 20);
+        assert!(
+            limiter
+                .check("channel-1", now + Duration::from_secs(10))
+                .is_err()
+        );
+        assert!(
+            limiter
+                .check("channel-1", now + Duration::from_secs(20))
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_issuers_have_their_own_bucket() {
+        let limiter = RateLimiter::new(1);
+        let now = Instant::now();
+        assert!(limiter.check("channel-1", now).is_ok());
+        assert!(limiter.check("channel-1", now).is_err());
+        assert!(limiter.check("channel-2", now).is_ok());
+    }
+
+    #[test]
+    fn test_refilled_buckets_swept() {
+        let limiter = RateLimiter::new(1);
+        let now = Instant::now();
+        let ttl = Duration::from_secs(10);
+        assert!(limiter.check("idle", now).is_ok());
+        assert_eq!(limiter.len(), 1);
+
+        // idle for longer than the TTL, but still refilling
+        assert_eq!(limiter.sweep(now + Duration::from_secs(30), ttl), 0);
+        // a minute later, "idle" is full again and dropped
+        assert_eq!(limiter.sweep(now + Duration::from_secs(61), ttl), 1);
+        assert!(limiter.is_empty());
+    }
+}
@@ -0,0 +1,59 @@
+//! `X-Request-Id` correlation of a channel request across Odoo, the gateway and the SFU
+//!
+//! The id received from the caller is kept as is, so that it can be searched for in the
+//! logs of every hop. Without one, the gateway generates a UUID v4.
+
+use actix_web::HttpRequest;
+
+/// Header carrying the request id, on the incoming request, to the SFU and on the response
+pub const HEADER: &str = "X-Request-Id";
+
+/// Longest incoming id kept, longer ones are replaced rather than copied in every log line.
+const MAX_LEN: usize = 128;
+
+/// Request id to use, `existing` when it is a usable id, a new UUID v4 otherwise.
+///
+/// A usable id is non-empty, at most `MAX_LEN` long and made of visible ASCII only.
+pub(crate) fn request_id(existing: Option<&str>) -> String {
+    existing
+        .filter(|id| {
+            !id.is_empty() && id.len() <= MAX_LEN && id.bytes().all(|b| b.is_ascii_graphic())
+        })
+        .map_or_else(|| uuid::Uuid::new_v4().to_string(), str::to_string)
+}
+
+/// Request id for this request, see `request_id`. A header that isn't valid UTF-8 is ignored.
+pub(crate) fn for_request(req: &HttpRequest) -> String {
+    request_id(req.headers().get(HEADER).and_then(|h| h.to_str().ok()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_existing_id_kept_verbatim() {
+        for id in [
+            "abc-123",
+            "0f8fad5b-d9cb-469f-a165-70867728950e",
+            "odoo:42/7",
+        ] {
+            assert_eq!(request_id(Some(id)), id);
+        }
+    }
+
+    #[test]
+    fn test_unusable_id_replaced() {
+        let too_long = "a".repeat(MAX_LEN + 1);
+        for id in [None, Some(""), Some("with space"), Some(too_long.as_str())] {
+            let generated = request_id(id);
+            let uuid = uuid::Uuid::parse_str(&generated).unwrap();
+            assert_eq!(uuid.get_version_num(), 4, "{id:?}");
+        }
+    }
+
+    #[test]
+    fn test_generated_ids_differ() {
+        assert_ne!(request_id(None), request_id(None));
+    }
+}
@@ -1,29 +1,335 @@
-use std::sync::Arc;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::{Arc, PoisonError, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use actix_web::{App, HttpRequest, HttpResponse, HttpServer, web};
+use actix_web::http::StatusCode;
+use actix_web::http::header::{
+    AUTHORIZATION, CONNECTION, CONTENT_ENCODING, CONTENT_TYPE, HeaderMap, HeaderName, HeaderValue,
+};
+use actix_web::middleware::Condition;
+use actix_web::web::{Bytes, BytesMut};
+use actix_web::{App, HttpRequest, HttpResponse, HttpServer, ResponseError, web};
 use serde::{Deserialize, Serialize};
-use tracing::{debug, info, warn};
+use tokio::task::JoinSet;
+use tracing::field::Empty;
+use tracing::{Instrument, debug, info, info_span, warn};
+use url::form_urlencoded;
 
-use super::auth::{extract_token, sign, verify};
-use crate::routing::Balancer;
-use crate::routing::country_to_region;
+use super::admin::{drain, reload, undrain};
+use super::auth::{
+    Claims, VerifyOptions, constant_time_eq, extract_token, rs256_kid, sign_with_header,
+    verify_any, verify_rs256,
+};
+use super::cors;
+use super::error::ChannelError;
+use super::forwarded;
+use super::jwks::JwksCache;
+use super::metrics::{self, Metrics};
+use super::proxy::proxy;
+use super::rate_limit::RateLimiter;
+use super::request_id;
+use crate::config::{
+    ApiKeyConfig, DEFAULT_FORWARD_HEADERS, DEFAULT_MAX_BODY_BYTES, DEFAULT_MAX_QUERY_BYTES,
+    DEFAULT_SFU_URL_SCHEMES, GeoConfig, HealthCheckMode, NodeData, SfuConfig, load_gateway_keys,
+};
+use crate::routing::{Balancer, GeoIp, SfuInstance, probe, sfu_url};
+use crate::routing::{country_region_mapping, country_to_region, is_known_region, known_regions};
+use crate::shutdown::InFlight;
+use crate::sweep::Sweep;
+use crate::telemetry;
 
+/// Lifetime of the claims synthesized for API key requests, the SFU sees them as a fresh JWT
+const API_KEY_CLAIMS_TTL_SECS: u64 = 60;
+
+/// Longest Authorization header accepted, a JWT is well below
+const MAX_AUTHORIZATION_BYTES: usize = 8 * 1024;
+
+/// Timeout of the probes sent by `/v1/status` when there are no background health checks
+const STATUS_PROBE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Bytes of an unparsable SFU response body logged, enough to recognize a proxy's error page
+const BODY_SNIPPET_BYTES: usize = 256;
+
+#[allow(clippy::struct_excessive_bools)] // independent switches of the handlers
 pub struct AppState {
-    pub balancer: Balancer,
+    /// Selection over the current SFU set, replaced as a whole when the set is reloaded
+    pub balancer: RwLock<Arc<Balancer>>,
     pub http_client: reqwest::Client,
-    /// Gateway's JWT secret key for verifying tokens from Odoo (decoded bytes)
-    pub gateway_key: Vec<u8>,
+    /// Gateway's JWT secret keys for verifying tokens from Odoo (decoded bytes), tried in order,
+    /// swapped on key reload
+    pub gateway_keys: RwLock<Vec<Vec<u8>>>,
     /// When true, trust X-Forwarded-For header from upstream proxy
     pub trust_proxy: bool,
+    /// Static API key accepted in `X-Api-Key` as an alternative to JWT (opt-in)
+    pub api_key: Option<ApiKeyConfig>,
+    /// Static headers sent on every request to any SFU, per-SFU headers take precedence
+    pub static_headers: Vec<(String, String)>,
+    /// Region hint for requests that don't provide one, see `GatewayConfig::hintless_region`
+    pub default_region: Option<String>,
+    /// Upper bound on the whole handling of a channel request, none when `None`
+    pub request_deadline: Option<Duration>,
+    /// Query parameter holding the JWT when the Authorization header is absent (opt-in)
+    pub token_query_param: Option<String>,
+    /// Public keys for RS256 tokens, HS256 tokens keep using `gateway_keys` (opt-in)
+    pub jwks: Option<JwksCache>,
+    /// SFUs tried for a channel request before giving up, each attempt on a different SFU
+    pub max_attempts: u32,
+    /// Counters exposed on `/metrics`
+    pub metrics: Metrics,
+    /// Checks of the time claims of incoming JWTs
+    pub verify_options: VerifyOptions,
+    /// Client IP to country database, for requests without region nor country (opt-in)
+    pub geoip: Option<GeoIp>,
+    /// Channel requests being handled, drained on shutdown
+    pub in_flight: InFlight,
+    /// Client headers forwarded to the SFU, lowercase names or prefixes ending with `*`
+    pub forward_headers: Vec<String>,
+    /// Per-issuer request rate limit (opt-in)
+    pub rate_limiter: Option<RateLimiter>,
+    /// Schemes the channel URL returned by an SFU may use, lowercase
+    pub sfu_url_schemes: Vec<String>,
+    /// Schemes accepted in the Authorization header, lowercase, any when empty
+    pub auth_schemes: Vec<String>,
+    /// Serve the unauthenticated debugging endpoints, 404 otherwise
+    pub debug_endpoints: bool,
+    /// Longest query string accepted on the forwarding endpoints, in bytes
+    pub max_query_bytes: usize,
+    /// Largest request body accepted, and SFU response body read
+    pub max_body_bytes: usize,
+    /// Send `SfuInstance::address_hash` in `X-SFU-Address` rather than the address
+    pub redact_sfu_address: bool,
+    /// Reject channel requests whose `region` or `country` isn't known, instead of ignoring it
+    pub strict_region: bool,
+    /// Origins allowed to call the channel routes from a browser, `*` for any, CORS is disabled
+    /// when empty
+    pub cors_origins: Vec<String>,
+    /// Key of the `/admin` endpoints in `X-Admin-Key`, they answer 404 when `None`
+    pub admin_key: Option<String>,
+    /// How long `/v1/status` reuses the last probe of an SFU rather than probing it again
+    pub status_probe_ttl: Duration,
+    /// How `/v1/status` probes SFUs that don't set their own `health_check`
+    pub health_check_mode: HealthCheckMode,
+    /// Secrets file re-read by `reload_secrets`, `None` when the SFUs don't come from a file
+    pub secrets_file: Option<PathBuf>,
+    /// Gateway keys file re-read by `reload_key_files`, `None` when the keys don't come from a
+    /// file
+    pub gateway_key_file: Option<PathBuf>,
+    /// Scheme given to SFU addresses without one when reloading, see `NodeData::load_with_default_scheme`
+    pub default_scheme: Option<String>,
+    /// `[geo]` table installed at startup, a reload changing it is warned about as it can't be
+    /// applied
+    pub geo_overrides: GeoConfig,
+    /// Reject a reload whose SFU keys are also gateway keys instead of warning, as at startup
+    pub strict_key_check: bool,
+}
+
+impl AppState {
+    /// State with all optional behaviors disabled, the default of two attempts, and the default
+    /// forwarded headers and SFU URL schemes.
+    #[must_use]
+    pub fn new(balancer: Balancer, http_client: reqwest::Client, gateway_key: Vec<u8>) -> Self {
+        Self {
+            balancer: RwLock::new(Arc::new(balancer)),
+            http_client,
+            gateway_keys: RwLock::new(vec![gateway_key]),
+            trust_proxy: false,
+            api_key: None,
+            static_headers: Vec::new(),
+            default_region: None,
+            request_deadline: None,
+            token_query_param: None,
+            jwks: None,
+            max_attempts: 2,
+            metrics: Metrics::new(),
+            verify_options: VerifyOptions::DEFAULT,
+            geoip: None,
+            in_flight: InFlight::default(),
+            forward_headers: DEFAULT_FORWARD_HEADERS
+                .iter()
+                .map(|h| (*h).to_string())
+                .collect(),
+            rate_limiter: None,
+            auth_schemes: Vec::new(),
+            sfu_url_schemes: DEFAULT_SFU_URL_SCHEMES
+                .iter()
+                .map(|s| (*s).to_string())
+                .collect(),
+            debug_endpoints: false,
+            max_query_bytes: DEFAULT_MAX_QUERY_BYTES,
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+            redact_sfu_address: false,
+            strict_region: false,
+            cors_origins: Vec::new(),
+            admin_key: None,
+            status_probe_ttl: Duration::from_secs(1),
+            health_check_mode: HealthCheckMode::default(),
+            secrets_file: None,
+            gateway_key_file: None,
+            default_scheme: None,
+            geo_overrides: GeoConfig::default(),
+            strict_key_check: false,
+        }
+    }
+
+    /// Rotate the gateway keys and the keys of the known SFUs, without touching the topology.
+    pub fn reload_keys(&self, gateway_keys: Vec<Vec<u8>>, sfu_configs: &[SfuConfig]) {
+        let mut current = self
+            .gateway_keys
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        let rotated = current.len() != gateway_keys.len()
+            || current
+                .iter()
+                .zip(&gateway_keys)
+                .any(|(old, new)| !constant_time_eq(old, new));
+        if rotated {
+            *current = gateway_keys;
+            info!("Gateway keys rotated");
+        }
+        drop(current);
+        let changed = self.balancer().reload_keys(sfu_configs);
+        info!(sfu_keys_changed = changed, "Keys reloaded");
+    }
+
+    /// Re-read `gateway_key_file` and `secrets_file`, whichever are set, and swap the keys they
+    /// hold in place, see `reload_keys`.
+    ///
+    /// # Errors
+    /// Returns why the keys can't be reloaded: no file to re-read or a file failing to load. The
+    /// current keys are all kept then.
+    pub fn reload_key_files(&self) -> Result<(), String> {
+        if self.gateway_key_file.is_none() && self.secrets_file.is_none() {
+            return Err("the keys don't come from a file".to_string());
+        }
+        let gateway_keys = match &self.gateway_key_file {
+            Some(path) => load_gateway_keys(path).map_err(|e| e.to_string())?,
+            None => self
+                .gateway_keys
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clone(),
+        };
+        let nodes = self
+            .secrets_file
+            .as_ref()
+            .map(|path| NodeData::load_with_default_scheme(path, self.default_scheme.as_deref()))
+            .transpose()
+            .map_err(|e| e.to_string())?;
+        if let Some(nodes) = &nodes {
+            self.check_key_isolation(nodes, &gateway_keys)?;
+        }
+        let sfu_configs = nodes.map_or_else(Vec::new, |nodes| nodes.sfu);
+        self.reload_keys(gateway_keys, &sfu_configs);
+        Ok(())
+    }
+
+    /// Current balancer, requests in flight keep the one they started with across reloads.
+    pub fn balancer(&self) -> Arc<Balancer> {
+        Arc::clone(&self.balancer.read().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    /// Replace the SFU set, see `Balancer::reconfigure` for what carries over.
+    pub fn reload_sfus(&self, sfu_configs: Vec<SfuConfig>) {
+        let mut current = self
+            .balancer
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        let count = sfu_configs.len();
+        *current = Arc::new(current.reconfigure(sfu_configs));
+        drop(current);
+        info!(sfu_count = count, "SFU set reloaded");
+    }
+
+    /// Re-read `secrets_file` and replace the SFU set with its SFUs, returns their count.
+    ///
+    /// # Errors
+    /// Returns why the file can't be used: none configured, failing to load or without SFUs.
+    /// The current SFUs are kept then.
+    pub fn reload_secrets(&self) -> Result<usize, String> {
+        let path = self
+            .secrets_file
+            .as_ref()
+            .ok_or("the SFUs don't come from a secrets file")?;
+        let nodes = NodeData::load_with_default_scheme(path, self.default_scheme.as_deref())
+            .map_err(|e| e.to_string())?;
+        self.reload_nodes(nodes)
+    }
+
+    /// Replace the SFU set with the SFUs of reloaded `nodes`, returns their count. Changes to the
+    /// global headers and geo table are warned about, they only apply at startup.
+    ///
+    /// # Errors
+    /// Returns why `nodes` can't be used: without SFUs, or an SFU key also a gateway key with
+    /// `strict_key_check`. The current SFUs are kept then.
+    pub fn reload_nodes(&self, nodes: NodeData) -> Result<usize, String> {
+        if nodes.sfu.is_empty() {
+            return Err("no SFU in the secrets file".to_string());
+        }
+        let gateway_keys = self
+            .gateway_keys
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        self.check_key_isolation(&nodes, &gateway_keys)?;
+        if nodes.headers != self.static_headers {
+            warn!("Global headers changed, they only apply after a restart");
+        }
+        if nodes.geo != self.geo_overrides {
+            warn!("Geo table changed, it only applies after a restart");
+        }
+        let count = nodes.sfu.len();
+        self.reload_sfus(nodes.sfu);
+        Ok(count)
+    }
+
+    /// Key isolation of reloaded `nodes`, warned about and only an error with `strict_key_check`,
+    /// as at startup.
+    fn check_key_isolation(
+        &self,
+        nodes: &NodeData,
+        gateway_keys: &[Vec<u8>],
+    ) -> Result<(), String> {
+        match nodes.check_key_isolation(gateway_keys) {
+            Err(e) if self.strict_key_check => Err(e.to_string()),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Sweep for AppState {
+    /// Sweeps the rate limiter and the issuer affinity.
+    fn sweep(&self, now: Instant, ttl: Duration) -> usize {
+        self.rate_limiter.sweep(now, ttl) + self.balancer().sweep(now, ttl)
+    }
 }
 
 /// Query parameters for /v1/channel (gateway-specific only)
 #[derive(Debug, Deserialize)]
 pub struct ChannelQuery {
-    /// Region hint for load balancing (not forwarded to SFU)
-    pub region: Option<String>,
+    /// Region hints for load balancing, first choice first, from a comma-separated `region`
+    /// such as `eu-west,eu-central` (not forwarded to SFU)
+    #[serde(rename = "region", default, deserialize_with = "comma_separated")]
+    pub regions: Vec<String>,
     /// ISO 3166-1 alpha-2 country code, converted to region hint if region is not provided
     pub country: Option<String>,
+    /// SFU to use instead of selecting one, by address or index, only honored with the debug
+    /// endpoints enabled (not forwarded to SFU)
+    #[serde(rename = "__sfu")]
+    pub sfu: Option<String>,
+}
+
+/// The non-empty entries of a comma-separated list, trimmed, in order.
+fn comma_separated<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<String>, D::Error> {
+    let list = String::deserialize(deserializer)?;
+    Ok(list
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(str::to_string)
+        .collect())
 }
 
 /// Response from SFU /v1/channel endpoint
@@ -33,218 +339,1327 @@ pub struct ChannelResponse {
     pub url: String,
 }
 
-/// Build X-Forwarded-For header value by appending new IP to existing chain.
-/// Per RFC 7239, each proxy appends the IP of the immediate client it received from.
+/// Merge global and per-SFU static headers, per-SFU values win on name conflicts.
 /// Pure function for testability.
-fn build_forwarded_for(existing: Option<&str>, new_ip: &str) -> String {
-    existing.map_or_else(|| new_ip.to_string(), |chain| format!("{chain}, {new_ip}"))
+fn merge_static_headers<'a>(
+    global: &'a [(String, String)],
+    per_sfu: &'a [(String, String)],
+) -> Vec<(&'a str, &'a str)> {
+    global
+        .iter()
+        .filter(|(name, _)| !per_sfu.iter().any(|(n, _)| n.eq_ignore_ascii_case(name)))
+        .chain(per_sfu)
+        .map(|(name, value)| (name.as_str(), value.as_str()))
+        .collect()
 }
 
-/// Build X-Forwarded-For header for the downstream SFU.
-///
-/// When `trust_proxy` is true, the gateway is behind a trusted reverse proxy.
-/// We trust the existing X-Forwarded-For header and append our direct peer IP
-/// (the proxy's IP) to maintain the complete chain.
-///
-/// When `trust_proxy` is false, the gateway is directly exposed to clients.
-/// Any existing X-Forwarded-For header is untrusted (could be spoofed), so we
-/// ignore it and use only our direct peer IP as the client.
-fn get_forwarded_for(req: &HttpRequest, trust_proxy: bool) -> String {
-    let peer_ip = req
-        .connection_info()
-        .realip_remote_addr()
-        .unwrap_or("unknown")
-        .to_string();
-
-    if trust_proxy {
-        let existing = req
-            .headers()
-            .get("X-Forwarded-For")
-            .and_then(|h| h.to_str().ok());
-        build_forwarded_for(existing, &peer_ip)
-    } else {
-        peer_ip
-    }
+/// Hop-by-hop headers, they describe the client's connection to the gateway, not the request
+const HOP_BY_HOP_HEADERS: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "proxy-connection",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// Client headers the gateway consumes or sets itself, whatever the allowlist says
+const GATEWAY_HEADERS: &[&str] = &[
+    "authorization",
+    "x-api-key",
+    "x-forwarded-for",
+    "x-request-id",
+    "host",
+    "content-length",
+    "content-type",
+    // the gateway's HTTP client advertises the encodings it decompresses
+    "accept-encoding",
+];
+
+/// Client headers matching the allowlist (names, or prefixes ending with `*`), except hop-by-hop
+/// headers, those named in `Connection`, and those the gateway handles itself.
+/// Pure function for testability.
+pub(crate) fn filter_headers<'a>(
+    headers: &'a HeaderMap,
+    allowlist: &[String],
+) -> Vec<(&'a HeaderName, &'a HeaderValue)> {
+    let connection_listed: Vec<String> = headers
+        .get_all(CONNECTION)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|name| name.trim().to_ascii_lowercase())
+        .collect();
+    headers
+        .iter()
+        .filter(|(name, _)| {
+            let name = name.as_str();
+            !HOP_BY_HOP_HEADERS.contains(&name)
+                && !GATEWAY_HEADERS.contains(&name)
+                && !connection_listed.iter().any(|listed| listed == name)
+                && allowlist.iter().any(|allowed| {
+                    allowed
+                        .strip_suffix('*')
+                        .map_or(allowed == name, |prefix| name.starts_with(prefix))
+                })
+        })
+        .collect()
+}
+
+/// Whether `url` is an absolute URL using one of `schemes` (lowercase).
+/// Pure function for testability.
+fn is_allowed_url(url: &str, schemes: &[String]) -> bool {
+    url::Url::parse(url).is_ok_and(|url| schemes.iter().any(|scheme| scheme == url.scheme()))
 }
 
-const BLACKLISTED_QUERY_PARAMS: &[&str] = &["region", "country"];
+const BLACKLISTED_QUERY_PARAMS: &[&str] = &["region", "country", "__sfu"];
 
-/// Filter query string, removing gateway-specific parameters (blacklist approach).
+/// Filter query string, removing gateway-specific parameters (blacklist approach),
+/// and the token parameter when one is configured.
+///
+/// Keys are decoded to decide which parameters to drop, the kept ones are copied byte for
+/// byte, in order, repeated keys, bare keys and empty values included.
 /// Pure function for testability.
-fn filter_query_params(query_string: &str) -> String {
+pub(crate) fn filter_query_params(query_string: &str, token_param: Option<&str>) -> String {
     query_string
         .split('&')
-        .filter(|part| {
-            !BLACKLISTED_QUERY_PARAMS
-                .iter()
-                .any(|blocked| part.starts_with(&format!("{blocked}=")))
+        .filter(|segment| {
+            form_urlencoded::parse(segment.as_bytes())
+                .next()
+                .is_some_and(|(key, _)| {
+                    !BLACKLISTED_QUERY_PARAMS
+                        .iter()
+                        .copied()
+                        .chain(token_param)
+                        .any(|blocked| key == blocked)
+                })
         })
         .collect::<Vec<_>>()
         .join("&")
 }
 
+/// Decoded value of the `name` query parameter, if present and not empty.
+fn query_token(query_string: &str, name: &str) -> Option<String> {
+    web::Query::<BTreeMap<String, String>>::from_query(query_string)
+        .ok()
+        .and_then(|params| params.into_inner().remove(name))
+        .filter(|token| !token.is_empty())
+}
+
+/// Reject an Authorization header or a query string over its size limit, before any
+/// decoding or verification work is spent on it.
+/// Pure function for testability.
+pub(crate) fn check_input_sizes(
+    headers: &HeaderMap,
+    query_string: &str,
+    max_query_bytes: usize,
+) -> Result<(), ChannelError> {
+    let authorization_len = headers
+        .get(AUTHORIZATION)
+        .map_or(0, |value| value.as_bytes().len());
+    if authorization_len > MAX_AUTHORIZATION_BYTES {
+        warn!(len = authorization_len, "Authorization header too large");
+        return Err(ChannelError::TooLarge {
+            part: "authorization header",
+        });
+    }
+    if query_string.len() > max_query_bytes {
+        warn!(len = query_string.len(), "Query string too large");
+        return Err(ChannelError::TooLarge {
+            part: "query string",
+        });
+    }
+    Ok(())
+}
+
+/// Check that every `%` in the query string starts a valid percent-encoded byte.
+/// Pure function for testability.
+pub(crate) fn is_well_formed_query(query_string: &str) -> bool {
+    let bytes = query_string.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let is_hex_pair = bytes
+                .get(i + 1..i + 3)
+                .is_some_and(|pair| pair.iter().all(u8::is_ascii_hexdigit));
+            if !is_hex_pair {
+                return false;
+            }
+            i += 3;
+        } else {
+            i += 1;
+        }
+    }
+    true
+}
+
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Authenticate with the `X-Api-Key` header, if the feature is enabled and the header present.
+///
+/// Returns `None` when the JWT path should be used instead.
+fn authenticate_api_key(
+    req: &HttpRequest,
+    api_key: Option<&ApiKeyConfig>,
+) -> Option<Result<Claims, ChannelError>> {
+    let api_key = api_key?;
+    let presented = req.headers().get("X-Api-Key")?;
+
+    if !constant_time_eq(presented.as_bytes(), api_key.key.as_bytes()) {
+        warn!("Invalid API key");
+        return Some(Err(ChannelError::InvalidApiKey));
+    }
+
+    let now = unix_now();
+    Some(Ok(Claims {
+        iss: api_key.iss.clone(),
+        key: None,
+        exp: Some(now + API_KEY_CLAIMS_TTL_SECS),
+        iat: Some(now),
+        nbf: None,
+    }))
+}
+
+/// Extract and verify the JWT from the Authorization header, or from the token query
+/// parameter when configured and the header is absent.
+///
+/// RS256 tokens are verified with the JWKS key matching their `kid` when a JWKS is
+/// configured, other tokens with the gateway key.
+async fn authenticate_jwt(req: &HttpRequest, state: &AppState) -> Result<Claims, ChannelError> {
+    let auth_header = req
+        .headers()
+        .get("Authorization")
+        .and_then(|h| h.to_str().ok());
+
+    debug!(auth_header = ?auth_header, "Received Authorization header");
+
+    let query_token = state
+        .token_query_param
+        .as_deref()
+        .filter(|_| auth_header.is_none())
+        .and_then(|name| query_token(req.query_string(), name));
+    let token = match query_token {
+        Some(token) => token,
+        None => extract_token(auth_header, &state.auth_schemes)
+            .map_err(|e| {
+                warn!(auth_header = ?auth_header, "Missing authorization: {}", e);
+                ChannelError::MissingAuth
+            })?
+            .to_string(),
+    };
+
+    let verified = if let Some((jwks, kid)) = state.jwks.as_ref().zip(rs256_kid(&token)) {
+        let key = jwks.decoding_key(&kid).await.ok_or_else(|| {
+            warn!(kid, "No JWKS key for token");
+            ChannelError::InvalidToken
+        })?;
+        verify_rs256(&token, &key, &state.verify_options)
+    } else {
+        let gateway_keys = state
+            .gateway_keys
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        verify_any(&token, &gateway_keys, &state.verify_options)
+    };
+
+    verified.map_err(|e| {
+        warn!("Invalid JWT: {}", e);
+        ChannelError::InvalidToken
+    })
+}
+
+/// Authenticate with the API key if enabled and presented, otherwise with the JWT.
+///
+/// Returns the claims and whether they come from the API key.
+pub(crate) async fn authenticate(
+    req: &HttpRequest,
+    state: &AppState,
+) -> Result<(Claims, bool), ChannelError> {
+    match authenticate_api_key(req, state.api_key.as_ref()) {
+        Some(result) => result.map(|claims| (claims, true)),
+        None => authenticate_jwt(req, state)
+            .await
+            .map(|claims| (claims, false)),
+    }
+}
+
 #[allow(clippy::unused_async)] // async required by actix
 pub async fn noop() -> HttpResponse {
     HttpResponse::Ok().json(serde_json::json!({ "status": "ok" }))
 }
 
+/// Liveness probe, answers as long as the process does.
+#[allow(clippy::unused_async)] // async required by actix
+pub async fn healthz() -> HttpResponse {
+    HttpResponse::Ok().json(serde_json::json!({ "status": "ok" }))
+}
+
+/// Readiness probe, 503 unless some SFU is configured and, with health checks, healthy.
+///
+/// Lets an orchestrator keep traffic away from a gateway whose SFUs are all down.
+pub async fn readyz(state: web::Data<Arc<AppState>>) -> HttpResponse {
+    if state.balancer().has_healthy_sfu() {
+        HttpResponse::Ok().json(serde_json::json!({ "status": "ready" }))
+    } else {
+        HttpResponse::ServiceUnavailable().json(serde_json::json!({ "status": "not ready" }))
+    }
+}
+
 /// Forward /v1/channel request to selected SFU
 ///
 /// Flow:
-/// 1. Extract and verify JWT from Odoo (using gateway's key)
+/// 1. Extract and verify JWT from Odoo (using gateway's key), or the API key if enabled
 /// 2. Select an SFU based on region hint
 /// 3. Re-sign the JWT with the selected SFU's key
-/// 4. Forward request to SFU with new JWT
+/// 4. Forward request to SFU with new JWT, with the method (GET or POST) and body it came with
+///
+/// The whole flow is bounded by the request deadline if one is configured.
+///
+/// # Errors
+/// Returns a `ChannelError`, rendered by actix, at the first failing step.
 pub async fn channel(
     req: HttpRequest,
     query: web::Query<ChannelQuery>,
+    body: Result<Bytes, actix_web::Error>,
     state: web::Data<Arc<AppState>>,
-) -> HttpResponse {
-    // 1. Extract and verify JWT from Authorization header
-    let auth_header = req
-        .headers()
-        .get("Authorization")
-        .and_then(|h| h.to_str().ok());
+) -> Result<HttpResponse, ChannelError> {
+    let (req, query, state) = (&req, &*query, &**state);
+    Ok(handle(req, state, "channel", |request_id| async move {
+        let body = body.map_err(|e| ChannelError::from_payload(&e))?;
+        forward_channel(req, query, body, state, &request_id).await
+    })
+    .await)
+}
 
-    debug!(auth_header = ?auth_header, "Received Authorization header");
+/// Run the flow of a forwarding handler within the request deadline, counted as in flight.
+///
+/// Every log line of the flow carries the request id, and the response too: errors are
+/// rendered here so that they get it as well. Once done, a single access log line sums the
+/// request up: client IP, region and address of the SFU, upstream and response status, and
+/// latency. The flow fills in what it learns on the current span, see `record_access`.
+pub(crate) async fn handle<F, Fut>(
+    req: &HttpRequest,
+    state: &AppState,
+    handler: &'static str,
+    flow: F,
+) -> HttpResponse
+where
+    F: FnOnce(String) -> Fut,
+    Fut: Future<Output = Result<HttpResponse, ChannelError>>,
+{
+    let _in_flight = state.in_flight.enter();
+    let started = std::time::Instant::now();
+    let request_id = request_id::for_request(req);
+    let span = info_span!(
+        "request",
+        handler,
+        request_id = %request_id,
+        client_ip = Empty,
+        region = Empty,
+        sfu = Empty,
+        upstream_status = Empty,
+    );
+    telemetry::set_remote_parent(&span, req.headers());
+    let mut response = within_deadline(state.request_deadline, flow(request_id.clone()))
+        .instrument(span.clone())
+        .await
+        .unwrap_or_else(|e| e.error_response());
+    info!(
+        parent: &span,
+        status = response.status().as_u16(),
+        latency_ms = started.elapsed().as_millis(),
+        "Request completed"
+    );
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response
+            .headers_mut()
+            .insert(HeaderName::from_static("x-request-id"), value);
+    }
+    response
+}
+
+/// Record `value` as the `field` of the access log line of the current request.
+pub(crate) fn record_access(field: &str, value: impl std::fmt::Display) {
+    tracing::Span::current().record(field, tracing::field::display(value));
+}
+
+async fn within_deadline(
+    deadline: Option<Duration>,
+    flow: impl Future<Output = Result<HttpResponse, ChannelError>>,
+) -> Result<HttpResponse, ChannelError> {
+    let Some(deadline) = deadline else {
+        return flow.await;
+    };
+
+    // on expiry the pipeline future is dropped, which also aborts the in-flight SFU request
+    tokio::time::timeout(deadline, flow)
+        .await
+        .unwrap_or_else(|_| {
+            warn!(
+                deadline_ms = deadline.as_millis(),
+                "Gateway deadline exceeded"
+            );
+            Err(ChannelError::Timeout)
+        })
+}
+
+/// What a request forwarded to an SFU carries besides its path, the same on every attempt
+pub(crate) struct Forward<'a> {
+    pub method: reqwest::Method,
+    pub claims: &'a Claims,
+    /// Query string for the SFU, without the gateway's parameters
+    pub query: String,
+    pub forwarded_for: String,
+    pub request_id: &'a str,
+    /// Allowlisted client headers, see `filter_headers`
+    pub headers: Vec<(&'a HeaderName, &'a HeaderValue)>,
+    pub content_type: Option<&'a HeaderValue>,
+    /// Request body, empty for none
+    pub body: Bytes,
+}
+
+impl<'a> Forward<'a> {
+    /// What `req` forwards to the SFU once authenticated as `claims`: its method, query string,
+    /// allowlisted headers and body.
+    pub(crate) fn new(
+        req: &'a HttpRequest,
+        state: &AppState,
+        claims: &'a Claims,
+        forwarded_for: String,
+        request_id: &'a str,
+        body: Bytes,
+    ) -> Result<Self, ChannelError> {
+        let method = reqwest::Method::from_bytes(req.method().as_str().as_bytes())
+            .map_err(|_| ChannelError::Internal)?;
+        Ok(Self {
+            method,
+            claims,
+            query: filter_query_params(req.query_string(), state.token_query_param.as_deref()),
+            forwarded_for,
+            request_id,
+            headers: filter_headers(req.headers(), &state.forward_headers),
+            content_type: req.headers().get(CONTENT_TYPE),
+            body,
+        })
+    }
+}
+
+async fn forward_channel(
+    req: &HttpRequest,
+    query: &ChannelQuery,
+    body: Bytes,
+    state: &AppState,
+    request_id: &str,
+) -> Result<HttpResponse, ChannelError> {
+    check_input_sizes(req.headers(), req.query_string(), state.max_query_bytes)?;
+    // The query string is forwarded to the SFU, don't pass along something it can't decode
+    if !is_well_formed_query(req.query_string()) {
+        warn!(
+            query = %filter_query_params(req.query_string(), state.token_query_param.as_deref()),
+            "Malformed query string"
+        );
+        return Err(ChannelError::MalformedQuery);
+    }
+    if state.strict_region {
+        check_region_query(query)?;
+    }
+
+    state.metrics.channel_request();
 
-    let token = match extract_token(auth_header) {
-        Ok(t) => t,
-        Err(e) => {
-            warn!(auth_header = ?auth_header, "Missing authorization: {}", e);
-            return HttpResponse::Unauthorized()
-                .json(serde_json::json!({ "error": "missing authorization" }));
+    // 1. Authenticate with the API key if enabled and presented, otherwise with the JWT
+    let (claims, authenticated_by_api_key) = authenticate(req, state)
+        .await
+        .inspect_err(|e| state.metrics.auth_failure(e))?;
+
+    info!(iss = %claims.iss, api_key = authenticated_by_api_key, "Authenticated request");
+    check_rate_limit(state, &claims)?;
+
+    // Computed once, each attempt sends the same chain
+    let forwarded_for = forwarded::for_request(req, state.trust_proxy);
+    if let Some(client_ip) = forwarded::client_ip(&forwarded_for) {
+        record_access("client_ip", client_ip);
+    }
+
+    // 2. Select an SFU based on region hint
+    let region_hints = region_hints(state, query, authenticated_by_api_key, &forwarded_for);
+
+    let forward = Forward::new(req, state, &claims, forwarded_for, request_id, body)?;
+
+    // 3. Forward to the selected SFU, retrying on another one when it is unreachable or
+    //    overloaded
+    let balancer = state.balancer();
+    let pinned = match query.sfu.as_deref().filter(|_| state.debug_endpoints) {
+        Some(pin) => {
+            let sfu = balancer.pinned(pin).ok_or_else(|| {
+                warn!(pin, "Unknown pinned SFU");
+                ChannelError::UnknownPin
+            })?;
+            info!(sfu_address = %sfu.address, "SFU pinned by request");
+            Some(sfu)
         }
+        None => None,
     };
+    let mut tried: Vec<&str> = Vec::new();
+    let mut last_error = ChannelError::NoSfu;
+    while tried.len() < state.max_attempts as usize {
+        let selected = match pinned {
+            // a pinned request is only tried on its SFU
+            Some(_) if !tried.is_empty() => break,
+            Some(sfu) => Ok(sfu),
+            None => balancer.select_for_issuer_with_exclusions(&region_hints, &claims.iss, &tried),
+        };
+        let sfu = match selected {
+            Ok(sfu) => sfu,
+            // the error of a failed attempt tells more than the lack of another SFU
+            Err(e) if tried.is_empty() => {
+                last_error = e.into();
+                break;
+            }
+            Err(_) => break,
+        };
+        info!(sfu_address = %sfu.address, attempt = tried.len() + 1, "Selected SFU");
+        let region = sfu
+            .regions
+            .iter()
+            .find(|region| region_hints.contains(&region.as_str()))
+            .or_else(|| sfu.regions.first());
+        if tried.is_empty() {
+            state.metrics.region_request(region.map(String::as_str));
+        }
+        record_access("sfu", &sfu.address);
+        if let Some(region) = region {
+            record_access("region", region);
+        }
 
-    let claims = match verify(token, &state.gateway_key) {
-        Ok(c) => c,
-        Err(e) => {
-            warn!("Invalid JWT: {}", e);
-            return HttpResponse::Unauthorized()
-                .json(serde_json::json!({ "error": "invalid token" }));
+        let result = forward_to_sfu(state, sfu, &forward).await;
+        state.metrics.forward_result(&result);
+        if result.is_ok() {
+            sfu.record_assignment();
+        }
+        match result {
+            Err(e) if e.is_retryable() => {
+                last_error = e;
+                tried.push(&sfu.address);
+            }
+            Ok(mut response) => {
+                insert_sfu_headers(&mut response, sfu, region, state.redact_sfu_address);
+                return Ok(response);
+            }
+            Err(e) => return Err(e),
         }
+    }
+
+    match last_error {
+        ChannelError::NoSfu => warn!("No SFU instances available"),
+        ChannelError::AllBusy => warn!("All SFU instances are at capacity"),
+        _ => {}
+    }
+    Err(last_error)
+}
+
+/// Tell the client which SFU served its channel, for debugging and reconnect hints: the region
+/// in `X-SFU-Region` and the address, or its hash when `redact` is set, in `X-SFU-Address`.
+fn insert_sfu_headers(
+    response: &mut HttpResponse,
+    sfu: &SfuInstance,
+    region: Option<&String>,
+    redact: bool,
+) {
+    let headers = response.headers_mut();
+    if let Some(value) = region.and_then(|region| HeaderValue::from_str(region).ok()) {
+        headers.insert(HeaderName::from_static("x-sfu-region"), value);
+    }
+    let address = if redact {
+        sfu.address_hash()
+    } else {
+        sfu.address.clone()
     };
+    if let Ok(value) = HeaderValue::from_str(&address) {
+        headers.insert(HeaderName::from_static("x-sfu-address"), value);
+    }
+}
 
-    info!(iss = %claims.iss, "Verified JWT from Odoo");
+/// Take a request from the issuer's rate limit, if enabled.
+///
+/// # Errors
+/// `ChannelError::RateLimited` when the issuer is over its limit.
+pub(crate) fn check_rate_limit(state: &AppState, claims: &Claims) -> Result<(), ChannelError> {
+    let Some(limiter) = &state.rate_limiter else {
+        return Ok(());
+    };
+    limiter
+        .check(&claims.iss, std::time::Instant::now())
+        .map_err(|retry_after| {
+            warn!(iss = %claims.iss, "Rate limit exceeded");
+            ChannelError::RateLimited {
+                // rounded up, retrying at the announced time must succeed
+                retry_after_secs: retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0),
+            }
+        })
+}
+
+/// Regions to select an SFU in, first choice first: the explicit regions, else the country's
+/// region, else the client IP's country region, else the default region if any. Empty when
+/// there is no hint at all.
+///
+/// API keys scoped to a region always route there.
+pub(crate) fn region_hints<'a>(
+    state: &'a AppState,
+    query: &'a ChannelQuery,
+    authenticated_by_api_key: bool,
+    forwarded_for: &str,
+) -> Vec<&'a str> {
+    let scoped_region = state
+        .api_key
+        .as_ref()
+        .filter(|_| authenticated_by_api_key)
+        .and_then(|api_key| api_key.region.as_deref());
+    if let Some(region) = scoped_region {
+        return vec![region];
+    }
+    if !query.regions.is_empty() {
+        return query.regions.iter().map(String::as_str).collect();
+    }
+    query
+        .country
+        .as_deref()
+        .and_then(country_to_region)
+        .or_else(|| geoip_region(state, query, forwarded_for))
+        .or(state.default_region.as_deref())
+        .into_iter()
+        .collect()
+}
 
-    // 2. Select an SFU based on region hint (prefer explicit region, fall back to country mapping)
-    let region_hint = query
-        .region
+/// Reject a `region` that isn't known or a `country` that maps to no region, both would
+/// otherwise be ignored and the request routed as if it had no hint.
+///
+/// # Errors
+/// `ChannelError::UnknownRegion` naming the offending parameter.
+fn check_region_query(query: &ChannelQuery) -> Result<(), ChannelError> {
+    if let Some(region) = query.regions.iter().find(|r| !is_known_region(r)) {
+        warn!(region, "Unknown region hint");
+        return Err(ChannelError::UnknownRegion { param: "region" });
+    }
+    if let Some(country) = query
+        .country
         .as_deref()
-        .or_else(|| query.country.as_deref().and_then(country_to_region));
-    let Some(sfu) = state.balancer.select(region_hint) else {
-        warn!("No SFU instances available");
-        return HttpResponse::ServiceUnavailable()
-            .json(serde_json::json!({ "error": "no SFU instances available" }));
+        .filter(|c| country_to_region(c).is_none())
+    {
+        warn!(country, "Country without region");
+        return Err(ChannelError::UnknownRegion { param: "country" });
+    }
+    Ok(())
+}
+
+/// Region of the client's country according to GeoIP, when the request has no hint itself.
+///
+/// The client is the first entry of the X-Forwarded-For chain sent to the SFU.
+fn geoip_region(
+    state: &AppState,
+    query: &ChannelQuery,
+    forwarded_for: &str,
+) -> Option<&'static str> {
+    if !query.regions.is_empty() || query.country.is_some() {
+        return None;
+    }
+    let geoip = state.geoip.as_ref()?;
+    let client_ip = forwarded::client_ip(forwarded_for)?;
+    let country = geoip.country(client_ip)?;
+    let region = country_to_region(&country);
+    debug!(%client_ip, country, region, "GeoIP region hint");
+    region
+}
+
+/// Request to `path` on one SFU, with the static headers, a JWT re-signed with its key, the
+/// forwarded client IP and the request id.
+pub(crate) fn sfu_request(
+    state: &AppState,
+    sfu: &SfuInstance,
+    path: &str,
+    forward: &Forward<'_>,
+) -> Result<reqwest::RequestBuilder, ChannelError> {
+    // The recording encryption key only goes to the SFUs trusted with it
+    let without_key;
+    let claims = if sfu.accepts_recording_key || forward.claims.key.is_none() {
+        forward.claims
+    } else {
+        debug!(sfu = %sfu.address, "Recording key withheld from SFU");
+        without_key = Claims {
+            key: None,
+            ..forward.claims.clone()
+        };
+        &without_key
     };
+    // Re-sign the JWT with the selected SFU's key
+    let sfu_token = sign_with_header(claims, &sfu.key(), &sfu.token_header()).map_err(|e| {
+        warn!("Failed to sign JWT for SFU: {}", e);
+        ChannelError::Internal
+    })?;
 
-    info!(sfu_address = %sfu.address, "Selected SFU");
+    let sfu_url = sfu_url(&sfu.address, &sfu.path_prefix, path, &forward.query);
+    let mut request = state.http_client.request(forward.method.clone(), &sfu_url);
+    let static_headers = merge_static_headers(&state.static_headers, &sfu.headers);
+    // the operator's static headers win over the client's
+    for (name, value) in &forward.headers {
+        if !static_headers
+            .iter()
+            .any(|(static_name, _)| static_name.eq_ignore_ascii_case(name.as_str()))
+        {
+            request = request.header(name.as_str(), value.as_bytes());
+        }
+    }
+    for (name, value) in static_headers {
+        request = request.header(name, value);
+    }
+    if let Some(content_type) = forward.content_type {
+        request = request.header(reqwest::header::CONTENT_TYPE, content_type.as_bytes());
+    }
+    if !forward.body.is_empty() {
+        request = request.body(forward.body.clone());
+    }
+    Ok(request
+        .header("Authorization", format!("Bearer {sfu_token}"))
+        .header("X-Forwarded-For", &forward.forwarded_for)
+        .header(request_id::HEADER, forward.request_id)
+        .headers(telemetry::trace_headers()))
+}
 
-    // 3. Re-sign the JWT with the selected SFU's key
-    let sfu_token = match sign(&claims, &sfu.key) {
-        Ok(t) => t,
-        Err(e) => {
-            warn!("Failed to sign JWT for SFU: {}", e);
-            return HttpResponse::InternalServerError()
-                .json(serde_json::json!({ "error": "internal error" }));
+/// Send a request to an SFU, error statuses become `ChannelError::UpstreamStatus`.
+///
+/// A connection not established, in time or at all, is `ChannelError::UpstreamUnreachable` (502),
+/// an SFU slow to answer is `ChannelError::UpstreamTimeout` (504) and a connection failing once
+/// the request was sent `ChannelError::UpstreamInterrupted` (502).
+pub(crate) async fn send_to_sfu(
+    sfu: &SfuInstance,
+    request: reqwest::RequestBuilder,
+    max_body_bytes: usize,
+) -> Result<reqwest::Response, ChannelError> {
+    let response = request.send().await.map_err(|e| {
+        warn!(sfu_address = %sfu.address, "Failed to contact SFU: {}", e);
+        if e.is_connect() {
+            ChannelError::UpstreamUnreachable
+        } else if e.is_timeout() {
+            ChannelError::UpstreamTimeout
+        } else {
+            ChannelError::UpstreamInterrupted
         }
+    })?;
+
+    let status = response.status();
+    record_access("upstream_status", status.as_u16());
+    if !status.is_success() {
+        warn!(sfu_address = %sfu.address, status = %status, "SFU returned error");
+        return Err(upstream_status_error(response, max_body_bytes).await);
+    }
+    Ok(response)
+}
+
+/// Forward the channel request to one SFU, with a JWT re-signed with its key.
+///
+/// The SFU's body is checked (a `uuid` and an allowed `url`) but sent to the client as
+/// received, fields the gateway doesn't know about included.
+async fn forward_to_sfu(
+    state: &AppState,
+    sfu: &SfuInstance,
+    forward: &Forward<'_>,
+) -> Result<HttpResponse, ChannelError> {
+    let request = sfu_request(state, sfu, "/v1/channel", forward)?;
+    let response = send_to_sfu(sfu, request, state.max_body_bytes).await?;
+
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| HeaderValue::from_bytes(value.as_bytes()).ok());
+    let body = read_body(response, state.max_body_bytes).await?;
+    let channel_resp = serde_json::from_slice::<ChannelResponse>(&body).map_err(|e| {
+        // often a hop between the gateway and the SFU answering with its own page, the
+        // content type and the start of the body tell which one
+        warn!(
+            sfu_address = %sfu.address,
+            content_type = content_type.as_ref().and_then(|value| value.to_str().ok()).unwrap_or("none"),
+            body = %body_snippet(&body),
+            "Failed to parse SFU response: {}", e
+        );
+        ChannelError::InvalidUpstreamResponse
+    })?;
+    if !is_allowed_url(&channel_resp.url, &state.sfu_url_schemes) {
+        warn!(sfu_address = %sfu.address, url = %channel_resp.url, "SFU returned a channel URL with a disallowed scheme");
+        return Err(ChannelError::InvalidUpstreamResponse);
+    }
+    info!(uuid = %channel_resp.uuid, url = %channel_resp.url, "Channel created");
+    Ok(HttpResponse::Ok()
+        .content_type(content_type.unwrap_or(HeaderValue::from_static("application/json")))
+        .body(body))
+}
+
+/// Start of `body` for the logs, lossily decoded, with the size of what was cut.
+fn body_snippet(body: &[u8]) -> String {
+    let snippet = String::from_utf8_lossy(&body[..body.len().min(BODY_SNIPPET_BYTES)]);
+    if body.len() > BODY_SNIPPET_BYTES {
+        format!("{snippet}... ({} bytes)", body.len())
+    } else {
+        snippet.into_owned()
+    }
+}
+
+/// Body of an SFU response, read as it arrives and given up on past `max_bytes`, rather than
+/// buffered whatever its size.
+///
+/// # Errors
+/// `ChannelError::UpstreamTooLarge` over `max_bytes`, `ChannelError::UpstreamTimeout` or
+/// `ChannelError::InvalidUpstreamResponse` when the body fails to read.
+async fn read_body(
+    mut response: reqwest::Response,
+    max_bytes: usize,
+) -> Result<Bytes, ChannelError> {
+    let too_large = |size: u64| {
+        warn!(size, max_bytes, "SFU response body too large");
+        ChannelError::UpstreamTooLarge
     };
+    if let Some(length) = response.content_length()
+        && length > max_bytes as u64
+    {
+        return Err(too_large(length));
+    }
+    let mut body = BytesMut::new();
+    while let Some(chunk) = response.chunk().await.map_err(|e| {
+        warn!("Failed to read SFU response: {}", e);
+        if e.is_timeout() {
+            ChannelError::UpstreamTimeout
+        } else {
+            ChannelError::InvalidUpstreamResponse
+        }
+    })? {
+        if body.len() + chunk.len() > max_bytes {
+            return Err(too_large((body.len() + chunk.len()) as u64));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body.freeze())
+}
 
-    // 4. Build the URL to the SFU and forward request
-    let mut sfu_url = format!("{}/v1/channel", sfu.address);
+/// An SFU response, as passed through to the client
+pub(crate) struct UpstreamResponse {
+    pub status: StatusCode,
+    pub content_type: Option<HeaderValue>,
+    /// Encoding the body is still in, `None` once decompressed by the gateway (gzip and
+    /// deflate with the `compression` feature) or when it never was compressed
+    pub content_encoding: Option<HeaderValue>,
+    pub body: Bytes,
+}
 
-    let filtered_query = filter_query_params(req.query_string());
-    if !filtered_query.is_empty() {
-        sfu_url.push('?');
-        sfu_url.push_str(&filtered_query);
+impl UpstreamResponse {
+    /// The response for the client, the body as received along with its type and encoding.
+    pub(crate) fn into_http_response(self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status);
+        if let Some(content_type) = self.content_type {
+            response.insert_header((CONTENT_TYPE, content_type));
+        }
+        if let Some(content_encoding) = self.content_encoding {
+            response.insert_header((CONTENT_ENCODING, content_encoding));
+        }
+        response.body(self.body)
     }
+}
 
-    // Make the request to the SFU with re-signed JWT and forwarded client IP
-    let request = state
-        .http_client
-        .get(&sfu_url)
-        .header("Authorization", format!("Bearer {sfu_token}"))
-        .header(
-            "X-Forwarded-For",
-            get_forwarded_for(&req, state.trust_proxy),
-        );
+/// Status, representation headers and body of an SFU response.
+///
+/// Statuses actix can't represent become 500, a body that fails to read is dropped.
+///
+/// # Errors
+/// `ChannelError::UpstreamTooLarge` when the body is over `max_body_bytes`.
+pub(crate) async fn read_upstream(
+    response: reqwest::Response,
+    max_body_bytes: usize,
+) -> Result<UpstreamResponse, ChannelError> {
+    let status = StatusCode::from_u16(response.status().as_u16())
+        .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    let header = |name| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| HeaderValue::from_bytes(value.as_bytes()).ok())
+    };
+    let content_type = header(reqwest::header::CONTENT_TYPE);
+    let content_encoding = header(reqwest::header::CONTENT_ENCODING);
+    let body = match read_body(response, max_body_bytes).await {
+        Err(ChannelError::UpstreamTooLarge) => return Err(ChannelError::UpstreamTooLarge),
+        Err(_) => Bytes::new(),
+        Ok(body) => body,
+    };
+    Ok(UpstreamResponse {
+        status,
+        content_type,
+        content_encoding,
+        body,
+    })
+}
 
-    match request.send().await {
-        Ok(response) => {
-            let status = response.status();
-            if status.is_success() {
-                match response.json::<ChannelResponse>().await {
-                    Ok(channel_resp) => {
-                        info!(uuid = %channel_resp.uuid, url = %channel_resp.url, "Channel created");
-                        HttpResponse::Ok().json(channel_resp)
-                    }
-                    Err(e) => {
-                        warn!("Failed to parse SFU response: {}", e);
-                        HttpResponse::BadGateway()
-                            .json(serde_json::json!({ "error": "invalid SFU response" }))
-                    }
-                }
-            } else {
-                warn!(status = %status, "SFU returned error");
-                HttpResponse::build(
-                    actix_web::http::StatusCode::from_u16(status.as_u16())
-                        .unwrap_or(actix_web::http::StatusCode::INTERNAL_SERVER_ERROR),
-                )
-                .finish()
-            }
+/// Error passing the SFU's error response through.
+async fn upstream_status_error(response: reqwest::Response, max_body_bytes: usize) -> ChannelError {
+    match read_upstream(response, max_body_bytes).await {
+        Ok(UpstreamResponse {
+            status,
+            content_type,
+            content_encoding,
+            body,
+        }) => ChannelError::UpstreamStatus {
+            status,
+            content_type,
+            content_encoding,
+            body,
+        },
+        Err(e) => e,
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct GeoRegion {
+    pub name: &'static str,
+    pub lat: f64,
+    pub lon: f64,
+}
+
+/// Static geo data used for routing decisions
+#[derive(Debug, Serialize)]
+pub struct GeoResponse {
+    pub regions: Vec<GeoRegion>,
+    /// ISO 3166-1 alpha-2 country code to region
+    pub countries: BTreeMap<&'static str, &'static str>,
+}
+
+/// Known regions with their coordinates and the country to region mapping, for dashboards.
+///
+/// # Errors
+/// Returns `ChannelError` when the request is not authenticated.
+pub async fn geo(
+    req: HttpRequest,
+    state: web::Data<Arc<AppState>>,
+) -> Result<HttpResponse, ChannelError> {
+    authenticate(&req, &state).await?;
+
+    Ok(HttpResponse::Ok().json(GeoResponse {
+        regions: known_regions()
+            .into_iter()
+            .map(|(name, lat, lon)| GeoRegion { name, lat, lon })
+            .collect(),
+        countries: country_region_mapping().into_iter().collect(),
+    }))
+}
+
+/// Where a channel request would be routed, see `select_preview`
+#[derive(Debug, Serialize)]
+pub struct SelectResponse {
+    /// First region hint resolved from the query, the client IP or the default region
+    pub region: Option<String>,
+    /// Known regions in the order SFUs are looked for in, see `Balancer::region_order`
+    pub fallback_order: Vec<String>,
+    /// Address of the SFU the request would be sent to, `None` when none is available
+    pub sfu: Option<String>,
+}
+
+/// Preview of the routing of a channel request with the same query, for debugging.
+///
+/// Resolves the region hint and selects an SFU like `channel` does, without authentication
+/// nor contacting the SFU. The selection still takes a turn of the round-robin. Only served
+/// when debug endpoints are enabled.
+#[allow(clippy::unused_async)] // async required by actix
+pub async fn select_preview(
+    req: HttpRequest,
+    query: web::Query<ChannelQuery>,
+    state: web::Data<Arc<AppState>>,
+) -> HttpResponse {
+    if !state.debug_endpoints {
+        return HttpResponse::NotFound().finish();
+    }
+    let forwarded_for = forwarded::for_request(&req, state.trust_proxy);
+    let region_hints = region_hints(&state, &query, false, &forwarded_for);
+    let balancer = state.balancer();
+    let sfu = balancer
+        .select(&region_hints)
+        .map(|sfu| sfu.address.clone())
+        .inspect_err(|e| debug!(?e, "No SFU to preview"))
+        .ok();
+    HttpResponse::Ok().json(SelectResponse {
+        region: region_hints.first().map(|region| (*region).to_string()),
+        fallback_order: balancer
+            .region_order(&region_hints)
+            .into_iter()
+            .map(str::to_string)
+            .collect(),
+        sfu,
+    })
+}
+
+#[derive(Debug, Serialize)]
+pub struct SfuStatus {
+    pub address: String,
+    pub regions: Vec<String>,
+    pub reachable: bool,
+    /// Time of the probe `reachable` comes from, in seconds since the Unix epoch
+    pub last_check: Option<u64>,
+    /// Drained for maintenance, it gets no new channels whatever its health
+    pub draining: bool,
+    /// Times it was selected since the gateway started
+    pub selected: u64,
+    /// Channels assigned to it within the channel lifetime, the load least-connections balances
+    pub assigned: u32,
+}
+
+/// Health of every configured SFU, for monitoring dashboards
+#[derive(Debug, Serialize)]
+pub struct StatusResponse {
+    pub total: usize,
+    pub healthy: usize,
+    pub unhealthy: usize,
+    pub sfus: Vec<SfuStatus>,
+}
+
+/// Reachability of each SFU with aggregate counts.
+///
+/// Reports the state of the background health checks when enabled, otherwise every SFU is
+/// probed on the spot with a short timeout, unless it was probed less than
+/// `AppState::status_probe_ttl` ago. SFUs never probed yet have no `last_check` and count as
+/// healthy, as they do for selection.
+///
+/// # Errors
+/// Returns `ChannelError` when the request is not authenticated.
+pub async fn status(
+    req: HttpRequest,
+    state: web::Data<Arc<AppState>>,
+) -> Result<HttpResponse, ChannelError> {
+    authenticate(&req, &state).await?;
+
+    let balancer = state.balancer();
+    let mut snapshot = balancer.snapshot();
+    if !snapshot.health_checks {
+        let probed = probe_all(
+            &state.http_client,
+            balancer.instances(),
+            state.status_probe_ttl,
+            state.health_check_mode,
+        )
+        .await;
+        for (sfu, (reachable, checked_at)) in snapshot.sfus.iter_mut().zip(probed) {
+            sfu.healthy = reachable;
+            sfu.last_check = checked_at
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map(|elapsed| elapsed.as_secs());
         }
-        Err(e) => {
-            warn!("Failed to contact SFU: {}", e);
-            HttpResponse::BadGateway().json(serde_json::json!({ "error": "failed to contact SFU" }))
+    }
+
+    let sfus: Vec<_> = snapshot
+        .sfus
+        .into_iter()
+        .map(|sfu| SfuStatus {
+            address: sfu.address,
+            regions: sfu.regions,
+            reachable: sfu.healthy,
+            last_check: sfu.last_check,
+            draining: sfu.draining,
+            selected: sfu.selected,
+            assigned: sfu.assigned,
+        })
+        .collect();
+    let healthy = sfus.iter().filter(|sfu| sfu.reachable).count();
+    Ok(HttpResponse::Ok().json(StatusResponse {
+        total: sfus.len(),
+        healthy,
+        unhealthy: sfus.len() - healthy,
+        sfus,
+    }))
+}
+
+/// Probe all SFUs concurrently, results in the order of `sfus` with the time of the probes.
+///
+/// SFUs probed less than `ttl` ago, on demand or by the background health checks, aren't
+/// probed again and their last result is returned instead. SFUs without their own
+/// `health_check` are probed with `default_mode`, as by the background health checks.
+async fn probe_all(
+    client: &reqwest::Client,
+    sfus: &[SfuInstance],
+    ttl: Duration,
+    default_mode: HealthCheckMode,
+) -> Vec<(bool, Option<SystemTime>)> {
+    let mut results = vec![(false, None); sfus.len()];
+    let mut probes = JoinSet::new();
+    for (index, sfu) in sfus.iter().enumerate() {
+        if let Some((up, at)) = sfu.health.recent_probe(ttl) {
+            results[index] = (up, Some(at));
+            continue;
         }
+        let client = client.clone();
+        let address = sfu.address.clone();
+        let path_prefix = sfu.path_prefix.clone();
+        let mode = sfu.health_check.unwrap_or(default_mode);
+        probes.spawn(async move {
+            let reachable =
+                probe(&client, &address, &path_prefix, mode, STATUS_PROBE_TIMEOUT).await;
+            (index, reachable)
+        });
+    }
+    let mut finished = Vec::new();
+    while let Some(result) = probes.join_next().await {
+        if let Ok((index, up)) = result {
+            finished.push((index, up));
+        }
+    }
+    let checked_at = SystemTime::now();
+    for (index, up) in finished {
+        sfus[index].health.record_probe(up);
+        results[index] = (up, Some(checked_at));
     }
+    results
+}
+
+/// Gateway counters in the Prometheus text format.
+pub async fn metrics(state: web::Data<Arc<AppState>>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(metrics::CONTENT_TYPE)
+        .body(state.metrics.render())
 }
 
 /// Create and configure the HTTP server with all routes.
 ///
+/// The server doesn't handle signals itself, the caller stops it through its handle (see
+/// `shutdown`). A graceful stop waits at most `shutdown_grace`, rounded up to the second, for
+/// the workers.
+///
 /// # Errors
 ///
 /// Returns an error if the server fails to bind to the specified address.
 pub fn create_server(
     state: Arc<AppState>,
     bind_addr: &str,
+    shutdown_grace: Duration,
 ) -> std::io::Result<actix_web::dev::Server> {
     Ok(HttpServer::new(move || {
+        let cors = Condition::new(
+            !state.cors_origins.is_empty(),
+            cors::cors(&state.cors_origins),
+        );
         App::new()
             .app_data(web::Data::new(state.clone()))
+            .app_data(web::PayloadConfig::new(state.max_body_bytes))
             .route("/noop", web::get().to(noop))
-            .route("/v1/channel", web::get().to(channel))
+            .route("/healthz", web::get().to(healthz))
+            .route("/readyz", web::get().to(readyz))
+            .service(
+                web::resource("/v1/channel")
+                    .wrap(cors)
+                    .route(web::get().to(channel))
+                    .route(web::post().to(channel)),
+            )
+            .route("/v1/geo", web::get().to(geo))
+            .route("/v1/status", web::get().to(status))
+            .route("/v1/select", web::get().to(select_preview))
+            .route("/metrics", web::get().to(metrics))
+            .route("/admin/drain", web::post().to(drain))
+            .route("/admin/undrain", web::post().to(undrain))
+            .route("/admin/reload", web::post().to(reload))
+            // last, the gateway's own /v1 routes take precedence
+            .route("/v1/{tail:.*}", web::route().to(proxy))
     })
+    .disable_signals()
+    .shutdown_timeout(shutdown_timeout_secs(shutdown_grace))
     .bind(bind_addr)?
     .run())
 }
 
+/// Workers' shutdown timeout, which actix counts in whole seconds: rounded up so that it never
+/// cuts the grace period short.
+fn shutdown_timeout_secs(grace: Duration) -> u64 {
+    grace.as_secs() + u64::from(grace.subsec_nanos() > 0)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
-    fn test_build_forwarded_for_no_existing() {
-        let result = build_forwarded_for(None, "192.168.1.100");
-        assert_eq!(result, "192.168.1.100");
+    fn test_shutdown_timeout_rounds_up() {
+        assert_eq!(shutdown_timeout_secs(Duration::ZERO), 0);
+        assert_eq!(shutdown_timeout_secs(Duration::from_millis(1)), 1);
+        assert_eq!(shutdown_timeout_secs(Duration::from_millis(1500)), 2);
+        assert_eq!(shutdown_timeout_secs(Duration::from_secs(30)), 30);
+    }
+
+    #[test]
+    fn test_keys_are_decoded_bytes() {
+        // the config decodes every key once, the handlers only ever see bytes
+        let nodes = crate::config::NodeData::from_json(
+            r#"{"sfu": [{"address": "http://sfu1:3000", "key": "dGVzdC1zZWNyZXQta2V5LTEyMzQ1Njc4OTAxMjM0NTY="}]}"#,
+        )
+        .unwrap();
+        let sfu_key: Vec<u8> = nodes.sfu[0].key.clone();
+        assert_eq!(sfu_key.as_slice(), b"test-secret-key-1234567890123456");
+
+        let state = AppState::new(
+            Balancer::new(nodes.sfu),
+            reqwest::Client::new(),
+            sfu_key.clone(),
+        );
+        let gateway_keys: &RwLock<Vec<Vec<u8>>> = &state.gateway_keys;
+        assert_eq!(*gateway_keys.read().unwrap(), vec![sfu_key.clone()]);
+        assert_eq!(state.balancer().instances()[0].key(), sfu_key);
+    }
+
+    #[test]
+    fn test_merge_static_headers() {
+        let global = vec![
+            ("X-Internal-Token".to_string(), "global".to_string()),
+            ("X-Env".to_string(), "prod".to_string()),
+        ];
+        let per_sfu = vec![("x-internal-token".to_string(), "sfu".to_string())];
+
+        let merged = merge_static_headers(&global, &per_sfu);
+        assert_eq!(merged, vec![("X-Env", "prod"), ("x-internal-token", "sfu")]);
+        assert_eq!(merge_static_headers(&global, &[]).len(), 2);
+    }
+
+    #[test]
+    fn test_well_formed_query_encoded() {
+        assert!(is_well_formed_query(
+            "webRTC=true&recordingAddress=http%3A%2F%2Flocalhost%3a8070"
+        ));
+    }
+
+    #[test]
+    fn test_well_formed_query_invalid_percent_sequence() {
+        assert!(!is_well_formed_query("name=%zz"));
+        assert!(!is_well_formed_query("name=abc%"));
+        assert!(!is_well_formed_query("name=abc%4"));
     }
 
     #[test]
-    fn test_build_forwarded_for_with_existing() {
-        let result = build_forwarded_for(Some("10.0.0.1"), "192.168.1.100");
-        assert_eq!(result, "10.0.0.1, 192.168.1.100");
+    fn test_well_formed_query_empty() {
+        assert!(is_well_formed_query(""));
     }
 
     #[test]
-    fn test_build_forwarded_for_with_chain() {
-        let result = build_forwarded_for(Some("10.0.0.1, 172.16.0.1"), "192.168.1.100");
-        assert_eq!(result, "10.0.0.1, 172.16.0.1, 192.168.1.100");
+    fn test_input_sizes_within_limits() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(check_input_sizes(&headers, "", 16), Ok(()));
+
+        let bearer = format!("Bearer {}", "a".repeat(1024));
+        headers.insert(AUTHORIZATION, HeaderValue::from_str(&bearer).unwrap());
+        assert_eq!(check_input_sizes(&headers, "webRTC=true", 16), Ok(()));
+    }
+
+    #[test]
+    fn test_input_sizes_over_limits() {
+        let mut headers = HeaderMap::new();
+        let bearer = format!("Bearer {}", "a".repeat(MAX_AUTHORIZATION_BYTES));
+        headers.insert(AUTHORIZATION, HeaderValue::from_str(&bearer).unwrap());
+        assert_eq!(
+            check_input_sizes(&headers, "", 16),
+            Err(ChannelError::TooLarge {
+                part: "authorization header"
+            })
+        );
+
+        assert_eq!(
+            check_input_sizes(&HeaderMap::new(), &"a".repeat(17), 16),
+            Err(ChannelError::TooLarge {
+                part: "query string"
+            })
+        );
+    }
+
+    fn header_map(headers: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut map = HeaderMap::new();
+        for (name, value) in headers {
+            map.append(
+                HeaderName::from_static(name),
+                HeaderValue::from_static(value),
+            );
+        }
+        map
+    }
+
+    fn filtered_names(headers: &HeaderMap, allowlist: &[&str]) -> Vec<String> {
+        let allowlist: Vec<String> = allowlist.iter().map(|h| (*h).to_string()).collect();
+        let mut names: Vec<String> = filter_headers(headers, &allowlist)
+            .into_iter()
+            .map(|(name, _)| name.to_string())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_filter_headers_keeps_allowlisted() {
+        let headers = header_map(&[
+            ("user-agent", "Odoo"),
+            ("accept-language", "fr-BE"),
+            ("x-odoo-db", "prod"),
+            ("x-odoo-version", "19.0"),
+            ("cookie", "session_id=abc"),
+            ("x-other", "1"),
+        ]);
+        assert_eq!(
+            filtered_names(&headers, DEFAULT_FORWARD_HEADERS),
+            [
+                "accept-language",
+                "user-agent",
+                "x-odoo-db",
+                "x-odoo-version"
+            ]
+        );
+        assert!(filtered_names(&headers, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_filter_headers_never_forwards_hop_by_hop_or_gateway_headers() {
+        let headers = header_map(&[
+            ("connection", "keep-alive, x-odoo-hop"),
+            ("keep-alive", "timeout=5"),
+            ("transfer-encoding", "chunked"),
+            ("upgrade", "websocket"),
+            ("x-odoo-hop", "1"),
+            ("authorization", "Bearer token"),
+            ("x-api-key", "secret"),
+            ("x-forwarded-for", "1.2.3.4"),
+            ("x-request-id", "abc"),
+            ("host", "gateway"),
+            ("x-odoo-db", "prod"),
+        ]);
+        assert_eq!(filtered_names(&headers, &["*"]), ["x-odoo-db"]);
+    }
+
+    #[test]
+    fn test_is_allowed_url() {
+        let schemes: Vec<String> = DEFAULT_SFU_URL_SCHEMES
+            .iter()
+            .map(|s| (*s).to_string())
+            .collect();
+        for url in [
+            "wss://sfu.example.com/ws",
+            "WSS://sfu.example.com",
+            "https://sfu:8070",
+        ] {
+            assert!(is_allowed_url(url, &schemes), "{url}");
+        }
+        for url in [
+            "http://sfu.example.com",
+            "javascript:alert(1)",
+            "sfu.example.com",
+            "",
+        ] {
+            assert!(!is_allowed_url(url, &schemes), "{url}");
+        }
     }
 
     #[test]
     fn test_filter_query_params_passes_through_all() {
-        let result = filter_query_params("webRTC=true&recordingAddress=http%3A%2F%2Flocalhost");
+        let result =
+            filter_query_params("webRTC=true&recordingAddress=http%3A%2F%2Flocalhost", None);
         assert_eq!(
             result,
             "webRTC=true&recordingAddress=http%3A%2F%2Flocalhost"
@@ -253,8 +1668,10 @@ mod tests {
 
     #[test]
     fn test_filter_query_params_removes_region() {
-        let result =
-            filter_query_params("webRTC=true&region=eu&recordingAddress=http%3A%2F%2Flocalhost");
+        let result = filter_query_params(
+            "webRTC=true&region=eu&recordingAddress=http%3A%2F%2Flocalhost",
+            None,
+        );
         assert_eq!(
             result,
             "webRTC=true&recordingAddress=http%3A%2F%2Flocalhost"
@@ -263,35 +1680,83 @@ mod tests {
 
     #[test]
     fn test_filter_query_params_region_only() {
-        let result = filter_query_params("region=us");
+        let result = filter_query_params("region=us", None);
         assert_eq!(result, "");
     }
 
     #[test]
     fn test_filter_query_params_empty() {
-        let result = filter_query_params("");
+        let result = filter_query_params("", None);
         assert_eq!(result, "");
     }
 
+    #[test]
+    fn test_filter_query_params_removes_bare_region() {
+        assert_eq!(
+            filter_query_params("region&webRTC=true", None),
+            "webRTC=true"
+        );
+    }
+
+    #[test]
+    fn test_filter_query_params_keeps_encoded_ampersand() {
+        assert_eq!(
+            filter_query_params("name=a%26region%3Deu&country=BE", None),
+            "name=a%26region%3Deu"
+        );
+    }
+
+    #[test]
+    fn test_filter_query_params_keeps_duplicates_and_empty_values() {
+        assert_eq!(
+            filter_query_params("tag=a&region=eu&tag=b&empty=&tag=a", None),
+            "tag=a&tag=b&empty=&tag=a"
+        );
+    }
+
+    #[test]
+    fn test_filter_query_params_keeps_bare_keys() {
+        assert_eq!(
+            filter_query_params("flag&region=eu&other", None),
+            "flag&other"
+        );
+    }
+
+    #[test]
+    fn test_filter_query_params_keeps_escapes() {
+        assert_eq!(
+            filter_query_params("name=a%20b+c&country=BE&path=%2fx", None),
+            "name=a%20b+c&path=%2fx"
+        );
+    }
+
     #[test]
     fn test_filter_query_params_preserves_new_params() {
-        let result = filter_query_params("newParam=value&anotherNew=123&region=eu");
+        let result = filter_query_params("newParam=value&anotherNew=123&region=eu", None);
         assert_eq!(result, "newParam=value&anotherNew=123");
     }
 
     #[test]
     fn test_filter_query_params_removes_country() {
-        let result =
-            filter_query_params("webRTC=true&country=FR&recordingAddress=http%3A%2F%2Flocalhost");
+        let result = filter_query_params(
+            "webRTC=true&country=FR&recordingAddress=http%3A%2F%2Flocalhost",
+            None,
+        );
         assert_eq!(
             result,
             "webRTC=true&recordingAddress=http%3A%2F%2Flocalhost"
         );
     }
 
+    #[test]
+    fn test_filter_query_params_removes_token_param() {
+        let result = filter_query_params("token=abc.def&webRTC=true&tokens=1", Some("token"));
+        assert_eq!(result, "webRTC=true&tokens=1");
+    }
+
     #[test]
     fn test_filter_query_params_removes_both_region_and_country() {
-        let result = filter_query_params("region=eu&country=FR&webRTC=true");
+        let result = filter_query_params("region=eu&country=FR&webRTC=true", None);
         assert_eq!(result, "webRTC=true");
     }
 }
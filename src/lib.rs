@@ -1,3 +1,6 @@
 pub mod config;
 pub mod http;
 pub mod routing;
+pub mod shutdown;
+pub mod sweep;
+pub mod telemetry;
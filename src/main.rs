@@ -1,12 +1,25 @@
-use std::sync::Arc;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
 
 use clap::Parser;
-use tracing::{Level, info};
+use maxminddb::MaxMindDBError;
+use tracing::{Level, info, warn};
 use tracing_subscriber::FmtSubscriber;
+use tracing_subscriber::layer::SubscriberExt;
 
-use sfu_gateway::config::{GatewayConfig, NodeData};
-use sfu_gateway::http::{self, AppState};
-use sfu_gateway::routing::Balancer;
+use sfu_gateway::config::{ConfigError, GatewayConfig, NodeData, NodesWatcher, watch_nodes};
+use sfu_gateway::http::{self, AppState, JwksCache, Metrics, RateLimiter, VerifyOptions};
+use sfu_gateway::routing::{
+    Balancer, GeoIp, GeoTable, HealthCheckConfig, HealthThresholds, RegionIssue, install_geo_table,
+    is_known_region,
+};
+use sfu_gateway::shutdown::{self, Drain, ShutdownToken};
+use sfu_gateway::sweep::{self, SweepConfig};
+use sfu_gateway::telemetry;
+
+/// Exit code of a command line usage error, sysexits' `EX_USAGE`, apart from the
+/// `ConfigError` codes
+const USAGE_EXIT_CODE: i32 = 64;
 
 #[derive(Parser, Debug)]
 #[command(name = "sfu-gateway")]
@@ -19,36 +32,56 @@ struct Args {
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
+    // read ahead of the gateway config, the subscriber must be set before anything logs
+    let otlp_endpoint = std::env::var("SFU_GATEWAY_OTLP_ENDPOINT")
+        .ok()
+        .filter(|endpoint| !endpoint.is_empty());
+    let tracer_provider = otlp_endpoint.as_deref().map(|endpoint| {
+        telemetry::otlp_provider(endpoint).unwrap_or_else(|e| {
+            let error = ConfigError::Env {
+                var: "SFU_GATEWAY_OTLP_ENDPOINT".to_string(),
+                message: e.to_string(),
+            };
+            eprintln!("Error loading gateway config: {error}");
+            std::process::exit(error.exit_code().into());
+        })
+    });
     let subscriber = FmtSubscriber::builder()
         .with_max_level(Level::INFO)
-        .finish();
+        .finish()
+        .with(tracer_provider.as_ref().map(telemetry::install));
     if tracing::subscriber::set_global_default(subscriber).is_err() {
         eprintln!("Failed to set tracing subscriber");
         std::process::exit(1);
     }
-    let args = Args::parse();
+    let args = Args::try_parse().unwrap_or_else(|e| {
+        if !e.use_stderr() {
+            // --help and --version
+            e.exit();
+        }
+        let _ = e.print();
+        std::process::exit(USAGE_EXIT_CODE);
+    });
+    if let Some(endpoint) = &otlp_endpoint {
+        info!(endpoint = %endpoint, "Exporting traces over OTLP");
+    }
 
     // Load gateway config from environment
     let gateway = GatewayConfig::from_env().unwrap_or_else(|e| {
         eprintln!("Error loading gateway config: {e}");
-        std::process::exit(1);
+        std::process::exit(e.exit_code().into());
     });
 
-    // Load secrets: prioritize environment variable JSON over local file
-    // TODO: replace it with self registing SFUs (see roadmap)
-    let nodes = if let Some(ref nodes_json) = gateway.nodes {
-        info!("Loading SFU nodes from environment variable");
-        NodeData::from_json(nodes_json).unwrap_or_else(|e| {
-            eprintln!("Error parsing SFU nodes from environment: {e}");
-            std::process::exit(1);
-        })
-    } else {
-        info!("Loading SFU nodes from file: {}", args.secrets);
-        NodeData::load(&args.secrets).unwrap_or_else(|e| {
-            eprintln!("Error loading secrets file: {e}");
-            std::process::exit(1);
-        })
-    };
+    let nodes = load_nodes(&gateway, &args.secrets).unwrap_or_else(|e| {
+        eprintln!("Error loading SFU nodes: {e}");
+        std::process::exit(e.exit_code().into());
+    });
+    if let Err(e) = nodes.check_key_isolation(&gateway.keys)
+        && gateway.strict_key_check
+    {
+        eprintln!("Error loading SFU nodes: {e}");
+        std::process::exit(e.exit_code().into());
+    }
 
     info!(
         bind = %gateway.bind,
@@ -60,19 +93,353 @@ async fn main() -> std::io::Result<()> {
     for sfu in &nodes.sfu {
         info!(
             address = %sfu.address,
-            region = ?sfu.region,
+            regions = ?sfu.regions,
             "Registered SFU"
         );
     }
 
+    if !nodes.geo.regions.is_empty() || !nodes.geo.countries.is_empty() {
+        info!(
+            regions = nodes.geo.regions.len(),
+            countries = nodes.geo.countries.len(),
+            "Applying geo overrides"
+        );
+    }
+    install_geo_table(GeoTable::default().with_overrides(&nodes.geo));
+    let geo_overrides = nodes.geo;
+    if let Some(region) = gateway
+        .default_region
+        .as_deref()
+        .filter(|region| !is_known_region(region))
+    {
+        let e = ConfigError::Env {
+            var: "SFU_GATEWAY_DEFAULT_REGION".to_string(),
+            message: format!("unknown region '{region}'"),
+        };
+        eprintln!("Error loading gateway config: {e}");
+        std::process::exit(e.exit_code().into());
+    }
+    let default_region = gateway.hintless_region().map(str::to_string);
+    if let Some(region) = &default_region {
+        info!(
+            region,
+            "Requests without region hint routed to the default region"
+        );
+    }
+
+    let nodes_from_file = gateway.nodes.is_none();
+    let static_headers = nodes.headers;
+    let mut balancer = match gateway.seed {
+        Some(seed) => {
+            info!(seed, "Using seeded balancer RNG");
+            Balancer::with_seed(nodes.sfu, seed)
+        }
+        None => Balancer::new(nodes.sfu),
+    };
+    if let Some(ttl) = gateway.affinity_ttl {
+        info!(ttl_ms = ttl.as_millis(), "Issuer affinity enabled");
+        balancer = balancer.with_affinity(ttl, gateway.affinity_max_entries);
+    }
+    if let Some(ramp) = gateway.slow_start {
+        balancer = balancer.with_slow_start(ramp);
+    }
+    balancer = balancer.with_channel_lifetime(gateway.channel_lifetime);
+    if let Some(max_km) = gateway.max_fallback_km {
+        info!(max_km, "Region fallback bounded");
+        balancer = balancer.with_max_fallback_km(max_km);
+    }
+    let region_issues = balancer.region_issues();
+    for issue in &region_issues {
+        match issue {
+            // already reported when loading the configuration
+            RegionIssue::UnknownRegion { .. } => {}
+            RegionIssue::Unreachable { .. } => warn!(%issue, "Region setup issue"),
+        }
+    }
+    if gateway.strict_region_check
+        && let Some(first) = region_issues.first()
+    {
+        let e = ConfigError::Region {
+            message: format!("{} issue(s), first: {first}", region_issues.len()),
+        };
+        eprintln!("Error loading SFU nodes: {e}");
+        std::process::exit(e.exit_code().into());
+    }
+    info!(strategy = ?gateway.strategy, "Selection strategy");
+    balancer = balancer.with_strategy(gateway.strategy);
+    let shutdown_token = ShutdownToken::new();
+    balancer = balancer.with_shutdown(shutdown_token.clone());
+
+    let mut http_client = reqwest::Client::builder()
+        .timeout(gateway.sfu_timeout)
+        .connect_timeout(gateway.connect_timeout)
+        .pool_max_idle_per_host(gateway.pool_max_idle)
+        .pool_idle_timeout(gateway.pool_idle_timeout)
+        .tcp_keepalive(gateway.tcp_keepalive)
+        .min_tls_version(gateway.min_tls_version);
+    if gateway.disable_proxy {
+        http_client = http_client.no_proxy();
+    }
+    if let Some(identity) = &gateway.client_identity {
+        let loaded = identity.load().unwrap_or_else(|e| {
+            eprintln!("Error loading client certificate: {e}");
+            std::process::exit(e.exit_code().into());
+        });
+        info!(cert = %identity.cert, "Presenting a client certificate to SFUs");
+        http_client = http_client.identity(loaded);
+    }
+    let http_client = http_client.build().unwrap_or_else(|e| {
+        let e = ConfigError::Tls {
+            message: e.to_string(),
+        };
+        eprintln!("Error building HTTP client: {e}");
+        std::process::exit(e.exit_code().into());
+    });
+    if let Some(interval) = gateway.health_check_interval {
+        info!(interval_ms = interval.as_millis(), "Health checks enabled");
+        balancer = balancer.spawn_health_checks(
+            http_client.clone(),
+            HealthCheckConfig {
+                interval,
+                max_interval: gateway.health_check_max_interval.max(interval),
+                timeout: gateway.health_check_timeout,
+                mode: gateway.health_check_mode,
+                thresholds: HealthThresholds {
+                    failures: gateway.health_failure_threshold,
+                    successes: gateway.health_success_threshold,
+                },
+            },
+        );
+    }
+
+    let jwks = match gateway.jwks_url {
+        Some(url) => {
+            let jwks = JwksCache::new(url, gateway.jwks_ttl, http_client.clone());
+            // a failure is not fatal, the next RS256 token triggers another fetch
+            jwks.refresh().await;
+            Some(jwks)
+        }
+        None => None,
+    };
+
+    let geoip = gateway.geoip_db.as_ref().map(|path| {
+        let geoip = GeoIp::open(path).unwrap_or_else(|e| {
+            let e = match e {
+                MaxMindDBError::IoError(message) => ConfigError::Io {
+                    path: path.clone(),
+                    source: std::io::Error::other(message),
+                },
+                e => ConfigError::Database {
+                    path: path.clone(),
+                    message: e.to_string(),
+                },
+            };
+            eprintln!("Error loading GeoIP database: {e}");
+            std::process::exit(e.exit_code().into());
+        });
+        info!(path = %path, "GeoIP region detection enabled");
+        geoip
+    });
+
+    if gateway.debug_endpoints {
+        warn!("Debug endpoints enabled, /v1/select is served without authentication");
+    }
+
     let state = Arc::new(AppState {
-        balancer: Balancer::new(nodes.sfu),
-        http_client: reqwest::Client::new(),
-        gateway_key: gateway.key,
+        balancer: RwLock::new(Arc::new(balancer)),
+        http_client,
+        gateway_keys: RwLock::new(gateway.keys),
         trust_proxy: gateway.trust_proxy,
+        api_key: gateway.api_key,
+        static_headers,
+        default_region,
+        request_deadline: gateway.request_deadline,
+        token_query_param: gateway.token_query_param,
+        jwks,
+        max_attempts: gateway.max_attempts,
+        metrics: Metrics::new(),
+        verify_options: VerifyOptions {
+            validate_exp: gateway.validate_exp,
+            leeway_secs: gateway.leeway_secs,
+            require_exp: gateway.validate_exp,
+            validate_nbf: gateway.max_iat_skew_secs.is_some(),
+            max_iat_skew_secs: gateway.max_iat_skew_secs,
+            max_age_secs: gateway.max_token_age_secs,
+        },
+        geoip,
+        in_flight: shutdown::InFlight::default(),
+        forward_headers: gateway.forward_headers,
+        sfu_url_schemes: gateway.sfu_url_schemes,
+        auth_schemes: gateway.auth_schemes,
+        debug_endpoints: gateway.debug_endpoints,
+        max_query_bytes: gateway.max_query_bytes,
+        max_body_bytes: gateway.max_body_bytes,
+        redact_sfu_address: gateway.redact_sfu_address,
+        strict_region: gateway.strict_region,
+        cors_origins: gateway.cors_origins,
+        admin_key: gateway.admin_key,
+        secrets_file: nodes_from_file.then(|| PathBuf::from(&args.secrets)),
+        gateway_key_file: gateway.key_file,
+        default_scheme: gateway.default_scheme,
+        geo_overrides,
+        strict_key_check: gateway.strict_key_check,
+        status_probe_ttl: gateway.status_probe_ttl,
+        health_check_mode: gateway.health_check_mode,
+        rate_limiter: gateway.rate_limit.map(|per_minute| {
+            info!(per_minute, "Per-issuer rate limit enabled");
+            RateLimiter::new(per_minute)
+        }),
     });
 
+    if let Some(interval) = gateway.sweep_interval {
+        let ttl = gateway.idle_ttl;
+        info!(
+            interval_ms = interval.as_millis(),
+            ttl_ms = ttl.as_millis(),
+            "Sweeping idle issuers"
+        );
+        sweep::spawn_sweeper(
+            Arc::clone(&state),
+            SweepConfig { interval, ttl },
+            shutdown_token.clone(),
+        );
+    }
+
+    let _watcher = watch_secrets(&state);
+
+    #[cfg(unix)]
+    spawn_key_reload(Arc::clone(&state));
+    #[cfg(unix)]
+    spawn_sighup_reload(Arc::clone(&state));
+
     let bind_addr = format!("{}:{}", gateway.bind, gateway.port);
 
-    http::create_server(state, &bind_addr)?.await
+    let server = http::create_server(Arc::clone(&state), &bind_addr, gateway.shutdown_grace)?;
+    let shutdown_task = spawn_graceful_shutdown(
+        &server,
+        state,
+        shutdown_token.clone(),
+        gateway.shutdown_grace,
+    );
+    server.await?;
+    if shutdown_token.is_triggered() {
+        let _ = shutdown_task.await;
+    }
+    if let Some(provider) = tracer_provider
+        && let Err(e) = provider.shutdown()
+    {
+        warn!("Failed to flush the pending traces: {e}");
+    }
+    Ok(())
+}
+
+/// On SIGTERM or SIGINT, stop accepting connections and give the in-flight forwards
+/// `grace` to finish before the server stops.
+fn spawn_graceful_shutdown(
+    server: &actix_web::dev::Server,
+    state: Arc<AppState>,
+    token: ShutdownToken,
+    grace: std::time::Duration,
+) -> actix_web::rt::task::JoinHandle<()> {
+    let signal_token = token.clone();
+    actix_web::rt::spawn(async move {
+        if let Err(e) = shutdown::trigger_on_signal(signal_token).await {
+            warn!("Cannot listen for shutdown signals: {e}");
+        }
+    });
+
+    let handle = server.handle();
+    actix_web::rt::spawn(async move {
+        // the server stops accepting right away, the drain only reports on the forwards
+        let stopping = async {
+            token.triggered().await;
+            handle.stop(true).await;
+        };
+        let (drained, ()) = tokio::join!(
+            shutdown::drain_on_shutdown(&token, &state.in_flight, grace),
+            stopping
+        );
+        match drained {
+            Drain::Completed => info!("In-flight forwards drained"),
+            Drain::TimedOut { remaining } => {
+                warn!(
+                    remaining,
+                    "Shutdown grace period over, dropping in-flight forwards"
+                );
+            }
+        }
+    })
+}
+
+/// Load secrets: prioritize environment variable JSON over local file
+// TODO: replace it with self registing SFUs (see roadmap)
+fn load_nodes(gateway: &GatewayConfig, secrets: &str) -> Result<NodeData, ConfigError> {
+    gateway.nodes.as_ref().map_or_else(
+        || {
+            info!("Loading SFU nodes from file: {secrets}");
+            NodeData::load_with_default_scheme(secrets, gateway.default_scheme.as_deref())
+        },
+        |nodes_json| {
+            info!("Loading SFU nodes from environment variable");
+            NodeData::from_json_with_default_scheme(nodes_json, gateway.default_scheme.as_deref())
+        },
+    )
+}
+
+/// Reload the SFU set when the secrets file changes, see `AppState::reload_nodes`.
+fn watch_secrets(state: &Arc<AppState>) -> Option<NodesWatcher> {
+    let secrets = state.secrets_file.clone()?;
+    let default_scheme = state.default_scheme.clone();
+    let state = Arc::clone(state);
+    watch_nodes(secrets, default_scheme, move |nodes| {
+        if let Err(e) = state.reload_nodes(nodes) {
+            warn!("SFU reload failed, keeping the current SFUs: {e}");
+        }
+    })
+    .map_err(|e| warn!("Secrets file hot-reload disabled: {e}"))
+    .ok()
+}
+
+/// Re-read the key files on SIGUSR1 and swap the keys in place, the topology is left as is.
+#[cfg(unix)]
+fn spawn_key_reload(state: Arc<AppState>) {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let mut signals = match signal(SignalKind::user_defined1()) {
+        Ok(signals) => signals,
+        Err(e) => {
+            warn!("Key reload disabled, cannot listen for SIGUSR1: {e}");
+            return;
+        }
+    };
+    actix_web::rt::spawn(async move {
+        while signals.recv().await.is_some() {
+            info!("SIGUSR1 received, reloading keys");
+            if let Err(e) = state.reload_key_files() {
+                warn!("Key reload failed, keeping current keys: {e}");
+            }
+        }
+    });
+}
+
+/// Re-read the secrets file on SIGHUP and swap the SFU set, as `/admin/reload` does.
+#[cfg(unix)]
+fn spawn_sighup_reload(state: Arc<AppState>) {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let mut signals = match signal(SignalKind::hangup()) {
+        Ok(signals) => signals,
+        Err(e) => {
+            warn!("SFU reload disabled, cannot listen for SIGHUP: {e}");
+            return;
+        }
+    };
+    actix_web::rt::spawn(async move {
+        while signals.recv().await.is_some() {
+            info!("SIGHUP received, reloading the SFUs");
+            if let Err(e) = state.reload_secrets() {
+                warn!("SFU reload failed, keeping the current SFUs: {e}");
+            }
+        }
+    });
 }
@@ -0,0 +1,170 @@
+//! Short-lived issuer → SFU affinity
+//!
+//! Keeps a burst of requests from the same issuer (channel) on the same SFU without
+//! any hashing: the last selection is remembered for a TTL and reused while valid.
+
+use std::collections::HashMap;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::sweep::Sweep;
+
+#[derive(Debug)]
+pub struct AffinityCache {
+    ttl: Duration,
+    max_entries: usize,
+    /// issuer → (SFU address, expiry)
+    entries: Mutex<HashMap<String, (String, Instant)>>,
+}
+
+impl AffinityCache {
+    #[must_use]
+    pub fn new(ttl: Duration, max_entries: usize) -> Self {
+        Self {
+            ttl,
+            max_entries,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Address of the SFU last selected for `issuer`, if the entry hasn't expired at `now`.
+    pub fn get(&self, issuer: &str, now: Instant) -> Option<String> {
+        let mut entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        match entries.get(issuer) {
+            Some((address, expiry)) if *expiry > now => Some(address.clone()),
+            Some(_) => {
+                entries.remove(issuer);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Remember `address` for `issuer` until `now + ttl`.
+    ///
+    /// When full, expired entries are dropped first, then the entry closest to expiry.
+    pub fn insert(&self, issuer: &str, address: &str, now: Instant) {
+        if self.max_entries == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        if entries.len() >= self.max_entries && !entries.contains_key(issuer) {
+            entries.retain(|_, (_, expiry)| *expiry > now);
+            if entries.len() >= self.max_entries
+                && let Some(oldest) = entries
+                    .iter()
+                    .min_by_key(|(_, (_, expiry))| *expiry)
+                    .map(|(issuer, _)| issuer.clone())
+            {
+                entries.remove(&oldest);
+            }
+        }
+        entries.insert(issuer.to_string(), (address.to_string(), now + self.ttl));
+    }
+
+    /// Forget the affinity of `issuer`.
+    pub fn remove(&self, issuer: &str) {
+        self.entries
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(issuer);
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Sweep for AffinityCache {
+    /// Expired entries are evicted whatever `ttl`.
+    fn sweep(&self, now: Instant, ttl: Duration) -> usize {
+        let mut entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        let before = entries.len();
+        entries.retain(|_, (_, expiry)| {
+            // the entry was last written `self.ttl` before its expiry
+            *expiry > now && (now + self.ttl).saturating_duration_since(*expiry) < ttl
+        });
+        before - entries.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TTL: Duration = Duration::from_secs(10);
+
+    #[test]
+    fn test_entry_reused_within_ttl() {
+        let cache = AffinityCache::new(TTL, 10);
+        let now = Instant::now();
+        cache.insert("channel-1", "http://sfu1:3000", now);
+
+        assert_eq!(
+            cache.get("channel-1", now + Duration::from_secs(9)),
+            Some("http://sfu1:3000".to_string())
+        );
+        assert_eq!(cache.get("channel-2", now), None);
+    }
+
+    #[test]
+    fn test_entry_expires() {
+        let cache = AffinityCache::new(TTL, 10);
+        let now = Instant::now();
+        cache.insert("channel-1", "http://sfu1:3000", now);
+
+        assert_eq!(cache.get("channel-1", now + TTL), None);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_idle_entries_swept() {
+        let cache = AffinityCache::new(TTL, 10);
+        let now = Instant::now();
+        cache.insert("idle", "http://sfu1:3000", now);
+        cache.insert("active", "http://sfu2:3000", now);
+        cache.insert("active", "http://sfu2:3000", now + Duration::from_secs(4));
+
+        assert_eq!(
+            cache.sweep(now + Duration::from_secs(5), Duration::from_secs(5)),
+            1
+        );
+        assert_eq!(cache.get("idle", now + Duration::from_secs(5)), None);
+        assert!(cache.get("active", now + Duration::from_secs(5)).is_some());
+        // expired, even though not idle for that long
+        assert_eq!(
+            cache.sweep(now + Duration::from_secs(14), Duration::from_mins(1)),
+            1
+        );
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_size_is_bounded() {
+        let cache = AffinityCache::new(TTL, 2);
+        let now = Instant::now();
+        cache.insert("channel-1", "http://sfu1:3000", now);
+        cache.insert(
+            "channel-2",
+            "http://sfu2:3000",
+            now + Duration::from_secs(1),
+        );
+        cache.insert(
+            "channel-3",
+            "http://sfu3:3000",
+            now + Duration::from_secs(2),
+        );
+
+        assert_eq!(cache.len(), 2);
+        // the entry closest to expiry was evicted
+        assert_eq!(cache.get("channel-1", now), None);
+        assert!(cache.get("channel-3", now).is_some());
+    }
+}
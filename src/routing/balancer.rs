@@ -1,119 +1,1194 @@
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+use std::time::{Duration, Instant, UNIX_EPOCH};
 
-use super::geo::region_fallback_order;
-use crate::config::SfuConfig;
+use super::affinity::AffinityCache;
+use super::geo::{
+    is_known_region, known_regions, region_fallback_order, region_fallback_order_within,
+};
+use super::health::{HealthCheckConfig, HealthChecker, HealthState, ProbeTarget};
+use super::rng::SelectionRng;
+use jsonwebtoken::{Algorithm, Header};
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::config::{DEFAULT_CHANNEL_LIFETIME, HealthCheckMode, SelectionStrategy, SfuConfig};
+use crate::http::constant_time_eq;
+use crate::shutdown::ShutdownToken;
+use crate::sweep::Sweep;
+
+/// Share of its full weight a freshly added SFU starts with during slow start, in per mille.
+const SLOW_START_INITIAL_PER_MILLE: u64 = 100;
+
+/// Candidates this much slower than the fastest one still share its traffic, in percent
+const LATENCY_TOLERANCE_PERCENT: u32 = 10;
 
 /// Manages SFU instances and selects the optimal one for requests.
 pub struct Balancer {
     sfus: Vec<SfuInstance>,
-    /// Round-robin counter for load distribution
+    /// Indices in `sfus` of the instances serving each region, kept in sync by `add_sfu`
+    region_index: HashMap<String, Vec<usize>>,
+    /// Round-robin counter of the all-SFUs group, used without region hint
     counter: AtomicUsize,
+    /// Round-robin counter of each region, so that a region rotates over its own SFUs whatever
+    /// the traffic of the others, kept in sync with `region_index`
+    region_counters: HashMap<String, AtomicUsize>,
+    /// Makes a smooth weighted round-robin step atomic over the candidates' current weights
+    weighted_lock: Mutex<()>,
+    /// Optional issuer → SFU affinity, disabled when `None`, shared with reconfigured balancers
+    affinity: Option<Arc<AffinityCache>>,
+    /// Ramp-up duration for SFUs added after the balancer was built, disabled when `None`
+    slow_start: Option<Duration>,
+    /// How long an assigned channel counts towards its SFU's load, see `SfuInstance::assigned`
+    channel_lifetime: Duration,
+    /// How an SFU is picked among the candidates
+    strategy: SelectionStrategy,
+    /// Furthest region a hinted request falls back to, in km, unbounded when `None`
+    max_fallback_km: Option<f64>,
+    /// Draws among ramping candidates
+    rng: SelectionRng,
+    /// Background probes updating the instances' health, none when `None`
+    health_checker: Option<HealthChecker>,
+    /// Stops the background tasks, carried over to reconfigured balancers
+    shutdown: ShutdownToken,
+}
+
+/// Candidates of the same priority, with the round-robin counter they rotate with
+struct Tier<'a> {
+    sfus: Vec<&'a SfuInstance>,
+    counter: &'a AtomicUsize,
+}
+
+/// Why `select` found no SFU
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectError {
+    /// No healthy SFU is left to pick from
+    NoSfu,
+    /// The highest priority candidates are all at their `max_channels`
+    AllBusy,
+}
+
+/// A region setup that routes some requests differently than intended, see
+/// `Balancer::region_issues`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RegionIssue {
+    /// The SFU serves a region without coordinates, region hints never route to it
+    UnknownRegion { address: String, region: String },
+    /// Requests hinted to this known region find no SFU, not even by fallback
+    Unreachable { region: String },
+}
+
+impl std::fmt::Display for RegionIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownRegion { address, region } => {
+                write!(f, "SFU {address} serves unknown region '{region}'")
+            }
+            Self::Unreachable { region } => {
+                write!(f, "no SFU reachable from region '{region}'")
+            }
+        }
+    }
+}
+
+/// Point-in-time view of an SFU, see `Balancer::snapshot`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SfuSnapshot {
+    pub address: String,
+    pub regions: Vec<String>,
+    /// As seen by the health checks, SFUs never probed count as healthy
+    pub healthy: bool,
+    /// Time of the last health check, in seconds since the Unix epoch
+    pub last_check: Option<u64>,
+    pub draining: bool,
+    pub weight: u32,
+    pub max_channels: Option<u32>,
+    /// Channels assigned to this SFU within the channel lifetime
+    pub assigned: u32,
+    /// Times this SFU was selected so far
+    pub selected: u64,
+}
+
+/// Point-in-time view of the balancer, see `Balancer::snapshot`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BalancerSnapshot {
+    /// Whether background health checks keep `healthy` up to date
+    pub health_checks: bool,
+    /// In configuration order
+    pub sfus: Vec<SfuSnapshot>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct SfuInstance {
     pub address: String,
-    pub region: Option<String>,
-    /// JWT secret key for signing tokens to this SFU (decoded bytes)
-    pub key: Vec<u8>,
+    /// Regions this SFU is a candidate for
+    pub regions: Vec<String>,
+    /// JWT secret key for signing tokens to this SFU (decoded bytes), swapped on key reload
+    key: RwLock<Vec<u8>>,
+    /// Per-SFU health check mode, the gateway-wide mode applies when `None`
+    pub health_check: Option<HealthCheckMode>,
+    /// Static headers sent on every request to this SFU
+    pub headers: Vec<(String, String)>,
+    /// Health as seen by the health checks
+    pub health: Arc<HealthState>,
+    /// When the instance was added to a running balancer, `None` for the initial instances
+    added_at: Option<Instant>,
+    /// Relative capacity, see `SfuConfig::weight`
+    pub weight: u32,
+    /// Smooth weighted round-robin state, only updated under `Balancer::weighted_lock`
+    current_weight: AtomicI64,
+    /// Channels this SFU is given at most, see `SfuConfig::max_channels`
+    pub max_channels: Option<u32>,
+    /// When the channels assigned to this SFU within `channel_lifetime` were, oldest first
+    assignments: Mutex<VecDeque<Instant>>,
+    /// How long an assigned channel counts, see `Balancer::with_channel_lifetime`
+    channel_lifetime: Duration,
+    /// Times this SFU was returned by a selection
+    selected: AtomicU64,
+    /// Whether the `key` claim is forwarded to this SFU, see `SfuConfig::accepts_recording_key`
+    pub accepts_recording_key: bool,
+    /// Prepended to the forwarded paths, see `SfuConfig::path_prefix`
+    pub path_prefix: String,
+    /// Algorithm of the tokens signed for this SFU, see `SfuConfig::algorithm`
+    pub algorithm: Algorithm,
+    /// `kid` of the tokens signed for this SFU, see `SfuConfig::kid`
+    pub kid: Option<String>,
+    /// Left out of selection while set, for maintenance, see `Balancer::set_draining`
+    draining: AtomicBool,
 }
 
 impl From<SfuConfig> for SfuInstance {
     fn from(config: SfuConfig) -> Self {
         Self {
             address: config.address,
-            region: config.region,
-            key: config.key,
+            regions: config.regions,
+            key: RwLock::new(config.key),
+            health_check: config.health_check,
+            headers: config.headers,
+            health: Arc::new(HealthState::default()),
+            added_at: None,
+            weight: config.weight,
+            current_weight: AtomicI64::new(0),
+            max_channels: config.max_channels,
+            assignments: Mutex::new(VecDeque::new()),
+            channel_lifetime: DEFAULT_CHANNEL_LIFETIME,
+            selected: AtomicU64::new(0),
+            accepts_recording_key: config.accepts_recording_key,
+            path_prefix: config.path_prefix,
+            algorithm: config.algorithm,
+            kid: config.kid,
+            draining: AtomicBool::new(false),
+        }
+    }
+}
+
+/// URL of `path` on the SFU at `address`, behind its path prefix, with `query` when there is one.
+/// Forwarded requests and health probes alike go through it.
+pub(crate) fn sfu_url(address: &str, path_prefix: &str, path: &str, query: &str) -> String {
+    let mut url = format!("{address}{path_prefix}{path}");
+    if !query.is_empty() {
+        url.push('?');
+        url.push_str(query);
+    }
+    url
+}
+
+impl SfuInstance {
+    /// Stable hash of the address, tells SFUs apart without revealing where they are. The same
+    /// on every gateway replica.
+    #[must_use]
+    pub fn address_hash(&self) -> String {
+        format!("{:016x}", stable_hash(&[self.address.as_bytes()]))
+    }
+
+    /// Header of the tokens signed for this SFU, with its algorithm and `kid`.
+    #[must_use]
+    pub fn token_header(&self) -> Header {
+        Header {
+            kid: self.kid.clone(),
+            ..Header::new(self.algorithm)
+        }
+    }
+
+    /// Current JWT secret key of this SFU.
+    pub fn key(&self) -> Vec<u8> {
+        self.key
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    fn set_key(&self, key: Vec<u8>) {
+        *self.key.write().unwrap_or_else(PoisonError::into_inner) = key;
+    }
+
+    /// Channels assigned to this SFU within the channel lifetime.
+    ///
+    /// An approximation of its live channels: the gateway doesn't learn when a channel closes,
+    /// so a channel is assumed to last the channel lifetime (`SFU_GATEWAY_CHANNEL_LIFETIME_MS`).
+    pub fn assigned(&self) -> u32 {
+        self.assigned_at(Instant::now())
+    }
+
+    fn assigned_at(&self, now: Instant) -> u32 {
+        let mut assignments = self.assignments();
+        while assignments
+            .front()
+            .is_some_and(|at| now.saturating_duration_since(*at) >= self.channel_lifetime)
+        {
+            assignments.pop_front();
+        }
+        u32::try_from(assignments.len()).unwrap_or(u32::MAX)
+    }
+
+    /// Count a channel assigned to this SFU, after a successful forward.
+    pub fn record_assignment(&self) {
+        self.record_assignment_at(Instant::now());
+    }
+
+    fn record_assignment_at(&self, at: Instant) {
+        self.assignments().push_back(at);
+    }
+
+    fn assignments(&self) -> std::sync::MutexGuard<'_, VecDeque<Instant>> {
+        self.assignments
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Times this SFU was returned by a selection, whether the forward succeeded or not.
+    pub fn selected(&self) -> u64 {
+        self.selected.load(Ordering::Relaxed)
+    }
+
+    /// Count a selection of this SFU and hand it out.
+    fn record_selection(&self) -> &Self {
+        self.selected.fetch_add(1, Ordering::Relaxed);
+        self
+    }
+
+    /// Forget the oldest channel assigned to this SFU, for callers that learn when channels end.
+    pub fn release_assignment(&self) {
+        self.assignments().pop_front();
+    }
+
+    /// Whether the SFU is being drained: it stays configured and reported, but gets no new
+    /// channels.
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::Relaxed)
+    }
+
+    /// Whether `select` may pick this SFU, healthy and not draining.
+    fn is_selectable(&self) -> bool {
+        self.health.is_healthy() && !self.is_draining()
+    }
+
+    /// Whether the SFU was given its `max_channels` within the channel lifetime.
+    pub fn is_at_capacity(&self) -> bool {
+        self.max_channels.is_some_and(|max| self.assigned() >= max)
+    }
+
+    /// Weight in per mille of the full weight, ramping linearly during slow start.
+    fn effective_weight(&self, slow_start: Option<Duration>, now: Instant) -> u64 {
+        let (Some(ramp), Some(added_at)) = (slow_start, self.added_at) else {
+            return 1000;
+        };
+        let elapsed = now.saturating_duration_since(added_at);
+        if elapsed >= ramp {
+            return 1000;
         }
+        let progress = elapsed.as_millis() * 1000 / ramp.as_millis().max(1);
+        let ramped = u64::try_from(progress).unwrap_or(1000);
+        SLOW_START_INITIAL_PER_MILLE + (1000 - SLOW_START_INITIAL_PER_MILLE) * ramped / 1000
     }
 }
 
 impl Balancer {
+    #[must_use]
     pub fn new(sfu_configs: Vec<SfuConfig>) -> Self {
-        let sfus = sfu_configs.into_iter().map(SfuInstance::from).collect();
+        Self::with_rng(sfu_configs, SelectionRng::from_entropy())
+    }
+
+    /// Same as `new`, but with a deterministic RNG so selection sequences are reproducible.
+    #[must_use]
+    pub fn with_seed(sfu_configs: Vec<SfuConfig>, seed: u64) -> Self {
+        Self::with_rng(sfu_configs, SelectionRng::from_seed(seed))
+    }
+
+    fn with_rng(sfu_configs: Vec<SfuConfig>, rng: SelectionRng) -> Self {
+        let sfus: Vec<_> = sfu_configs.into_iter().map(SfuInstance::from).collect();
+        let region_index = build_region_index(&sfus);
+        let region_counters = region_counters(&region_index, |_| 0);
         Self {
             sfus,
+            region_index,
             counter: AtomicUsize::new(0),
+            region_counters,
+            weighted_lock: Mutex::new(()),
+            affinity: None,
+            slow_start: None,
+            channel_lifetime: DEFAULT_CHANNEL_LIFETIME,
+            strategy: SelectionStrategy::RoundRobin,
+            max_fallback_km: None,
+            rng,
+            health_checker: None,
+            shutdown: ShutdownToken::new(),
+        }
+    }
+
+    /// Same as `new`, with a background task probing every SFU each `interval`.
+    ///
+    /// See `spawn_health_checks`, must be called within a tokio runtime.
+    #[must_use]
+    pub fn with_health_checks(
+        sfu_configs: Vec<SfuConfig>,
+        http_client: reqwest::Client,
+        interval: Duration,
+    ) -> Self {
+        Self::new(sfu_configs).spawn_health_checks(
+            http_client,
+            HealthCheckConfig {
+                interval,
+                ..HealthCheckConfig::default()
+            },
+        )
+    }
+
+    /// Probe the SFUs in the background and skip those marked unhealthy in `select`.
+    ///
+    /// Only the instances known at this point are probed. The task stops when the balancer
+    /// is dropped or its shutdown token (see `with_shutdown`) is triggered. Must be called
+    /// within a tokio runtime.
+    #[must_use]
+    pub fn spawn_health_checks(
+        mut self,
+        http_client: reqwest::Client,
+        config: HealthCheckConfig,
+    ) -> Self {
+        self.health_checker = Some(HealthChecker::spawn(
+            probe_targets(&self.sfus, config),
+            http_client,
+            config,
+            self.shutdown.clone(),
+        ));
+        self
+    }
+
+    /// Balancer for a new set of SFUs, with the settings and state of this one.
+    ///
+    /// SFUs already known (by address) keep their health, assigned channels and selection count, the others are new instances
+    /// subject to slow start. Affinity is shared and the health checks move to the new
+    /// balancer: this one stops probing. Must be called within a tokio runtime when health
+    /// checks are enabled.
+    #[must_use]
+    pub fn reconfigure(&self, sfu_configs: Vec<SfuConfig>) -> Self {
+        let now = Instant::now();
+        let sfus: Vec<_> = sfu_configs
+            .into_iter()
+            .map(
+                |config| match self.sfus.iter().find(|sfu| sfu.address == config.address) {
+                    Some(known) => SfuInstance {
+                        health: Arc::clone(&known.health),
+                        added_at: known.added_at,
+                        assignments: Mutex::new(known.assignments().clone()),
+                        channel_lifetime: self.channel_lifetime,
+                        selected: AtomicU64::new(known.selected()),
+                        draining: AtomicBool::new(known.is_draining()),
+                        ..SfuInstance::from(config)
+                    },
+                    None => SfuInstance {
+                        added_at: Some(now),
+                        channel_lifetime: self.channel_lifetime,
+                        ..SfuInstance::from(config)
+                    },
+                },
+            )
+            .collect();
+        let health_checker = self.health_checker.as_ref().map(|checker| {
+            checker.stop();
+            HealthChecker::spawn(
+                probe_targets(&sfus, checker.config),
+                checker.client.clone(),
+                checker.config,
+                self.shutdown.clone(),
+            )
+        });
+        let region_index = build_region_index(&sfus);
+        // regions already known carry on where they were
+        let region_counters = region_counters(&region_index, |region| {
+            self.region_counters
+                .get(region)
+                .map_or(0, |counter| counter.load(Ordering::Relaxed))
+        });
+        Self {
+            region_index,
+            region_counters,
+            sfus,
+            counter: AtomicUsize::new(self.counter.load(Ordering::Relaxed)),
+            weighted_lock: Mutex::new(()),
+            affinity: self.affinity.clone(),
+            slow_start: self.slow_start,
+            channel_lifetime: self.channel_lifetime,
+            strategy: self.strategy,
+            max_fallback_km: self.max_fallback_km,
+            rng: SelectionRng::from_seed(self.rng.next_u64()),
+            health_checker,
+            shutdown: self.shutdown.clone(),
+        }
+    }
+
+    /// Reuse the SFU last selected for an issuer during `ttl`, remembering at most
+    /// `max_entries` issuers.
+    #[must_use]
+    pub fn with_affinity(mut self, ttl: Duration, max_entries: usize) -> Self {
+        self.affinity = Some(Arc::new(AffinityCache::new(ttl, max_entries)));
+        self
+    }
+
+    /// Ramp the traffic of SFUs added later with `add_sfu` up to their full weight over
+    /// `duration`, instead of giving them their full share right away.
+    #[must_use]
+    pub const fn with_slow_start(mut self, duration: Duration) -> Self {
+        self.slow_start = Some(duration);
+        self
+    }
+
+    /// Count an assigned channel towards its SFU's load (`max_channels`, least-connections)
+    /// for `lifetime`, the expected duration of a channel, instead of `DEFAULT_CHANNEL_LIFETIME`.
+    #[must_use]
+    pub fn with_channel_lifetime(mut self, lifetime: Duration) -> Self {
+        self.channel_lifetime = lifetime;
+        for sfu in &mut self.sfus {
+            sfu.channel_lifetime = lifetime;
+        }
+        self
+    }
+
+    /// Stop the background tasks spawned afterwards (health checks) when `shutdown` is
+    /// triggered.
+    #[must_use]
+    pub fn with_shutdown(mut self, shutdown: ShutdownToken) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    /// Pick SFUs with `strategy`, `SelectionStrategy::Sticky` takes precedence over affinity.
+    #[must_use]
+    pub const fn with_strategy(mut self, strategy: SelectionStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Don't fall back to regions further than `max_km` from the hinted one: when none of
+    /// them has a usable SFU, selection fails rather than routing across the world.
+    #[must_use]
+    pub const fn with_max_fallback_km(mut self, max_km: f64) -> Self {
+        self.max_fallback_km = Some(max_km);
+        self
+    }
+
+    /// Add an SFU to the balancer, subject to slow start if enabled.
+    pub fn add_sfu(&mut self, sfu_config: SfuConfig) {
+        self.add_sfu_at(sfu_config, Instant::now());
+    }
+
+    fn add_sfu_at(&mut self, sfu_config: SfuConfig, now: Instant) {
+        index_regions(&mut self.region_index, self.sfus.len(), &sfu_config.regions);
+        for region in &sfu_config.regions {
+            self.region_counters
+                .entry(region.clone())
+                .or_insert_with(|| AtomicUsize::new(0));
+        }
+        self.sfus.push(SfuInstance {
+            added_at: Some(now),
+            channel_lifetime: self.channel_lifetime,
+            ..SfuInstance::from(sfu_config)
+        });
+    }
+
+    /// Swap the keys of known SFUs with the ones in `sfu_configs`, matched by address.
+    ///
+    /// Health, counters and affinity are left untouched, entries for unknown addresses
+    /// are ignored: changing the topology takes a full reload. Returns the number of
+    /// keys that changed.
+    pub fn reload_keys(&self, sfu_configs: &[SfuConfig]) -> usize {
+        let mut changed = 0;
+        for config in sfu_configs {
+            let Some(sfu) = self.sfus.iter().find(|sfu| sfu.address == config.address) else {
+                warn!(address = %config.address, "Ignoring key of unknown SFU");
+                continue;
+            };
+            if !constant_time_eq(&sfu.key(), &config.key) {
+                sfu.set_key(config.key.clone());
+                info!(address = %sfu.address, "SFU key rotated");
+                changed += 1;
+            }
+        }
+        changed
+    }
+
+    /// All SFUs, healthy or not, in configuration order.
+    #[must_use]
+    pub fn instances(&self) -> &[SfuInstance] {
+        &self.sfus
+    }
+
+    /// Whether background health checks keep the instances' health up to date.
+    #[must_use]
+    pub const fn has_health_checks(&self) -> bool {
+        self.health_checker.is_some()
+    }
+
+    /// The instances as the balancer currently sees them, for the status endpoint and tests.
+    #[must_use]
+    pub fn snapshot(&self) -> BalancerSnapshot {
+        BalancerSnapshot {
+            health_checks: self.has_health_checks(),
+            sfus: self
+                .sfus
+                .iter()
+                .map(|sfu| SfuSnapshot {
+                    address: sfu.address.clone(),
+                    regions: sfu.regions.clone(),
+                    healthy: sfu.health.is_healthy(),
+                    last_check: sfu
+                        .health
+                        .last_check()
+                        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                        .map(|elapsed| elapsed.as_secs()),
+                    draining: sfu.is_draining(),
+                    weight: sfu.weight,
+                    max_channels: sfu.max_channels,
+                    assigned: sfu.assigned(),
+                    selected: sfu.selected(),
+                })
+                .collect(),
         }
     }
 
-    /// Get available regions from configured SFUs
-    fn available_regions(&self) -> Vec<&str> {
-        self.sfus
+    /// Whether some SFU is configured, healthy and not draining, SFUs count as healthy until
+    /// probed otherwise.
+    #[must_use]
+    pub fn has_healthy_sfu(&self) -> bool {
+        self.selectable_sfus().next().is_some()
+    }
+
+    /// Stop (`draining`) or resume giving new channels to the SFU at `address`, without
+    /// removing it. Returns whether such an SFU is known.
+    pub fn set_draining(&self, address: &str, draining: bool) -> bool {
+        let Some(sfu) = self.sfus.iter().find(|sfu| sfu.address == address) else {
+            return false;
+        };
+        sfu.draining.store(draining, Ordering::Relaxed);
+        info!(address, draining, "SFU drain state changed");
+        true
+    }
+
+    /// Problems of the configured regions, for a check at startup, health and drain aside.
+    ///
+    /// Flags every SFU region unknown to the geo table, and every known region whose
+    /// requests would find no SFU: with no SFU at all, or none within the fallback distance.
+    #[must_use]
+    pub fn region_issues(&self) -> Vec<RegionIssue> {
+        let mut issues: Vec<_> = self
+            .sfus
             .iter()
-            .filter_map(|sfu| sfu.region.as_deref())
-            .collect()
+            .flat_map(|sfu| {
+                sfu.regions
+                    .iter()
+                    .filter(|region| !is_known_region(region))
+                    .map(|region| RegionIssue::UnknownRegion {
+                        address: sfu.address.clone(),
+                        region: region.clone(),
+                    })
+            })
+            .collect();
+        // without a fallback distance, requests matching no region fall back to all SFUs
+        let reachable = |region: &str| {
+            self.max_fallback_km
+                .map_or(!self.sfus.is_empty(), |max_km| {
+                    region_fallback_order_within(region, max_km)
+                        .iter()
+                        .any(|candidate| self.region_index.contains_key(*candidate))
+                })
+        };
+        let mut unreachable: Vec<_> = known_regions()
+            .into_iter()
+            .map(|(region, _, _)| region)
+            .filter(|region| !reachable(region))
+            .collect();
+        unreachable.sort_unstable();
+        issues.extend(
+            unreachable
+                .into_iter()
+                .map(|region| RegionIssue::Unreachable {
+                    region: region.to_string(),
+                }),
+        );
+        issues
     }
 
-    /// Filter SFUs by region
+    /// The SFU `pin` names, by address or by index in configuration order, healthy or not.
+    #[must_use]
+    pub fn pinned(&self, pin: &str) -> Option<&SfuInstance> {
+        pin.parse::<usize>().map_or_else(
+            |_| {
+                let address = pin.trim_end_matches('/');
+                self.sfus.iter().find(|sfu| sfu.address == address)
+            },
+            |index| self.sfus.get(index),
+        )
+    }
+
+    /// Selectable SFUs serving a region, in configuration order
     fn sfus_in_region(&self, region: &str) -> Vec<&SfuInstance> {
-        self.sfus
-            .iter()
-            .filter(|sfu| sfu.region.as_deref() == Some(region))
+        self.region_index
+            .get(region)
+            .into_iter()
+            .flatten()
+            .map(|&index| &self.sfus[index])
+            .filter(|sfu| sfu.is_selectable())
             .collect()
     }
 
-    /// Select an SFU using round-robin from candidates
-    fn round_robin_select<'a>(&self, candidates: &[&'a SfuInstance]) -> Option<&'a SfuInstance> {
+    fn selectable_sfus(&self) -> impl Iterator<Item = &SfuInstance> {
+        self.sfus.iter().filter(|sfu| sfu.is_selectable())
+    }
+
+    /// Random pick weighted by the effective weights, only while some candidate is ramping up.
+    ///
+    /// Returns `None` when all candidates are at full weight, round-robin applies then.
+    fn slow_start_select<'a>(
+        &self,
+        candidates: &[&'a SfuInstance],
+        now: Instant,
+    ) -> Option<&'a SfuInstance> {
+        if candidates
+            .iter()
+            .all(|sfu| sfu.effective_weight(self.slow_start, now) == 1000)
+        {
+            return None;
+        }
+        self.weighted_random_select(candidates, now)
+    }
+
+    /// Random pick weighted by the SFU weights scaled by their slow start ramp.
+    ///
+    /// Draws from the balancer's RNG, a seeded balancer gives the same sequence of picks.
+    fn weighted_random_select<'a>(
+        &self,
+        candidates: &[&'a SfuInstance],
+        now: Instant,
+    ) -> Option<&'a SfuInstance> {
+        let ramps = candidates
+            .iter()
+            .map(|sfu| sfu.effective_weight(self.slow_start, now));
+        let weights: Vec<_> = candidates
+            .iter()
+            .zip(ramps)
+            .map(|(sfu, ramp)| ramp * u64::from(sfu.weight))
+            .collect();
+        let total = usize::try_from(weights.iter().sum::<u64>()).ok()?;
+        let mut draw = u64::try_from(self.rng.next_below(total)).ok()?;
+        candidates
+            .iter()
+            .zip(weights)
+            .find(|(_, weight)| {
+                let hit = draw < *weight;
+                draw = draw.saturating_sub(*weight);
+                hit
+            })
+            .map(|(sfu, _)| *sfu)
+    }
+
+    /// Smooth weighted round-robin (as in nginx) among candidates of different weights.
+    ///
+    /// Each step adds its weight to every candidate's current weight, picks the highest and
+    /// lowers it by the total: picks are proportional to the weights and interleaved (3:1
+    /// gives A A B A). Deterministic, it doesn't use the counter nor the RNG.
+    /// Returns `None` when all candidates have the same weight, round-robin applies then.
+    fn weighted_select<'a>(&self, candidates: &[&'a SfuInstance]) -> Option<&'a SfuInstance> {
+        let first = candidates.first()?;
+        if candidates.iter().all(|sfu| sfu.weight == first.weight) {
+            return None;
+        }
+        let _step = self
+            .weighted_lock
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let mut selected: Option<(&SfuInstance, i64)> = None;
+        for sfu in candidates {
+            let weight = i64::from(sfu.weight);
+            let current = sfu.current_weight.fetch_add(weight, Ordering::Relaxed) + weight;
+            if selected.is_none_or(|(_, best)| current > best) {
+                selected = Some((sfu, current));
+            }
+        }
+        let total: i64 = candidates.iter().map(|sfu| i64::from(sfu.weight)).sum();
+        let (sfu, _) = selected?;
+        sfu.current_weight.fetch_sub(total, Ordering::Relaxed);
+        Some(sfu)
+    }
+
+    /// The candidates with the fewest assigned channels for their weight, round-robin among
+    /// them.
+    ///
+    /// The count is of the channels given out within the channel lifetime (see
+    /// `SfuInstance::assigned`), an approximation of the live ones: old history expires, so an
+    /// SFU that was idle or busy long ago is weighed on its recent load only.
+    fn least_connections_select<'a>(
+        candidates: &[&'a SfuInstance],
+        counter: &AtomicUsize,
+    ) -> Option<&'a SfuInstance> {
+        // assigned / weight, compared without division
+        let load = |sfu: &SfuInstance| (u64::from(sfu.assigned()), u64::from(sfu.weight));
+        let (least, least_weight) = candidates
+            .iter()
+            .map(|sfu| load(sfu))
+            .min_by(|(a, a_weight), (b, b_weight)| (a * b_weight).cmp(&(b * a_weight)))?;
+        let tied: Vec<_> = candidates
+            .iter()
+            .copied()
+            .filter(|sfu| {
+                let (assigned, weight) = load(sfu);
+                assigned * least_weight == least * weight
+            })
+            .collect();
+        Self::round_robin_select(&tied, counter)
+    }
+
+    /// Candidate with the lowest probe round-trip time, round-robin among those within
+    /// `LATENCY_TOLERANCE_PERCENT` of it so that a few microseconds don't send all the traffic
+    /// to one SFU. Candidates not measured yet are left out.
+    ///
+    /// Returns `None` when no candidate was measured, without health checks for instance.
+    fn lowest_latency_select<'a>(
+        candidates: &[&'a SfuInstance],
+        counter: &AtomicUsize,
+    ) -> Option<&'a SfuInstance> {
+        let lowest = candidates
+            .iter()
+            .filter_map(|sfu| sfu.health.latency())
+            .min()?;
+        let tolerated = lowest + lowest * LATENCY_TOLERANCE_PERCENT / 100;
+        let fastest: Vec<_> = candidates
+            .iter()
+            .copied()
+            .filter(|sfu| sfu.health.latency().is_some_and(|rtt| rtt <= tolerated))
+            .collect();
+        Self::round_robin_select(&fastest, counter)
+    }
+
+    /// Select an SFU using round-robin from candidates, advancing `counter`
+    fn round_robin_select<'a>(
+        candidates: &[&'a SfuInstance],
+        counter: &AtomicUsize,
+    ) -> Option<&'a SfuInstance> {
         if candidates.is_empty() {
             return None;
         }
-        let index = self.counter.fetch_add(1, Ordering::Relaxed) % candidates.len();
+        let index = counter.fetch_add(1, Ordering::Relaxed) % candidates.len();
         Some(candidates[index])
     }
 
-    /// Select an SFU instance based on optional region hint.
+    /// Instances `select` would draw from for these region hints, in priority order.
     ///
-    /// Strategy:
-    /// 1. If `region_hint` is provided, try to find SFUs in that region
-    /// 2. If no SFUs in that region, try nearby regions in order of proximity
-    /// 3. Fall back to round-robin among all SFUs
-    pub fn select(&self, region_hint: Option<&str>) -> Option<&SfuInstance> {
-        if self.sfus.is_empty() {
-            return None;
+    /// Doesn't pick one nor touch the round-robin counters. See `candidate_tiers`.
+    #[must_use]
+    pub fn candidates(&self, region_hints: &[&str]) -> Vec<&SfuInstance> {
+        self.candidate_tiers(region_hints)
+            .into_iter()
+            .flat_map(|tier| tier.sfus)
+            .collect()
+    }
+
+    /// Known regions SFUs are looked for in with these region hints, in order: the hinted
+    /// regions as listed, then the regions around the first one by proximity, within the
+    /// fallback distance if set.
+    ///
+    /// Unknown regions are left out, region hints never route to them.
+    #[must_use]
+    pub fn region_order<'a>(&self, region_hints: &[&'a str]) -> Vec<&'a str> {
+        let Some(preferred_region) = region_hints.first() else {
+            return Vec::new();
+        };
+        let fallback = self.max_fallback_km.map_or_else(
+            || region_fallback_order(preferred_region),
+            |max_km| region_fallback_order_within(preferred_region, max_km),
+        );
+        let mut order: Vec<&str> = Vec::new();
+        let listed = region_hints.iter().copied().filter(|r| is_known_region(r));
+        for region in listed.chain(fallback) {
+            if !order.contains(&region) {
+                order.push(region);
+            }
         }
+        order
+    }
 
-        let Some(preferred_region) = region_hint else {
-            let all: Vec<_> = self.sfus.iter().collect();
-            return self.round_robin_select(&all);
+    /// Candidates grouped by priority, `select` uses the first group with an SFU left that
+    /// isn't at capacity.
+    /// Unhealthy and draining SFUs are left out as if they didn't exist.
+    ///
+    /// `region_hints` lists the preferred regions, first choice first, empty when the request
+    /// has no hint.
+    ///
+    /// Strategy:
+    /// 1. If there are region hints, one group per hinted region with SFUs, in the listed
+    ///    order, then one per region with SFUs around the first hinted one, closest first,
+    ///    within the fallback distance if set
+    /// 2. Otherwise, or when no region matches (unknown regions), a single group of all SFUs,
+    ///    unless a fallback distance is set and the first hinted region is known: no group then
+    ///
+    /// A region group rotates with the counter of its region, the all-SFUs group with its own.
+    fn candidate_tiers(&self, region_hints: &[&str]) -> Vec<Tier<'_>> {
+        let all = || {
+            vec![Tier {
+                sfus: self.selectable_sfus().collect(),
+                counter: &self.counter,
+            }]
         };
+        let Some(preferred_region) = region_hints.first() else {
+            return all();
+        };
+
+        let order = self.region_order(region_hints);
+        let tiers: Vec<_> = order
+            .iter()
+            .filter_map(|candidate_region| {
+                let counter = self.region_counters.get(*candidate_region)?;
+                Some(Tier {
+                    sfus: self.sfus_in_region(candidate_region),
+                    counter,
+                })
+            })
+            .filter(|tier| !tier.sfus.is_empty())
+            .collect();
+
+        // a known region always comes first in its own order, the budget leaves it in
+        let bounded = self.max_fallback_km.is_some() && is_known_region(preferred_region);
+        if tiers.is_empty() && !bounded {
+            all()
+        } else {
+            tiers
+        }
+    }
+
+    /// Select an SFU instance based on the region hints, first choice first (none for no hint).
+    ///
+    /// Round-robin among the highest priority candidates, see `candidate_tiers`, weighted
+    /// when their weights differ. SFUs in slow start get a reduced share, SFUs at capacity
+    /// are skipped. With the least-connections strategy, the least loaded candidates are
+    /// picked instead and slow start doesn't apply. With the weighted-random strategy, a
+    /// candidate is drawn at random in proportion to its weight.
+    ///
+    /// # Errors
+    /// `SelectError::AllBusy` when every candidate left is at capacity, `SelectError::NoSfu`
+    /// when there is no candidate at all.
+    pub fn select(&self, region_hints: &[&str]) -> Result<&SfuInstance, SelectError> {
+        self.select_at(region_hints, Instant::now())
+    }
+
+    fn select_at(&self, region_hints: &[&str], now: Instant) -> Result<&SfuInstance, SelectError> {
+        self.select_excluding_at(region_hints, &[], now)
+    }
 
-        // available_regions should be build one at boot time
-        // and cached for the duration of the application
-        // later, when SFUs register themselves, available regions
-        // should be updated at runtime, but still not recomputed on access
-        let available = self.available_regions();
-        let fallback_order = region_fallback_order(preferred_region);
-
-        for candidate_region in &fallback_order {
-            if available.contains(candidate_region) {
-                // same as above, sfus_in_region should be a fast access data structure
-                // and built at boot time, or updated when the SFUs register
-                let candidates = self.sfus_in_region(candidate_region);
-                if !candidates.is_empty() {
-                    return self.round_robin_select(&candidates);
+    /// Same as `select`, but never returns an SFU whose address is in `excluded`.
+    ///
+    /// Used to retry on another instance, when every candidate of the highest priority
+    /// group was excluded the next group is used.
+    ///
+    /// # Errors
+    /// Same as `select`.
+    pub fn select_with_exclusions(
+        &self,
+        region_hints: &[&str],
+        excluded: &[&str],
+    ) -> Result<&SfuInstance, SelectError> {
+        self.select_excluding_at(region_hints, excluded, Instant::now())
+    }
+
+    fn select_excluding_at(
+        &self,
+        region_hints: &[&str],
+        excluded: &[&str],
+        now: Instant,
+    ) -> Result<&SfuInstance, SelectError> {
+        let Tier {
+            sfus: candidates,
+            counter,
+        } = self.first_tier(region_hints, excluded)?;
+        self.pick(&candidates, counter, now)
+            .map(SfuInstance::record_selection)
+            .ok_or(SelectError::NoSfu)
+    }
+
+    /// One of `candidates`, according to the strategy.
+    fn pick<'a>(
+        &self,
+        candidates: &[&'a SfuInstance],
+        counter: &AtomicUsize,
+        now: Instant,
+    ) -> Option<&'a SfuInstance> {
+        match self.strategy {
+            SelectionStrategy::LeastConnections => {
+                return Self::least_connections_select(candidates, counter);
+            }
+            SelectionStrategy::LowestLatency => {
+                if let Some(sfu) = Self::lowest_latency_select(candidates, counter) {
+                    return Some(sfu);
                 }
             }
+            SelectionStrategy::WeightedRandom => {
+                return self.weighted_random_select(candidates, now);
+            }
+            SelectionStrategy::RoundRobin | SelectionStrategy::Sticky => {}
+        }
+        self.slow_start_select(candidates, now)
+            .or_else(|| self.weighted_select(candidates))
+            .or_else(|| Self::round_robin_select(candidates, counter))
+    }
+
+    /// Highest priority candidates once `excluded` is left out, without those at capacity.
+    ///
+    /// A group whose candidates are all at capacity is skipped like one without healthy SFUs,
+    /// the next closest region with room is used. When every group left is at capacity it is
+    /// `SelectError::AllBusy`, the request can be retried later.
+    fn first_tier(
+        &self,
+        region_hints: &[&str],
+        excluded: &[&str],
+    ) -> Result<Tier<'_>, SelectError> {
+        let mut saturated = false;
+        for tier in self.candidate_tiers(region_hints) {
+            let (available, busy): (Vec<_>, Vec<_>) = tier
+                .sfus
+                .into_iter()
+                .filter(|sfu| !excluded.contains(&sfu.address.as_str()))
+                .partition(|sfu| !sfu.is_at_capacity());
+            if !available.is_empty() {
+                return Ok(Tier {
+                    sfus: available,
+                    counter: tier.counter,
+                });
+            }
+            saturated |= !busy.is_empty();
+        }
+        Err(if saturated {
+            SelectError::AllBusy
+        } else {
+            SelectError::NoSfu
+        })
+    }
+
+    /// Select the SFU `key` hashes to among the highest priority candidates.
+    ///
+    /// Rendezvous hashing weighted by the SFU weights: the same key gets the same SFU while
+    /// the candidates don't change, and adding or removing an SFU only moves the keys that
+    /// belong to it. The hash is stable across restarts and gateway replicas. Slow start
+    /// doesn't apply, SFUs at capacity are skipped.
+    ///
+    /// # Errors
+    /// Same as `select`.
+    pub fn select_sticky(
+        &self,
+        region_hints: &[&str],
+        key: &str,
+        excluded: &[&str],
+    ) -> Result<&SfuInstance, SelectError> {
+        self.first_tier(region_hints, excluded)?
+            .sfus
+            .into_iter()
+            .max_by(|a, b| {
+                rendezvous_score(key, a)
+                    .total_cmp(&rendezvous_score(key, b))
+                    // equal scores (practically never) are broken by address, for determinism
+                    .then_with(|| b.address.cmp(&a.address))
+            })
+            .map(SfuInstance::record_selection)
+            .ok_or(SelectError::NoSfu)
+    }
+
+    /// Same as `select`, but keeps an issuer on the SFU it was last given while the
+    /// affinity window is open and that SFU is still among the candidates `select` would pick
+    /// from for `region_hints`: healthy, not at capacity and in the first group of regions.
+    ///
+    /// With the sticky strategy, the issuer's SFU is chosen with `select_sticky` instead.
+    ///
+    /// # Errors
+    /// Same as `select`.
+    pub fn select_for_issuer(
+        &self,
+        region_hints: &[&str],
+        issuer: &str,
+    ) -> Result<&SfuInstance, SelectError> {
+        self.select_for_issuer_at(region_hints, issuer, &[], Instant::now())
+    }
+
+    /// Same as `select_for_issuer`, with the exclusions of `select_with_exclusions`.
+    ///
+    /// The issuer is moved to the newly selected SFU when its affine one is excluded or at
+    /// capacity.
+    ///
+    /// # Errors
+    /// Same as `select`.
+    pub fn select_for_issuer_with_exclusions(
+        &self,
+        region_hints: &[&str],
+        issuer: &str,
+        excluded: &[&str],
+    ) -> Result<&SfuInstance, SelectError> {
+        self.select_for_issuer_at(region_hints, issuer, excluded, Instant::now())
+    }
+
+    fn select_for_issuer_at(
+        &self,
+        region_hints: &[&str],
+        issuer: &str,
+        excluded: &[&str],
+        now: Instant,
+    ) -> Result<&SfuInstance, SelectError> {
+        if self.strategy == SelectionStrategy::Sticky {
+            return self.select_sticky(region_hints, issuer, excluded);
+        }
+        let Some(affinity) = &self.affinity else {
+            return self.select_excluding_at(region_hints, excluded, now);
+        };
+
+        // the affine SFU is only kept while it is one the hints would select from
+        let tier = self.first_tier(region_hints, excluded);
+        let affine = tier.as_ref().ok().and_then(|tier| {
+            let address = affinity.get(issuer, now)?;
+            tier.sfus.iter().copied().find(|sfu| sfu.address == address)
+        });
+        if let Some(sfu) = affine {
+            return Ok(sfu.record_selection());
+        }
+
+        let selected = tier.and_then(|tier| {
+            self.pick(&tier.sfus, tier.counter, now)
+                .map(SfuInstance::record_selection)
+                .ok_or(SelectError::NoSfu)
+        });
+        match selected {
+            Ok(sfu) => affinity.insert(issuer, &sfu.address, now),
+            Err(_) => affinity.remove(issuer),
         }
+        selected
+    }
+}
+
+impl Sweep for Balancer {
+    /// Sweeps the affinity cache, shared with the reconfigured balancers.
+    fn sweep(&self, now: Instant, ttl: Duration) -> usize {
+        self.affinity
+            .as_ref()
+            .map_or(0, |affinity| affinity.sweep(now, ttl))
+    }
+}
+
+/// Weighted rendezvous score of an SFU for `key`, the highest score wins.
+fn rendezvous_score(key: &str, sfu: &SfuInstance) -> f64 {
+    let hash = stable_hash(&[key.as_bytes(), b"\0", sfu.address.as_bytes()]);
+    let high = u32::try_from(hash >> 32).unwrap_or(u32::MAX);
+    let low = u32::try_from(hash & 0xFFFF_FFFF).unwrap_or(0);
+    // uniform in (0, 1)
+    let unit =
+        (f64::from(high) * 4_294_967_296.0 + f64::from(low) + 0.5) / 18_446_744_073_709_551_616.0;
+    -f64::from(sfu.weight) / unit.ln()
+}
+
+/// FNV-1a with a `SplitMix64` finalizer: unlike `std`'s hashers, it doesn't change between
+/// processes or Rust releases, so every gateway replica maps a key the same way.
+fn stable_hash(parts: &[&[u8]]) -> u64 {
+    let mut hash: u64 = 0xCBF2_9CE4_8422_2325;
+    for byte in parts.iter().flat_map(|part| part.iter()) {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x0000_0100_0000_01B3);
+    }
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    hash ^ (hash >> 31)
+}
+
+/// What the health checks probe for each instance.
+fn probe_targets(sfus: &[SfuInstance], config: HealthCheckConfig) -> Vec<ProbeTarget> {
+    sfus.iter()
+        .map(|sfu| ProbeTarget {
+            address: sfu.address.clone(),
+            path_prefix: sfu.path_prefix.clone(),
+            mode: sfu.health_check.unwrap_or(config.mode),
+            health: Arc::clone(&sfu.health),
+        })
+        .collect()
+}
+
+/// Indices of the instances serving each region.
+fn build_region_index(sfus: &[SfuInstance]) -> HashMap<String, Vec<usize>> {
+    let mut index = HashMap::new();
+    for (i, sfu) in sfus.iter().enumerate() {
+        index_regions(&mut index, i, &sfu.regions);
+    }
+    index
+}
 
-        // Fallback to all SFUs if no proximity match (unknown region or no match)
-        let all: Vec<_> = self.sfus.iter().collect();
-        self.round_robin_select(&all)
+/// A round-robin counter for each region of `region_index`, starting at `start(region)`.
+fn region_counters(
+    region_index: &HashMap<String, Vec<usize>>,
+    mut start: impl FnMut(&str) -> usize,
+) -> HashMap<String, AtomicUsize> {
+    region_index
+        .keys()
+        .map(|region| (region.clone(), AtomicUsize::new(start(region))))
+        .collect()
+}
+
+/// Add the instance at `i` to the index of each of its regions.
+fn index_regions(index: &mut HashMap<String, Vec<usize>>, i: usize, regions: &[String]) {
+    for region in regions {
+        let indices = index.entry(region.clone()).or_default();
+        // a region listed twice on the same SFU keeps a single entry
+        if indices.last() != Some(&i) {
+            indices.push(i);
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::routing::HealthThresholds;
 
     fn make_sfu(address: &str, region: Option<&str>, key: &[u8]) -> SfuConfig {
         SfuConfig {
             address: address.to_string(),
-            region: region.map(String::from),
+            regions: region.into_iter().map(String::from).collect(),
             key: key.to_vec(),
+            ..Default::default()
         }
     }
 
+    #[test]
+    fn test_sfu_url_with_and_without_prefix() {
+        assert_eq!(
+            sfu_url("http://sfu1:3000", "", "/v1/channel", ""),
+            "http://sfu1:3000/v1/channel"
+        );
+        assert_eq!(
+            sfu_url("http://sfu1:3000", "", "/v1/channel", "webRTC=true"),
+            "http://sfu1:3000/v1/channel?webRTC=true"
+        );
+        assert_eq!(
+            sfu_url("http://sfu2:3000", "/sfu", "/v1/channel", "webRTC=true"),
+            "http://sfu2:3000/sfu/v1/channel?webRTC=true"
+        );
+        assert_eq!(
+            sfu_url("http://sfu2:3000", "/sfu", "/noop", ""),
+            "http://sfu2:3000/sfu/noop"
+        );
+    }
+
     #[test]
     fn test_round_robin_selection() {
         let balancer = Balancer::new(vec![
@@ -122,10 +1197,10 @@ mod tests {
             make_sfu("http://sfu3:3000", None, b"key3-padded-to-32-bytes-1234567"),
         ]);
 
-        let first = balancer.select(None).unwrap().address.clone();
-        let second = balancer.select(None).unwrap().address.clone();
-        let third = balancer.select(None).unwrap().address.clone();
-        let fourth = balancer.select(None).unwrap().address.clone();
+        let first = balancer.select(&[]).unwrap().address.clone();
+        let second = balancer.select(&[]).unwrap().address.clone();
+        let third = balancer.select(&[]).unwrap().address.clone();
+        let fourth = balancer.select(&[]).unwrap().address.clone();
 
         // Should cycle through all three
         assert_ne!(first, second);
@@ -155,10 +1230,10 @@ mod tests {
         ]);
 
         // Select from eu-west only
-        let selected = balancer.select(Some("eu-west")).unwrap();
+        let selected = balancer.select(&["eu-west"]).unwrap();
         assert!(selected.address.starts_with("http://eu"));
 
-        let selected = balancer.select(Some("eu-west")).unwrap();
+        let selected = balancer.select(&["eu-west"]).unwrap();
         assert!(selected.address.starts_with("http://eu"));
     }
 
@@ -178,7 +1253,7 @@ mod tests {
         ]);
 
         // Request eu-north, should fall back to eu-west (closest available)
-        let selected = balancer.select(Some("eu-north")).unwrap();
+        let selected = balancer.select(&["eu-north"]).unwrap();
         assert!(
             selected.address.contains("eu-west"),
             "Expected eu-west, got {}",
@@ -187,50 +1262,1393 @@ mod tests {
     }
 
     #[test]
-    fn test_fallback_to_distant_region() {
-        let balancer = Balancer::new(vec![make_sfu(
-            "http://us-east1:3000",
-            Some("us-east"),
-            b"key1-padded-to-32-bytes-1234567",
-        )]);
-
-        // Request ap-northeast, should eventually fall back to us-east
-        let selected = balancer.select(Some("ap-northeast")).unwrap();
-        assert_eq!(selected.address, "http://us-east1:3000");
-    }
-
-    #[test]
-    fn test_fallback_when_no_region_match() {
+    fn test_region_hints_in_order() {
         let balancer = Balancer::new(vec![
             make_sfu(
-                "http://eu1:3000",
+                "http://eu-west1:3000",
                 Some("eu-west"),
                 b"key1-padded-to-32-bytes-1234567",
             ),
             make_sfu(
-                "http://us1:3000",
+                "http://us-east1:3000",
                 Some("us-east"),
                 b"key2-padded-to-32-bytes-1234567",
             ),
         ]);
 
-        // Request unknown region, should fall back to any
-        let selected = balancer.select(Some("unknown-region")).unwrap();
-        assert!(!selected.address.is_empty());
+        // eu-north has no SFU: the second choice wins over the closest region
+        let selected = balancer.select(&["eu-north", "us-east"]).unwrap();
+        assert_eq!(selected.address, "http://us-east1:3000");
+        // the first listed region with SFUs is used
+        let selected = balancer.select(&["eu-west", "us-east"]).unwrap();
+        assert_eq!(selected.address, "http://eu-west1:3000");
+        // no listed region has SFUs: fallback around the first one
+        let selected = balancer.select(&["eu-north", "ap-south"]).unwrap();
+        assert_eq!(selected.address, "http://eu-west1:3000");
+        // unknown regions are skipped, as a single hint would be
+        let selected = balancer.select(&["mars", "us-east"]).unwrap();
+        assert_eq!(selected.address, "http://us-east1:3000");
+
+        let order = balancer.region_order(&["eu-north", "us-east", "eu-north", "mars"]);
+        assert_eq!(order[..3], ["eu-north", "us-east", "eu-central"]);
+        assert_eq!(order.iter().filter(|r| **r == "us-east").count(), 1);
+        assert!(!order.contains(&"mars"));
+        assert!(balancer.region_order(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_fallback_to_distant_region() {
+        let balancer = Balancer::new(vec![make_sfu(
+            "http://us-east1:3000",
+            Some("us-east"),
+            b"key1-padded-to-32-bytes-1234567",
+        )]);
+
+        // Request ap-northeast, should eventually fall back to us-east
+        let selected = balancer.select(&["ap-northeast"]).unwrap();
+        assert_eq!(selected.address, "http://us-east1:3000");
+    }
+
+    #[test]
+    fn test_fallback_within_max_distance() {
+        let sfus = || {
+            vec![make_sfu(
+                "http://eu-west1:3000",
+                Some("eu-west"),
+                b"key1-padded-to-32-bytes-1234567",
+            )]
+        };
+
+        let tight = Balancer::new(sfus()).with_max_fallback_km(1000.0);
+        assert_eq!(tight.select(&["ap-south"]).err(), Some(SelectError::NoSfu));
+        assert!(tight.candidates(&["ap-south"]).is_empty());
+        // unknown regions and requests without hint aren't bounded
+        assert!(tight.select(&["unknown-region"]).is_ok());
+        assert!(tight.select(&[]).is_ok());
+
+        let generous = Balancer::new(sfus()).with_max_fallback_km(20_000.0);
+        assert_eq!(
+            generous.select(&["ap-south"]).unwrap().address,
+            "http://eu-west1:3000"
+        );
+    }
+
+    #[test]
+    fn test_region_issues_reachable_config() {
+        let balancer = Balancer::new(vec![
+            make_sfu(
+                "http://eu-west1:3000",
+                Some("eu-west"),
+                b"key1-padded-to-32-bytes-1234567",
+            ),
+            make_sfu(
+                "http://us-east1:3000",
+                Some("us-east"),
+                b"key2-padded-to-32-bytes-1234567",
+            ),
+        ]);
+        assert!(balancer.region_issues().is_empty());
+        // every region falls back somewhere within the diameter of the earth
+        assert!(
+            balancer
+                .with_max_fallback_km(20_100.0)
+                .region_issues()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_region_issues_orphaned_regions() {
+        let balancer = Balancer::new(vec![
+            make_sfu(
+                "http://eu-west1:3000",
+                Some("eu-west"),
+                b"key1-padded-to-32-bytes-1234567",
+            ),
+            make_sfu(
+                "http://lost:3000",
+                Some("atlantis"),
+                b"key2-padded-to-32-bytes-1234567",
+            ),
+        ])
+        .with_max_fallback_km(1000.0);
+        let issues = balancer.region_issues();
+        assert_eq!(
+            issues[0],
+            RegionIssue::UnknownRegion {
+                address: "http://lost:3000".to_string(),
+                region: "atlantis".to_string(),
+            }
+        );
+        assert!(issues.contains(&RegionIssue::Unreachable {
+            region: "ap-south".to_string()
+        }));
+        assert!(!issues.contains(&RegionIssue::Unreachable {
+            region: "eu-west".to_string()
+        }));
+    }
+
+    #[test]
+    fn test_fallback_when_no_region_match() {
+        let balancer = Balancer::new(vec![
+            make_sfu(
+                "http://eu1:3000",
+                Some("eu-west"),
+                b"key1-padded-to-32-bytes-1234567",
+            ),
+            make_sfu(
+                "http://us1:3000",
+                Some("us-east"),
+                b"key2-padded-to-32-bytes-1234567",
+            ),
+        ]);
+
+        // Request unknown region, should fall back to any
+        let selected = balancer.select(&["unknown-region"]).unwrap();
+        assert!(!selected.address.is_empty());
+    }
+
+    #[test]
+    fn test_multi_region_sfu() {
+        let balancer = Balancer::new(vec![
+            SfuConfig {
+                address: "http://eu1:3000".to_string(),
+                regions: vec!["eu-west".to_string(), "eu-central".to_string()],
+                key: b"key1-padded-to-32-bytes-1234567".to_vec(),
+                ..Default::default()
+            },
+            make_sfu(
+                "http://us1:3000",
+                Some("us-east"),
+                b"key2-padded-to-32-bytes-1234567",
+            ),
+        ]);
+
+        for _ in 0..4 {
+            assert_eq!(
+                balancer.select(&["eu-west"]).unwrap().address,
+                "http://eu1:3000"
+            );
+            assert_eq!(
+                balancer.select(&["eu-central"]).unwrap().address,
+                "http://eu1:3000"
+            );
+            assert_eq!(
+                balancer.select(&["us-east"]).unwrap().address,
+                "http://us1:3000"
+            );
+        }
+    }
+
+    #[test]
+    fn test_candidates_exact_region_first() {
+        let balancer = Balancer::new(vec![
+            make_sfu(
+                "http://us1:3000",
+                Some("us-east"),
+                b"key1-padded-to-32-bytes-1234567",
+            ),
+            make_sfu(
+                "http://eu1:3000",
+                Some("eu-west"),
+                b"key2-padded-to-32-bytes-1234567",
+            ),
+            make_sfu(
+                "http://eu2:3000",
+                Some("eu-west"),
+                b"key3-padded-to-32-bytes-1234567",
+            ),
+        ]);
+        let counter = balancer.counter.load(Ordering::Relaxed);
+        let eu_counter = balancer.region_counters["eu-west"].load(Ordering::Relaxed);
+
+        let addresses: Vec<_> = balancer
+            .candidates(&["eu-west"])
+            .iter()
+            .map(|sfu| sfu.address.as_str())
+            .collect();
+
+        assert_eq!(
+            addresses,
+            ["http://eu1:3000", "http://eu2:3000", "http://us1:3000"]
+        );
+        assert_eq!(balancer.counter.load(Ordering::Relaxed), counter);
+        assert_eq!(
+            balancer.region_counters["eu-west"].load(Ordering::Relaxed),
+            eu_counter
+        );
+    }
+
+    #[test]
+    fn test_round_robin_per_region() {
+        let mut balancer = Balancer::with_seed(
+            vec![
+                make_sfu(
+                    "http://eu1:3000",
+                    Some("eu-west"),
+                    b"key1-padded-to-32-bytes-1234567",
+                ),
+                make_sfu(
+                    "http://eu2:3000",
+                    Some("eu-west"),
+                    b"key2-padded-to-32-bytes-1234567",
+                ),
+                make_sfu(
+                    "http://us1:3000",
+                    Some("us-east"),
+                    b"key3-padded-to-32-bytes-1234567",
+                ),
+                make_sfu(
+                    "http://us2:3000",
+                    Some("us-east"),
+                    b"key4-padded-to-32-bytes-1234567",
+                ),
+                make_sfu(
+                    "http://us3:3000",
+                    Some("us-east"),
+                    b"key5-padded-to-32-bytes-1234567",
+                ),
+            ],
+            7,
+        );
+
+        // whatever is selected in between, eu-west alternates between its two SFUs
+        let mut eu_picks = Vec::new();
+        for i in 0..6 {
+            eu_picks.push(balancer.select(&["eu-west"]).unwrap().address.clone());
+            for _ in 0..=i % 3 {
+                balancer.select(&["us-east"]).unwrap();
+                balancer.select(&[]).unwrap();
+            }
+        }
+        assert!(
+            eu_picks
+                .iter()
+                .all(|address| address.starts_with("http://eu"))
+        );
+        for pair in eu_picks.windows(2) {
+            assert_ne!(pair[0], pair[1], "{eu_picks:?}");
+        }
+
+        // a region first seen on an added SFU gets its own counter
+        balancer.add_sfu(make_sfu(
+            "http://ap1:3000",
+            Some("ap-south"),
+            b"key6-padded-to-32-bytes-1234567",
+        ));
+        assert!(balancer.region_counters.contains_key("ap-south"));
+
+        // and reconfiguring carries each region's rotation over
+        let eu_counter = balancer.region_counters["eu-west"].load(Ordering::Relaxed);
+        let reconfigured = balancer.reconfigure(vec![
+            make_sfu(
+                "http://eu1:3000",
+                Some("eu-west"),
+                b"key1-padded-to-32-bytes-1234567",
+            ),
+            make_sfu(
+                "http://eu2:3000",
+                Some("eu-west"),
+                b"key2-padded-to-32-bytes-1234567",
+            ),
+        ]);
+        assert_eq!(
+            reconfigured.region_counters["eu-west"].load(Ordering::Relaxed),
+            eu_counter
+        );
+        assert!(!reconfigured.region_counters.contains_key("us-east"));
+    }
+
+    #[test]
+    fn test_candidates_proximity_fallback() {
+        let balancer = Balancer::new(vec![
+            make_sfu(
+                "http://us1:3000",
+                Some("us-east"),
+                b"key1-padded-to-32-bytes-1234567",
+            ),
+            make_sfu(
+                "http://eu1:3000",
+                Some("eu-west"),
+                b"key2-padded-to-32-bytes-1234567",
+            ),
+            make_sfu(
+                "http://ec1:3000",
+                Some("eu-central"),
+                b"key3-padded-to-32-bytes-1234567",
+            ),
+            make_sfu("http://any:3000", None, b"key4-padded-to-32-bytes-1234567"),
+        ]);
+
+        let addresses: Vec<_> = balancer
+            .candidates(&["eu-north"])
+            .iter()
+            .map(|sfu| sfu.address.as_str())
+            .collect();
+
+        // Stockholm is closer to Berlin than to Paris, SFUs without region are never preferred
+        assert_eq!(
+            addresses,
+            ["http://ec1:3000", "http://eu1:3000", "http://us1:3000"]
+        );
+        assert_eq!(
+            balancer.select(&["eu-north"]).unwrap().address,
+            "http://ec1:3000"
+        );
+        assert_eq!(balancer.candidates(&["unknown-region"]).len(), 4);
+        assert_eq!(balancer.candidates(&[]).len(), 4);
+    }
+
+    #[test]
+    fn test_slow_start_ramps_new_sfu() {
+        let mut balancer = Balancer::with_seed(
+            vec![
+                make_sfu("http://sfu1:3000", None, b"key1-padded-to-32-bytes-1234567"),
+                make_sfu("http://sfu2:3000", None, b"key2-padded-to-32-bytes-1234567"),
+            ],
+            42,
+        )
+        .with_slow_start(Duration::from_mins(1));
+        let added_at = Instant::now();
+        balancer.add_sfu_at(
+            make_sfu("http://new:3000", None, b"key3-padded-to-32-bytes-1234567"),
+            added_at,
+        );
+        let new_sfu_share = |now: Instant| {
+            (0..3000)
+                .filter(|_| balancer.select_at(&[], now).unwrap().address == "http://new:3000")
+                .count()
+        };
+
+        // 10% of the way: ~19% of a full weight, ~260 of 3000 draws instead of 1000
+        let ramping = new_sfu_share(added_at + Duration::from_secs(6));
+        assert!(ramping > 100 && ramping < 500, "ramping share: {ramping}");
+
+        // ramp over: plain round-robin, exactly a third
+        assert_eq!(new_sfu_share(added_at + Duration::from_secs(61)), 1000);
+    }
+
+    #[test]
+    fn test_slow_start_disabled_gives_full_weight() {
+        let mut balancer = Balancer::new(vec![make_sfu(
+            "http://sfu1:3000",
+            None,
+            b"key1-padded-to-32-bytes-1234567",
+        )]);
+        let now = Instant::now();
+        balancer.add_sfu_at(
+            make_sfu("http://new:3000", None, b"key2-padded-to-32-bytes-1234567"),
+            now,
+        );
+        let picked_new = (0..100)
+            .filter(|_| balancer.select_at(&[], now).unwrap().address == "http://new:3000")
+            .count();
+        assert_eq!(picked_new, 50);
+    }
+
+    #[test]
+    fn test_weighted_round_robin_ratio() {
+        let balancer = Balancer::new(vec![
+            SfuConfig {
+                weight: 3,
+                ..make_sfu(
+                    "http://big:3000",
+                    Some("eu-west"),
+                    b"key1-padded-to-32-bytes-1234567",
+                )
+            },
+            make_sfu(
+                "http://small:3000",
+                Some("eu-west"),
+                b"key2-padded-to-32-bytes-1234567",
+            ),
+        ]);
+
+        let picks: Vec<_> = (0..8)
+            .map(|_| balancer.select(&["eu-west"]).unwrap().address.clone())
+            .collect();
+        // smooth: the small SFU is interleaved instead of getting a run of picks
+        assert_eq!(
+            picks,
+            [
+                "http://big:3000",
+                "http://big:3000",
+                "http://small:3000",
+                "http://big:3000",
+            ]
+            .repeat(2)
+        );
+
+        let small = (0..4000)
+            .filter(|_| balancer.select(&["eu-west"]).unwrap().address == "http://small:3000")
+            .count();
+        assert_eq!(small, 1000);
+    }
+
+    #[test]
+    fn test_weights_scale_slow_start_share() {
+        let mut balancer = Balancer::with_seed(
+            vec![make_sfu(
+                "http://sfu1:3000",
+                None,
+                b"key1-padded-to-32-bytes-1234567",
+            )],
+            42,
+        )
+        .with_slow_start(Duration::from_mins(1));
+        let added_at = Instant::now();
+        balancer.add_sfu_at(
+            SfuConfig {
+                weight: 9,
+                ..make_sfu("http://new:3000", None, b"key2-padded-to-32-bytes-1234567")
+            },
+            added_at,
+        );
+
+        // at 10% of a full weight of 9, the new SFU weighs ~0.9 against 1
+        let picked_new = (0..2000)
+            .filter(|_| balancer.select_at(&[], added_at).unwrap().address == "http://new:3000")
+            .count();
+        assert!(
+            picked_new > 800 && picked_new < 1100,
+            "ramping share: {picked_new}"
+        );
+    }
+
+    #[test]
+    fn test_round_robin_ignores_seed() {
+        let sfus = || {
+            vec![
+                make_sfu("http://sfu1:3000", None, b"key1-padded-to-32-bytes-1234567"),
+                make_sfu("http://sfu2:3000", None, b"key2-padded-to-32-bytes-1234567"),
+                make_sfu("http://sfu3:3000", None, b"key3-padded-to-32-bytes-1234567"),
+            ]
+        };
+        let run = |balancer: &Balancer| -> Vec<String> {
+            (0..4)
+                .map(|_| balancer.select(&[]).unwrap().address.clone())
+                .collect()
+        };
+
+        // the rotation starts at the first SFU, only the random strategies draw from the seed
+        let unseeded = run(&Balancer::new(sfus()));
+        assert_eq!(
+            unseeded,
+            [
+                "http://sfu1:3000",
+                "http://sfu2:3000",
+                "http://sfu3:3000",
+                "http://sfu1:3000"
+            ]
+        );
+        assert_eq!(run(&Balancer::with_seed(sfus(), 1234)), unseeded);
+    }
+
+    fn affinity_balancer(ttl: Duration) -> Balancer {
+        Balancer::new(vec![
+            make_sfu("http://sfu1:3000", None, b"key1-padded-to-32-bytes-1234567"),
+            make_sfu("http://sfu2:3000", None, b"key2-padded-to-32-bytes-1234567"),
+        ])
+        .with_affinity(ttl, 100)
+    }
+
+    #[test]
+    fn test_affinity_reused_within_window() {
+        let balancer = affinity_balancer(Duration::from_secs(10));
+        let now = Instant::now();
+
+        let first = balancer
+            .select_for_issuer_at(&[], "channel-1", &[], now)
+            .unwrap()
+            .address
+            .clone();
+        for i in 1..5 {
+            let selected = balancer
+                .select_for_issuer_at(&[], "channel-1", &[], now + Duration::from_secs(i))
+                .unwrap();
+            assert_eq!(selected.address, first);
+        }
+
+        // another issuer is not affected and gets the next SFU in the rotation
+        let other = balancer
+            .select_for_issuer_at(&[], "channel-2", &[], now)
+            .unwrap();
+        assert_ne!(other.address, first);
+    }
+
+    #[test]
+    fn test_affinity_follows_region_hints() {
+        let balancer = Balancer::new(vec![
+            make_sfu("http://eu1:3000", Some("eu-west"), b"key1"),
+            make_sfu("http://us1:3000", Some("us-east"), b"key2"),
+        ])
+        .with_affinity(Duration::from_secs(10), 100);
+        let now = Instant::now();
+
+        let first = balancer
+            .select_for_issuer_at(&["eu-west"], "channel-1", &[], now)
+            .unwrap();
+        assert_eq!(first.address, "http://eu1:3000");
+
+        // another region within the window moves the issuer there
+        let moved = balancer
+            .select_for_issuer_at(&["us-east"], "channel-1", &[], now + Duration::from_secs(1))
+            .unwrap();
+        assert_eq!(moved.address, "http://us1:3000");
+        let kept = balancer
+            .select_for_issuer_at(&[], "channel-1", &[], now + Duration::from_secs(2))
+            .unwrap();
+        assert_eq!(kept.address, "http://us1:3000");
+    }
+
+    #[test]
+    fn test_affinity_reselects_after_expiry() {
+        let balancer = affinity_balancer(Duration::from_secs(10));
+        let now = Instant::now();
+
+        let first = balancer
+            .select_for_issuer_at(&[], "channel-1", &[], now)
+            .unwrap()
+            .address
+            .clone();
+        let after_expiry = balancer
+            .select_for_issuer_at(&[], "channel-1", &[], now + Duration::from_secs(10))
+            .unwrap();
+        // round-robin moved on to the other SFU
+        assert_ne!(after_expiry.address, first);
+    }
+
+    #[test]
+    fn test_affinity_reselects_when_unhealthy() {
+        use crate::routing::HealthThresholds;
+
+        let balancer = affinity_balancer(Duration::from_secs(10));
+        let now = Instant::now();
+
+        let first = balancer
+            .select_for_issuer_at(&[], "channel-1", &[], now)
+            .unwrap();
+        first.health.record(
+            false,
+            HealthThresholds {
+                failures: 1,
+                successes: 1,
+            },
+        );
+
+        let reselected = balancer
+            .select_for_issuer_at(&[], "channel-1", &[], now)
+            .unwrap();
+        assert_ne!(reselected.address, first.address);
     }
 
     #[test]
     fn test_empty_balancer() {
         let balancer = Balancer::new(vec![]);
-        assert!(balancer.select(None).is_none());
+        assert_eq!(balancer.select(&[]).err(), Some(SelectError::NoSfu));
+    }
+
+    #[test]
+    fn test_sfu_has_key() {
+        let key = b"secret-key-padded-to-32-bytes12";
+        let balancer = Balancer::new(vec![make_sfu("http://sfu1:3000", None, key)]);
+        let selected = balancer.select(&[]).unwrap();
+        assert_eq!(selected.key(), key);
+    }
+
+    #[test]
+    fn test_snapshot_reflects_instances() {
+        let balancer = Balancer::new(vec![
+            make_sfu(
+                "http://sfu1:3000",
+                Some("eu-west"),
+                b"key1-padded-to-32-bytes-1234567",
+            ),
+            SfuConfig {
+                weight: 3,
+                max_channels: Some(10),
+                ..make_sfu("http://sfu2:3000", None, b"key2-padded-to-32-bytes-1234567")
+            },
+        ]);
+        let snapshot = balancer.snapshot();
+        assert!(!snapshot.health_checks);
+        assert_eq!(
+            snapshot.sfus[0],
+            SfuSnapshot {
+                address: "http://sfu1:3000".to_string(),
+                regions: vec!["eu-west".to_string()],
+                healthy: true,
+                last_check: None,
+                draining: false,
+                weight: 1,
+                max_channels: None,
+                assigned: 0,
+                selected: 0,
+            }
+        );
+        assert_eq!(snapshot.sfus[1].weight, 3);
+        assert_eq!(snapshot.sfus[1].max_channels, Some(10));
+
+        for _ in 0..HealthThresholds::default().failures {
+            balancer.sfus[1]
+                .health
+                .record(false, HealthThresholds::default());
+        }
+        balancer.select(&[]).unwrap().record_assignment();
+        balancer.set_draining("http://sfu1:3000", true);
+
+        let snapshot = balancer.snapshot();
+        assert!(!snapshot.sfus[1].healthy);
+        assert!(snapshot.sfus[1].last_check.is_some());
+        assert!(snapshot.sfus[0].draining);
+        assert_eq!(snapshot.sfus[0].assigned, 1);
+        assert_eq!(snapshot.sfus[0].selected, 1);
+        // a copy, later changes don't show in it
+        balancer.set_draining("http://sfu1:3000", false);
+        assert!(snapshot.sfus[0].draining);
+    }
+
+    #[test]
+    fn test_reload_keys_keeps_state() {
+        let balancer = Balancer::new(vec![
+            make_sfu("http://sfu1:3000", None, b"key1-padded-to-32-bytes-1234567"),
+            make_sfu("http://sfu2:3000", None, b"key2-padded-to-32-bytes-1234567"),
+        ]);
+        let sfu2 = &balancer.sfus[1];
+        for _ in 0..HealthThresholds::default().failures {
+            sfu2.health.record(false, HealthThresholds::default());
+        }
+        assert!(!sfu2.health.is_healthy());
+        balancer.select(&[]).unwrap();
+        let counter = balancer.counter.load(Ordering::Relaxed);
+
+        let changed = balancer.reload_keys(&[
+            make_sfu("http://sfu1:3000", None, b"key1-rotated-to-32-bytes-123456"),
+            make_sfu("http://sfu2:3000", None, b"key2-padded-to-32-bytes-1234567"),
+            make_sfu(
+                "http://unknown:3000",
+                None,
+                b"key3-padded-to-32-bytes-1234567",
+            ),
+        ]);
+
+        assert_eq!(changed, 1);
+        assert_eq!(balancer.sfus[0].key(), b"key1-rotated-to-32-bytes-123456");
+        assert_eq!(balancer.sfus[1].key(), b"key2-padded-to-32-bytes-1234567");
+        assert_eq!(balancer.sfus.len(), 2);
+        assert!(!balancer.sfus[1].health.is_healthy());
+        // the round-robin carries on where it was
+        assert_eq!(balancer.counter.load(Ordering::Relaxed), counter);
+    }
+
+    #[test]
+    fn test_reconfigure_keeps_known_sfus_state() {
+        let balancer = Balancer::new(vec![
+            make_sfu("http://sfu1:3000", None, b"key1-padded-to-32-bytes-1234567"),
+            make_sfu("http://sfu2:3000", None, b"key2-padded-to-32-bytes-1234567"),
+        ])
+        .with_affinity(Duration::from_secs(10), 100);
+        for _ in 0..HealthThresholds::default().failures {
+            balancer.sfus[1]
+                .health
+                .record(false, HealthThresholds::default());
+        }
+        let pinned = balancer.select_for_issuer(&[], "issuer").unwrap();
+        assert_eq!(pinned.address, "http://sfu1:3000");
+
+        let reconfigured = balancer.reconfigure(vec![
+            make_sfu(
+                "http://sfu2:3000",
+                Some("eu-west"),
+                b"key2-rotated-to-32-bytes-123456",
+            ),
+            make_sfu("http://sfu3:3000", None, b"key3-padded-to-32-bytes-1234567"),
+            make_sfu("http://sfu1:3000", None, b"key1-padded-to-32-bytes-1234567"),
+        ]);
+
+        let addresses: Vec<_> = reconfigured
+            .instances()
+            .iter()
+            .map(|sfu| sfu.address.as_str())
+            .collect();
+        assert_eq!(
+            addresses,
+            ["http://sfu2:3000", "http://sfu3:3000", "http://sfu1:3000"]
+        );
+        let sfu2 = &reconfigured.sfus[0];
+        assert!(!sfu2.health.is_healthy());
+        assert_eq!(sfu2.key(), b"key2-rotated-to-32-bytes-123456");
+        assert_eq!(reconfigured.region_index["eu-west"], vec![0]);
+        assert_eq!(sfu2.added_at, None);
+        assert!(reconfigured.sfus[1].added_at.is_some());
+        // the affinity cache is shared
+        assert_eq!(
+            reconfigured
+                .select_for_issuer(&[], "issuer")
+                .unwrap()
+                .address,
+            "http://sfu1:3000"
+        );
+    }
+
+    #[test]
+    fn test_unhealthy_sfus_skipped() {
+        let balancer = Balancer::new(vec![
+            make_sfu(
+                "http://eu1:3000",
+                Some("eu-west"),
+                b"key1-padded-to-32-bytes-1234567",
+            ),
+            make_sfu(
+                "http://eu2:3000",
+                Some("eu-west"),
+                b"key2-padded-to-32-bytes-1234567",
+            ),
+            make_sfu(
+                "http://ec1:3000",
+                Some("eu-central"),
+                b"key3-padded-to-32-bytes-1234567",
+            ),
+        ]);
+        let mark_down = |index: usize| {
+            for _ in 0..HealthThresholds::default().failures {
+                balancer.sfus[index]
+                    .health
+                    .record(false, HealthThresholds::default());
+            }
+        };
+
+        mark_down(0);
+        for _ in 0..4 {
+            assert_eq!(
+                balancer.select(&["eu-west"]).unwrap().address,
+                "http://eu2:3000"
+            );
+        }
+
+        // region without healthy SFUs falls through to the next closest one
+        mark_down(1);
+        assert_eq!(
+            balancer.select(&["eu-west"]).unwrap().address,
+            "http://ec1:3000"
+        );
+        assert_eq!(balancer.select(&[]).unwrap().address, "http://ec1:3000");
+
+        mark_down(2);
+        assert_eq!(
+            balancer.select(&["eu-west"]).err(),
+            Some(SelectError::NoSfu)
+        );
+        assert_eq!(balancer.select(&[]).err(), Some(SelectError::NoSfu));
     }
 
     #[test]
-    fn test_sfu_has_key() {
-        let key = b"secret-key-padded-to-32-bytes12";
-        let balancer = Balancer::new(vec![make_sfu("http://sfu1:3000", None, key)]);
-        let selected = balancer.select(None).unwrap();
-        assert_eq!(selected.key, key);
+    fn test_dead_region_falls_through_for_every_strategy() {
+        for strategy in [
+            SelectionStrategy::RoundRobin,
+            SelectionStrategy::Sticky,
+            SelectionStrategy::LeastConnections,
+            SelectionStrategy::LowestLatency,
+            SelectionStrategy::WeightedRandom,
+        ] {
+            let balancer = Balancer::new(vec![
+                make_sfu(
+                    "http://eu1:3000",
+                    Some("eu-west"),
+                    b"key1-padded-to-32-bytes-1234567",
+                ),
+                make_sfu(
+                    "http://eu2:3000",
+                    Some("eu-west"),
+                    b"key2-padded-to-32-bytes-1234567",
+                ),
+                make_sfu(
+                    "http://ec1:3000",
+                    Some("eu-central"),
+                    b"key3-padded-to-32-bytes-1234567",
+                ),
+            ])
+            .with_strategy(strategy)
+            .with_affinity(Duration::from_mins(1), 16);
+            // eu-west keeps its SFUs configured, none of them answers
+            for sfu in &balancer.sfus[..2] {
+                for _ in 0..HealthThresholds::default().failures {
+                    sfu.health.record(false, HealthThresholds::default());
+                }
+            }
+
+            let candidates = balancer.candidates(&["eu-west"]);
+            assert_eq!(candidates.len(), 1, "{strategy:?}");
+            assert_eq!(candidates[0].address, "http://ec1:3000", "{strategy:?}");
+            for issuer in ["a", "b", "c", "d"] {
+                assert_eq!(
+                    balancer.select(&["eu-west"]).unwrap().address,
+                    "http://ec1:3000",
+                    "{strategy:?}"
+                );
+                assert_eq!(
+                    balancer
+                        .select_for_issuer(&["eu-west"], issuer)
+                        .unwrap()
+                        .address,
+                    "http://ec1:3000",
+                    "{strategy:?}"
+                );
+                assert_eq!(
+                    balancer
+                        .select_sticky(&["eu-west"], issuer, &[])
+                        .unwrap()
+                        .address,
+                    "http://ec1:3000",
+                    "{strategy:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_draining_sfu_never_selected() {
+        let balancer = Balancer::new(vec![
+            make_sfu(
+                "http://eu1:3000",
+                Some("eu-west"),
+                b"key1-padded-to-32-bytes-1234567",
+            ),
+            make_sfu(
+                "http://eu2:3000",
+                Some("eu-west"),
+                b"key2-padded-to-32-bytes-1234567",
+            ),
+        ])
+        .with_affinity(Duration::from_secs(10), 100);
+
+        assert!(balancer.set_draining("http://eu1:3000", true));
+        assert!(!balancer.set_draining("http://unknown:3000", true));
+        for issuer in ["a", "b", "c", "d"] {
+            assert_eq!(
+                balancer.select(&["eu-west"]).unwrap().address,
+                "http://eu2:3000"
+            );
+            assert_eq!(
+                balancer.select_for_issuer(&[], issuer).unwrap().address,
+                "http://eu2:3000"
+            );
+        }
+        // still configured, and the drain survives a reload
+        assert_eq!(balancer.instances().len(), 2);
+        let reconfigured = balancer.reconfigure(vec![
+            make_sfu(
+                "http://eu1:3000",
+                Some("eu-west"),
+                b"key1-padded-to-32-bytes-1234567",
+            ),
+            make_sfu(
+                "http://eu2:3000",
+                Some("eu-west"),
+                b"key2-padded-to-32-bytes-1234567",
+            ),
+        ]);
+        assert!(reconfigured.instances()[0].is_draining());
+
+        assert!(reconfigured.set_draining("http://eu2:3000", true));
+        assert_eq!(reconfigured.select(&[]).err(), Some(SelectError::NoSfu));
+        assert!(!reconfigured.has_healthy_sfu());
+
+        assert!(reconfigured.set_draining("http://eu1:3000", false));
+        assert!(reconfigured.set_draining("http://eu2:3000", false));
+        let picked: std::collections::HashSet<_> = (0..4)
+            .map(|_| reconfigured.select(&[]).unwrap().address.clone())
+            .collect();
+        assert_eq!(picked.len(), 2);
+    }
+
+    #[test]
+    fn test_region_index_matches_scan() {
+        let mut balancer = Balancer::new(vec![
+            make_sfu("http://eu1:3000", Some("eu-west"), b"key1"),
+            SfuConfig {
+                regions: vec![
+                    "eu-west".to_string(),
+                    "eu-central".to_string(),
+                    "eu-west".to_string(),
+                ],
+                ..make_sfu("http://eu2:3000", None, b"key2")
+            },
+            make_sfu("http://any:3000", None, b"key3"),
+        ]);
+        balancer.add_sfu(make_sfu("http://ec1:3000", Some("eu-central"), b"key4"));
+
+        let mut scanned: HashMap<String, Vec<usize>> = HashMap::new();
+        for (i, sfu) in balancer.sfus.iter().enumerate() {
+            for region in &sfu.regions {
+                let indices = scanned.entry(region.clone()).or_default();
+                if !indices.contains(&i) {
+                    indices.push(i);
+                }
+            }
+        }
+        assert_eq!(balancer.region_index, scanned);
+        assert_eq!(balancer.region_index["eu-central"], vec![1, 3]);
+        assert_eq!(balancer.sfus_in_region("eu-west").len(), 2);
+        assert!(balancer.sfus_in_region("us-east").is_empty());
+    }
+
+    #[test]
+    fn test_select_with_exclusions() {
+        let balancer = Balancer::new(vec![
+            make_sfu(
+                "http://eu1:3000",
+                Some("eu-west"),
+                b"key1-padded-to-32-bytes-1234567",
+            ),
+            make_sfu(
+                "http://eu2:3000",
+                Some("eu-west"),
+                b"key2-padded-to-32-bytes-1234567",
+            ),
+            make_sfu(
+                "http://ec1:3000",
+                Some("eu-central"),
+                b"key3-padded-to-32-bytes-1234567",
+            ),
+        ]);
+
+        for _ in 0..4 {
+            assert_eq!(
+                balancer
+                    .select_with_exclusions(&["eu-west"], &["http://eu1:3000"])
+                    .unwrap()
+                    .address,
+                "http://eu2:3000"
+            );
+        }
+
+        // exhausted region falls through to the next closest one
+        let excluded = ["http://eu1:3000", "http://eu2:3000"];
+        assert_eq!(
+            balancer
+                .select_with_exclusions(&["eu-west"], &excluded)
+                .unwrap()
+                .address,
+            "http://ec1:3000"
+        );
+
+        let excluded = ["http://eu1:3000", "http://eu2:3000", "http://ec1:3000"];
+        assert_eq!(
+            balancer.select_with_exclusions(&[], &excluded).err(),
+            Some(SelectError::NoSfu)
+        );
+    }
+
+    #[test]
+    fn test_sfus_at_capacity_are_skipped() {
+        let balancer = Balancer::new(vec![
+            SfuConfig {
+                max_channels: Some(1),
+                ..make_sfu(
+                    "http://eu1:3000",
+                    Some("eu-west"),
+                    b"key1-padded-to-32-bytes-1234567",
+                )
+            },
+            make_sfu(
+                "http://eu2:3000",
+                Some("eu-west"),
+                b"key2-padded-to-32-bytes-1234567",
+            ),
+        ]);
+        balancer.sfus[0].record_assignment();
+        assert!(balancer.sfus[0].is_at_capacity());
+        assert!(!balancer.sfus[1].is_at_capacity());
+
+        for _ in 0..4 {
+            assert_eq!(
+                balancer.select(&["eu-west"]).unwrap().address,
+                "http://eu2:3000"
+            );
+        }
+    }
+
+    #[test]
+    fn test_all_busy_vs_no_sfu() {
+        let busy = |address: &str, region: &str, key: &[u8]| SfuConfig {
+            max_channels: Some(1),
+            ..make_sfu(address, Some(region), key)
+        };
+        let balancer = Balancer::new(vec![
+            busy(
+                "http://eu1:3000",
+                "eu-west",
+                b"key1-padded-to-32-bytes-1234567",
+            ),
+            busy(
+                "http://us1:3000",
+                "us-east",
+                b"key2-padded-to-32-bytes-1234567",
+            ),
+        ]);
+        balancer.sfus[0].record_assignment();
+        balancer.sfus[1].record_assignment();
+
+        assert_eq!(
+            balancer.select(&["eu-west"]).err(),
+            Some(SelectError::AllBusy)
+        );
+        assert_eq!(
+            balancer.select_sticky(&["eu-west"], "channel-1", &[]).err(),
+            Some(SelectError::AllBusy)
+        );
+        assert_eq!(
+            balancer
+                .select_with_exclusions(&["eu-west"], &["http://eu1:3000"])
+                .err(),
+            Some(SelectError::AllBusy)
+        );
+        // excluded SFUs are not busy, they are not candidates at all
+        assert_eq!(
+            balancer
+                .select_with_exclusions(&["eu-west"], &["http://eu1:3000", "http://us1:3000"])
+                .err(),
+            Some(SelectError::NoSfu)
+        );
+
+        let empty = Balancer::new(vec![]);
+        assert_eq!(empty.select(&["eu-west"]).err(), Some(SelectError::NoSfu));
+    }
+
+    #[test]
+    fn test_fallback_skips_down_and_saturated_regions() {
+        let balancer = Balancer::new(vec![
+            make_sfu(
+                "http://ec1:3000",
+                Some("eu-central"),
+                b"key1-padded-to-32-bytes-1234567",
+            ),
+            SfuConfig {
+                max_channels: Some(1),
+                ..make_sfu(
+                    "http://en1:3000",
+                    Some("eu-north"),
+                    b"key2-padded-to-32-bytes-1234567",
+                )
+            },
+            make_sfu(
+                "http://us1:3000",
+                Some("us-east"),
+                b"key3-padded-to-32-bytes-1234567",
+            ),
+        ]);
+        assert_eq!(
+            balancer.select(&["eu-central"]).unwrap().address,
+            "http://ec1:3000"
+        );
+
+        // the nearest region's only SFU is down, the next closest one takes over
+        for _ in 0..HealthThresholds::default().failures {
+            balancer.sfus[0]
+                .health
+                .record(false, HealthThresholds::default());
+        }
+        assert_eq!(
+            balancer.select(&["eu-central"]).unwrap().address,
+            "http://en1:3000"
+        );
+
+        // then saturated, the one after
+        balancer.sfus[1].record_assignment();
+        for strategy_select in [
+            balancer.select(&["eu-central"]),
+            balancer.select_sticky(&["eu-central"], "channel-1", &[]),
+            balancer.select_for_issuer(&["eu-central"], "channel-1"),
+        ] {
+            assert_eq!(strategy_select.unwrap().address, "http://us1:3000");
+        }
+
+        // back to the nearest region once it has room again
+        balancer.sfus[1].release_assignment();
+        assert_eq!(
+            balancer.select(&["eu-central"]).unwrap().address,
+            "http://en1:3000"
+        );
+    }
+
+    #[test]
+    fn test_capacity_frees_up_as_channels_expire() {
+        let balancer = Balancer::new(vec![SfuConfig {
+            max_channels: Some(2),
+            ..make_sfu("http://sfu1:3000", None, b"key1-padded-to-32-bytes-1234567")
+        }])
+        .with_channel_lifetime(Duration::from_mins(1));
+        let sfu = &balancer.sfus[0];
+        let now = Instant::now();
+        sfu.record_assignment_at(now.checked_sub(Duration::from_secs(90)).unwrap());
+        sfu.record_assignment_at(now.checked_sub(Duration::from_secs(30)).unwrap());
+
+        // the oldest channel is over its lifetime, its slot is free again
+        assert_eq!(sfu.assigned(), 1);
+        assert_eq!(balancer.select(&[]).unwrap().address, "http://sfu1:3000");
+
+        sfu.record_assignment();
+        assert_eq!(balancer.select(&[]).err(), Some(SelectError::AllBusy));
+        assert_eq!(sfu.assigned_at(now + Duration::from_secs(45)), 1);
+        assert_eq!(balancer.select(&[]).unwrap().address, "http://sfu1:3000");
+
+        sfu.release_assignment();
+        assert_eq!(sfu.assigned(), 0);
+    }
+
+    #[test]
+    fn test_reconfigure_keeps_assigned_channels() {
+        let sfu = SfuConfig {
+            max_channels: Some(1),
+            ..make_sfu("http://sfu1:3000", None, b"key1-padded-to-32-bytes-1234567")
+        };
+        let balancer = Balancer::new(vec![sfu.clone()]);
+        balancer.sfus[0].record_assignment();
+
+        let reconfigured = balancer.reconfigure(vec![sfu]);
+        assert_eq!(reconfigured.sfus[0].assigned(), 1);
+        assert_eq!(reconfigured.select(&[]).err(), Some(SelectError::AllBusy));
+    }
+
+    #[test]
+    fn test_least_connections_picks_least_loaded() {
+        let balancer = Balancer::new(vec![
+            make_sfu("http://sfu1:3000", None, b"key1-padded-to-32-bytes-1234567"),
+            make_sfu("http://sfu2:3000", None, b"key2-padded-to-32-bytes-1234567"),
+            make_sfu("http://sfu3:3000", None, b"key3-padded-to-32-bytes-1234567"),
+        ])
+        .with_strategy(SelectionStrategy::LeastConnections);
+        for _ in 0..5 {
+            balancer.sfus[0].record_assignment();
+        }
+        balancer.sfus[1].record_assignment();
+
+        let selected = balancer.select(&[]).unwrap();
+        assert_eq!(selected.address, "http://sfu3:3000");
+
+        // load spreads toward the least loaded until they even out
+        for _ in 0..7 {
+            balancer.select(&[]).unwrap().record_assignment();
+        }
+        let assigned: Vec<_> = balancer.sfus.iter().map(SfuInstance::assigned).collect();
+        assert_eq!(assigned, [5, 4, 4]);
+        assert_ne!(balancer.select(&[]).unwrap().address, "http://sfu1:3000");
+    }
+
+    #[test]
+    fn test_least_connections_ties_rotate() {
+        let balancer = Balancer::new(vec![
+            make_sfu("http://sfu1:3000", None, b"key1-padded-to-32-bytes-1234567"),
+            make_sfu("http://sfu2:3000", None, b"key2-padded-to-32-bytes-1234567"),
+        ])
+        .with_strategy(SelectionStrategy::LeastConnections);
+
+        let first = balancer.select(&[]).unwrap().address.clone();
+        let second = balancer.select(&[]).unwrap().address.clone();
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn test_least_connections_weighs_load() {
+        let balancer = Balancer::new(vec![
+            SfuConfig {
+                weight: 3,
+                ..make_sfu("http://big:3000", None, b"key1-padded-to-32-bytes-1234567")
+            },
+            make_sfu(
+                "http://small:3000",
+                None,
+                b"key2-padded-to-32-bytes-1234567",
+            ),
+        ])
+        .with_strategy(SelectionStrategy::LeastConnections);
+        for _ in 0..2 {
+            balancer.sfus[0].record_assignment();
+        }
+        balancer.sfus[1].record_assignment();
+
+        // 2 channels for a weight of 3 is less load than 1 for a weight of 1
+        assert_eq!(balancer.select(&[]).unwrap().address, "http://big:3000");
+    }
+
+    #[test]
+    fn test_least_connections_forgets_expired_channels() {
+        let balancer = Balancer::new(vec![
+            make_sfu("http://sfu1:3000", None, b"key1-padded-to-32-bytes-1234567"),
+            make_sfu("http://sfu2:3000", None, b"key2-padded-to-32-bytes-1234567"),
+        ])
+        .with_strategy(SelectionStrategy::LeastConnections)
+        .with_channel_lifetime(Duration::from_mins(1));
+        // a busy past on sfu1, long over, and one live channel
+        let long_ago = Instant::now().checked_sub(Duration::from_mins(5)).unwrap();
+        for _ in 0..10 {
+            balancer.sfus[0].record_assignment_at(long_ago);
+        }
+        balancer.sfus[0].record_assignment();
+        balancer.sfus[1].record_assignment();
+        balancer.sfus[1].record_assignment();
+
+        assert_eq!(balancer.select(&[]).unwrap().address, "http://sfu1:3000");
+    }
+
+    #[test]
+    fn test_lowest_latency_picks_fastest() {
+        let balancer = Balancer::new(vec![
+            make_sfu("http://slow:3000", None, b"key1-padded-to-32-bytes-1234567"),
+            make_sfu("http://fast:3000", None, b"key2-padded-to-32-bytes-1234567"),
+            make_sfu("http://new:3000", None, b"key3-padded-to-32-bytes-1234567"),
+        ])
+        .with_strategy(SelectionStrategy::LowestLatency);
+        balancer.sfus[0]
+            .health
+            .record_latency(Duration::from_millis(80));
+        balancer.sfus[1]
+            .health
+            .record_latency(Duration::from_millis(12));
+
+        for _ in 0..4 {
+            assert_eq!(balancer.select(&[]).unwrap().address, "http://fast:3000");
+        }
+    }
+
+    #[test]
+    fn test_lowest_latency_close_ones_rotate() {
+        let balancer = Balancer::new(vec![
+            make_sfu("http://sfu1:3000", None, b"key1-padded-to-32-bytes-1234567"),
+            make_sfu("http://sfu2:3000", None, b"key2-padded-to-32-bytes-1234567"),
+        ])
+        .with_strategy(SelectionStrategy::LowestLatency);
+
+        // nothing measured yet, round-robin
+        let first = balancer.select(&[]).unwrap().address.clone();
+        assert_ne!(balancer.select(&[]).unwrap().address, first);
+
+        balancer.sfus[0]
+            .health
+            .record_latency(Duration::from_millis(20));
+        balancer.sfus[1]
+            .health
+            .record_latency(Duration::from_millis(21));
+        let first = balancer.select(&[]).unwrap().address.clone();
+        assert_ne!(balancer.select(&[]).unwrap().address, first);
+    }
+
+    #[test]
+    fn test_weighted_random_reproducible_with_seed() {
+        let sfus = || {
+            vec![
+                SfuConfig {
+                    weight: 3,
+                    ..make_sfu("http://big:3000", None, b"key1-padded-to-32-bytes-1234567")
+                },
+                make_sfu(
+                    "http://small:3000",
+                    None,
+                    b"key2-padded-to-32-bytes-1234567",
+                ),
+            ]
+        };
+        let picks = |seed| {
+            let balancer =
+                Balancer::with_seed(sfus(), seed).with_strategy(SelectionStrategy::WeightedRandom);
+            (0..4000)
+                .map(|_| balancer.select(&[]).unwrap().address.clone())
+                .collect::<Vec<_>>()
+        };
+
+        let sequence = picks(42);
+        assert_eq!(sequence, picks(42));
+        assert_ne!(sequence, picks(43));
+
+        // 3:1 over many draws, not interleaved like the weighted round-robin
+        let big = sequence.iter().filter(|a| *a == "http://big:3000").count();
+        assert!((2850..3150).contains(&big), "{big} of 4000");
+        assert!(
+            sequence
+                .windows(3)
+                .any(|w| w.iter().all(|a| a == "http://small:3000"))
+        );
+    }
+
+    #[test]
+    fn test_selection_counts() {
+        let balancer = Balancer::new(vec![
+            make_sfu("http://sfu1:3000", None, b"key1-padded-to-32-bytes-1234567"),
+            make_sfu("http://sfu2:3000", None, b"key2-padded-to-32-bytes-1234567"),
+            make_sfu("http://sfu3:3000", None, b"key3-padded-to-32-bytes-1234567"),
+        ])
+        .with_affinity(Duration::from_mins(1), 16);
+
+        for _ in 0..9 {
+            balancer.select(&[]).unwrap();
+        }
+        let selected: Vec<_> = balancer.sfus.iter().map(SfuInstance::selected).collect();
+        assert_eq!(selected, [3, 3, 3]);
+
+        // affinity hits and retries count as well, every selection once
+        let first = balancer.select_for_issuer(&[], "issuer").unwrap();
+        let affine = first.address.clone();
+        balancer.select_for_issuer(&[], "issuer").unwrap();
+        balancer
+            .select_with_exclusions(&[], &[affine.as_str()])
+            .unwrap();
+        balancer.select_sticky(&[], "channel", &[]).unwrap();
+        let selected: Vec<_> = balancer.sfus.iter().map(SfuInstance::selected).collect();
+        assert_eq!(selected.iter().sum::<u64>(), 13);
+        let affine_count = balancer
+            .sfus
+            .iter()
+            .find(|sfu| sfu.address == affine)
+            .unwrap()
+            .selected();
+        assert!(affine_count >= 5, "{selected:?}");
+
+        let reconfigured = balancer.reconfigure(vec![make_sfu(
+            "http://sfu1:3000",
+            None,
+            b"key1-padded-to-32-bytes-1234567",
+        )]);
+        assert_eq!(reconfigured.sfus[0].selected(), selected[0]);
+        assert_eq!(reconfigured.snapshot().sfus[0].selected, selected[0]);
+    }
+
+    #[test]
+    fn test_release_assignment() {
+        let balancer = Balancer::new(vec![make_sfu(
+            "http://sfu1:3000",
+            None,
+            b"key1-padded-to-32-bytes-1234567",
+        )]);
+        let sfu = &balancer.sfus[0];
+        sfu.record_assignment();
+        sfu.release_assignment();
+        sfu.release_assignment();
+        assert_eq!(sfu.assigned(), 0);
+    }
+
+    #[test]
+    fn test_affinity_moves_off_excluded_sfu() {
+        let balancer = affinity_balancer(Duration::from_secs(10));
+        let now = Instant::now();
+
+        let first = balancer
+            .select_for_issuer_at(&[], "channel-1", &[], now)
+            .unwrap()
+            .address
+            .clone();
+        let retried = balancer
+            .select_for_issuer_at(&[], "channel-1", &[&first], now)
+            .unwrap()
+            .address
+            .clone();
+        assert_ne!(retried, first);
+
+        // the issuer now sticks to the SFU of the retry
+        let next = balancer
+            .select_for_issuer_at(&[], "channel-1", &[], now)
+            .unwrap();
+        assert_eq!(next.address, retried);
     }
 
     #[test]
@@ -249,11 +2667,105 @@ mod tests {
         ]);
 
         // ap-south should prefer ap-southeast over eu-west
-        let selected = balancer.select(Some("ap-south")).unwrap();
+        let selected = balancer.select(&["ap-south"]).unwrap();
         assert!(
             selected.address.contains("ap-southeast"),
             "Expected ap-southeast to be preferred, got {}",
             selected.address
         );
     }
+
+    fn sticky_pool(count: usize) -> Vec<SfuConfig> {
+        (0..count)
+            .map(|i| {
+                make_sfu(
+                    &format!("http://sfu{i}:3000"),
+                    Some("eu-west"),
+                    b"key-padded-to-32-bytes-123456789",
+                )
+            })
+            .collect()
+    }
+
+    fn sticky_owners(balancer: &Balancer, keys: usize) -> Vec<String> {
+        (0..keys)
+            .map(|i| {
+                let sfu = balancer.select_sticky(&["eu-west"], &format!("channel-{i}"), &[]);
+                sfu.unwrap().address.clone()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_sticky_key_maps_to_same_sfu() {
+        let balancer = Balancer::new(sticky_pool(4)).with_strategy(SelectionStrategy::Sticky);
+        let owners = sticky_owners(&balancer, 1000);
+        assert_eq!(owners, sticky_owners(&balancer, 1000));
+        // selection for an issuer goes through the hash, round-robin state is irrelevant
+        for (i, owner) in owners.iter().enumerate().take(50) {
+            let issuer = format!("channel-{i}");
+            let selected = balancer.select_for_issuer(&["eu-west"], &issuer).unwrap();
+            assert_eq!(&selected.address, owner);
+        }
+
+        // different keys spread over the pool, 250 each on average
+        for i in 0..4 {
+            let count = owners
+                .iter()
+                .filter(|owner| **owner == format!("http://sfu{i}:3000"))
+                .count();
+            assert!((175..=325).contains(&count), "sfu{i}: {count}");
+        }
+    }
+
+    #[test]
+    fn test_sticky_hash_is_stable() {
+        // replicas and restarts must agree, the hash can't depend on the process
+        assert_eq!(stable_hash(&[b"channel-1"]), 0xF312_2CD0_E8A1_638E);
+        let balancer = Balancer::new(sticky_pool(4));
+        assert_eq!(
+            balancer
+                .select_sticky(&[], "channel-1", &[])
+                .unwrap()
+                .address,
+            "http://sfu1:3000"
+        );
+    }
+
+    #[test]
+    fn test_sticky_only_moves_keys_of_removed_sfu() {
+        let balancer = Balancer::new(sticky_pool(5));
+        let before = sticky_owners(&balancer, 1000);
+        let shrunk = balancer.reconfigure(sticky_pool(4));
+        let after = sticky_owners(&shrunk, 1000);
+
+        for (before, after) in before.iter().zip(&after) {
+            if before != "http://sfu4:3000" {
+                assert_eq!(before, after);
+            }
+        }
+        assert!(after.iter().all(|owner| owner != "http://sfu4:3000"));
+    }
+
+    #[test]
+    fn test_sticky_respects_weights_and_exclusions() {
+        let mut sfus = sticky_pool(2);
+        sfus[0].weight = 3;
+        let balancer = Balancer::new(sfus);
+        let owners = sticky_owners(&balancer, 1000);
+        let heavy = owners
+            .iter()
+            .filter(|owner| *owner == "http://sfu0:3000")
+            .count();
+        assert!((680..=820).contains(&heavy), "{heavy}");
+
+        let key = (0..100)
+            .map(|i| format!("channel-{i}"))
+            .find(|key| {
+                balancer.select_sticky(&[], key, &[]).unwrap().address == "http://sfu0:3000"
+            })
+            .unwrap();
+        let moved = balancer.select_sticky(&[], &key, &["http://sfu0:3000"]);
+        assert_eq!(moved.unwrap().address, "http://sfu1:3000");
+    }
 }
@@ -3,60 +3,84 @@
 // Or we could make static lookup tables for the regions fallback priority.
 // experiement if we can have a compact representation of this data.
 
-/// Maps ISO 3166-1 alpha-2 country codes to SFU regions.
-#[must_use]
-pub fn country_to_region(country_code: &str) -> Option<&'static str> {
-    match country_code.to_uppercase().as_str() {
-        // Western Europe
-        "FR" | "DE" | "GB" | "ES" | "IT" | "NL" | "BE" | "PT" | "IE" | "AT" | "CH" | "LU"
-        | "MC" | "AD" | "MT" | "SM" | "VA" | "LI" => Some("eu-west"),
-
-        // Northern Europe
-        "SE" | "NO" | "DK" | "FI" | "IS" | "EE" | "LV" | "LT" => Some("eu-north"),
-
-        // Eastern/Central Europe + Russia + Central Asia
-        "PL" | "CZ" | "SK" | "HU" | "RO" | "BG" | "HR" | "SI" | "RS" | "BA" | "ME" | "MK"
-        | "AL" | "XK" | "MD" | "UA" | "BY" | "RU" | "KZ" | "UZ" | "TM" | "KG" | "TJ" | "AZ"
-        | "GE" | "AM" => Some("eu-central"),
-
-        // Greece, Turkey, Cyprus, North Africa
-        "GR" | "TR" | "CY" | "EG" | "LY" | "TN" | "DZ" | "MA" => Some("eu-south"),
-
-        // US, Canada, Mexico, Central America, Caribbean
-        "US" | "CA" | "MX" | "GT" | "BZ" | "SV" | "HN" | "NI" | "CR" | "PA" | "CU" | "JM"
-        | "HT" | "DO" | "PR" | "TT" | "BB" | "BS" => Some("us-east"),
-
-        // South America - East
-        "BR" | "AR" | "UY" | "PY" | "VE" | "CO" | "GY" | "SR" | "GF" => Some("sa-east"),
-
-        // South America - West (Andes)
-        "CL" | "PE" | "EC" | "BO" => Some("sa-west"),
-
-        // East Asia
-        "JP" | "KR" | "TW" | "HK" | "MO" => Some("ap-northeast"),
-
-        // China
-        "CN" => Some("ap-east"),
-
-        // Southeast Asia + Oceania
-        "SG" | "MY" | "TH" | "VN" | "ID" | "PH" | "MM" | "KH" | "LA" | "BN" | "AU" | "NZ"
-        | "FJ" | "PG" | "NC" | "VU" | "WS" | "TO" => Some("ap-southeast"),
-
-        // South Asia
-        "IN" | "PK" | "BD" | "LK" | "NP" | "BT" | "MV" => Some("ap-south"),
-
-        // Middle East
-        "AE" | "SA" | "QA" | "KW" | "BH" | "OM" | "IL" | "JO" | "LB" | "IQ" | "IR" | "YE" => {
-            Some("me-south")
-        }
-
-        // Africa - Sub-Saharan
-        "ZA" | "NG" | "KE" | "GH" | "TZ" | "UG" | "ET" | "SN" | "CI" | "CM" | "AO" | "ZW"
-        | "ZM" | "MZ" | "BW" | "NA" | "RW" | "MU" | "MG" => Some("af-south"),
-
-        _ => None,
-    }
-}
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+
+use crate::config::GeoConfig;
+
+/// ISO 3166-1 alpha-2 country codes served by each SFU region.
+const COUNTRY_REGIONS: &[(&str, &[&str])] = &[
+    // Western Europe
+    (
+        "eu-west",
+        &[
+            "FR", "DE", "GB", "ES", "IT", "NL", "BE", "PT", "IE", "AT", "CH", "LU", "MC", "AD",
+            "MT", "SM", "VA", "LI",
+        ],
+    ),
+    // Northern Europe
+    (
+        "eu-north",
+        &["SE", "NO", "DK", "FI", "IS", "EE", "LV", "LT"],
+    ),
+    // Eastern/Central Europe + Russia + Central Asia
+    (
+        "eu-central",
+        &[
+            "PL", "CZ", "SK", "HU", "RO", "BG", "HR", "SI", "RS", "BA", "ME", "MK", "AL", "XK",
+            "MD", "UA", "BY", "RU", "KZ", "UZ", "TM", "KG", "TJ", "AZ", "GE", "AM",
+        ],
+    ),
+    // Greece, Turkey, Cyprus, North Africa
+    (
+        "eu-south",
+        &["GR", "TR", "CY", "EG", "LY", "TN", "DZ", "MA"],
+    ),
+    // US, Canada, Mexico, Central America, Caribbean
+    (
+        "us-east",
+        &[
+            "US", "CA", "MX", "GT", "BZ", "SV", "HN", "NI", "CR", "PA", "CU", "JM", "HT", "DO",
+            "PR", "TT", "BB", "BS",
+        ],
+    ),
+    // South America - East
+    (
+        "sa-east",
+        &["BR", "AR", "UY", "PY", "VE", "CO", "GY", "SR", "GF"],
+    ),
+    // South America - West (Andes)
+    ("sa-west", &["CL", "PE", "EC", "BO"]),
+    // East Asia
+    ("ap-northeast", &["JP", "KR", "TW", "HK", "MO"]),
+    // China
+    ("ap-east", &["CN"]),
+    // Southeast Asia + Oceania
+    (
+        "ap-southeast",
+        &[
+            "SG", "MY", "TH", "VN", "ID", "PH", "MM", "KH", "LA", "BN", "AU", "NZ", "FJ", "PG",
+            "NC", "VU", "WS", "TO",
+        ],
+    ),
+    // South Asia
+    ("ap-south", &["IN", "PK", "BD", "LK", "NP", "BT", "MV"]),
+    // Middle East
+    (
+        "me-south",
+        &[
+            "AE", "SA", "QA", "KW", "BH", "OM", "IL", "JO", "LB", "IQ", "IR", "YE",
+        ],
+    ),
+    // Africa - Sub-Saharan
+    (
+        "af-south",
+        &[
+            "ZA", "NG", "KE", "GH", "TZ", "UG", "ET", "SN", "CI", "CM", "AO", "ZW", "ZM", "MZ",
+            "BW", "NA", "RW", "MU", "MG",
+        ],
+    ),
+];
 
 /// Region with approximate geographic coordinates (latitude, longitude).
 struct RegionCoord {
@@ -134,12 +158,165 @@ const REGIONS: &[RegionCoord] = &[
     }, // Johannesburg
 ];
 
-/// Get coordinates for a region, returns None if unknown.
-fn region_coords(region: &str) -> Option<(f64, f64)> {
-    REGIONS
-        .iter()
-        .find(|r| r.name == region)
-        .map(|r| (r.lat, r.lon))
+/// Regions and country mapping used for routing: the built-in data, with the `[geo]` section
+/// of the configuration merged over it.
+#[derive(Debug, Clone)]
+pub struct GeoTable {
+    /// Known regions with their approximate center coordinates (name, latitude, longitude)
+    regions: Vec<(String, f64, f64)>,
+    /// Upper case country code → region
+    countries: BTreeMap<String, String>,
+}
+
+impl Default for GeoTable {
+    /// The built-in regions and country mapping.
+    fn default() -> Self {
+        let regions = REGIONS
+            .iter()
+            .map(|r| (r.name.to_string(), r.lat, r.lon))
+            .collect();
+        let mut countries = BTreeMap::new();
+        for (region, codes) in COUNTRY_REGIONS {
+            for code in *codes {
+                countries
+                    .entry((*code).to_string())
+                    .or_insert_with(|| (*region).to_string());
+            }
+        }
+        Self { regions, countries }
+    }
+}
+
+impl GeoTable {
+    /// Add the configured regions (moving built-in ones given again) and remap the configured
+    /// countries.
+    #[must_use]
+    pub fn with_overrides(mut self, geo: &GeoConfig) -> Self {
+        for (name, lat, lon) in &geo.regions {
+            match self.regions.iter_mut().find(|(known, _, _)| known == name) {
+                Some(region) => *region = (name.clone(), *lat, *lon),
+                None => self.regions.push((name.clone(), *lat, *lon)),
+            }
+        }
+        for (country, region) in &geo.countries {
+            self.countries
+                .insert(country.to_ascii_uppercase(), region.clone());
+        }
+        self
+    }
+
+    /// Region of an ISO 3166-1 alpha-2 country code, case insensitive.
+    #[must_use]
+    pub fn country_to_region(&self, country_code: &str) -> Option<&str> {
+        self.countries
+            .get(&country_code.to_ascii_uppercase())
+            .map(String::as_str)
+    }
+
+    /// Every known country code with the region it maps to.
+    #[must_use]
+    pub fn country_region_mapping(&self) -> Vec<(&str, &str)> {
+        self.countries
+            .iter()
+            .map(|(country, region)| (country.as_str(), region.as_str()))
+            .collect()
+    }
+
+    /// Whether `region` is in the table, the only regions region hints and fallbacks work with.
+    #[must_use]
+    pub fn is_known_region(&self, region: &str) -> bool {
+        self.region_coords(region).is_some()
+    }
+
+    /// Every known region with its approximate center coordinates (latitude, longitude).
+    #[must_use]
+    pub fn known_regions(&self) -> Vec<(&str, f64, f64)> {
+        self.regions
+            .iter()
+            .map(|(name, lat, lon)| (name.as_str(), *lat, *lon))
+            .collect()
+    }
+
+    /// Get coordinates for a region, returns None if unknown.
+    fn region_coords(&self, region: &str) -> Option<(f64, f64)> {
+        self.regions
+            .iter()
+            .find(|(name, _, _)| name == region)
+            .map(|(_, lat, lon)| (*lat, *lon))
+    }
+
+    /// Returns regions ordered by proximity from the given region.
+    /// Unknown regions return an empty vector.
+    #[must_use]
+    pub fn region_fallback_order(&self, region: &str) -> Vec<&str> {
+        self.regions_by_distance(region)
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    /// Same as `region_fallback_order`, without the regions further than `max_km` away.
+    #[must_use]
+    pub fn region_fallback_order_within(&self, region: &str, max_km: f64) -> Vec<&str> {
+        self.regions_by_distance(region)
+            .into_iter()
+            .take_while(|(_, dist)| *dist <= max_km)
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    /// Every region with its distance from `region` in km, closest first.
+    fn regions_by_distance(&self, region: &str) -> Vec<(&str, f64)> {
+        let Some((origin_lat, origin_lon)) = self.region_coords(region) else {
+            return Vec::new();
+        };
+
+        let mut regions_with_distance: Vec<_> = self
+            .regions
+            .iter()
+            .map(|(name, lat, lon)| {
+                let dist = haversine_distance(origin_lat, origin_lon, *lat, *lon);
+                (name.as_str(), dist)
+            })
+            .collect();
+
+        regions_with_distance
+            .sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+        regions_with_distance
+    }
+}
+
+/// Table consulted by the functions below, the built-in one until `install_geo_table` is called
+static TABLE: OnceLock<GeoTable> = OnceLock::new();
+
+/// Route with `table` for the lifetime of the process, instead of the built-in data.
+///
+/// Meant to be called once at startup, before the first request: only the first call has an
+/// effect, later ones return `false`.
+pub fn install_geo_table(table: GeoTable) -> bool {
+    TABLE.set(table).is_ok()
+}
+
+fn table() -> &'static GeoTable {
+    TABLE.get_or_init(GeoTable::default)
+}
+
+/// Maps ISO 3166-1 alpha-2 country codes to SFU regions.
+#[must_use]
+pub fn country_to_region(country_code: &str) -> Option<&'static str> {
+    table().country_to_region(country_code)
+}
+
+/// Every known country code with the region it maps to.
+#[must_use]
+pub fn country_region_mapping() -> Vec<(&'static str, &'static str)> {
+    table().country_region_mapping()
+}
+
+/// Every known region with its approximate center coordinates (latitude, longitude).
+#[must_use]
+pub fn known_regions() -> Vec<(&'static str, f64, f64)> {
+    table().known_regions()
 }
 
 /// Approximate great-circle distance using Haversine formula (returns km).
@@ -158,29 +335,23 @@ fn haversine_distance(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
     EARTH_RADIUS_KM * c
 }
 
+/// Whether `region` is a known region, built in or added by the `[geo]` section.
+#[must_use]
+pub fn is_known_region(region: &str) -> bool {
+    table().is_known_region(region)
+}
+
 /// Returns regions ordered by proximity from the given region.
 /// Unknown regions return an empty vector.
 #[must_use]
 pub fn region_fallback_order(region: &str) -> Vec<&'static str> {
-    let Some((origin_lat, origin_lon)) = region_coords(region) else {
-        return Vec::new();
-    };
-
-    let mut regions_with_distance: Vec<_> = REGIONS
-        .iter()
-        .map(|r| {
-            let dist = haversine_distance(origin_lat, origin_lon, r.lat, r.lon);
-            (r.name, dist)
-        })
-        .collect();
-
-    regions_with_distance
-        .sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
-
-    regions_with_distance
-        .into_iter()
-        .map(|(name, _)| name)
-        .collect()
+    table().region_fallback_order(region)
+}
+
+/// Same as `region_fallback_order`, without the regions further than `max_km` away.
+#[must_use]
+pub fn region_fallback_order_within(region: &str, max_km: f64) -> Vec<&'static str> {
+    table().region_fallback_order_within(region, max_km)
 }
 
 #[cfg(test)]
@@ -244,6 +415,17 @@ mod tests {
         assert_eq!(country_to_region("EG"), Some("eu-south"));
     }
 
+    #[test]
+    fn test_country_region_mapping_is_consistent() {
+        let mapping = country_region_mapping();
+        let regions = known_regions();
+        for (country, region) in &mapping {
+            assert_eq!(country_to_region(country), Some(*region));
+            assert!(regions.iter().any(|(name, _, _)| name == region));
+        }
+        assert!(mapping.contains(&("FR", "eu-west")));
+    }
+
     #[test]
     fn test_region_fallback_starts_with_self() {
         assert_eq!(region_fallback_order("eu-west")[0], "eu-west");
@@ -272,6 +454,20 @@ mod tests {
         assert_eq!(order.len(), 13);
     }
 
+    #[test]
+    fn test_region_fallback_within_budget() {
+        let tight = region_fallback_order_within("ap-south", 1000.0);
+        assert_eq!(tight, ["ap-south"]);
+
+        let medium = region_fallback_order_within("ap-south", 5000.0);
+        assert!(medium.contains(&"ap-southeast"));
+        assert!(!medium.contains(&"eu-west"));
+
+        let generous = region_fallback_order_within("ap-south", 50_000.0);
+        assert_eq!(generous, region_fallback_order("ap-south"));
+        assert!(region_fallback_order_within("unknown-region", 50_000.0).is_empty());
+    }
+
     #[test]
     fn test_ap_south_prefers_ap_southeast() {
         let order = region_fallback_order("ap-south");
@@ -283,11 +479,73 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_is_known_region() {
+        assert!(is_known_region("eu-west"));
+        assert!(!is_known_region("eu-wset"));
+        assert!(!is_known_region("EU-WEST"));
+        assert!(!is_known_region(""));
+
+        let table = GeoTable::default().with_overrides(&GeoConfig {
+            regions: vec![("eu-wset".to_string(), 48.8, 2.3)],
+            countries: Vec::new(),
+        });
+        assert!(table.is_known_region("eu-wset"));
+    }
+
+    #[test]
+    fn test_custom_region_in_fallback_order() {
+        let table = GeoTable::default().with_overrides(&GeoConfig {
+            regions: vec![("ap-east-2".to_string(), 22.3, 114.2)],
+            countries: vec![("HK".to_string(), "ap-east-2".to_string())],
+        });
+
+        let order = table.region_fallback_order("ap-east-2");
+        assert_eq!(order.len(), 14);
+        assert_eq!(order[0], "ap-east-2");
+        assert_eq!(order[1], "ap-east");
+
+        let from_tokyo = table.region_fallback_order("ap-northeast");
+        let ap_east_2 = from_tokyo.iter().position(|&r| r == "ap-east-2");
+        let eu_west = from_tokyo.iter().position(|&r| r == "eu-west");
+        assert!(ap_east_2 < eu_west);
+
+        assert_eq!(table.country_to_region("hk"), Some("ap-east-2"));
+        assert!(
+            table
+                .known_regions()
+                .iter()
+                .any(|(name, _, _)| *name == "ap-east-2")
+        );
+    }
+
+    #[test]
+    fn test_overrides_move_region_and_remap_country() {
+        let table = GeoTable::default().with_overrides(&GeoConfig {
+            // Frankfurt rather than Berlin
+            regions: vec![("eu-central".to_string(), 50.1, 8.7)],
+            countries: vec![("PL".to_string(), "eu-north".to_string())],
+        });
+        assert_eq!(table.region_coords("eu-central"), Some((50.1, 8.7)));
+        assert_eq!(table.known_regions().len(), 13);
+        assert_eq!(table.country_to_region("PL"), Some("eu-north"));
+        assert_eq!(table.country_to_region("FR"), Some("eu-west"));
+        assert_eq!(
+            table
+                .country_region_mapping()
+                .iter()
+                .filter(|(country, _)| *country == "PL")
+                .count(),
+            1
+        );
+    }
+
     #[test]
     fn test_haversine_distance() {
-        let (paris_lat, paris_lon) = region_coords("eu-west").unwrap();
-        let (berlin_lat, berlin_lon) = region_coords("eu-central").unwrap();
-        let (singapore_lat, singapore_lon) = region_coords("ap-southeast").unwrap();
+        let table = GeoTable::default();
+        let (paris_lat, paris_lon) = table.region_coords("eu-west").unwrap();
+        let (berlin_lat, berlin_lon) = table.region_coords("eu-central").unwrap();
+        let (singapore_lat, singapore_lon) = table.region_coords("ap-southeast").unwrap();
 
         let dist = haversine_distance(paris_lat, paris_lon, berlin_lat, berlin_lon);
         assert!(
@@ -0,0 +1,123 @@
+//! Client IP to country resolution with a MaxMind GeoLite2/GeoIP2 country database
+//!
+//! Used as a region hint when the caller sends neither `region` nor `country`.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::Path;
+
+use maxminddb::{MaxMindDBError, Reader, geoip2};
+
+pub struct GeoIp {
+    reader: Reader<Vec<u8>>,
+}
+
+impl GeoIp {
+    /// Load the database file in memory.
+    ///
+    /// # Errors
+    /// Returns the reader error if the file can't be read or isn't a MaxMind database.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, MaxMindDBError> {
+        Reader::open_readfile(path).map(|reader| Self { reader })
+    }
+
+    /// ISO 3166-1 alpha-2 code of the country of `ip`.
+    ///
+    /// `None` for addresses that aren't publicly routable (private, loopback, ...), and for
+    /// addresses the database doesn't know.
+    #[must_use]
+    pub fn country(&self, ip: IpAddr) -> Option<String> {
+        // IPv4 clients of a dual-stack listener, as found in IPv4-only databases
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+            IpAddr::V4(_) => ip,
+        };
+        if !is_public(ip) {
+            return None;
+        }
+        self.reader
+            .lookup::<geoip2::Country>(ip)
+            .ok()?
+            .country?
+            .iso_code
+            .map(str::to_string)
+    }
+}
+
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => is_public_v6(ip),
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let shared = ip.octets()[0] == 100 && (ip.octets()[1] & 0xC0) == 64; // 100.64.0.0/10
+    !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || shared)
+}
+
+const fn is_public_v6(ip: Ipv6Addr) -> bool {
+    !(ip.is_loopback() || ip.is_unspecified() || ip.is_unique_local() || ip.is_unicast_link_local())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test database: 2.125.160.0/24 is FR, 216.160.83.0/24 is US, nothing else
+    fn fixture() -> GeoIp {
+        GeoIp::open(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/geoip_country.mmdb"
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_country_lookup() {
+        let geoip = fixture();
+        assert_eq!(
+            geoip.country("2.125.160.216".parse().unwrap()),
+            Some("FR".to_string())
+        );
+        assert_eq!(
+            geoip.country("216.160.83.56".parse().unwrap()),
+            Some("US".to_string())
+        );
+        assert_eq!(
+            geoip.country("::ffff:2.125.160.216".parse().unwrap()),
+            Some("FR".to_string())
+        );
+        assert_eq!(geoip.country("8.8.8.8".parse().unwrap()), None);
+        assert_eq!(geoip.country("2001:4860::8888".parse().unwrap()), None);
+    }
+
+    #[test]
+    fn test_non_public_addresses_skipped() {
+        for ip in [
+            "10.0.0.1",
+            "192.168.1.100",
+            "127.0.0.1",
+            "100.64.0.1",
+            "169.254.0.1",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{ip}");
+        }
+        assert!(is_public("2.125.160.216".parse().unwrap()));
+        assert!(is_public("2001:4860::8888".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_open_invalid_database() {
+        assert!(GeoIp::open("/nonexistent/geoip.mmdb").is_err());
+        assert!(GeoIp::open(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml")).is_err());
+    }
+}
@@ -0,0 +1,643 @@
+//! SFU health probing
+//!
+//! An SFU is probed either over HTTP (`GET /noop`, behind its path prefix) or with a plain TCP connect
+//! to its host and port, which is cheaper when the fleet is large.
+//!
+//! Probe results go through `HealthState`, which only flips an instance after a number
+//! of consecutive identical results so that a transient blip doesn't eject it. An instance
+//! marked down is probed less and less often, see `HealthState::next_probe_delay`.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use tokio::net::TcpStream;
+use tokio::task::{JoinHandle, JoinSet};
+use tracing::{debug, info, warn};
+
+use super::balancer::sfu_url;
+use super::rng::SelectionRng;
+use crate::config::HealthCheckMode;
+use crate::shutdown::ShutdownToken;
+
+/// Largest jitter added to a backed-off probe delay, in per mille of the delay
+const BACKOFF_JITTER_PER_MILLE: u64 = 100;
+
+/// Consecutive probe results required before an instance changes state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HealthThresholds {
+    /// Consecutive failures before a healthy instance is marked down
+    pub failures: u32,
+    /// Consecutive successes before an unhealthy instance is marked up
+    pub successes: u32,
+}
+
+impl Default for HealthThresholds {
+    fn default() -> Self {
+        Self {
+            failures: 3,
+            successes: 2,
+        }
+    }
+}
+
+/// How and how often the background health checks probe the SFUs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HealthCheckConfig {
+    /// Delay between two probes of an SFU
+    pub interval: Duration,
+    /// Longest delay between two probes of an SFU marked down, see `HealthState::next_probe_delay`
+    pub max_interval: Duration,
+    /// Maximum duration of a single probe
+    pub timeout: Duration,
+    /// Mode of the SFUs that don't set their own
+    pub mode: HealthCheckMode,
+    pub thresholds: HealthThresholds,
+}
+
+impl Default for HealthCheckConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(10),
+            max_interval: Duration::from_mins(5),
+            timeout: Duration::from_secs(2),
+            mode: HealthCheckMode::default(),
+            thresholds: HealthThresholds::default(),
+        }
+    }
+}
+
+/// Health of a single SFU instance, instances start healthy.
+#[derive(Debug)]
+pub struct HealthState {
+    healthy: AtomicBool,
+    consecutive_failures: AtomicU32,
+    consecutive_successes: AtomicU32,
+    /// Time of the last recorded probe in milliseconds since the Unix epoch, 0 if none
+    last_check_ms: AtomicU64,
+    /// Moving average of the probe round-trip times in microseconds, 0 if none
+    latency_us: AtomicU64,
+    /// Time of the last probe in milliseconds since the Unix epoch, 0 if none, with its result,
+    /// including the on-demand probes that don't count towards `healthy`
+    last_probe_ms: AtomicU64,
+    last_probe_up: AtomicBool,
+}
+
+impl Default for HealthState {
+    fn default() -> Self {
+        Self {
+            healthy: AtomicBool::new(true),
+            consecutive_failures: AtomicU32::new(0),
+            consecutive_successes: AtomicU32::new(0),
+            last_check_ms: AtomicU64::new(0),
+            latency_us: AtomicU64::new(0),
+            last_probe_ms: AtomicU64::new(0),
+            last_probe_up: AtomicBool::new(false),
+        }
+    }
+}
+
+impl HealthState {
+    pub fn is_healthy(&self) -> bool {
+        self.healthy.load(Ordering::Relaxed)
+    }
+
+    /// Time of the last recorded probe, `None` if the instance was never probed.
+    pub fn last_check(&self) -> Option<SystemTime> {
+        match self.last_check_ms.load(Ordering::Relaxed) {
+            0 => None,
+            ms => Some(UNIX_EPOCH + Duration::from_millis(ms)),
+        }
+    }
+
+    /// Moving average of the round-trip times of the successful probes, `None` before the first.
+    pub fn latency(&self) -> Option<Duration> {
+        match self.latency_us.load(Ordering::Relaxed) {
+            0 => None,
+            us => Some(Duration::from_micros(us)),
+        }
+    }
+
+    /// Fold the round-trip time of a successful probe into `latency`, the last probe weighs
+    /// a quarter so that a node slowing down is noticed within a few probes.
+    ///
+    /// Meant to be called by a single prober per instance.
+    pub fn record_latency(&self, rtt: Duration) {
+        let sample = u64::try_from(rtt.as_micros()).unwrap_or(u64::MAX).max(1);
+        let average = match self.latency_us.load(Ordering::Relaxed) {
+            0 => sample,
+            previous => previous - previous / 4 + sample / 4,
+        };
+        self.latency_us.store(average.max(1), Ordering::Relaxed);
+    }
+
+    /// Result of the last probe if it's less than `max_age` old, with its time.
+    pub fn recent_probe(&self, max_age: Duration) -> Option<(bool, SystemTime)> {
+        let at = match self.last_probe_ms.load(Ordering::Relaxed) {
+            0 => return None,
+            ms => UNIX_EPOCH + Duration::from_millis(ms),
+        };
+        let age = SystemTime::now().duration_since(at).unwrap_or_default();
+        (age < max_age).then(|| (self.last_probe_up.load(Ordering::Relaxed), at))
+    }
+
+    /// Remember the result of a probe for `recent_probe`, without affecting `is_healthy`.
+    pub fn record_probe(&self, success: bool) {
+        self.last_probe_up.store(success, Ordering::Relaxed);
+        self.last_probe_ms
+            .store(unix_ms(SystemTime::now()), Ordering::Relaxed);
+    }
+
+    /// Record a probe result, returns true if the instance changed state.
+    ///
+    /// Meant to be called by a single prober per instance.
+    pub fn record(&self, success: bool, thresholds: HealthThresholds) -> bool {
+        self.record_probe(success);
+        let (streak, other_streak, threshold) = if success {
+            (
+                &self.consecutive_successes,
+                &self.consecutive_failures,
+                thresholds.successes,
+            )
+        } else {
+            (
+                &self.consecutive_failures,
+                &self.consecutive_successes,
+                thresholds.failures,
+            )
+        };
+        other_streak.store(0, Ordering::Relaxed);
+        self.last_check_ms
+            .store(unix_ms(SystemTime::now()), Ordering::Relaxed);
+        let streak = streak.fetch_add(1, Ordering::Relaxed).saturating_add(1);
+
+        if self.is_healthy() != success && streak >= threshold {
+            self.healthy.store(success, Ordering::Relaxed);
+            return true;
+        }
+        false
+    }
+
+    /// Delay before the next probe, given the results recorded so far.
+    ///
+    /// `config.interval` while the instance is up. Once marked down, the delay doubles with
+    /// every further failure, up to `config.max_interval`, plus `jitter_per_mille` of it so
+    /// that the probes of several gateways spread out. The first success brings it back to
+    /// `config.interval`.
+    pub fn next_probe_delay(&self, config: &HealthCheckConfig, jitter_per_mille: u64) -> Duration {
+        if self.is_healthy() {
+            return config.interval;
+        }
+        // no failure since the last success when recovering: normal pace until marked up
+        let failures = self.consecutive_failures.load(Ordering::Relaxed);
+        let doublings = failures.saturating_sub(config.thresholds.failures).min(31);
+        let delay = config
+            .interval
+            .saturating_mul(1 << doublings)
+            .min(config.max_interval);
+        let jitter = u32::try_from(jitter_per_mille.min(1000)).unwrap_or(0);
+        delay + delay * jitter / 1000
+    }
+}
+
+/// Milliseconds since the Unix epoch, at least 1 so that it is never taken for "never".
+fn unix_ms(time: SystemTime) -> u64 {
+    let ms = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis());
+    u64::try_from(ms).unwrap_or(u64::MAX).max(1)
+}
+
+/// An SFU as seen by the background health checks.
+pub(crate) struct ProbeTarget {
+    pub address: String,
+    pub path_prefix: String,
+    pub mode: HealthCheckMode,
+    pub health: Arc<HealthState>,
+}
+
+/// Background task probing SFUs periodically, stopped when dropped.
+pub(crate) struct HealthChecker {
+    handle: JoinHandle<()>,
+    pub client: reqwest::Client,
+    pub config: HealthCheckConfig,
+}
+
+impl Drop for HealthChecker {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+impl HealthChecker {
+    /// Start probing each of `targets` every `config.interval`, backing off while it is down,
+    /// the first probes run right away.
+    ///
+    /// Must be called within a tokio runtime.
+    /// Probing also stops when `shutdown` is triggered.
+    pub(crate) fn spawn(
+        targets: Vec<ProbeTarget>,
+        client: reqwest::Client,
+        config: HealthCheckConfig,
+        shutdown: ShutdownToken,
+    ) -> Self {
+        let task_client = client.clone();
+        let handle = tokio::spawn(async move {
+            let rng = Arc::new(SelectionRng::from_entropy());
+            let mut probes = JoinSet::new();
+            for target in targets {
+                probes.spawn(check_periodically(
+                    target,
+                    task_client.clone(),
+                    config,
+                    Arc::clone(&rng),
+                ));
+            }
+            let probing = async {
+                while probes.join_next().await.is_some() {}
+                // nothing to probe, still wait for the shutdown
+                std::future::pending::<()>().await;
+            };
+            tokio::select! {
+                () = probing => {}
+                () = shutdown.triggered() => info!("Health checks stopped"),
+            }
+        });
+        Self {
+            handle,
+            client,
+            config,
+        }
+    }
+
+    /// Stop probing, the task is aborted at its next await point.
+    pub(crate) fn stop(&self) {
+        self.handle.abort();
+    }
+}
+
+/// Probe a target and record the result, forever, waiting `HealthState::next_probe_delay`
+/// between two probes.
+async fn check_periodically(
+    target: ProbeTarget,
+    client: reqwest::Client,
+    config: HealthCheckConfig,
+    rng: Arc<SelectionRng>,
+) {
+    loop {
+        let started = std::time::Instant::now();
+        let healthy = probe(
+            &client,
+            &target.address,
+            &target.path_prefix,
+            target.mode,
+            config.timeout,
+        )
+        .await;
+        if healthy {
+            target.health.record_latency(started.elapsed());
+        }
+        if target.health.record(healthy, config.thresholds) {
+            if healthy {
+                info!(address = %target.address, "SFU is back up");
+            } else {
+                warn!(address = %target.address, "SFU marked down");
+            }
+        }
+        let jitter = rng.next_u64() % (BACKOFF_JITTER_PER_MILLE + 1);
+        let delay = target.health.next_probe_delay(&config, jitter);
+        if !healthy && !target.health.is_healthy() {
+            debug!(address = %target.address, delay_ms = delay.as_millis(), "Next probe of SFU down");
+        }
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// Probe an SFU once, returns true if it answered within `timeout`. HTTP probes go through
+/// `path_prefix`, like the forwarded requests.
+pub async fn probe(
+    client: &reqwest::Client,
+    address: &str,
+    path_prefix: &str,
+    mode: HealthCheckMode,
+    timeout: Duration,
+) -> bool {
+    match mode {
+        HealthCheckMode::Http => probe_http(client, address, path_prefix, timeout).await,
+        HealthCheckMode::Tcp => probe_tcp(address, timeout).await,
+    }
+}
+
+async fn probe_http(
+    client: &reqwest::Client,
+    address: &str,
+    path_prefix: &str,
+    timeout: Duration,
+) -> bool {
+    match client
+        .get(sfu_url(address, path_prefix, "/noop", ""))
+        .timeout(timeout)
+        .send()
+        .await
+    {
+        Ok(response) => response.status().is_success(),
+        Err(e) => {
+            debug!(address, error = %e, "HTTP health probe failed");
+            false
+        }
+    }
+}
+
+async fn probe_tcp(address: &str, timeout: Duration) -> bool {
+    let Some((host, port)) = host_and_port(address) else {
+        debug!(address, "Cannot extract host and port for TCP health probe");
+        return false;
+    };
+    match tokio::time::timeout(timeout, TcpStream::connect((host.as_str(), port))).await {
+        Ok(Ok(_)) => true,
+        Ok(Err(e)) => {
+            debug!(address, error = %e, "TCP health probe failed");
+            false
+        }
+        Err(_) => {
+            debug!(address, "TCP health probe timed out");
+            false
+        }
+    }
+}
+
+/// Extract host and port from an SFU base URL, using the scheme's default port if omitted.
+fn host_and_port(address: &str) -> Option<(String, u16)> {
+    let url = reqwest::Url::parse(address).ok()?;
+    let port = url.port_or_known_default()?;
+    let host = url
+        .host_str()?
+        .trim_start_matches('[')
+        .trim_end_matches(']');
+    Some((host.to_string(), port))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tokio::net::TcpListener;
+
+    const TIMEOUT: Duration = Duration::from_millis(500);
+
+    #[test]
+    fn test_flaky_sfu_does_not_flap() {
+        let state = HealthState::default();
+        let thresholds = HealthThresholds::default();
+
+        // alternating results never reach the failure threshold
+        for i in 0..10 {
+            assert!(!state.record(i % 2 == 1, thresholds));
+            assert!(state.is_healthy());
+        }
+
+        // marked down on the third consecutive failure only
+        assert!(!state.record(false, thresholds));
+        assert!(!state.record(false, thresholds));
+        assert!(state.is_healthy());
+        assert!(state.record(false, thresholds));
+        assert!(!state.is_healthy());
+
+        // alternating results don't bring it back either
+        for i in 0..10 {
+            assert!(!state.record(i % 2 == 0, thresholds));
+            assert!(!state.is_healthy());
+        }
+
+        // marked up on the second consecutive success
+        assert!(!state.record(true, thresholds));
+        assert!(!state.is_healthy());
+        assert!(state.record(true, thresholds));
+        assert!(state.is_healthy());
+    }
+
+    #[test]
+    fn test_last_check_recorded() {
+        let state = HealthState::default();
+        assert_eq!(state.last_check(), None);
+
+        let before = SystemTime::now() - Duration::from_millis(1);
+        state.record(false, HealthThresholds::default());
+        let last_check = state.last_check().unwrap();
+        assert!(last_check >= before && last_check <= SystemTime::now());
+    }
+
+    #[test]
+    fn test_recent_probe_expires() {
+        let state = HealthState::default();
+        assert_eq!(state.recent_probe(Duration::from_mins(1)), None);
+
+        // on-demand probes are remembered but don't count towards health
+        state.record_probe(false);
+        assert!(state.is_healthy());
+        assert_eq!(state.last_check(), None);
+        let (up, _) = state.recent_probe(Duration::from_mins(1)).unwrap();
+        assert!(!up);
+        assert_eq!(state.recent_probe(Duration::ZERO), None);
+
+        // background probes update it as well
+        state.record(true, HealthThresholds::default());
+        let (up, _) = state.recent_probe(Duration::from_mins(1)).unwrap();
+        assert!(up);
+    }
+
+    #[test]
+    fn test_thresholds_of_one_flip_immediately() {
+        let state = HealthState::default();
+        let thresholds = HealthThresholds {
+            failures: 1,
+            successes: 1,
+        };
+        assert!(state.record(false, thresholds));
+        assert!(!state.is_healthy());
+        assert!(state.record(true, thresholds));
+        assert!(state.is_healthy());
+    }
+
+    #[test]
+    fn test_latency_moving_average() {
+        let state = HealthState::default();
+        assert_eq!(state.latency(), None);
+
+        state.record_latency(Duration::from_millis(100));
+        assert_eq!(state.latency(), Some(Duration::from_millis(100)));
+        state.record_latency(Duration::from_millis(20));
+        assert_eq!(state.latency(), Some(Duration::from_millis(80)));
+
+        // converges toward the new round-trip time
+        for _ in 0..30 {
+            state.record_latency(Duration::from_millis(20));
+        }
+        let latency = state.latency().unwrap();
+        assert!(latency < Duration::from_millis(21), "{latency:?}");
+    }
+
+    #[test]
+    fn test_backoff_while_down() {
+        let state = HealthState::default();
+        let config = HealthCheckConfig {
+            interval: Duration::from_secs(10),
+            max_interval: Duration::from_mins(1),
+            ..HealthCheckConfig::default()
+        };
+        let delay = |state: &HealthState| state.next_probe_delay(&config, 0);
+
+        // normal pace until marked down, on the third failure
+        for _ in 0..2 {
+            state.record(false, config.thresholds);
+            assert_eq!(delay(&state), Duration::from_secs(10));
+        }
+        let mut schedule = Vec::new();
+        for _ in 0..5 {
+            state.record(false, config.thresholds);
+            schedule.push(delay(&state).as_secs());
+        }
+        assert_eq!(schedule, [10, 20, 40, 60, 60]);
+
+        // jitter only ever lengthens the delay, by 10% at most
+        assert_eq!(
+            state.next_probe_delay(&config, 100),
+            Duration::from_secs(66)
+        );
+    }
+
+    #[test]
+    fn test_backoff_reset_on_success() {
+        let state = HealthState::default();
+        let config = HealthCheckConfig::default();
+        for _ in 0..6 {
+            state.record(false, config.thresholds);
+        }
+        assert_eq!(state.next_probe_delay(&config, 0), config.interval * 8);
+
+        // still down, but probed at the normal pace to come back up quickly
+        state.record(true, config.thresholds);
+        assert!(!state.is_healthy());
+        assert_eq!(state.next_probe_delay(&config, 0), config.interval);
+
+        state.record(false, config.thresholds);
+        assert_eq!(state.next_probe_delay(&config, 0), config.interval);
+        state.record(true, config.thresholds);
+        state.record(true, config.thresholds);
+        assert!(state.is_healthy());
+        assert_eq!(state.next_probe_delay(&config, 0), config.interval);
+    }
+
+    #[test]
+    fn test_host_and_port() {
+        assert_eq!(
+            host_and_port("http://sfu1.example.com:3000"),
+            Some(("sfu1.example.com".to_string(), 3000))
+        );
+        assert_eq!(
+            host_and_port("https://sfu1.example.com"),
+            Some(("sfu1.example.com".to_string(), 443))
+        );
+        assert_eq!(
+            host_and_port("http://[::1]:8070"),
+            Some(("::1".to_string(), 8070))
+        );
+        assert_eq!(host_and_port("not a url"), None);
+    }
+
+    #[tokio::test]
+    async fn test_tcp_probe_listening_socket_is_healthy() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+
+        let client = reqwest::Client::new();
+        assert!(probe(&client, &address, "", HealthCheckMode::Tcp, TIMEOUT).await);
+    }
+
+    #[tokio::test]
+    async fn test_tcp_probe_closed_port_is_unhealthy() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+
+        let client = reqwest::Client::new();
+        assert!(!probe(&client, &address, "", HealthCheckMode::Tcp, TIMEOUT).await);
+    }
+
+    #[tokio::test]
+    async fn test_http_probe() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let up = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/noop"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&up)
+            .await;
+        let down = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/noop"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&down)
+            .await;
+
+        let client = reqwest::Client::new();
+        assert!(probe(&client, &up.uri(), "", HealthCheckMode::Http, TIMEOUT).await);
+        assert!(!probe(&client, &down.uri(), "", HealthCheckMode::Http, TIMEOUT).await);
+    }
+
+    #[tokio::test]
+    async fn test_http_probe_behind_path_prefix() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        // an ingress only routing the prefixed paths to the SFU
+        let prefixed = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/sfu/noop"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&prefixed)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/noop"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&prefixed)
+            .await;
+
+        let client = reqwest::Client::new();
+        assert!(
+            probe(
+                &client,
+                &prefixed.uri(),
+                "/sfu",
+                HealthCheckMode::Http,
+                TIMEOUT
+            )
+            .await
+        );
+        assert!(!probe(&client, &prefixed.uri(), "", HealthCheckMode::Http, TIMEOUT).await);
+    }
+
+    #[tokio::test]
+    async fn test_health_checks_stop_on_shutdown() {
+        let shutdown = ShutdownToken::new();
+        let checker = HealthChecker::spawn(
+            Vec::new(),
+            reqwest::Client::new(),
+            HealthCheckConfig::default(),
+            shutdown.clone(),
+        );
+        tokio::task::yield_now().await;
+        assert!(!checker.handle.is_finished());
+
+        shutdown.trigger();
+        tokio::time::timeout(TIMEOUT, async {
+            while !checker.handle.is_finished() {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+}
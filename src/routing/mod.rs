@@ -1,5 +1,19 @@
+mod affinity;
 mod balancer;
 mod geo;
+mod geoip;
+mod health;
+mod rng;
 
-pub use balancer::{Balancer, SfuInstance};
-pub use geo::{country_to_region, region_fallback_order};
+pub use affinity::AffinityCache;
+pub(crate) use balancer::sfu_url;
+pub use balancer::{
+    Balancer, BalancerSnapshot, RegionIssue, SelectError, SfuInstance, SfuSnapshot,
+};
+pub use geo::{
+    GeoTable, country_region_mapping, country_to_region, install_geo_table, is_known_region,
+    known_regions, region_fallback_order, region_fallback_order_within,
+};
+pub use geoip::GeoIp;
+pub use health::{HealthCheckConfig, HealthState, HealthThresholds, probe};
+pub use rng::SelectionRng;
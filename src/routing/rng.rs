@@ -0,0 +1,83 @@
+use std::hash::{BuildHasher, RandomState};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// `SplitMix64` state increment (golden ratio).
+const GOLDEN_GAMMA: u64 = 0x9E37_79B9_7F4A_7C15;
+
+/// Seedable pseudo-random generator shared by concurrent `select` calls.
+///
+/// Counter-based (`SplitMix64`): each draw atomically advances the state and mixes it,
+/// so no lock is needed and a given seed always yields the same sequence of draws.
+/// Not suitable for anything security related.
+#[derive(Debug)]
+pub struct SelectionRng {
+    state: AtomicU64,
+}
+
+impl SelectionRng {
+    /// Deterministic generator, for reproducible tests and debugging.
+    #[must_use]
+    pub const fn from_seed(seed: u64) -> Self {
+        Self {
+            state: AtomicU64::new(seed),
+        }
+    }
+
+    /// Generator seeded from the process' hash randomness and the current time.
+    #[must_use]
+    pub fn from_entropy() -> Self {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default();
+        Self::from_seed(RandomState::new().hash_one(now))
+    }
+
+    pub fn next_u64(&self) -> u64 {
+        let mut z = self
+            .state
+            .fetch_add(GOLDEN_GAMMA, Ordering::Relaxed)
+            .wrapping_add(GOLDEN_GAMMA);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Draw a value in `0..bound`, returns 0 when `bound` is 0.
+    pub fn next_below(&self, bound: usize) -> usize {
+        let bound = u64::try_from(bound).unwrap_or(u64::MAX);
+        self.next_u64()
+            .checked_rem(bound)
+            .and_then(|v| usize::try_from(v).ok())
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_seed_same_sequence() {
+        let a = SelectionRng::from_seed(42);
+        let b = SelectionRng::from_seed(42);
+        let seq_a: Vec<_> = (0..16).map(|_| a.next_u64()).collect();
+        let seq_b: Vec<_> = (0..16).map(|_| b.next_u64()).collect();
+        assert_eq!(seq_a, seq_b);
+    }
+
+    #[test]
+    fn test_different_seeds_diverge() {
+        let a = SelectionRng::from_seed(1);
+        let b = SelectionRng::from_seed(2);
+        assert_ne!(a.next_u64(), b.next_u64());
+    }
+
+    #[test]
+    fn test_next_below_in_range() {
+        let rng = SelectionRng::from_seed(7);
+        for _ in 0..1000 {
+            assert!(rng.next_below(3) < 3);
+        }
+        assert_eq!(rng.next_below(0), 0);
+    }
+}
@@ -0,0 +1,216 @@
+//! Graceful shutdown: a token triggered on SIGTERM or SIGINT, and the drain of in-flight forwards
+//!
+//! The pieces are kept apart from the server so that the sequence can be tested without one:
+//! `drain_on_shutdown` resolves once the token is triggered and the forwards are done, or the
+//! grace period is over.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use tokio::sync::{Notify, watch};
+use tracing::info;
+
+/// Shared shutdown signal, cheap to clone, every clone sees the trigger.
+#[derive(Debug, Clone)]
+pub struct ShutdownToken {
+    sender: Arc<watch::Sender<bool>>,
+}
+
+impl Default for ShutdownToken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ShutdownToken {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            sender: Arc::new(watch::Sender::new(false)),
+        }
+    }
+
+    /// Start the shutdown, idempotent.
+    pub fn trigger(&self) {
+        self.sender.send_replace(true);
+    }
+
+    #[must_use]
+    pub fn is_triggered(&self) -> bool {
+        *self.sender.borrow()
+    }
+
+    /// Resolves once the token is triggered, right away if it already is.
+    pub async fn triggered(&self) {
+        let mut receiver = self.sender.subscribe();
+        // the sender lives as long as `self`, the wait can't fail
+        let _ = receiver.wait_for(|triggered| *triggered).await;
+    }
+}
+
+/// Count of the channel forwards in progress.
+#[derive(Debug, Default)]
+pub struct InFlight {
+    count: AtomicUsize,
+    idle: Notify,
+}
+
+impl InFlight {
+    /// Count a forward in progress until the returned guard is dropped.
+    pub fn enter(&self) -> InFlightGuard<'_> {
+        self.count.fetch_add(1, Ordering::AcqRel);
+        InFlightGuard(self)
+    }
+
+    #[must_use]
+    pub fn count(&self) -> usize {
+        self.count.load(Ordering::Acquire)
+    }
+
+    /// Resolves once no forward is in progress.
+    async fn idle(&self) {
+        loop {
+            // created before the check, so that a guard dropped in between still wakes it
+            let idle = self.idle.notified();
+            if self.count() == 0 {
+                return;
+            }
+            idle.await;
+        }
+    }
+}
+
+/// A forward in progress, see `InFlight::enter`.
+#[must_use = "the forward is only counted while the guard is alive"]
+pub struct InFlightGuard<'a>(&'a InFlight);
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        if self.0.count.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.0.idle.notify_waiters();
+        }
+    }
+}
+
+/// How the drain of the in-flight forwards ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Drain {
+    /// Every forward finished within the grace period
+    Completed,
+    /// The grace period ran out with forwards still in progress
+    TimedOut { remaining: usize },
+}
+
+/// Wait for the forwards in progress to finish, at most `grace`.
+pub async fn drain(in_flight: &InFlight, grace: Duration) -> Drain {
+    match tokio::time::timeout(grace, in_flight.idle()).await {
+        Ok(()) => Drain::Completed,
+        Err(_) => Drain::TimedOut {
+            remaining: in_flight.count(),
+        },
+    }
+}
+
+/// Wait for `token` to be triggered, then drain the forwards in progress, see `drain`.
+pub async fn drain_on_shutdown(
+    token: &ShutdownToken,
+    in_flight: &InFlight,
+    grace: Duration,
+) -> Drain {
+    token.triggered().await;
+    info!(
+        in_flight = in_flight.count(),
+        grace_ms = grace.as_millis(),
+        "Shutting down, draining in-flight forwards"
+    );
+    drain(in_flight, grace).await
+}
+
+/// Trigger `token` on the first SIGTERM or SIGINT (Ctrl-C).
+///
+/// # Errors
+/// Returns the error of registering the signal handlers.
+pub async fn trigger_on_signal(token: ShutdownToken) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        let mut terminate = signal(SignalKind::terminate())?;
+        tokio::select! {
+            _ = terminate.recv() => info!("SIGTERM received"),
+            result = tokio::signal::ctrl_c() => {
+                result?;
+                info!("SIGINT received");
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        tokio::signal::ctrl_c().await?;
+        info!("Ctrl-C received");
+    }
+    token.trigger();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_token_triggered_once_for_all_clones() {
+        let token = ShutdownToken::new();
+        let clone = token.clone();
+        assert!(!clone.is_triggered());
+
+        let waiting = tokio::spawn(async move { clone.triggered().await });
+        token.trigger();
+        token.trigger();
+        assert!(waiting.await.is_ok());
+        assert!(token.is_triggered());
+        // already triggered, resolves right away
+        token.triggered().await;
+    }
+
+    #[tokio::test]
+    async fn test_drain_waits_for_forwards() {
+        let in_flight = InFlight::default();
+        let guard = in_flight.enter();
+        let forward = async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            drop(guard);
+        };
+
+        let started = std::time::Instant::now();
+        let (drained, ()) = tokio::join!(drain(&in_flight, Duration::from_secs(5)), forward);
+        assert_eq!(drained, Drain::Completed);
+        assert!(started.elapsed() >= Duration::from_millis(50));
+        assert_eq!(in_flight.count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_drain_gives_up_after_grace() {
+        let in_flight = InFlight::default();
+        let _stuck = in_flight.enter();
+        assert_eq!(
+            drain(&in_flight, Duration::from_millis(20)).await,
+            Drain::TimedOut { remaining: 1 }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_drain_starts_on_trigger() {
+        let token = ShutdownToken::new();
+        let in_flight = InFlight::default();
+        let shutdown = drain_on_shutdown(&token, &in_flight, Duration::from_secs(5));
+        tokio::pin!(shutdown);
+
+        // nothing in flight, but no shutdown either
+        let pending = tokio::time::timeout(Duration::from_millis(20), shutdown.as_mut()).await;
+        assert!(pending.is_err());
+
+        token.trigger();
+        assert_eq!(shutdown.await, Drain::Completed);
+    }
+}
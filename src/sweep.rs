@@ -0,0 +1,92 @@
+//! Periodic eviction of the idle per-issuer entries
+//!
+//! The per-issuer maps (rate limiter buckets, issuer affinity) only drop entries when they
+//! are written to, so the issuers seen once linger while the gateway is quiet. The sweeper
+//! walks them at an interval and evicts the entries untouched for longer than a TTL, which
+//! keeps memory bounded over long uptimes.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::task::JoinHandle;
+use tracing::{debug, info};
+
+use crate::shutdown::ShutdownToken;
+
+/// A map of entries evicted once idle.
+pub trait Sweep {
+    /// Evict the entries untouched for at least `ttl` at `now`, returns how many were.
+    fn sweep(&self, now: Instant, ttl: Duration) -> usize;
+}
+
+impl<T: Sweep> Sweep for Option<T> {
+    fn sweep(&self, now: Instant, ttl: Duration) -> usize {
+        self.as_ref().map_or(0, |inner| inner.sweep(now, ttl))
+    }
+}
+
+/// How often the sweeper runs, and how long an entry may stay untouched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SweepConfig {
+    pub interval: Duration,
+    pub ttl: Duration,
+}
+
+/// Sweep `target` every `config.interval` until `shutdown` is triggered.
+pub fn spawn_sweeper<T>(
+    target: Arc<T>,
+    config: SweepConfig,
+    shutdown: ShutdownToken,
+) -> JoinHandle<()>
+where
+    T: Sweep + Send + Sync + 'static,
+{
+    tokio::spawn(async move {
+        let sweeping = async {
+            loop {
+                tokio::time::sleep(config.interval).await;
+                // the tokio clock, so that tests can pause and advance it
+                let now = tokio::time::Instant::now().into_std();
+                let evicted = target.sweep(now, config.ttl);
+                if evicted > 0 {
+                    debug!(evicted, "Idle entries evicted");
+                }
+            }
+        };
+        tokio::select! {
+            () = sweeping => {}
+            () = shutdown.triggered() => info!("Sweeper stopped"),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::RateLimiter;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_idle_entry_evicted_after_ttl() {
+        let limiter = Arc::new(RateLimiter::new(60));
+        let shutdown = ShutdownToken::new();
+        let config = SweepConfig {
+            interval: Duration::from_mins(1),
+            ttl: Duration::from_mins(2),
+        };
+        let sweeper = spawn_sweeper(Arc::clone(&limiter), config, shutdown.clone());
+        let now = || tokio::time::Instant::now().into_std();
+
+        assert!(limiter.check("idle", now()).is_ok());
+        assert!(limiter.check("active", now()).is_ok());
+        for step in 1..=6 {
+            tokio::time::sleep(Duration::from_secs(25)).await;
+            // swept at 60s and 120s, "idle" is only untouched for long enough at 120s
+            let expected = if step * 25 < 120 { 2 } else { 1 };
+            assert_eq!(limiter.len(), expected, "after {}s", step * 25);
+            assert!(limiter.check("active", now()).is_ok());
+        }
+
+        shutdown.trigger();
+        assert!(sweeper.await.is_ok());
+    }
+}
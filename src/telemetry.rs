@@ -0,0 +1,128 @@
+//! OpenTelemetry export of the request spans, and W3C trace context propagation
+//!
+//! Opt-in: nothing is exported until `install` is called, and until then the global
+//! propagator is a no-op so `set_remote_parent` and `trace_headers` do nothing. Once
+//! installed, the `request` span of each forwarding handler is exported, as a child of the
+//! caller's `traceparent` when there is one, and the SFU receives a `traceparent` naming it.
+
+use actix_web::http::header::HeaderMap;
+use opentelemetry::global;
+use opentelemetry::propagation::{Extractor, Injector};
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_otlp::{ExporterBuildError, SpanExporter, WithExportConfig};
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{SdkTracer, SdkTracerProvider};
+use tracing::Subscriber;
+use tracing_opentelemetry::{OpenTelemetryLayer, OpenTelemetrySpanExt};
+use tracing_subscriber::registry::LookupSpan;
+
+/// Service name the spans are exported under
+const SERVICE_NAME: &str = "sfu-gateway";
+
+/// Path of the traces on an OTLP/HTTP collector
+const TRACES_PATH: &str = "/v1/traces";
+
+/// Tracer provider exporting in batches to the OTLP/HTTP collector at `endpoint`.
+///
+/// `endpoint` is the base URL of the collector (`http://jaeger:4318`), `/v1/traces` is
+/// appended unless already there.
+///
+/// # Errors
+/// Returns the exporter error when `endpoint` isn't a valid URL.
+pub fn otlp_provider(endpoint: &str) -> Result<SdkTracerProvider, ExporterBuildError> {
+    let base = endpoint.trim_end_matches('/');
+    let endpoint = if base.ends_with(TRACES_PATH) {
+        base.to_string()
+    } else {
+        format!("{base}{TRACES_PATH}")
+    };
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(endpoint)
+        .build()?;
+    Ok(SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(Resource::builder().with_service_name(SERVICE_NAME).build())
+        .build())
+}
+
+/// Make `provider` the global tracer provider with W3C trace context propagation, returns
+/// the layer sending the `tracing` spans to it.
+#[must_use]
+pub fn install<S>(provider: &SdkTracerProvider) -> OpenTelemetryLayer<S, SdkTracer>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    global::set_text_map_propagator(TraceContextPropagator::new());
+    global::set_tracer_provider(provider.clone());
+    tracing_opentelemetry::layer().with_tracer(provider.tracer(SERVICE_NAME))
+}
+
+/// Make `span` a child of the trace context in `headers`, when there is a valid one.
+pub(crate) fn set_remote_parent(span: &tracing::Span, headers: &HeaderMap) {
+    let parent =
+        global::get_text_map_propagator(|propagator| propagator.extract(&HeaderExtractor(headers)));
+    // no exporter installed or no span in the caller's context: the span starts a trace
+    let _ = span.set_parent(parent);
+}
+
+/// Trace context headers naming the current span, for the request to the SFU.
+///
+/// Empty when no exporter is installed.
+pub(crate) fn trace_headers() -> reqwest::header::HeaderMap {
+    let mut headers = reqwest::header::HeaderMap::new();
+    let context = tracing::Span::current().context();
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut HeaderInjector(&mut headers));
+    });
+    headers
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0
+            .keys()
+            .map(actix_web::http::header::HeaderName::as_str)
+            .collect()
+    }
+}
+
+struct HeaderInjector<'a>(&'a mut reqwest::header::HeaderMap);
+
+impl Injector for HeaderInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(name), Ok(value)) = (
+            reqwest::header::HeaderName::from_bytes(key.as_bytes()),
+            reqwest::header::HeaderValue::from_str(&value),
+        ) {
+            self.0.insert(name, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nothing_propagated_when_not_installed() {
+        let span = tracing::info_span!("request");
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            actix_web::http::header::HeaderName::from_static("traceparent"),
+            actix_web::http::header::HeaderValue::from_static(
+                "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            ),
+        );
+        set_remote_parent(&span, &headers);
+        let _entered = span.enter();
+        assert!(trace_headers().is_empty());
+    }
+}
@@ -1,16 +1,52 @@
 mod common;
 
+use std::sync::Arc;
+
 use actix_web::{App, http::StatusCode, test, web};
 use serde_json::json;
-use wiremock::matchers::{method, path};
-use wiremock::{Mock, MockServer, ResponseTemplate};
+use wiremock::matchers::{method, path, query_param, query_param_is_missing};
+use wiremock::{Match, Mock, MockServer, Request, ResponseTemplate};
 
 use common::{GATEWAY_KEY, create_app_state, make_test_claims, sign_claims};
-use sfu_gateway::config::SfuConfig;
-use sfu_gateway::http::{channel, noop};
+use sfu_gateway::config::{ApiKeyConfig, SfuConfig};
+use sfu_gateway::http::{AppState, VerifyOptions, channel, noop, verify};
+use sfu_gateway::routing::Balancer;
 
 const SFU_KEY: &[u8] = b"sfu-key-padded-to-32-bytes-here!";
 
+/// Matches requests whose bearer token verifies with `key`, like the SFU would check it.
+struct VerifiedWith {
+    key: &'static [u8],
+    iss: &'static str,
+}
+
+impl Match for VerifiedWith {
+    fn matches(&self, request: &Request) -> bool {
+        request
+            .headers
+            .get("Authorization")
+            .and_then(|h| h.to_str().ok())
+            .and_then(|h| h.strip_prefix("Bearer "))
+            .and_then(|token| verify(token, self.key, &VerifyOptions::default()).ok())
+            .is_some_and(|claims| claims.iss == self.iss)
+    }
+}
+
+/// Matches requests whose bearer token, verified with `SFU_KEY`, carries `key` as its `key` claim.
+struct ForwardedRecordingKey(Option<&'static str>);
+
+impl Match for ForwardedRecordingKey {
+    fn matches(&self, request: &Request) -> bool {
+        request
+            .headers
+            .get("Authorization")
+            .and_then(|h| h.to_str().ok())
+            .and_then(|h| h.strip_prefix("Bearer "))
+            .and_then(|token| verify(token, SFU_KEY, &VerifyOptions::default()).ok())
+            .is_some_and(|claims| claims.key.as_deref() == self.0)
+    }
+}
+
 #[actix_web::test]
 async fn test_noop_endpoint() {
     let app = test::init_service(App::new().route("/noop", web::get().to(noop))).await;
@@ -29,8 +65,9 @@ async fn test_channel_missing_auth() {
     let state = create_app_state(
         vec![SfuConfig {
             address: mock_server.uri(),
-            region: Some("eu-west".to_string()),
+            regions: vec!["eu-west".to_string()],
             key: SFU_KEY.to_vec(),
+            ..Default::default()
         }],
         GATEWAY_KEY,
         false,
@@ -48,7 +85,7 @@ async fn test_channel_missing_auth() {
 
     assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
     let body: serde_json::Value = test::read_body_json(resp).await;
-    assert_eq!(body, json!({ "error": "missing authorization" }));
+    assert_eq!(body["error"]["code"], "MISSING_AUTH");
 }
 
 #[actix_web::test]
@@ -57,8 +94,9 @@ async fn test_channel_invalid_token() {
     let state = create_app_state(
         vec![SfuConfig {
             address: mock_server.uri(),
-            region: Some("eu-west".to_string()),
+            regions: vec!["eu-west".to_string()],
             key: SFU_KEY.to_vec(),
+            ..Default::default()
         }],
         GATEWAY_KEY,
         false,
@@ -79,7 +117,7 @@ async fn test_channel_invalid_token() {
 
     assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
     let body: serde_json::Value = test::read_body_json(resp).await;
-    assert_eq!(body, json!({ "error": "invalid token" }));
+    assert_eq!(body["error"]["code"], "INVALID_TOKEN");
 }
 
 #[actix_web::test]
@@ -88,8 +126,9 @@ async fn test_channel_wrong_key() {
     let state = create_app_state(
         vec![SfuConfig {
             address: mock_server.uri(),
-            region: Some("eu-west".to_string()),
+            regions: vec!["eu-west".to_string()],
             key: SFU_KEY.to_vec(),
+            ..Default::default()
         }],
         GATEWAY_KEY,
         false,
@@ -114,14 +153,73 @@ async fn test_channel_wrong_key() {
     assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
 }
 
+#[actix_web::test]
+async fn test_channel_expired_token() {
+    let mock_server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/v1/channel"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "uuid": "test-uuid",
+            "url": "wss://test"
+        })))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+    let state = |verify_options| {
+        Arc::new(AppState {
+            verify_options,
+            ..AppState::new(
+                Balancer::new(vec![SfuConfig {
+                    address: mock_server.uri(),
+                    regions: vec!["eu-west".to_string()],
+                    key: SFU_KEY.to_vec(),
+                    ..Default::default()
+                }]),
+                reqwest::Client::new(),
+                GATEWAY_KEY.to_vec(),
+            )
+        })
+    };
+    let mut claims = make_test_claims();
+    claims.exp = claims.exp.map(|exp| exp - 3600 - 120);
+    let token = sign_claims(&claims, GATEWAY_KEY);
+
+    for (verify_options, expected) in [
+        (VerifyOptions::default(), StatusCode::UNAUTHORIZED),
+        (
+            VerifyOptions {
+                leeway_secs: 300,
+                ..VerifyOptions::default()
+            },
+            StatusCode::OK,
+        ),
+    ] {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(state(verify_options)))
+                .route("/v1/channel", web::get().to(channel)),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/v1/channel")
+            .insert_header(("Authorization", format!("Bearer {token}")))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), expected, "{verify_options:?}");
+    }
+}
+
 #[actix_web::test]
 async fn test_channel_valid_flow() {
     let mock_server = MockServer::start().await;
     let state = create_app_state(
         vec![SfuConfig {
             address: mock_server.uri(),
-            region: Some("eu-west".to_string()),
+            regions: vec!["eu-west".to_string()],
             key: SFU_KEY.to_vec(),
+            ..Default::default()
         }],
         GATEWAY_KEY,
         false,
@@ -157,3 +255,485 @@ async fn test_channel_valid_flow() {
     assert_eq!(body["uuid"], "test-uuid-123");
     assert_eq!(body["url"], "wss://sfu.example.com/channel/test-uuid-123");
 }
+
+#[actix_web::test]
+async fn test_forwarded_token_verifies_with_sfu_key() {
+    let mock_server = MockServer::start().await;
+    let state = create_app_state(
+        vec![SfuConfig {
+            address: mock_server.uri(),
+            regions: vec!["eu-west".to_string()],
+            key: SFU_KEY.to_vec(),
+            ..Default::default()
+        }],
+        GATEWAY_KEY,
+        false,
+    );
+
+    Mock::given(method("GET"))
+        .and(path("/v1/channel"))
+        .and(VerifiedWith {
+            key: SFU_KEY,
+            iss: "test-channel-123",
+        })
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "uuid": "test-uuid-123",
+            "url": "wss://sfu.example.com/channel/test-uuid-123"
+        })))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let token = sign_claims(&make_test_claims(), GATEWAY_KEY);
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/v1/channel", web::get().to(channel)),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/v1/channel")
+        .insert_header(("Authorization", format!("Bearer {token}")))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn test_recording_key_forwarded_only_to_opted_in_sfus() {
+    for (accepts_recording_key, forwarded_key) in [(true, Some("encryption-key")), (false, None)] {
+        let mock_server = MockServer::start().await;
+        let state = create_app_state(
+            vec![SfuConfig {
+                address: mock_server.uri(),
+                key: SFU_KEY.to_vec(),
+                accepts_recording_key,
+                ..Default::default()
+            }],
+            GATEWAY_KEY,
+            false,
+        );
+        Mock::given(method("GET"))
+            .and(path("/v1/channel"))
+            .and(ForwardedRecordingKey(forwarded_key))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "uuid": "test-uuid-123",
+                "url": "wss://sfu.example.com/channel/test-uuid-123"
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(state))
+                .route("/v1/channel", web::get().to(channel)),
+        )
+        .await;
+        let token = sign_claims(&make_test_claims(), GATEWAY_KEY);
+        let req = test::TestRequest::get()
+            .uri("/v1/channel")
+            .insert_header(("Authorization", format!("Bearer {token}")))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), StatusCode::OK, "{accepts_recording_key}");
+    }
+}
+
+#[actix_web::test]
+async fn test_forwarded_token_with_wrong_sfu_key_rejected() {
+    let mock_server = MockServer::start().await;
+    // the gateway believes the SFU uses another key than the one the SFU checks against
+    let state = create_app_state(
+        vec![SfuConfig {
+            address: mock_server.uri(),
+            regions: vec!["eu-west".to_string()],
+            key: b"stale-sfu-key-padded-to-32-bytes".to_vec(),
+            ..Default::default()
+        }],
+        GATEWAY_KEY,
+        false,
+    );
+
+    Mock::given(method("GET"))
+        .and(path("/v1/channel"))
+        .and(VerifiedWith {
+            key: SFU_KEY,
+            iss: "test-channel-123",
+        })
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "uuid": "test-uuid-123",
+            "url": "wss://sfu.example.com/channel/test-uuid-123"
+        })))
+        .expect(0)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/v1/channel"))
+        .respond_with(ResponseTemplate::new(401))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let token = sign_claims(&make_test_claims(), GATEWAY_KEY);
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/v1/channel", web::get().to(channel)),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/v1/channel")
+        .insert_header(("Authorization", format!("Bearer {token}")))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn test_reloaded_keys_take_effect() {
+    const ROTATED_GATEWAY_KEY: &[u8] = b"rotated-gateway-key-32-bytes-123";
+    let mock_server = MockServer::start().await;
+    // starts with a stale SFU key, the reload brings the one the SFU checks against
+    let sfu = |key: &[u8]| SfuConfig {
+        address: mock_server.uri(),
+        regions: vec!["eu-west".to_string()],
+        key: key.to_vec(),
+        ..Default::default()
+    };
+    let state = create_app_state(
+        vec![sfu(b"stale-sfu-key-padded-to-32-bytes")],
+        GATEWAY_KEY,
+        false,
+    );
+    state.reload_keys(vec![ROTATED_GATEWAY_KEY.to_vec()], &[sfu(SFU_KEY)]);
+
+    Mock::given(method("GET"))
+        .and(path("/v1/channel"))
+        .and(VerifiedWith {
+            key: SFU_KEY,
+            iss: "test-channel-123",
+        })
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "uuid": "test-uuid-123",
+            "url": "wss://sfu.example.com/channel/test-uuid-123"
+        })))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/v1/channel", web::get().to(channel)),
+    )
+    .await;
+
+    let old_token = sign_claims(&make_test_claims(), GATEWAY_KEY);
+    let req = test::TestRequest::get()
+        .uri("/v1/channel")
+        .insert_header(("Authorization", format!("Bearer {old_token}")))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let token = sign_claims(&make_test_claims(), ROTATED_GATEWAY_KEY);
+    let req = test::TestRequest::get()
+        .uri("/v1/channel")
+        .insert_header(("Authorization", format!("Bearer {token}")))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn test_previous_gateway_key_accepted_during_rotation() {
+    const PREVIOUS_GATEWAY_KEY: &[u8] = b"previous-gateway-key-32-bytes-12";
+    let mock_server = MockServer::start().await;
+    let state = create_app_state(
+        vec![SfuConfig {
+            address: mock_server.uri(),
+            regions: vec!["eu-west".to_string()],
+            key: SFU_KEY.to_vec(),
+            ..Default::default()
+        }],
+        GATEWAY_KEY,
+        false,
+    );
+    state.reload_keys(
+        vec![GATEWAY_KEY.to_vec(), PREVIOUS_GATEWAY_KEY.to_vec()],
+        &[],
+    );
+
+    // re-signed with the SFU key whatever gateway key the token was signed with
+    Mock::given(method("GET"))
+        .and(path("/v1/channel"))
+        .and(VerifiedWith {
+            key: SFU_KEY,
+            iss: "test-channel-123",
+        })
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "uuid": "test-uuid-123",
+            "url": "wss://sfu.example.com/channel/test-uuid-123"
+        })))
+        .expect(2)
+        .mount(&mock_server)
+        .await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/v1/channel", web::get().to(channel)),
+    )
+    .await;
+
+    for (key, expected) in [
+        (GATEWAY_KEY, StatusCode::OK),
+        (PREVIOUS_GATEWAY_KEY, StatusCode::OK),
+        (
+            b"unknown-gateway-key-32-bytes-123",
+            StatusCode::UNAUTHORIZED,
+        ),
+    ] {
+        let token = sign_claims(&make_test_claims(), key);
+        let req = test::TestRequest::get()
+            .uri("/v1/channel")
+            .insert_header(("Authorization", format!("Bearer {token}")))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), expected);
+    }
+}
+
+fn token_param_state(sfu_address: String) -> Arc<AppState> {
+    Arc::new(AppState {
+        token_query_param: Some("token".to_string()),
+        ..AppState::new(
+            Balancer::new(vec![SfuConfig {
+                address: sfu_address,
+                regions: vec!["eu-west".to_string()],
+                key: SFU_KEY.to_vec(),
+                ..Default::default()
+            }]),
+            reqwest::Client::new(),
+            GATEWAY_KEY.to_vec(),
+        )
+    })
+}
+
+#[actix_web::test]
+async fn test_token_query_param_fallback() {
+    let mock_server = MockServer::start().await;
+    let state = token_param_state(mock_server.uri());
+
+    // the token is re-signed in the header, never passed along in the query
+    Mock::given(method("GET"))
+        .and(path("/v1/channel"))
+        .and(query_param("webRTC", "true"))
+        .and(query_param_is_missing("token"))
+        .and(VerifiedWith {
+            key: SFU_KEY,
+            iss: "test-channel-123",
+        })
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "uuid": "test-uuid-123",
+            "url": "wss://sfu.example.com/channel/test-uuid-123"
+        })))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let token = sign_claims(&make_test_claims(), GATEWAY_KEY);
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/v1/channel", web::get().to(channel)),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/v1/channel?token={token}&webRTC=true"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn test_header_preferred_over_token_query_param() {
+    let mock_server = MockServer::start().await;
+    let state = token_param_state(mock_server.uri());
+
+    Mock::given(method("GET"))
+        .and(path("/v1/channel"))
+        .and(query_param_is_missing("token"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "uuid": "test-uuid-123",
+            "url": "wss://sfu.example.com/channel/test-uuid-123"
+        })))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let token = sign_claims(&make_test_claims(), GATEWAY_KEY);
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/v1/channel", web::get().to(channel)),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/v1/channel?token=not-a-jwt")
+        .insert_header(("Authorization", format!("Bearer {token}")))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn test_token_query_param_ignored_when_not_configured() {
+    let mock_server = MockServer::start().await;
+    let state = create_app_state(
+        vec![SfuConfig {
+            address: mock_server.uri(),
+            regions: vec!["eu-west".to_string()],
+            key: SFU_KEY.to_vec(),
+            ..Default::default()
+        }],
+        GATEWAY_KEY,
+        false,
+    );
+
+    let token = sign_claims(&make_test_claims(), GATEWAY_KEY);
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/v1/channel", web::get().to(channel)),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/v1/channel?token={token}"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+}
+
+fn api_key_state(sfu_address: String, api_key: Option<ApiKeyConfig>) -> Arc<AppState> {
+    Arc::new(AppState {
+        api_key,
+        ..AppState::new(
+            Balancer::new(vec![SfuConfig {
+                address: sfu_address,
+                regions: vec!["eu-west".to_string()],
+                key: SFU_KEY.to_vec(),
+                ..Default::default()
+            }]),
+            reqwest::Client::new(),
+            GATEWAY_KEY.to_vec(),
+        )
+    })
+}
+
+fn api_key_config() -> ApiKeyConfig {
+    ApiKeyConfig {
+        key: "static-api-key".to_string(),
+        iss: "api-key-integration".to_string(),
+        region: None,
+    }
+}
+
+#[actix_web::test]
+async fn test_channel_valid_api_key() {
+    let mock_server = MockServer::start().await;
+    let state = api_key_state(mock_server.uri(), Some(api_key_config()));
+
+    Mock::given(method("GET"))
+        .and(path("/v1/channel"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "uuid": "test-uuid-123",
+            "url": "wss://sfu.example.com/channel/test-uuid-123"
+        })))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/v1/channel", web::get().to(channel)),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/v1/channel")
+        .insert_header(("X-Api-Key", "static-api-key"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["uuid"], "test-uuid-123");
+}
+
+#[actix_web::test]
+async fn test_channel_wrong_api_key() {
+    let mock_server = MockServer::start().await;
+    let state = api_key_state(mock_server.uri(), Some(api_key_config()));
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/v1/channel", web::get().to(channel)),
+    )
+    .await;
+
+    // a valid JWT does not rescue a wrong API key
+    let token = sign_claims(&make_test_claims(), GATEWAY_KEY);
+    let req = test::TestRequest::get()
+        .uri("/v1/channel")
+        .insert_header(("X-Api-Key", "wrong-api-key"))
+        .insert_header(("Authorization", format!("Bearer {token}")))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"]["code"], "INVALID_API_KEY");
+}
+
+#[actix_web::test]
+async fn test_channel_api_key_ignored_when_disabled() {
+    let mock_server = MockServer::start().await;
+    let state = api_key_state(mock_server.uri(), None);
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/v1/channel", web::get().to(channel)),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/v1/channel")
+        .insert_header(("X-Api-Key", "static-api-key"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"]["code"], "MISSING_AUTH");
+}
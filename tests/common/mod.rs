@@ -18,6 +18,7 @@ pub fn make_test_claims() -> Claims {
                 + 3600,
         ),
         iat: None,
+        nbf: None,
     }
 }
 
@@ -27,10 +28,12 @@ pub fn create_app_state(
     trust_proxy: bool,
 ) -> Arc<AppState> {
     Arc::new(AppState {
-        balancer: Balancer::new(sfus),
-        http_client: reqwest::Client::new(),
-        gateway_key: gateway_key.to_vec(),
         trust_proxy,
+        ..AppState::new(
+            Balancer::new(sfus),
+            reqwest::Client::new(),
+            gateway_key.to_vec(),
+        )
     })
 }
 
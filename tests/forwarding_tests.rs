@@ -1,15 +1,17 @@
 mod common;
 
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use actix_web::{App, http::StatusCode, test, web};
+use jsonwebtoken::{Algorithm, decode_header};
 use serde_json::json;
-use wiremock::matchers::{header_exists, method, path};
+use wiremock::matchers::{body_json, header, header_exists, method, path, query_param};
 use wiremock::{Mock, MockServer, ResponseTemplate};
 
 use common::{GATEWAY_KEY, create_app_state, make_test_claims, sign_claims};
-use sfu_gateway::config::SfuConfig;
-use sfu_gateway::http::{AppState, channel};
+use sfu_gateway::config::{NodeData, SfuConfig};
+use sfu_gateway::http::{AppState, Claims, RateLimiter, VerifyOptions, channel, proxy, verify};
 use sfu_gateway::routing::Balancer;
 
 const SFU_KEY: &[u8] = b"sfu-key-padded-to-32-bytes-here!";
@@ -20,8 +22,9 @@ async fn test_forwarded_for_no_proxy() {
     let state = create_app_state(
         vec![SfuConfig {
             address: mock_server.uri(),
-            region: Some("eu-west".to_string()),
+            regions: vec!["eu-west".to_string()],
             key: SFU_KEY.to_vec(),
+            ..Default::default()
         }],
         GATEWAY_KEY,
         false,
@@ -63,8 +66,9 @@ async fn test_forwarded_for_with_proxy() {
     let state = create_app_state(
         vec![SfuConfig {
             address: mock_server.uri(),
-            region: Some("eu-west".to_string()),
+            regions: vec!["eu-west".to_string()],
             key: SFU_KEY.to_vec(),
+            ..Default::default()
         }],
         GATEWAY_KEY,
         true,
@@ -106,8 +110,9 @@ async fn test_query_params_forwarded_except_region() {
     let state = create_app_state(
         vec![SfuConfig {
             address: mock_server.uri(),
-            region: Some("eu-west".to_string()),
+            regions: vec!["eu-west".to_string()],
             key: SFU_KEY.to_vec(),
+            ..Default::default()
         }],
         GATEWAY_KEY,
         false,
@@ -142,14 +147,158 @@ async fn test_query_params_forwarded_except_region() {
 }
 
 #[actix_web::test]
-async fn test_sfu_unavailable() {
+async fn test_static_headers_forwarded() {
+    let mock_server = MockServer::start().await;
+    let state = Arc::new(AppState {
+        static_headers: vec![
+            ("X-Internal-Token".to_string(), "global-token".to_string()),
+            ("X-Env".to_string(), "test".to_string()),
+        ],
+        ..AppState::new(
+            Balancer::new(vec![SfuConfig {
+                address: mock_server.uri(),
+                regions: vec!["eu-west".to_string()],
+                key: SFU_KEY.to_vec(),
+                headers: vec![("X-Internal-Token".to_string(), "sfu-token".to_string())],
+                ..Default::default()
+            }]),
+            reqwest::Client::new(),
+            GATEWAY_KEY.to_vec(),
+        )
+    });
+
+    Mock::given(method("GET"))
+        .and(path("/v1/channel"))
+        .and(header("X-Internal-Token", "sfu-token"))
+        .and(header("X-Env", "test"))
+        .and(header_exists("Authorization"))
+        .and(header_exists("X-Forwarded-For"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "uuid": "test-uuid",
+            "url": "wss://test"
+        })))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let token = sign_claims(&make_test_claims(), GATEWAY_KEY);
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/v1/channel", web::get().to(channel)),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/v1/channel")
+        .insert_header(("Authorization", format!("Bearer {token}")))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn test_malformed_query_rejected() {
+    let mock_server = MockServer::start().await;
+    let state = create_app_state(
+        vec![SfuConfig {
+            address: mock_server.uri(),
+            regions: vec!["eu-west".to_string()],
+            key: SFU_KEY.to_vec(),
+            ..Default::default()
+        }],
+        GATEWAY_KEY,
+        false,
+    );
+
+    Mock::given(method("GET"))
+        .and(path("/v1/channel"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&mock_server)
+        .await;
+
+    let token = sign_claims(&make_test_claims(), GATEWAY_KEY);
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/v1/channel", web::get().to(channel)),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/v1/channel?webRTC=true&recordingAddress=%zz")
+        .insert_header(("Authorization", format!("Bearer {token}")))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"]["code"], "MALFORMED_QUERY");
+}
+
+#[actix_web::test]
+async fn test_request_deadline_exceeded() {
+    let mock_server = MockServer::start().await;
     let state = Arc::new(AppState {
-        balancer: Balancer::new(vec![]),
-        http_client: reqwest::Client::new(),
-        gateway_key: GATEWAY_KEY.to_vec(),
-        trust_proxy: false,
+        request_deadline: Some(Duration::from_millis(200)),
+        ..AppState::new(
+            Balancer::new(vec![SfuConfig {
+                address: mock_server.uri(),
+                regions: vec!["eu-west".to_string()],
+                key: SFU_KEY.to_vec(),
+                ..Default::default()
+            }]),
+            reqwest::Client::new(),
+            GATEWAY_KEY.to_vec(),
+        )
     });
 
+    // hit exactly once: the deadline aborts the forward, it is never retried
+    Mock::given(method("GET"))
+        .and(path("/v1/channel"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(json!({ "uuid": "test-uuid", "url": "wss://test" }))
+                .set_delay(Duration::from_secs(5)),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let token = sign_claims(&make_test_claims(), GATEWAY_KEY);
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/v1/channel", web::get().to(channel)),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/v1/channel")
+        .insert_header(("Authorization", format!("Bearer {token}")))
+        .to_request();
+    let started = Instant::now();
+    let resp = test::call_service(&app, req).await;
+
+    assert!(started.elapsed() < Duration::from_secs(2));
+    assert_eq!(resp.status(), StatusCode::GATEWAY_TIMEOUT);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"]["code"], "DEADLINE_EXCEEDED");
+}
+
+#[actix_web::test]
+async fn test_sfu_unavailable() {
+    let state = Arc::new(AppState::new(
+        Balancer::new(vec![]),
+        reqwest::Client::new(),
+        GATEWAY_KEY.to_vec(),
+    ));
+
     let token = sign_claims(&make_test_claims(), GATEWAY_KEY);
 
     let app = test::init_service(
@@ -166,6 +315,1257 @@ async fn test_sfu_unavailable() {
     let resp = test::call_service(&app, req).await;
 
     assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert!(resp.headers().get("Retry-After").is_none());
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"]["code"], "NO_SFU");
+}
+
+/// Matches requests whose bearer token verifies with `key`.
+fn signed_with(key: &'static [u8]) -> impl Fn(&wiremock::Request) -> bool {
+    move |request| {
+        request
+            .headers
+            .get("Authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|token| verify(token, key, &VerifyOptions::default()).is_ok())
+    }
+}
+
+/// Matches requests with exactly this X-Forwarded-For value, `header` would split it on commas.
+fn forwarded_for(expected: &'static str) -> impl Fn(&wiremock::Request) -> bool {
+    move |request| {
+        request
+            .headers
+            .get("X-Forwarded-For")
+            .is_some_and(|value| value == expected)
+    }
+}
+
+#[actix_web::test]
+async fn test_retry_on_another_sfu() {
+    const OTHER_SFU_KEY: &[u8] = b"other-sfu-key-padded-to-32-bytes";
+    let failing = MockServer::start().await;
+    let healthy = MockServer::start().await;
+    let state = create_app_state(
+        vec![
+            SfuConfig {
+                address: failing.uri(),
+                regions: vec!["eu-west".to_string()],
+                key: SFU_KEY.to_vec(),
+                ..Default::default()
+            },
+            // the retry falls through to the next closest region
+            SfuConfig {
+                address: healthy.uri(),
+                regions: vec!["eu-central".to_string()],
+                key: OTHER_SFU_KEY.to_vec(),
+                ..Default::default()
+            },
+        ],
+        GATEWAY_KEY,
+        true,
+    );
+
+    // each SFU gets a token signed with its own key and the same forwarded chain
+    Mock::given(method("GET"))
+        .and(path("/v1/channel"))
+        .and(signed_with(SFU_KEY))
+        .and(forwarded_for("10.0.0.1, 192.168.1.100"))
+        .respond_with(ResponseTemplate::new(503))
+        .expect(1)
+        .mount(&failing)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/v1/channel"))
+        .and(signed_with(OTHER_SFU_KEY))
+        .and(forwarded_for("10.0.0.1, 192.168.1.100"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "uuid": "test-uuid",
+            "url": "wss://test"
+        })))
+        .expect(1)
+        .mount(&healthy)
+        .await;
+
+    let token = sign_claims(&make_test_claims(), GATEWAY_KEY);
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/v1/channel", web::get().to(channel)),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/v1/channel?region=eu-west")
+        .peer_addr("192.168.1.100:54321".parse().unwrap())
+        .insert_header(("Authorization", format!("Bearer {token}")))
+        .insert_header(("X-Forwarded-For", "10.0.0.1"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn test_retries_bounded_by_max_attempts() {
+    let mut servers = Vec::new();
+    for _ in 0..3 {
+        servers.push(MockServer::start().await);
+    }
+    let state = Arc::new(AppState {
+        max_attempts: 2,
+        ..AppState::new(
+            Balancer::new(
+                servers
+                    .iter()
+                    .zip(["eu-west", "eu-central", "us-east"])
+                    .map(|(server, region)| SfuConfig {
+                        address: server.uri(),
+                        regions: vec![region.to_string()],
+                        key: SFU_KEY.to_vec(),
+                        ..Default::default()
+                    })
+                    .collect(),
+            ),
+            reqwest::Client::new(),
+            GATEWAY_KEY.to_vec(),
+        )
+    });
+
+    // attempts follow the proximity order from eu-west, the farthest SFU is never tried
+    for (server, hits) in servers.iter().zip([1, 1, 0]) {
+        Mock::given(method("GET"))
+            .and(path("/v1/channel"))
+            .respond_with(ResponseTemplate::new(503))
+            .expect(hits)
+            .mount(server)
+            .await;
+    }
+
+    let token = sign_claims(&make_test_claims(), GATEWAY_KEY);
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/v1/channel", web::get().to(channel)),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/v1/channel?region=eu-west")
+        .insert_header(("Authorization", format!("Bearer {token}")))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    // the last SFU error is passed through
+    assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+}
+
+#[actix_web::test]
+async fn test_sfu_timeout_returns_gateway_timeout() {
+    let mock_server = MockServer::start().await;
+    let state = Arc::new(AppState::new(
+        Balancer::new(vec![SfuConfig {
+            address: mock_server.uri(),
+            regions: vec!["eu-west".to_string()],
+            key: SFU_KEY.to_vec(),
+            ..Default::default()
+        }]),
+        reqwest::Client::builder()
+            .timeout(Duration::from_millis(100))
+            .build()
+            .unwrap(),
+        GATEWAY_KEY.to_vec(),
+    ));
+
+    Mock::given(method("GET"))
+        .and(path("/v1/channel"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(json!({ "uuid": "test-uuid", "url": "wss://test" }))
+                .set_delay(Duration::from_secs(2)),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let token = sign_claims(&make_test_claims(), GATEWAY_KEY);
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/v1/channel", web::get().to(channel)),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/v1/channel")
+        .insert_header(("Authorization", format!("Bearer {token}")))
+        .to_request();
+    let started = Instant::now();
+    let resp = test::call_service(&app, req).await;
+
+    assert!(started.elapsed() < Duration::from_secs(1));
+    assert_eq!(resp.status(), StatusCode::GATEWAY_TIMEOUT);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"]["code"], "SFU_TIMEOUT");
+}
+
+#[actix_web::test]
+async fn test_timed_out_channel_not_retried() {
+    let slow = MockServer::start().await;
+    let other = MockServer::start().await;
+    let state = Arc::new(AppState::new(
+        Balancer::new(vec![
+            SfuConfig {
+                address: slow.uri(),
+                regions: vec!["eu-west".to_string()],
+                key: SFU_KEY.to_vec(),
+                ..Default::default()
+            },
+            SfuConfig {
+                address: other.uri(),
+                regions: vec!["eu-central".to_string()],
+                key: SFU_KEY.to_vec(),
+                ..Default::default()
+            },
+        ]),
+        reqwest::Client::builder()
+            .timeout(Duration::from_millis(100))
+            .build()
+            .unwrap(),
+        GATEWAY_KEY.to_vec(),
+    ));
+
+    // the slow SFU may still create the channel, another one must not get a duplicate
+    Mock::given(method("POST"))
+        .and(path("/v1/channel"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(json!({ "uuid": "test-uuid", "url": "wss://test" }))
+                .set_delay(Duration::from_secs(2)),
+        )
+        .expect(1)
+        .mount(&slow)
+        .await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&other)
+        .await;
+
+    let token = sign_claims(&make_test_claims(), GATEWAY_KEY);
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/v1/channel", web::post().to(channel)),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/v1/channel?region=eu-west")
+        .insert_header(("Authorization", format!("Bearer {token}")))
+        .set_json(json!({ "webRTC": true }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), StatusCode::GATEWAY_TIMEOUT);
+}
+
+#[actix_web::test]
+async fn test_sfu_connect_timeout_returns_bad_gateway() {
+    // unroutable, the connection never completes
+    let state = Arc::new(AppState::new(
+        Balancer::new(vec![SfuConfig {
+            address: "http://10.255.255.1:3000".to_string(),
+            regions: vec!["eu-west".to_string()],
+            key: SFU_KEY.to_vec(),
+            ..Default::default()
+        }]),
+        reqwest::Client::builder()
+            .timeout(Duration::from_secs(5))
+            .connect_timeout(Duration::from_millis(100))
+            .build()
+            .unwrap(),
+        GATEWAY_KEY.to_vec(),
+    ));
+
+    let token = sign_claims(&make_test_claims(), GATEWAY_KEY);
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/v1/channel", web::get().to(channel)),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/v1/channel")
+        .insert_header(("Authorization", format!("Bearer {token}")))
+        .to_request();
+    let started = Instant::now();
+    let resp = test::call_service(&app, req).await;
+
+    // the connect timeout gave up, well before the request timeout
+    assert!(started.elapsed() < Duration::from_secs(1));
+    assert_eq!(resp.status(), StatusCode::BAD_GATEWAY);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"]["code"], "SFU_UNREACHABLE");
+}
+
+#[actix_web::test]
+async fn test_client_errors_not_retried() {
+    let first = MockServer::start().await;
+    let second = MockServer::start().await;
+    let state = create_app_state(
+        [(&first, "eu-west"), (&second, "eu-central")]
+            .iter()
+            .map(|(server, region)| SfuConfig {
+                address: server.uri(),
+                regions: vec![region.to_string()],
+                key: SFU_KEY.to_vec(),
+                ..Default::default()
+            })
+            .collect(),
+        GATEWAY_KEY,
+        false,
+    );
+
+    Mock::given(method("GET"))
+        .and(path("/v1/channel"))
+        .respond_with(ResponseTemplate::new(409))
+        .expect(1)
+        .mount(&first)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/v1/channel"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&second)
+        .await;
+
+    let token = sign_claims(&make_test_claims(), GATEWAY_KEY);
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/v1/channel", web::get().to(channel)),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/v1/channel?region=eu-west")
+        .insert_header(("Authorization", format!("Bearer {token}")))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+}
+
+#[actix_web::test]
+async fn test_sfu_error_body_passed_through() {
+    let mock_server = MockServer::start().await;
+    let state = create_app_state(
+        vec![SfuConfig {
+            address: mock_server.uri(),
+            regions: vec!["eu-west".to_string()],
+            key: SFU_KEY.to_vec(),
+            ..Default::default()
+        }],
+        GATEWAY_KEY,
+        false,
+    );
+
+    Mock::given(method("GET"))
+        .and(path("/v1/channel"))
+        .respond_with(ResponseTemplate::new(403).set_body_json(json!({
+            "error": "quota exceeded"
+        })))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let token = sign_claims(&make_test_claims(), GATEWAY_KEY);
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/v1/channel", web::get().to(channel)),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/v1/channel")
+        .insert_header(("Authorization", format!("Bearer {token}")))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    assert_eq!(
+        resp.headers().get("Content-Type").unwrap(),
+        "application/json"
+    );
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body, json!({ "error": "quota exceeded" }));
+}
+
+#[actix_web::test]
+async fn test_oversized_sfu_response_rejected() {
+    let mock_server = MockServer::start().await;
+    let padding = "x".repeat(2048);
+    Mock::given(method("GET"))
+        .and(path("/v1/channel"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "uuid": "test-uuid",
+            "url": "wss://test",
+            "padding": padding
+        })))
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/v1/stats"))
+        .respond_with(ResponseTemplate::new(500).set_body_string(padding.clone()))
+        .mount(&mock_server)
+        .await;
+    let state = Arc::new(AppState {
+        max_body_bytes: 1024,
+        ..AppState::new(
+            Balancer::new(vec![SfuConfig {
+                address: mock_server.uri(),
+                key: SFU_KEY.to_vec(),
+                ..Default::default()
+            }]),
+            reqwest::Client::new(),
+            GATEWAY_KEY.to_vec(),
+        )
+    });
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/v1/channel", web::get().to(channel))
+            .route("/v1/{tail:.*}", web::route().to(proxy)),
+    )
+    .await;
+
+    let token = sign_claims(&make_test_claims(), GATEWAY_KEY);
+    // a success to parse, and an error to pass through
+    for uri in ["/v1/channel", "/v1/stats"] {
+        let req = test::TestRequest::get()
+            .uri(uri)
+            .insert_header(("Authorization", format!("Bearer {token}")))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_GATEWAY, "{uri}");
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["error"]["code"], "SFU_RESPONSE_TOO_LARGE", "{uri}");
+    }
+}
+
+#[actix_web::test]
+async fn test_sfu_success_body_passed_verbatim() {
+    let mock_server = MockServer::start().await;
+    let state = create_app_state(
+        vec![SfuConfig {
+            address: mock_server.uri(),
+            regions: vec!["eu-west".to_string()],
+            key: SFU_KEY.to_vec(),
+            ..Default::default()
+        }],
+        GATEWAY_KEY,
+        false,
+    );
+
+    // fields the gateway doesn't know, in the SFU's own order and formatting
+    let sfu_body = r#"{"url": "wss://sfu.example.com", "uuid": "abc", "token": "t0k", "expires_at": 1767225600}"#;
+    Mock::given(method("GET"))
+        .and(path("/v1/channel"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(sfu_body, "application/json"))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let token = sign_claims(&make_test_claims(), GATEWAY_KEY);
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/v1/channel", web::get().to(channel)),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/v1/channel")
+        .insert_header(("Authorization", format!("Bearer {token}")))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        resp.headers().get("Content-Type").unwrap(),
+        "application/json"
+    );
+    let body = test::read_body(resp).await;
+    assert_eq!(body, sfu_body.as_bytes());
+}
+
+#[actix_web::test]
+async fn test_request_id_forwarded_and_echoed() {
+    let mock_server = MockServer::start().await;
+    let state = create_app_state(
+        vec![SfuConfig {
+            address: mock_server.uri(),
+            key: SFU_KEY.to_vec(),
+            ..Default::default()
+        }],
+        GATEWAY_KEY,
+        false,
+    );
+
+    Mock::given(method("GET"))
+        .and(path("/v1/channel"))
+        .and(header("X-Request-Id", "odoo-req-42"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "uuid": "test-uuid",
+            "url": "wss://test"
+        })))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let token = sign_claims(&make_test_claims(), GATEWAY_KEY);
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/v1/channel", web::get().to(channel)),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/v1/channel")
+        .insert_header(("Authorization", format!("Bearer {token}")))
+        .insert_header(("X-Request-Id", "odoo-req-42"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.headers().get("X-Request-Id").unwrap(), "odoo-req-42");
+
+    // generated when absent, and sent back on errors too
+    let req = test::TestRequest::get().uri("/v1/channel").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let generated = resp
+        .headers()
+        .get("X-Request-Id")
+        .unwrap()
+        .to_str()
+        .unwrap();
+    assert_eq!(generated.len(), 36);
+}
+
+fn proxy_app_state(sfu_address: String) -> Arc<AppState> {
+    create_app_state(
+        vec![SfuConfig {
+            address: sfu_address,
+            key: SFU_KEY.to_vec(),
+            ..Default::default()
+        }],
+        GATEWAY_KEY,
+        false,
+    )
+}
+
+#[actix_web::test]
+async fn test_proxy_forwards_sub_path_with_resigned_token() {
+    let mock_server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/v1/stats"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "channels": 3 })))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(proxy_app_state(mock_server.uri())))
+            .route("/v1/channel", web::get().to(channel))
+            .route("/v1/{tail:.*}", web::route().to(proxy)),
+    )
+    .await;
+
+    let token = sign_claims(&make_test_claims(), GATEWAY_KEY);
+    let req = test::TestRequest::get()
+        .uri("/v1/stats")
+        .insert_header(("Authorization", format!("Bearer {token}")))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(resp.headers().contains_key("X-Request-Id"));
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body, json!({ "channels": 3 }));
+
+    let received = mock_server.received_requests().await.unwrap();
+    let authorization = received[0].headers.get("Authorization").unwrap();
+    let sfu_token = authorization
+        .to_str()
+        .unwrap()
+        .strip_prefix("Bearer ")
+        .unwrap();
+    let claims = verify(sfu_token, SFU_KEY, &VerifyOptions::default()).unwrap();
+    assert_eq!(claims.iss, "test-channel-123");
+}
+
+#[actix_web::test]
+async fn test_sfu_token_signed_with_configured_header() {
+    let mock_server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/v1/channel"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "uuid": "test-uuid",
+            "url": "wss://sfu.example.com"
+        })))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+    let state = create_app_state(
+        vec![SfuConfig {
+            address: mock_server.uri(),
+            key: SFU_KEY.to_vec(),
+            algorithm: Algorithm::HS512,
+            kid: Some("sfu-2024".to_string()),
+            ..Default::default()
+        }],
+        GATEWAY_KEY,
+        false,
+    );
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/v1/channel", web::get().to(channel)),
+    )
+    .await;
+
+    let token = sign_claims(&make_test_claims(), GATEWAY_KEY);
+    let req = test::TestRequest::get()
+        .uri("/v1/channel")
+        .insert_header(("Authorization", format!("Bearer {token}")))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let received = mock_server.received_requests().await.unwrap();
+    let authorization = received[0].headers.get("Authorization").unwrap();
+    let sfu_token = authorization
+        .to_str()
+        .unwrap()
+        .strip_prefix("Bearer ")
+        .unwrap();
+    let header = decode_header(sfu_token).unwrap();
+    assert_eq!(header.alg, Algorithm::HS512);
+    assert_eq!(header.kid.as_deref(), Some("sfu-2024"));
+}
+
+#[actix_web::test]
+async fn test_sfu_token_signed_with_current_key() {
+    use base64::Engine;
+    use base64::engine::general_purpose::STANDARD;
+
+    const OLD_SFU_KEY: &[u8] = b"old-sfu-key-padded-to-32-bytes!!";
+    let mock_server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/v1/channel"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "uuid": "test-uuid",
+            "url": "wss://sfu.example.com"
+        })))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+    let nodes = NodeData::from_json(
+        &json!({
+            "sfu": [{
+                "address": mock_server.uri(),
+                "keys": [STANDARD.encode(SFU_KEY), STANDARD.encode(OLD_SFU_KEY)],
+            }]
+        })
+        .to_string(),
+    )
+    .unwrap();
+    let state = create_app_state(nodes.sfu, GATEWAY_KEY, false);
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/v1/channel", web::get().to(channel)),
+    )
+    .await;
+
+    let token = sign_claims(&make_test_claims(), GATEWAY_KEY);
+    let req = test::TestRequest::get()
+        .uri("/v1/channel")
+        .insert_header(("Authorization", format!("Bearer {token}")))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let received = mock_server.received_requests().await.unwrap();
+    let authorization = received[0].headers.get("Authorization").unwrap();
+    let sfu_token = authorization
+        .to_str()
+        .unwrap()
+        .strip_prefix("Bearer ")
+        .unwrap();
+    assert!(verify(sfu_token, SFU_KEY, &VerifyOptions::default()).is_ok());
+    assert!(verify(sfu_token, OLD_SFU_KEY, &VerifyOptions::default()).is_err());
+}
+
+#[actix_web::test]
+async fn test_proxy_forwards_method_query_and_body() {
+    let mock_server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/rooms/42/disconnect"))
+        .and(query_param("reason", "kick"))
+        .and(header("Content-Type", "application/json"))
+        .and(body_json(json!({ "session": 7 })))
+        .respond_with(ResponseTemplate::new(202))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/v1/rooms/43/disconnect"))
+        .respond_with(ResponseTemplate::new(404).set_body_string("no such room"))
+        .mount(&mock_server)
+        .await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(proxy_app_state(mock_server.uri())))
+            .route("/v1/{tail:.*}", web::route().to(proxy)),
+    )
+    .await;
+
+    let token = sign_claims(&make_test_claims(), GATEWAY_KEY);
+    // the gateway's parameters are not forwarded
+    let req = test::TestRequest::post()
+        .uri("/v1/rooms/42/disconnect?reason=kick&region=eu-west")
+        .insert_header(("Authorization", format!("Bearer {token}")))
+        .set_json(json!({ "session": 7 }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+    let received = mock_server.received_requests().await.unwrap();
+    assert_eq!(received[0].url.query(), Some("reason=kick"));
+
+    // errors are passed through as well
+    let req = test::TestRequest::post()
+        .uri("/v1/rooms/43/disconnect")
+        .insert_header(("Authorization", format!("Bearer {token}")))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    assert_eq!(test::read_body(resp).await, "no such room");
+}
+
+#[cfg(feature = "compression")]
+#[actix_web::test]
+async fn test_gzip_responses_decompressed() {
+    use std::io::Write;
+
+    let gzip = |body: &[u8]| {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(body).unwrap();
+        encoder.finish().unwrap()
+    };
+    let mock_server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/v1/channel"))
+        .and(header_exists("Accept-Encoding"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("Content-Encoding", "gzip")
+                .set_body_raw(
+                    gzip(br#"{"uuid": "test-uuid", "url": "wss://sfu.example.com"}"#),
+                    "application/json",
+                ),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/v1/stats"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("Content-Encoding", "gzip")
+                .set_body_raw(gzip(b"channels: 3"), "text/plain"),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(proxy_app_state(mock_server.uri())))
+            .route("/v1/channel", web::get().to(channel))
+            .route("/v1/{tail:.*}", web::route().to(proxy)),
+    )
+    .await;
+
+    let token = sign_claims(&make_test_claims(), GATEWAY_KEY);
+    let req = test::TestRequest::get()
+        .uri("/v1/channel")
+        .insert_header(("Authorization", format!("Bearer {token}")))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(resp.headers().get("Content-Encoding").is_none());
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["uuid"], "test-uuid");
+
+    let req = test::TestRequest::get()
+        .uri("/v1/stats")
+        .insert_header(("Authorization", format!("Bearer {token}")))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(resp.headers().get("Content-Encoding").is_none());
+    assert_eq!(test::read_body(resp).await, "channels: 3");
+}
+
+#[actix_web::test]
+async fn test_proxy_passes_undecoded_encoding_through() {
+    let mock_server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/v1/stats"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("Content-Encoding", "br")
+                .set_body_raw(b"\x1b\x0a\x00brotli".to_vec(), "text/plain"),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let state = Arc::new(AppState {
+        forward_headers: vec!["accept-encoding".to_string()],
+        ..Arc::into_inner(proxy_app_state(mock_server.uri())).unwrap()
+    });
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/v1/{tail:.*}", web::route().to(proxy)),
+    )
+    .await;
+
+    let token = sign_claims(&make_test_claims(), GATEWAY_KEY);
+    let req = test::TestRequest::get()
+        .uri("/v1/stats")
+        .insert_header(("Authorization", format!("Bearer {token}")))
+        .insert_header(("Accept-Encoding", "br"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.headers().get("Content-Encoding").unwrap(), "br");
+    assert_eq!(test::read_body(resp).await, &b"\x1b\x0a\x00brotli"[..]);
+
+    // the encodings asked for are the gateway's, not the client's
+    let received = mock_server.received_requests().await.unwrap();
+    let accepted = received[0]
+        .headers
+        .get("Accept-Encoding")
+        .map(|value| value.to_str().unwrap().to_string());
+    assert!(!accepted.is_some_and(|value| value.contains("br")));
+}
+
+#[actix_web::test]
+async fn test_proxy_rejects_unauthenticated_and_gateway_paths() {
+    let mock_server = MockServer::start().await;
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(proxy_app_state(mock_server.uri())))
+            .route("/v1/channel", web::get().to(channel))
+            .route("/v1/{tail:.*}", web::route().to(proxy)),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/v1/stats").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let token = sign_claims(&make_test_claims(), GATEWAY_KEY);
+    let req = test::TestRequest::post()
+        .uri("/v1/channel")
+        .insert_header(("Authorization", format!("Bearer {token}")))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+    let req = test::TestRequest::get()
+        .uri("/v1/a//b")
+        .insert_header(("Authorization", format!("Bearer {token}")))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    assert!(mock_server.received_requests().await.unwrap().is_empty());
+}
+
+#[actix_web::test]
+async fn test_allowlisted_headers_forwarded() {
+    let mock_server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/v1/channel"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "uuid": "test-uuid",
+            "url": "wss://test"
+        })))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let state = Arc::new(AppState {
+        forward_headers: vec!["user-agent".to_string(), "x-odoo-*".to_string()],
+        ..Arc::into_inner(proxy_app_state(mock_server.uri())).unwrap()
+    });
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/v1/channel", web::get().to(channel)),
+    )
+    .await;
+
+    let token = sign_claims(&make_test_claims(), GATEWAY_KEY);
+    let req = test::TestRequest::get()
+        .uri("/v1/channel")
+        .insert_header(("Authorization", format!("Bearer {token}")))
+        .insert_header(("User-Agent", "Odoo/19.0"))
+        .insert_header(("X-Odoo-Db", "prod"))
+        .insert_header(("Accept-Language", "fr-BE"))
+        .insert_header(("Cookie", "session_id=abc"))
+        .insert_header(("Connection", "keep-alive"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let received = mock_server.received_requests().await.unwrap();
+    let headers = &received[0].headers;
+    assert_eq!(headers.get("User-Agent").unwrap(), "Odoo/19.0");
+    assert_eq!(headers.get("X-Odoo-Db").unwrap(), "prod");
+    assert!(headers.get("Accept-Language").is_none());
+    assert!(headers.get("Cookie").is_none());
+    assert_ne!(
+        headers.get("Connection").map(|v| v.to_str().unwrap()),
+        Some("keep-alive")
+    );
+}
+
+#[actix_web::test]
+async fn test_channel_post_forwards_json_body() {
+    let mock_server = MockServer::start().await;
+    let payload = json!({ "webRTC": true, "recordingAddress": "http://recorder:8080" });
+    Mock::given(method("POST"))
+        .and(path("/v1/channel"))
+        .and(header("Content-Type", "application/json"))
+        .and(body_json(payload.clone()))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "uuid": "test-uuid",
+            "url": "wss://test"
+        })))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(proxy_app_state(mock_server.uri())))
+            .route("/v1/channel", web::get().to(channel))
+            .route("/v1/channel", web::post().to(channel)),
+    )
+    .await;
+
+    let token = sign_claims(&make_test_claims(), GATEWAY_KEY);
+    let req = test::TestRequest::post()
+        .uri("/v1/channel?region=eu-west")
+        .insert_header(("Authorization", format!("Bearer {token}")))
+        .set_json(&payload)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["uuid"], "test-uuid");
+}
+
+#[actix_web::test]
+async fn test_oversized_request_body_rejected() {
+    let mock_server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&mock_server)
+        .await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(proxy_app_state(mock_server.uri())))
+            .app_data(web::PayloadConfig::new(1024))
+            .route("/v1/channel", web::post().to(channel))
+            .route("/v1/{tail:.*}", web::route().to(proxy)),
+    )
+    .await;
+
+    let token = sign_claims(&make_test_claims(), GATEWAY_KEY);
+    for uri in ["/v1/channel", "/v1/disconnect"] {
+        let req = test::TestRequest::post()
+            .uri(uri)
+            .insert_header(("Authorization", format!("Bearer {token}")))
+            .set_payload("x".repeat(2048))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE, "{uri}");
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["error"]["code"], "REQUEST_TOO_LARGE", "{uri}");
+        assert_eq!(body["error"]["message"], "request body too large", "{uri}");
+    }
+}
+
+#[actix_web::test]
+async fn test_rate_limit_per_issuer() {
+    let mock_server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/v1/channel"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "uuid": "test-uuid",
+            "url": "wss://test"
+        })))
+        .expect(3)
+        .mount(&mock_server)
+        .await;
+
+    let state = Arc::new(AppState {
+        rate_limiter: Some(RateLimiter::new(2)),
+        ..Arc::into_inner(proxy_app_state(mock_server.uri())).unwrap()
+    });
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/v1/channel", web::get().to(channel)),
+    )
+    .await;
+
+    let request = |iss: &str| {
+        let claims = Claims {
+            iss: iss.to_string(),
+            ..make_test_claims()
+        };
+        let token = sign_claims(&claims, GATEWAY_KEY);
+        test::TestRequest::get()
+            .uri("/v1/channel")
+            .insert_header(("Authorization", format!("Bearer {token}")))
+            .to_request()
+    };
+
+    for _ in 0..2 {
+        let resp = test::call_service(&app, request("channel-1")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+    // rejected before reaching any SFU
+    let resp = test::call_service(&app, request("channel-1")).await;
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(resp.headers().get("Retry-After").unwrap(), "30");
+
+    let resp = test::call_service(&app, request("channel-2")).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+/// Log lines written while the guard is alive, on this thread.
+#[derive(Clone, Default)]
+struct LogBuffer(Arc<std::sync::Mutex<Vec<u8>>>);
+
+impl std::io::Write for LogBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .extend_from_slice(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[actix_web::test]
+async fn test_access_log_line_per_request() {
+    let logs = LogBuffer::default();
+    let writer = logs.clone();
+    let _subscriber = tracing::subscriber::set_default(
+        tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .finish(),
+    );
+
+    let mock_server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/v1/channel"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "uuid": "test-uuid",
+            "url": "wss://test"
+        })))
+        .mount(&mock_server)
+        .await;
+    let state = create_app_state(
+        vec![SfuConfig {
+            address: mock_server.uri(),
+            regions: vec!["eu-west".to_string()],
+            key: SFU_KEY.to_vec(),
+            ..Default::default()
+        }],
+        GATEWAY_KEY,
+        false,
+    );
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/v1/channel", web::get().to(channel)),
+    )
+    .await;
+
+    let token = sign_claims(&make_test_claims(), GATEWAY_KEY);
+    let req = test::TestRequest::get()
+        .uri("/v1/channel?region=eu-west")
+        .peer_addr("203.0.113.7:50000".parse().unwrap())
+        .insert_header(("Authorization", format!("Bearer {token}")))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let logs = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+    let summaries: Vec<&str> = logs
+        .lines()
+        .filter(|line| line.contains("Request completed"))
+        .collect();
+    assert_eq!(summaries.len(), 1, "{logs}");
+    let summary = summaries[0];
+    for field in [
+        "handler=\"channel\"".to_string(),
+        "client_ip=203.0.113.7".to_string(),
+        "region=eu-west".to_string(),
+        format!("sfu={}", mock_server.uri()),
+        "upstream_status=200".to_string(),
+        "status=200".to_string(),
+        "latency_ms=".to_string(),
+    ] {
+        assert!(summary.contains(&field), "{field} missing in {summary}");
+    }
+}
+
+#[actix_web::test]
+async fn test_non_json_response_logged() {
+    let logs = LogBuffer::default();
+    let writer = logs.clone();
+    let _subscriber = tracing::subscriber::set_default(
+        tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .finish(),
+    );
+
+    let mock_server = MockServer::start().await;
+    let page = format!(
+        "<html><body><h1>Service Unavailable</h1>{}</body></html>",
+        "x".repeat(1024)
+    );
+    Mock::given(method("GET"))
+        .and(path("/v1/channel"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(page, "text/html"))
+        .mount(&mock_server)
+        .await;
+    let state = create_app_state(
+        vec![SfuConfig {
+            address: mock_server.uri(),
+            key: SFU_KEY.to_vec(),
+            ..Default::default()
+        }],
+        GATEWAY_KEY,
+        false,
+    );
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/v1/channel", web::get().to(channel)),
+    )
+    .await;
+
+    let token = sign_claims(&make_test_claims(), GATEWAY_KEY);
+    let req = test::TestRequest::get()
+        .uri("/v1/channel")
+        .insert_header(("Authorization", format!("Bearer {token}")))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_GATEWAY);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"]["code"], "BAD_SFU_RESPONSE");
+    assert!(!body.to_string().contains("Service Unavailable"), "{body}");
+
+    let logs = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+    let line = logs
+        .lines()
+        .find(|line| line.contains("Failed to parse SFU response"))
+        .unwrap_or_else(|| panic!("no parse failure logged: {logs}"));
+    assert!(line.contains("content_type=\"text/html\""), "{line}");
+    assert!(line.contains("<h1>Service Unavailable</h1>"), "{line}");
+    assert!(line.contains("(1078 bytes)"), "{line}");
+}
+
+#[actix_web::test]
+async fn test_channel_url_scheme_validated() {
+    let mock_server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/v1/channel"))
+        .and(query_param("room", "secure"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "uuid": "test-uuid",
+            "url": "wss://sfu.example.com"
+        })))
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/v1/channel"))
+        .and(query_param("room", "plain"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "uuid": "test-uuid",
+            "url": "http://sfu.example.com"
+        })))
+        .mount(&mock_server)
+        .await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(proxy_app_state(mock_server.uri())))
+            .route("/v1/channel", web::get().to(channel)),
+    )
+    .await;
+
+    let token = sign_claims(&make_test_claims(), GATEWAY_KEY);
+    let request = |room: &str| {
+        test::TestRequest::get()
+            .uri(&format!("/v1/channel?room={room}"))
+            .insert_header(("Authorization", format!("Bearer {token}")))
+            .to_request()
+    };
+
+    let resp = test::call_service(&app, request("secure")).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["url"], "wss://sfu.example.com");
+
+    let resp = test::call_service(&app, request("plain")).await;
+    assert_eq!(resp.status(), StatusCode::BAD_GATEWAY);
     let body: serde_json::Value = test::read_body_json(resp).await;
-    assert_eq!(body, json!({ "error": "no SFU instances available" }));
+    assert_eq!(body["error"]["code"], "BAD_SFU_RESPONSE");
 }
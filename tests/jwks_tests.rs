@@ -0,0 +1,217 @@
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_web::{App, http::StatusCode, test, web};
+use jsonwebtoken::{Algorithm, EncodingKey, Header, encode};
+use serde_json::json;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use common::{GATEWAY_KEY, create_app_state, make_test_claims, sign_claims};
+use sfu_gateway::config::SfuConfig;
+use sfu_gateway::http::{AppState, JwksCache, channel};
+use sfu_gateway::routing::Balancer;
+
+const SFU_KEY: &[u8] = b"sfu-key-padded-to-32-bytes-here!";
+
+/// Test-only key pairs, the moduli below are the public halves published in the JWKS
+const RSA_1_PEM: &[u8] = include_bytes!("fixtures/jwks_rsa_1.pem");
+const RSA_2_PEM: &[u8] = include_bytes!("fixtures/jwks_rsa_2.pem");
+const RSA_1_N: &str = "tW5u8vzFYxCfA7IZe6lyqcqnY7i4uMxfMjcGRUmLmReIDQQDILKiYU75dJvYFmKVtN0sy6J9gXGKkSSETV1MnpbvrgG-IyXvYRfDZVRGaoVmAp1O81D6tPy5WBP2emYC9py5uqDozG4wFVElmImkGKopJjTYBSop0kXcPKLrLZ2y6zITF99mtsGu6MDQWk_EJfTD6-u5gYjUYxLVwI-KG2lXZidq8fOlnh8NnkbfthRrsN-gwOXyBi6P9NIgCRwNjdF9jlQrFMvDFHdd2UXMx4Vdh7fdAqFOCCtHThxgtZ1CYO5siNs6LgnaB3gukBRyQqLoLDe-3POBg8e1pd1Yaw";
+const RSA_2_N: &str = "3vqp8l2blVu7TgQLl3EFHaDO7qgdqNTPiA3XbgqyTsfHrpGS4v66DF_s2-xdrzTcj2J2Q7Y2wV4HBg6hUwJ34iPrqdqDwqBQZzL1M975pygbgyt5N-9qdUYWLTDYKIpBYP3_7XRo6K7CzGR5l29A3XZ1IttjZjm-8YxvuFWwlAGsaZMuchNY4eVY0NxWvYNgTy3oOuf-UfXG8xCKNSlLK_hK4a3MslkZaDtADz0wrz3jy_IUol92YC-zNLOj1zgh8kBjxmLp8PlJ8mwlQQOsfYEgiRN5isWtpb7KRSNKa7sPPs6k6PV3McCA1LAwmdg5IC8Om0TIPUnuyaRm71sUrQ";
+
+fn jwk(kid: &str, n: &str) -> serde_json::Value {
+    json!({ "kty": "RSA", "alg": "RS256", "use": "sig", "kid": kid, "n": n, "e": "AQAB" })
+}
+
+fn sign_rs256(pem: &[u8], kid: &str) -> String {
+    let header = Header {
+        kid: Some(kid.to_string()),
+        ..Header::new(Algorithm::RS256)
+    };
+    let key = EncodingKey::from_rsa_pem(pem).expect("fixture should be a valid RSA key");
+    encode(&header, &make_test_claims(), &key).expect("signing should work")
+}
+
+async fn mock_sfu() -> MockServer {
+    let sfu = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/v1/channel"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "uuid": "test-uuid-123",
+            "url": "wss://sfu.example.com/channel/test-uuid-123"
+        })))
+        .mount(&sfu)
+        .await;
+    sfu
+}
+
+fn jwks_state(sfu: &MockServer, jwks: JwksCache) -> Arc<AppState> {
+    Arc::new(AppState {
+        jwks: Some(jwks.with_min_refresh_interval(Duration::ZERO)),
+        ..AppState::new(
+            Balancer::new(vec![SfuConfig {
+                address: sfu.uri(),
+                key: SFU_KEY.to_vec(),
+                ..Default::default()
+            }]),
+            reqwest::Client::new(),
+            GATEWAY_KEY.to_vec(),
+        )
+    })
+}
+
+async fn call_channel(state: Arc<AppState>, tokens: &[&str]) -> Vec<StatusCode> {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/v1/channel", web::get().to(channel)),
+    )
+    .await;
+    let mut statuses = Vec::new();
+    for token in tokens {
+        let req = test::TestRequest::get()
+            .uri("/v1/channel")
+            .insert_header(("Authorization", format!("Bearer {token}")))
+            .to_request();
+        statuses.push(test::call_service(&app, req).await.status());
+    }
+    statuses
+}
+
+#[actix_web::test]
+async fn test_jwks_key_selected_by_kid() {
+    let sfu = mock_sfu().await;
+    let idp = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/jwks.json"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(json!({ "keys": [jwk("k1", RSA_1_N), jwk("k2", RSA_2_N)] })),
+        )
+        .expect(1)
+        .mount(&idp)
+        .await;
+    let jwks = JwksCache::new(
+        format!("{}/jwks.json", idp.uri()),
+        Duration::from_mins(5),
+        reqwest::Client::new(),
+    );
+    assert!(jwks.refresh().await);
+
+    let statuses = call_channel(
+        jwks_state(&sfu, jwks),
+        &[
+            &sign_rs256(RSA_2_PEM, "k2"),
+            &sign_rs256(RSA_1_PEM, "k1"),
+            // signed with the first key but claiming to be the second
+            &sign_rs256(RSA_1_PEM, "k2"),
+            // HS256 tokens keep using the gateway key
+            &sign_claims(&make_test_claims(), GATEWAY_KEY),
+        ],
+    )
+    .await;
+
+    assert_eq!(
+        statuses,
+        [
+            StatusCode::OK,
+            StatusCode::OK,
+            StatusCode::UNAUTHORIZED,
+            StatusCode::OK
+        ]
+    );
+}
+
+#[actix_web::test]
+async fn test_jwks_refreshed_on_unknown_kid() {
+    let sfu = mock_sfu().await;
+    let idp = MockServer::start().await;
+    // the identity provider rotates in a second key after the initial fetch
+    Mock::given(method("GET"))
+        .and(path("/jwks.json"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(json!({ "keys": [jwk("k1", RSA_1_N)] })),
+        )
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&idp)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/jwks.json"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(json!({ "keys": [jwk("k1", RSA_1_N), jwk("k2", RSA_2_N)] })),
+        )
+        .expect(1)
+        .mount(&idp)
+        .await;
+    let jwks = JwksCache::new(
+        format!("{}/jwks.json", idp.uri()),
+        Duration::from_mins(5),
+        reqwest::Client::new(),
+    );
+    assert!(jwks.refresh().await);
+
+    let statuses = call_channel(
+        jwks_state(&sfu, jwks),
+        &[&sign_rs256(RSA_1_PEM, "k1"), &sign_rs256(RSA_2_PEM, "k2")],
+    )
+    .await;
+
+    assert_eq!(statuses, [StatusCode::OK, StatusCode::OK]);
+}
+
+#[actix_web::test]
+async fn test_jwks_fetch_failure_serves_cached_keys() {
+    let sfu = mock_sfu().await;
+    let idp = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/jwks.json"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(json!({ "keys": [jwk("k1", RSA_1_N)] })),
+        )
+        .up_to_n_times(1)
+        .mount(&idp)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/jwks.json"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&idp)
+        .await;
+    // always expired, every request tries to refresh and fails
+    let jwks = JwksCache::new(
+        format!("{}/jwks.json", idp.uri()),
+        Duration::ZERO,
+        reqwest::Client::new(),
+    );
+    assert!(jwks.refresh().await);
+
+    let statuses = call_channel(
+        jwks_state(&sfu, jwks),
+        &[&sign_rs256(RSA_1_PEM, "k1"), &sign_rs256(RSA_2_PEM, "k2")],
+    )
+    .await;
+
+    assert_eq!(statuses, [StatusCode::OK, StatusCode::UNAUTHORIZED]);
+}
+
+#[actix_web::test]
+async fn test_rs256_rejected_without_jwks() {
+    let sfu = mock_sfu().await;
+    let state = create_app_state(
+        vec![SfuConfig {
+            address: sfu.uri(),
+            key: SFU_KEY.to_vec(),
+            ..Default::default()
+        }],
+        GATEWAY_KEY,
+        false,
+    );
+
+    let statuses = call_channel(state, &[&sign_rs256(RSA_1_PEM, "k1")]).await;
+
+    assert_eq!(statuses, [StatusCode::UNAUTHORIZED]);
+}
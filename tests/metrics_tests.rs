@@ -0,0 +1,85 @@
+mod common;
+
+use actix_web::{App, http::StatusCode, test, web};
+use serde_json::json;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use common::{GATEWAY_KEY, create_app_state, make_test_claims, sign_claims};
+use sfu_gateway::config::SfuConfig;
+use sfu_gateway::http::{channel, metrics};
+
+const SFU_KEY: &[u8] = b"sfu-key-padded-to-32-bytes-here!";
+
+#[actix_web::test]
+async fn test_metrics_count_channel_requests() {
+    let mock_server = MockServer::start().await;
+    let state = create_app_state(
+        vec![SfuConfig {
+            address: mock_server.uri(),
+            regions: vec!["eu-west".to_string()],
+            key: SFU_KEY.to_vec(),
+            ..Default::default()
+        }],
+        GATEWAY_KEY,
+        false,
+    );
+
+    Mock::given(method("GET"))
+        .and(path("/v1/channel"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "uuid": "test-uuid",
+            "url": "wss://test"
+        })))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let token = sign_claims(&make_test_claims(), GATEWAY_KEY);
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/v1/channel", web::get().to(channel))
+            .route("/metrics", web::get().to(metrics)),
+    )
+    .await;
+
+    let requests = [
+        test::TestRequest::get()
+            .uri("/v1/channel?region=eu-west")
+            .insert_header(("Authorization", format!("Bearer {token}")))
+            .to_request(),
+        test::TestRequest::get().uri("/v1/channel").to_request(),
+        test::TestRequest::get()
+            .uri("/v1/channel")
+            .insert_header(("Authorization", "Bearer not-a-jwt"))
+            .to_request(),
+    ];
+    for req in requests {
+        test::call_service(&app, req).await;
+    }
+
+    let resp =
+        test::call_service(&app, test::TestRequest::get().uri("/metrics").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(
+        resp.headers()
+            .get("Content-Type")
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .starts_with("text/plain; version=0.0.4")
+    );
+    let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+    for line in [
+        "sfu_gateway_channel_requests_total 3",
+        "sfu_gateway_region_requests_total{region=\"eu-west\"} 1",
+        "sfu_gateway_auth_failures_total{reason=\"missing\"} 1",
+        "sfu_gateway_auth_failures_total{reason=\"invalid\"} 1",
+        "sfu_gateway_forward_successes_total 1",
+        "sfu_gateway_forward_failures_total{class=\"5xx\"} 0",
+    ] {
+        assert!(body.lines().any(|l| l == line), "missing {line}:\n{body}");
+    }
+}
@@ -0,0 +1,266 @@
+mod common;
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use actix_web::{App, http::StatusCode, test, web};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde_json::json;
+use sfu_gateway::config::{NodeData, SfuConfig, watch_nodes};
+use sfu_gateway::http::{AppState, channel, reload};
+use sfu_gateway::routing::Balancer;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use common::{GATEWAY_KEY, create_app_state, make_test_claims, sign_claims};
+
+fn secrets(address: &str) -> String {
+    format!(
+        "[[sfu]]\naddress = \"{address}\"\nkey = \"uHhYOue/up+kZ2sIks5mK7ec+74UJtXLBKAbAS5bN/0=\"\n"
+    )
+}
+
+fn selected_address(state: &AppState) -> Option<String> {
+    state
+        .balancer()
+        .select(&[])
+        .ok()
+        .map(|sfu| sfu.address.clone())
+}
+
+/// Wait for the watcher to pick up a change, or give up after a few seconds.
+async fn wait_for_address(state: &AppState, address: &str) -> bool {
+    let started = Instant::now();
+    while started.elapsed() < Duration::from_secs(5) {
+        if selected_address(state).as_deref() == Some(address) {
+            return true;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    false
+}
+
+#[tokio::test]
+async fn test_secrets_file_change_swaps_sfus() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("secrets.toml");
+    std::fs::write(&path, secrets("http://sfu-a:8070")).unwrap();
+
+    let nodes = NodeData::load(&path).unwrap();
+    let state = Arc::new(AppState::new(
+        Balancer::new(nodes.sfu),
+        reqwest::Client::new(),
+        GATEWAY_KEY.to_vec(),
+    ));
+    let reloaded = Arc::clone(&state);
+    let _watcher = watch_nodes(&path, None, move |nodes| {
+        let _ = reloaded.reload_nodes(nodes);
+    })
+    .unwrap();
+    assert_eq!(selected_address(&state).unwrap(), "http://sfu-a:8070");
+
+    std::fs::write(&path, secrets("http://sfu-b:8070")).unwrap();
+    assert!(wait_for_address(&state, "http://sfu-b:8070").await);
+
+    // files that don't load are ignored, the current SFUs are kept
+    std::fs::write(
+        &path,
+        "[[sfu]]\naddress = \"http://sfu-c:8070\"\nkey = \"not-base64!\"\n",
+    )
+    .unwrap();
+    std::fs::write(dir.path().join("other.toml"), secrets("http://sfu-d:8070")).unwrap();
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert_eq!(selected_address(&state).unwrap(), "http://sfu-b:8070");
+
+    // replaced rather than rewritten, as editors do
+    let replacement = dir.path().join("secrets.toml.tmp");
+    std::fs::write(&replacement, secrets("http://sfu-e:8070")).unwrap();
+    std::fs::rename(&replacement, &path).unwrap();
+    assert!(wait_for_address(&state, "http://sfu-e:8070").await);
+}
+
+#[actix_web::test]
+async fn test_admin_reload_swaps_sfus() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("secrets.toml");
+    std::fs::write(&path, secrets("http://sfu-a:8070")).unwrap();
+
+    let state = Arc::new(AppState {
+        admin_key: Some("admin-secret".to_string()),
+        secrets_file: Some(path.clone()),
+        ..AppState::new(
+            Balancer::new(NodeData::load(&path).unwrap().sfu),
+            reqwest::Client::new(),
+            GATEWAY_KEY.to_vec(),
+        )
+    });
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(Arc::clone(&state)))
+            .route("/admin/reload", web::post().to(reload)),
+    )
+    .await;
+    let reload_request = || {
+        test::TestRequest::post()
+            .uri("/admin/reload")
+            .insert_header(("X-Admin-Key", "admin-secret"))
+            .to_request()
+    };
+
+    std::fs::write(
+        &path,
+        secrets("http://sfu-b:8070") + &secrets("http://sfu-c:8070"),
+    )
+    .unwrap();
+    let resp = test::call_service(&app, reload_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["sfu_count"], 2);
+    assert_eq!(state.balancer().instances().len(), 2);
+    assert_ne!(selected_address(&state).unwrap(), "http://sfu-a:8070");
+
+    // a file that doesn't load leaves the SFUs in place
+    std::fs::write(&path, "[[sfu]]\naddress = \"http://sfu-d:8070\"\n").unwrap();
+    let resp = test::call_service(&app, reload_request()).await;
+    assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"]["code"], "RELOAD_FAILED");
+    assert_eq!(state.balancer().instances().len(), 2);
+
+    let unauthenticated = test::TestRequest::post().uri("/admin/reload").to_request();
+    let resp = test::call_service(&app, unauthenticated).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn test_strict_key_check_rejects_reloads_sharing_a_gateway_key() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("secrets.toml");
+    std::fs::write(&path, secrets("http://sfu-a:8070")).unwrap();
+    let key_file = dir.path().join("gateway.key");
+    std::fs::write(&key_file, STANDARD.encode(GATEWAY_KEY)).unwrap();
+    let shared_key = "uHhYOue/up+kZ2sIks5mK7ec+74UJtXLBKAbAS5bN/0=";
+
+    let state = AppState {
+        secrets_file: Some(path.clone()),
+        gateway_key_file: Some(key_file.clone()),
+        strict_key_check: true,
+        ..AppState::new(
+            Balancer::new(NodeData::load(&path).unwrap().sfu),
+            reqwest::Client::new(),
+            GATEWAY_KEY.to_vec(),
+        )
+    };
+
+    // the SFU key of the secrets file becoming a gateway key
+    std::fs::write(&key_file, shared_key).unwrap();
+    assert!(state.reload_key_files().is_err());
+    assert_eq!(*state.gateway_keys.read().unwrap(), [GATEWAY_KEY.to_vec()]);
+
+    // an SFU reusing the gateway key, on SIGHUP and on file changes alike
+    let shared = format!(
+        "[[sfu]]\naddress = \"http://sfu-b:8070\"\nkey = \"{}\"\n",
+        STANDARD.encode(GATEWAY_KEY)
+    );
+    std::fs::write(&path, &shared).unwrap();
+    assert!(state.reload_secrets().is_err());
+    assert!(state.reload_nodes(NodeData::load(&path).unwrap()).is_err());
+    assert_eq!(selected_address(&state).unwrap(), "http://sfu-a:8070");
+
+    // only warned about without the strict check
+    let state = AppState {
+        strict_key_check: false,
+        ..state
+    };
+    assert_eq!(state.reload_secrets(), Ok(1));
+    assert_eq!(selected_address(&state).unwrap(), "http://sfu-b:8070");
+}
+
+#[actix_web::test]
+async fn test_reload_swaps_sfus_despite_startup_only_changes() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("secrets.toml");
+    std::fs::write(&path, secrets("http://sfu-a:8070")).unwrap();
+    let state = AppState {
+        secrets_file: Some(path.clone()),
+        ..AppState::new(
+            Balancer::new(NodeData::load(&path).unwrap().sfu),
+            reqwest::Client::new(),
+            GATEWAY_KEY.to_vec(),
+        )
+    };
+
+    std::fs::write(
+        &path,
+        secrets("http://sfu-b:8070")
+            + "[headers]\nX-Tenant = \"odoo\"\n[geo.countries]\nBE = \"eu-west\"\n",
+    )
+    .unwrap();
+    assert_eq!(state.reload_secrets(), Ok(1));
+    assert_eq!(selected_address(&state).unwrap(), "http://sfu-b:8070");
+    assert!(state.static_headers.is_empty());
+}
+
+#[actix_web::test]
+async fn test_key_file_reload_rotates_gateway_keys() {
+    const NEW_GATEWAY_KEY: &[u8] = b"new-gateway-key-padded-to-32-by!";
+    let sfu = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/v1/channel"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "uuid": "test-uuid",
+            "url": "wss://test"
+        })))
+        .mount(&sfu)
+        .await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let key_file = dir.path().join("gateway.key");
+    std::fs::write(&key_file, STANDARD.encode(GATEWAY_KEY)).unwrap();
+    let state = Arc::new(AppState {
+        gateway_key_file: Some(key_file.clone()),
+        ..Arc::into_inner(create_app_state(
+            vec![SfuConfig {
+                address: sfu.uri(),
+                key: b"sfu-key-padded-to-32-bytes-here!".to_vec(),
+                ..Default::default()
+            }],
+            GATEWAY_KEY,
+            false,
+        ))
+        .unwrap()
+    });
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(Arc::clone(&state)))
+            .route("/v1/channel", web::get().to(channel)),
+    )
+    .await;
+    let channel_request = |key: &[u8]| {
+        test::TestRequest::get()
+            .uri("/v1/channel")
+            .insert_header((
+                "Authorization",
+                format!("Bearer {}", sign_claims(&make_test_claims(), key)),
+            ))
+            .to_request()
+    };
+
+    let resp = test::call_service(&app, channel_request(NEW_GATEWAY_KEY)).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    // the new key only, as at the end of a rotation
+    std::fs::write(&key_file, STANDARD.encode(NEW_GATEWAY_KEY)).unwrap();
+    state.reload_key_files().unwrap();
+    let resp = test::call_service(&app, channel_request(NEW_GATEWAY_KEY)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let resp = test::call_service(&app, channel_request(GATEWAY_KEY)).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    // a file that doesn't load keeps the current keys
+    std::fs::write(&key_file, "").unwrap();
+    assert!(state.reload_key_files().is_err());
+    let resp = test::call_service(&app, channel_request(NEW_GATEWAY_KEY)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
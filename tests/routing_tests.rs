@@ -1,13 +1,20 @@
 mod common;
 
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_web::middleware::Condition;
 use actix_web::{App, http::StatusCode, test, web};
 use serde_json::json;
 use wiremock::matchers::{method, path};
 use wiremock::{Mock, MockServer, ResponseTemplate};
 
 use common::{GATEWAY_KEY, create_app_state, make_test_claims, sign_claims};
-use sfu_gateway::config::SfuConfig;
-use sfu_gateway::http::channel;
+use sfu_gateway::config::{HealthCheckMode, SelectionStrategy, SfuConfig};
+use sfu_gateway::http::{
+    AppState, channel, cors, drain, geo, readyz, select_preview, status, undrain,
+};
+use sfu_gateway::routing::{Balancer, GeoIp, HealthCheckConfig, HealthThresholds};
 
 const SFU_KEY_EU: &[u8] = b"sfu-key-eu-padded-to-32-bytes!!";
 const SFU_KEY_US: &[u8] = b"sfu-key-us-padded-to-32-bytes!!";
@@ -16,13 +23,15 @@ fn multi_region_sfus(eu_address: &str, us_address: &str) -> Vec<SfuConfig> {
     vec![
         SfuConfig {
             address: eu_address.to_string(),
-            region: Some("eu-west".to_string()),
+            regions: vec!["eu-west".to_string()],
             key: SFU_KEY_EU.to_vec(),
+            ..Default::default()
         },
         SfuConfig {
             address: us_address.to_string(),
-            region: Some("us-east".to_string()),
+            regions: vec!["us-east".to_string()],
             key: SFU_KEY_US.to_vec(),
+            ..Default::default()
         },
     ]
 }
@@ -72,6 +81,276 @@ async fn test_region_routing_selects_correct_sfu() {
     assert_eq!(body["uuid"], "eu-channel");
 }
 
+#[actix_web::test]
+async fn test_response_names_serving_sfu() {
+    let mock_eu = MockServer::start().await;
+    let mock_us = MockServer::start().await;
+
+    setup_mock_sfu(&mock_eu, "eu-channel", "wss://eu.sfu.example.com").await;
+    setup_mock_sfu(&mock_us, "us-channel", "wss://us.sfu.example.com").await;
+
+    for redact in [false, true] {
+        let state = Arc::new(AppState {
+            redact_sfu_address: redact,
+            ..AppState::new(
+                Balancer::new(multi_region_sfus(&mock_eu.uri(), &mock_us.uri())),
+                reqwest::Client::new(),
+                GATEWAY_KEY.to_vec(),
+            )
+        });
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(state))
+                .route("/v1/channel", web::get().to(channel)),
+        )
+        .await;
+        let token = sign_claims(&make_test_claims(), GATEWAY_KEY);
+
+        let req = test::TestRequest::get()
+            .uri("/v1/channel?region=us-east")
+            .insert_header(("Authorization", format!("Bearer {token}")))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(header(&resp, "X-SFU-Region"), Some("us-east"));
+        let address = header(&resp, "X-SFU-Address")
+            .unwrap_or_default()
+            .to_string();
+        if redact {
+            assert_eq!(address.len(), 16, "{address}");
+            assert!(!address.contains("127.0.0.1"), "{address}");
+        } else {
+            assert_eq!(address, mock_us.uri());
+        }
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["uuid"], "us-channel");
+    }
+}
+
+async fn channel_status(strict_region: bool, query: &str) -> (StatusCode, serde_json::Value) {
+    let mock_eu = MockServer::start().await;
+    let mock_us = MockServer::start().await;
+    setup_mock_sfu(&mock_eu, "eu-channel", "wss://eu.sfu.example.com").await;
+    setup_mock_sfu(&mock_us, "us-channel", "wss://us.sfu.example.com").await;
+
+    let state = Arc::new(AppState {
+        strict_region,
+        ..AppState::new(
+            Balancer::new(multi_region_sfus(&mock_eu.uri(), &mock_us.uri())),
+            reqwest::Client::new(),
+            GATEWAY_KEY.to_vec(),
+        )
+    });
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/v1/channel", web::get().to(channel)),
+    )
+    .await;
+    let token = sign_claims(&make_test_claims(), GATEWAY_KEY);
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/v1/channel?{query}"))
+        .insert_header(("Authorization", format!("Bearer {token}")))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    let status = resp.status();
+    (status, test::read_body_json(resp).await)
+}
+
+#[actix_web::test]
+async fn test_strict_region_rejects_unknown_hints() {
+    for query in [
+        "region=mars-north",
+        "country=ZZ",
+        "region=eu-west&country=ZZ",
+    ] {
+        let (status, body) = channel_status(true, query).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{query}");
+        assert_eq!(body["error"]["code"], "UNKNOWN_REGION", "{query}");
+    }
+
+    let (status, body) = channel_status(true, "region=us-east").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["uuid"], "us-channel");
+    let (status, body) = channel_status(true, "country=fr").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["uuid"], "eu-channel");
+}
+
+#[actix_web::test]
+async fn test_lenient_region_ignores_unknown_hints() {
+    for query in ["region=mars-north", "country=ZZ"] {
+        let (status, body) = channel_status(false, query).await;
+        assert_eq!(status, StatusCode::OK, "{query}");
+        assert!(body["uuid"].is_string(), "{query}");
+    }
+}
+
+async fn pinned_channel_status(
+    debug_endpoints: bool,
+    mock_eu: &MockServer,
+    mock_us: &MockServer,
+    query: &str,
+) -> (StatusCode, serde_json::Value) {
+    let state = Arc::new(AppState {
+        debug_endpoints,
+        ..AppState::new(
+            Balancer::new(multi_region_sfus(&mock_eu.uri(), &mock_us.uri())),
+            reqwest::Client::new(),
+            GATEWAY_KEY.to_vec(),
+        )
+    });
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/v1/channel", web::get().to(channel)),
+    )
+    .await;
+    let token = sign_claims(&make_test_claims(), GATEWAY_KEY);
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/v1/channel?{query}"))
+        .insert_header(("Authorization", format!("Bearer {token}")))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    let status = resp.status();
+    (status, test::read_body_json(resp).await)
+}
+
+#[actix_web::test]
+async fn test_debug_pin_targets_sfu() {
+    let mock_eu = MockServer::start().await;
+    let mock_us = MockServer::start().await;
+    setup_mock_sfu(&mock_eu, "eu-channel", "wss://eu.sfu.example.com").await;
+    setup_mock_sfu(&mock_us, "us-channel", "wss://us.sfu.example.com").await;
+
+    // the region hint says eu-west, the pin wins
+    let by_address = format!("region=eu-west&__sfu={}/&webRTC=1", mock_us.uri());
+    for query in [by_address.as_str(), "region=eu-west&__sfu=1"] {
+        let (status, body) = pinned_channel_status(true, &mock_eu, &mock_us, query).await;
+        assert_eq!(status, StatusCode::OK, "{query}");
+        assert_eq!(body["uuid"], "us-channel", "{query}");
+    }
+    let received = mock_us.received_requests().await.unwrap();
+    assert_eq!(received[0].url.query(), Some("webRTC=1"));
+
+    // ignored without the debug endpoints
+    let (status, body) = pinned_channel_status(false, &mock_eu, &mock_us, &by_address).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["uuid"], "eu-channel");
+}
+
+#[actix_web::test]
+async fn test_debug_pin_unknown_sfu_rejected() {
+    let mock_eu = MockServer::start().await;
+    let mock_us = MockServer::start().await;
+    setup_mock_sfu(&mock_eu, "eu-channel", "wss://eu.sfu.example.com").await;
+    setup_mock_sfu(&mock_us, "us-channel", "wss://us.sfu.example.com").await;
+
+    for query in ["__sfu=http://nowhere:3000", "__sfu=2"] {
+        let (status, body) = pinned_channel_status(true, &mock_eu, &mock_us, query).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{query}");
+        assert_eq!(body["error"]["code"], "UNKNOWN_PIN", "{query}");
+    }
+    assert!(mock_eu.received_requests().await.unwrap().is_empty());
+    assert!(mock_us.received_requests().await.unwrap().is_empty());
+}
+
+#[actix_web::test]
+async fn test_admin_drain_and_undrain() {
+    let mock_eu = MockServer::start().await;
+    let mock_us = MockServer::start().await;
+    setup_mock_sfu(&mock_eu, "eu-channel", "wss://eu.sfu.example.com").await;
+    setup_mock_sfu(&mock_us, "us-channel", "wss://us.sfu.example.com").await;
+
+    let state = Arc::new(AppState {
+        admin_key: Some("admin-secret".to_string()),
+        ..AppState::new(
+            Balancer::new(multi_region_sfus(&mock_eu.uri(), &mock_us.uri())),
+            reqwest::Client::new(),
+            GATEWAY_KEY.to_vec(),
+        )
+    });
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/v1/channel", web::get().to(channel))
+            .route("/admin/drain", web::post().to(drain))
+            .route("/admin/undrain", web::post().to(undrain)),
+    )
+    .await;
+    let token = sign_claims(&make_test_claims(), GATEWAY_KEY);
+    let admin = |path: &str, key: &str, address: &str| {
+        test::TestRequest::post()
+            .uri(path)
+            .insert_header(("X-Admin-Key", key))
+            .set_json(json!({ "address": address }))
+            .to_request()
+    };
+    let us_channel = || {
+        test::TestRequest::get()
+            .uri("/v1/channel?region=us-east")
+            .insert_header(("Authorization", format!("Bearer {token}")))
+            .to_request()
+    };
+
+    let resp = test::call_service(&app, admin("/admin/drain", "wrong", &mock_us.uri())).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let resp = test::call_service(
+        &app,
+        admin("/admin/drain", "admin-secret", "http://unknown:3000"),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    let resp =
+        test::call_service(&app, admin("/admin/drain", "admin-secret", &mock_us.uri())).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body, json!({ "address": mock_us.uri(), "draining": true }));
+    // the drained region falls back to the other one
+    for _ in 0..3 {
+        let body: serde_json::Value =
+            test::read_body_json(test::call_service(&app, us_channel()).await).await;
+        assert_eq!(body["uuid"], "eu-channel");
+    }
+
+    let resp = test::call_service(
+        &app,
+        admin("/admin/undrain", "admin-secret", &mock_us.uri()),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: serde_json::Value =
+        test::read_body_json(test::call_service(&app, us_channel()).await).await;
+    assert_eq!(body["uuid"], "us-channel");
+}
+
+#[actix_web::test]
+async fn test_admin_endpoints_disabled_without_key() {
+    let state = create_app_state(
+        multi_region_sfus("http://127.0.0.1:1", "http://127.0.0.1:2"),
+        GATEWAY_KEY,
+        false,
+    );
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/admin/drain", web::post().to(drain)),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/admin/drain")
+        .insert_header(("X-Admin-Key", ""))
+        .set_json(json!({ "address": "http://127.0.0.1:1" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
+
 #[actix_web::test]
 async fn test_country_routing_maps_to_region() {
     let mock_eu = MockServer::start().await;
@@ -105,3 +384,798 @@ async fn test_country_routing_maps_to_region() {
     let body: serde_json::Value = test::read_body_json(resp).await;
     assert_eq!(body["uuid"], "eu-channel", "France should route to EU");
 }
+
+#[actix_web::test]
+async fn test_hintless_request_prefers_local_region() {
+    let mock_eu = MockServer::start().await;
+    let mock_us = MockServer::start().await;
+
+    setup_mock_sfu(&mock_eu, "eu-channel", "wss://eu.sfu.example.com").await;
+    setup_mock_sfu(&mock_us, "us-channel", "wss://us.sfu.example.com").await;
+
+    let state = Arc::new(AppState {
+        default_region: Some("us-east".to_string()),
+        ..AppState::new(
+            Balancer::new(multi_region_sfus(&mock_eu.uri(), &mock_us.uri())),
+            reqwest::Client::new(),
+            GATEWAY_KEY.to_vec(),
+        )
+    });
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/v1/channel", web::get().to(channel)),
+    )
+    .await;
+
+    let token = sign_claims(&make_test_claims(), GATEWAY_KEY);
+
+    for _ in 0..4 {
+        let req = test::TestRequest::get()
+            .uri("/v1/channel")
+            .insert_header(("Authorization", format!("Bearer {token}")))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["uuid"], "us-channel");
+    }
+
+    // an explicit hint still wins
+    let req = test::TestRequest::get()
+        .uri("/v1/channel?country=FR")
+        .insert_header(("Authorization", format!("Bearer {token}")))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["uuid"], "eu-channel");
+}
+
+#[actix_web::test]
+async fn test_default_region_steers_hintless_requests() {
+    let mock_eu = MockServer::start().await;
+    let mock_us = MockServer::start().await;
+
+    setup_mock_sfu(&mock_eu, "eu-channel", "wss://eu.sfu.example.com").await;
+    setup_mock_sfu(&mock_us, "us-channel", "wss://us.sfu.example.com").await;
+
+    let state = Arc::new(AppState {
+        default_region: Some("eu-west".to_string()),
+        ..AppState::new(
+            Balancer::new(multi_region_sfus(&mock_eu.uri(), &mock_us.uri())),
+            reqwest::Client::new(),
+            GATEWAY_KEY.to_vec(),
+        )
+    });
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/v1/channel", web::get().to(channel)),
+    )
+    .await;
+
+    let token = sign_claims(&make_test_claims(), GATEWAY_KEY);
+    let uuid = |uri: &'static str| {
+        let req = test::TestRequest::get()
+            .uri(uri)
+            .insert_header(("Authorization", format!("Bearer {token}")))
+            .to_request();
+        let app = &app;
+        async move {
+            let resp = test::call_service(app, req).await;
+            assert_eq!(resp.status(), StatusCode::OK);
+            let body: serde_json::Value = test::read_body_json(resp).await;
+            body["uuid"].as_str().unwrap().to_string()
+        }
+    };
+
+    // without the default, half of these would go to us-east
+    for _ in 0..4 {
+        assert_eq!(uuid("/v1/channel").await, "eu-channel");
+    }
+    assert_eq!(uuid("/v1/channel?region=us-east").await, "us-channel");
+    assert_eq!(uuid("/v1/channel?country=US").await, "us-channel");
+}
+
+#[actix_web::test]
+async fn test_region_list_tried_in_order() {
+    let mock_eu = MockServer::start().await;
+    let mock_us = MockServer::start().await;
+
+    setup_mock_sfu(&mock_eu, "eu-channel", "wss://eu.sfu.example.com").await;
+    setup_mock_sfu(&mock_us, "us-channel", "wss://us.sfu.example.com").await;
+
+    let state = create_app_state(
+        multi_region_sfus(&mock_eu.uri(), &mock_us.uri()),
+        GATEWAY_KEY,
+        false,
+    );
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/v1/channel", web::get().to(channel)),
+    )
+    .await;
+
+    let token = sign_claims(&make_test_claims(), GATEWAY_KEY);
+    let request = |uri: &str| {
+        test::TestRequest::get()
+            .uri(uri)
+            .insert_header(("Authorization", format!("Bearer {token}")))
+            .to_request()
+    };
+
+    // eu-north has no SFU, us-east is listed next, ahead of the nearby eu-west
+    let resp = test::call_service(&app, request("/v1/channel?region=eu-north,us-east")).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.headers().get("X-SFU-Region").unwrap(), "us-east");
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["uuid"], "us-channel");
+
+    // a single region still falls back by distance
+    let resp = test::call_service(&app, request("/v1/channel?region=eu-north")).await;
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["uuid"], "eu-channel");
+
+    // the list isn't forwarded to the SFU
+    let received = mock_us.received_requests().await.unwrap();
+    assert_eq!(received[0].url.query(), None);
+}
+
+#[actix_web::test]
+async fn test_sticky_routing_pins_channel_to_sfu() {
+    let mock_a = MockServer::start().await;
+    let mock_b = MockServer::start().await;
+    let sfus = [&mock_a, &mock_b]
+        .iter()
+        .map(|server| SfuConfig {
+            address: server.uri(),
+            regions: vec!["eu-west".to_string()],
+            key: SFU_KEY_EU.to_vec(),
+            ..Default::default()
+        })
+        .collect();
+    let balancer = Balancer::new(sfus).with_strategy(SelectionStrategy::Sticky);
+    let claims = make_test_claims();
+    let owner = balancer
+        .select_sticky(&["eu-west"], &claims.iss, &[])
+        .unwrap()
+        .address
+        .clone();
+
+    for server in [&mock_a, &mock_b] {
+        Mock::given(method("GET"))
+            .and(path("/v1/channel"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "uuid": "channel",
+                "url": server.uri().replacen("http", "wss", 1)
+            })))
+            .expect(if server.uri() == owner { 5 } else { 0 })
+            .mount(server)
+            .await;
+    }
+
+    let state = Arc::new(AppState::new(
+        balancer,
+        reqwest::Client::new(),
+        GATEWAY_KEY.to_vec(),
+    ));
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/v1/channel", web::get().to(channel)),
+    )
+    .await;
+
+    let token = sign_claims(&claims, GATEWAY_KEY);
+    for _ in 0..5 {
+        let req = test::TestRequest::get()
+            .uri("/v1/channel?region=eu-west")
+            .insert_header(("Authorization", format!("Bearer {token}")))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+}
+
+#[actix_web::test]
+async fn test_geo_endpoint_lists_regions() {
+    let state = create_app_state(vec![], GATEWAY_KEY, false);
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/v1/geo", web::get().to(geo)),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/v1/geo").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let token = sign_claims(&make_test_claims(), GATEWAY_KEY);
+    let req = test::TestRequest::get()
+        .uri("/v1/geo")
+        .insert_header(("Authorization", format!("Bearer {token}")))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    let regions = body["regions"].as_array().unwrap();
+    assert_eq!(regions.len(), 13);
+    for region in regions {
+        let lat = region["lat"].as_f64().unwrap();
+        let lon = region["lon"].as_f64().unwrap();
+        assert!((-90.0..=90.0).contains(&lat), "invalid latitude {region}");
+        assert!(
+            (-180.0..=180.0).contains(&lon),
+            "invalid longitude {region}"
+        );
+    }
+    assert_eq!(body["countries"]["FR"], "eu-west");
+    assert_eq!(body["countries"]["JP"], "ap-northeast");
+}
+
+async fn mount_noop(server: &MockServer, status: u16) {
+    Mock::given(method("GET"))
+        .and(path("/noop"))
+        .respond_with(ResponseTemplate::new(status))
+        .mount(server)
+        .await;
+}
+
+async fn get_status(state: Arc<AppState>) -> serde_json::Value {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/v1/status", web::get().to(status)),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/v1/status").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let token = sign_claims(&make_test_claims(), GATEWAY_KEY);
+    let req = test::TestRequest::get()
+        .uri("/v1/status")
+        .insert_header(("Authorization", format!("Bearer {token}")))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    test::read_body_json(resp).await
+}
+
+#[actix_web::test]
+async fn test_status_probes_sfus_on_demand() {
+    let mock_eu = MockServer::start().await;
+    let mock_us = MockServer::start().await;
+    mount_noop(&mock_eu, 200).await;
+    mount_noop(&mock_us, 503).await;
+    let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let closed_address = format!("http://{}", closed.local_addr().unwrap());
+    drop(closed);
+
+    let mut sfus = multi_region_sfus(&mock_eu.uri(), &mock_us.uri());
+    sfus.push(SfuConfig {
+        address: closed_address.clone(),
+        key: SFU_KEY_EU.to_vec(),
+        ..Default::default()
+    });
+    let body = get_status(create_app_state(sfus, GATEWAY_KEY, false)).await;
+
+    assert_eq!(body["total"], 3);
+    assert_eq!(body["healthy"], 1);
+    assert_eq!(body["unhealthy"], 2);
+    let sfus = body["sfus"].as_array().unwrap();
+    assert_eq!(
+        sfus.iter()
+            .map(|sfu| (
+                sfu["address"].as_str().unwrap(),
+                sfu["reachable"].as_bool().unwrap()
+            ))
+            .collect::<Vec<_>>(),
+        vec![
+            (mock_eu.uri().as_str(), true),
+            (mock_us.uri().as_str(), false),
+            (closed_address.as_str(), false),
+        ]
+    );
+    assert_eq!(sfus[0]["regions"], json!(["eu-west"]));
+    assert_eq!(sfus[2]["regions"], json!([]));
+    for sfu in sfus {
+        assert!(sfu["last_check"].as_u64().unwrap() > 0, "{sfu}");
+    }
+}
+
+#[actix_web::test]
+async fn test_status_reuses_recent_probes() {
+    let mock_eu = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/noop"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&mock_eu)
+        .await;
+    let mock_us = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/noop"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&mock_us)
+        .await;
+
+    // the second request within the TTL is answered from the first probe
+    let cached = create_app_state(
+        multi_region_sfus(&mock_eu.uri(), "http://127.0.0.1:1"),
+        GATEWAY_KEY,
+        false,
+    );
+    let first = get_status(Arc::clone(&cached)).await;
+    let second = get_status(cached).await;
+    assert_eq!(first["sfus"][0]["reachable"], true);
+    assert_eq!(second["sfus"], first["sfus"]);
+
+    // a zero TTL probes on every request
+    let uncached = Arc::new(AppState {
+        status_probe_ttl: Duration::ZERO,
+        ..AppState::new(
+            Balancer::new(multi_region_sfus("http://127.0.0.1:1", &mock_us.uri())),
+            reqwest::Client::new(),
+            GATEWAY_KEY.to_vec(),
+        )
+    });
+    get_status(Arc::clone(&uncached)).await;
+    let body = get_status(uncached).await;
+    assert_eq!(body["sfus"][1]["reachable"], true);
+}
+
+#[actix_web::test]
+async fn test_status_probes_with_gateway_health_check_mode() {
+    // accepts connections but never answers HTTP
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = format!("http://{}", listener.local_addr().unwrap());
+
+    let tcp_only = Arc::new(AppState {
+        health_check_mode: HealthCheckMode::Tcp,
+        ..AppState::new(
+            Balancer::new(vec![SfuConfig {
+                address: address.clone(),
+                key: SFU_KEY_EU.to_vec(),
+                ..Default::default()
+            }]),
+            reqwest::Client::new(),
+            GATEWAY_KEY.to_vec(),
+        )
+    });
+    let body = get_status(tcp_only).await;
+    assert_eq!(body["healthy"], 1);
+    assert_eq!(body["sfus"][0]["reachable"], true);
+
+    // the SFU's own mode wins over the gateway's
+    let http_sfu = Arc::new(AppState {
+        health_check_mode: HealthCheckMode::Tcp,
+        ..AppState::new(
+            Balancer::new(vec![SfuConfig {
+                address,
+                key: SFU_KEY_EU.to_vec(),
+                health_check: Some(HealthCheckMode::Http),
+                ..Default::default()
+            }]),
+            reqwest::Client::new(),
+            GATEWAY_KEY.to_vec(),
+        )
+    });
+    let body = get_status(http_sfu).await;
+    assert_eq!(body["sfus"][0]["reachable"], false);
+    drop(listener);
+}
+
+#[actix_web::test]
+async fn test_status_reports_background_health() {
+    let mock_eu = MockServer::start().await;
+    let mock_us = MockServer::start().await;
+    mount_noop(&mock_eu, 503).await;
+    mount_noop(&mock_us, 200).await;
+
+    // a single round of probes, run right away
+    let balancer = Balancer::new(multi_region_sfus(&mock_eu.uri(), &mock_us.uri()))
+        .spawn_health_checks(
+            reqwest::Client::new(),
+            HealthCheckConfig {
+                interval: Duration::from_hours(1),
+                thresholds: HealthThresholds {
+                    failures: 1,
+                    successes: 1,
+                },
+                ..HealthCheckConfig::default()
+            },
+        );
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    // the status reflects the last probes, it doesn't probe again
+    mock_eu.reset().await;
+    mount_noop(&mock_eu, 200).await;
+
+    let state = Arc::new(AppState::new(
+        balancer,
+        reqwest::Client::new(),
+        GATEWAY_KEY.to_vec(),
+    ));
+    let body = get_status(state).await;
+
+    assert_eq!(body["total"], 2);
+    assert_eq!(body["healthy"], 1);
+    assert_eq!(body["unhealthy"], 1);
+    assert_eq!(body["sfus"][0]["reachable"], false);
+    assert_eq!(body["sfus"][1]["reachable"], true);
+    assert!(body["sfus"][0]["last_check"].as_u64().is_some());
+}
+
+async fn get_readyz(state: Arc<AppState>) -> StatusCode {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/readyz", web::get().to(readyz)),
+    )
+    .await;
+    let req = test::TestRequest::get().uri("/readyz").to_request();
+    test::call_service(&app, req).await.status()
+}
+
+async fn preflight(cors_origins: &[&str], origin: &str) -> actix_web::dev::ServiceResponse {
+    let cors_origins: Vec<String> = cors_origins.iter().map(|o| (*o).to_string()).collect();
+    let state = create_app_state(
+        multi_region_sfus("http://127.0.0.1:1", "http://127.0.0.1:2"),
+        GATEWAY_KEY,
+        false,
+    );
+    let app = test::init_service(
+        App::new().app_data(web::Data::new(state)).service(
+            web::resource("/v1/channel")
+                .wrap(Condition::new(
+                    !cors_origins.is_empty(),
+                    cors(&cors_origins),
+                ))
+                .route(web::get().to(channel)),
+        ),
+    )
+    .await;
+    let req = test::TestRequest::default()
+        .method(actix_web::http::Method::OPTIONS)
+        .uri("/v1/channel")
+        .insert_header(("Origin", origin))
+        .insert_header(("Access-Control-Request-Method", "GET"))
+        .insert_header(("Access-Control-Request-Headers", "authorization"))
+        .to_request();
+    test::call_service(&app, req).await
+}
+
+fn header<'a>(resp: &'a actix_web::dev::ServiceResponse, name: &str) -> Option<&'a str> {
+    resp.headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+}
+
+#[actix_web::test]
+async fn test_cors_preflight_allowed_origin() {
+    let resp = preflight(&["https://odoo.example.com"], "https://odoo.example.com").await;
+
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        header(&resp, "Access-Control-Allow-Origin"),
+        Some("https://odoo.example.com")
+    );
+    let methods = header(&resp, "Access-Control-Allow-Methods").unwrap_or_default();
+    assert!(
+        methods.contains("GET") && methods.contains("POST"),
+        "{methods}"
+    );
+    let headers = header(&resp, "Access-Control-Allow-Headers")
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    assert!(headers.contains("authorization"), "{headers}");
+}
+
+#[actix_web::test]
+async fn test_cors_preflight_other_origin_rejected() {
+    let resp = preflight(&["https://odoo.example.com"], "https://evil.example.com").await;
+
+    assert!(resp.status().is_client_error());
+    assert_eq!(header(&resp, "Access-Control-Allow-Origin"), None);
+}
+
+#[actix_web::test]
+async fn test_cors_disabled_by_default() {
+    let resp = preflight(&[], "https://odoo.example.com").await;
+
+    assert_eq!(header(&resp, "Access-Control-Allow-Origin"), None);
+    assert_ne!(resp.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn test_readyz_without_health_checks() {
+    let configured = create_app_state(
+        multi_region_sfus("http://127.0.0.1:1", "http://127.0.0.1:2"),
+        GATEWAY_KEY,
+        false,
+    );
+    assert_eq!(get_readyz(configured).await, StatusCode::OK);
+
+    let empty = create_app_state(vec![], GATEWAY_KEY, false);
+    assert_eq!(get_readyz(empty).await, StatusCode::SERVICE_UNAVAILABLE);
+}
+
+#[actix_web::test]
+async fn test_readyz_follows_health_checks() {
+    let mock_eu = MockServer::start().await;
+    let mock_us = MockServer::start().await;
+    mount_noop(&mock_eu, 503).await;
+    mount_noop(&mock_us, 503).await;
+
+    let state = Arc::new(AppState::new(
+        Balancer::new(multi_region_sfus(&mock_eu.uri(), &mock_us.uri())).spawn_health_checks(
+            reqwest::Client::new(),
+            HealthCheckConfig {
+                interval: Duration::from_millis(50),
+                thresholds: HealthThresholds {
+                    failures: 1,
+                    successes: 1,
+                },
+                ..HealthCheckConfig::default()
+            },
+        ),
+        reqwest::Client::new(),
+        GATEWAY_KEY.to_vec(),
+    ));
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(
+        get_readyz(state.clone()).await,
+        StatusCode::SERVICE_UNAVAILABLE
+    );
+
+    mock_us.reset().await;
+    mount_noop(&mock_us, 200).await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(get_readyz(state).await, StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn test_unhealthy_sfu_skipped_by_health_checks() {
+    let mock_eu = MockServer::start().await;
+    let mock_us = MockServer::start().await;
+
+    setup_mock_sfu(&mock_eu, "eu-channel", "wss://eu.sfu.example.com").await;
+    setup_mock_sfu(&mock_us, "us-channel", "wss://us.sfu.example.com").await;
+    Mock::given(method("GET"))
+        .and(path("/noop"))
+        .respond_with(ResponseTemplate::new(503))
+        .mount(&mock_eu)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/noop"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&mock_us)
+        .await;
+
+    let balancer = Balancer::with_health_checks(
+        multi_region_sfus(&mock_eu.uri(), &mock_us.uri()),
+        reqwest::Client::new(),
+        Duration::from_millis(20),
+    );
+    // the default threshold marks the SFU down after 3 failed probes
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    let state = Arc::new(AppState::new(
+        balancer,
+        reqwest::Client::new(),
+        GATEWAY_KEY.to_vec(),
+    ));
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/v1/channel", web::get().to(channel)),
+    )
+    .await;
+
+    let token = sign_claims(&make_test_claims(), GATEWAY_KEY);
+
+    let req = test::TestRequest::get()
+        .uri("/v1/channel?region=eu-west")
+        .insert_header(("Authorization", format!("Bearer {token}")))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["uuid"], "us-channel");
+}
+
+#[actix_web::test]
+async fn test_hintless_request_routed_by_geoip() {
+    let mock_eu = MockServer::start().await;
+    let mock_us = MockServer::start().await;
+
+    setup_mock_sfu(&mock_eu, "eu-channel", "wss://eu.sfu.example.com").await;
+    setup_mock_sfu(&mock_us, "us-channel", "wss://us.sfu.example.com").await;
+
+    // fixture database: 2.125.160.0/24 is FR, 216.160.83.0/24 is US
+    let geoip = || {
+        GeoIp::open(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/geoip_country.mmdb"
+        ))
+        .ok()
+    };
+    let state = Arc::new(AppState {
+        trust_proxy: true,
+        geoip: geoip(),
+        ..AppState::new(
+            Balancer::new(multi_region_sfus(&mock_eu.uri(), &mock_us.uri())),
+            reqwest::Client::new(),
+            GATEWAY_KEY.to_vec(),
+        )
+    });
+    assert!(state.geoip.is_some());
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/v1/channel", web::get().to(channel)),
+    )
+    .await;
+
+    let token = sign_claims(&make_test_claims(), GATEWAY_KEY);
+    let channel_for = |uri: &str, client_ip: &str| {
+        test::TestRequest::get()
+            .uri(uri)
+            .peer_addr("10.0.0.1:54321".parse().unwrap())
+            .insert_header(("Authorization", format!("Bearer {token}")))
+            .insert_header(("X-Forwarded-For", client_ip.to_string()))
+            .to_request()
+    };
+
+    for (uri, client_ip, expected) in [
+        ("/v1/channel", "2.125.160.216", "eu-channel"),
+        ("/v1/channel", "216.160.83.56", "us-channel"),
+        // an explicit hint wins over the client IP
+        ("/v1/channel?country=US", "2.125.160.216", "us-channel"),
+    ] {
+        let resp = test::call_service(&app, channel_for(uri, client_ip)).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["uuid"], expected, "{uri} from {client_ip}");
+    }
+}
+
+#[actix_web::test]
+async fn test_saturated_region_falls_back_then_retry_after() {
+    let mock_eu = MockServer::start().await;
+    let mock_us = MockServer::start().await;
+    setup_mock_sfu(&mock_eu, "eu-channel", "wss://eu.sfu.example.com").await;
+    setup_mock_sfu(&mock_us, "us-channel", "wss://us.sfu.example.com").await;
+
+    let mut sfus = multi_region_sfus(&mock_eu.uri(), &mock_us.uri());
+    sfus[0].max_channels = Some(1);
+    sfus[1].max_channels = Some(1);
+    let state = create_app_state(sfus, GATEWAY_KEY, false);
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/v1/channel", web::get().to(channel)),
+    )
+    .await;
+    let token = sign_claims(&make_test_claims(), GATEWAY_KEY);
+    let request = || {
+        test::TestRequest::get()
+            .uri("/v1/channel?region=eu-west")
+            .insert_header(("Authorization", format!("Bearer {token}")))
+            .to_request()
+    };
+
+    let body: serde_json::Value = test::call_and_read_body_json(&app, request()).await;
+    assert_eq!(body["uuid"], "eu-channel");
+
+    // eu-west is full, the next closest region with room takes the channel
+    let body: serde_json::Value = test::call_and_read_body_json(&app, request()).await;
+    assert_eq!(body["uuid"], "us-channel");
+
+    let resp = test::call_service(&app, request()).await;
+    assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(resp.headers().get("Retry-After").unwrap(), "2");
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"]["code"], "ALL_BUSY");
+}
+
+#[actix_web::test]
+async fn test_least_connections_routes_to_least_loaded_sfu() {
+    let mock_busy = MockServer::start().await;
+    let mock_idle = MockServer::start().await;
+    for (server, expected) in [(&mock_busy, 0), (&mock_idle, 4)] {
+        Mock::given(method("GET"))
+            .and(path("/v1/channel"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "uuid": "channel",
+                "url": server.uri().replacen("http", "wss", 1)
+            })))
+            .expect(expected)
+            .mount(server)
+            .await;
+    }
+    let sfus = [&mock_busy, &mock_idle]
+        .iter()
+        .map(|server| SfuConfig {
+            address: server.uri(),
+            regions: vec!["eu-west".to_string()],
+            key: SFU_KEY_EU.to_vec(),
+            ..Default::default()
+        })
+        .collect();
+    let balancer = Balancer::new(sfus).with_strategy(SelectionStrategy::LeastConnections);
+    for _ in 0..4 {
+        balancer.instances()[0].record_assignment();
+    }
+
+    let state = Arc::new(AppState::new(
+        balancer,
+        reqwest::Client::new(),
+        GATEWAY_KEY.to_vec(),
+    ));
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(Arc::clone(&state)))
+            .route("/v1/channel", web::get().to(channel)),
+    )
+    .await;
+
+    let token = sign_claims(&make_test_claims(), GATEWAY_KEY);
+    for _ in 0..4 {
+        let req = test::TestRequest::get()
+            .uri("/v1/channel?region=eu-west")
+            .insert_header(("Authorization", format!("Bearer {token}")))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+    assert_eq!(state.balancer().instances()[1].assigned(), 4);
+}
+
+#[actix_web::test]
+async fn test_select_preview_resolves_country() {
+    let sfus = multi_region_sfus("http://eu.invalid:3000", "http://us.invalid:3000");
+    let enabled = Arc::new(AppState {
+        debug_endpoints: true,
+        ..Arc::into_inner(create_app_state(sfus.clone(), GATEWAY_KEY, false)).unwrap()
+    });
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(enabled))
+            .route("/v1/select", web::get().to(select_preview)),
+    )
+    .await;
+
+    // no token needed, and no SFU contacted
+    let req = test::TestRequest::get()
+        .uri("/v1/select?country=FR")
+        .to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["region"], "eu-west");
+    assert_eq!(body["sfu"], "http://eu.invalid:3000");
+    assert_eq!(body["fallback_order"][0], "eu-west");
+    assert!(body["fallback_order"].as_array().unwrap().len() > 1);
+
+    let disabled = create_app_state(sfus, GATEWAY_KEY, false);
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(disabled))
+            .route("/v1/select", web::get().to(select_preview)),
+    )
+    .await;
+    let req = test::TestRequest::get()
+        .uri("/v1/select?country=FR")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
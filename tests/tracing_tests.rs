@@ -0,0 +1,129 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use actix_web::{App, http::StatusCode, test, web};
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use serde_json::json;
+use tracing_subscriber::layer::SubscriberExt;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use common::{GATEWAY_KEY, create_app_state, make_test_claims, sign_claims};
+use sfu_gateway::config::SfuConfig;
+use sfu_gateway::http::{AppState, channel};
+use sfu_gateway::telemetry;
+
+const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+const CALLER_SPAN_ID: &str = "00f067aa0ba902b7";
+
+#[actix_web::test]
+async fn test_trace_context_forwarded_to_sfu() {
+    // no exporter, the spans are only recorded
+    let provider = SdkTracerProvider::builder().build();
+    let _subscriber = tracing::subscriber::set_default(
+        tracing_subscriber::registry().with(telemetry::install(&provider)),
+    );
+
+    let mock_server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/v1/channel"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "uuid": "test-uuid",
+            "url": "wss://test"
+        })))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+    let state = create_app_state(
+        vec![SfuConfig {
+            address: mock_server.uri(),
+            key: b"sfu-key-padded-to-32-bytes-here!".to_vec(),
+            ..Default::default()
+        }],
+        GATEWAY_KEY,
+        false,
+    );
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/v1/channel", web::get().to(channel)),
+    )
+    .await;
+
+    let token = sign_claims(&make_test_claims(), GATEWAY_KEY);
+    let req = test::TestRequest::get()
+        .uri("/v1/channel")
+        .insert_header(("Authorization", format!("Bearer {token}")))
+        .insert_header(("traceparent", format!("00-{TRACE_ID}-{CALLER_SPAN_ID}-01")))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    // same trace, with the gateway's span as the parent
+    let received = mock_server.received_requests().await.unwrap();
+    let traceparent = received[0].headers["traceparent"].to_str().unwrap();
+    let parts: Vec<_> = traceparent.split('-').collect();
+    assert_eq!(parts.len(), 4, "{traceparent}");
+    assert_eq!(parts[1], TRACE_ID);
+    assert_ne!(parts[2], CALLER_SPAN_ID);
+    assert_eq!(parts[3], "01");
+}
+
+/// Log output kept in memory, to check what a request logged.
+#[derive(Clone, Default)]
+struct LogBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl std::io::Write for LogBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[actix_web::test]
+async fn test_malformed_query_log_omits_token() {
+    let logs = LogBuffer::default();
+    let writer = logs.clone();
+    let _subscriber = tracing::subscriber::set_default(
+        tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .finish(),
+    );
+
+    let state = Arc::new(AppState {
+        token_query_param: Some("access_token".to_string()),
+        ..Arc::into_inner(create_app_state(
+            vec![SfuConfig {
+                address: "http://127.0.0.1:1".to_string(),
+                key: b"sfu-key-padded-to-32-bytes-here!".to_vec(),
+                ..Default::default()
+            }],
+            GATEWAY_KEY,
+            false,
+        ))
+        .unwrap()
+    });
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .route("/v1/channel", web::get().to(channel)),
+    )
+    .await;
+
+    let token = sign_claims(&make_test_claims(), GATEWAY_KEY);
+    let req = test::TestRequest::get()
+        .uri(&format!("/v1/channel?access_token={token}&webRTC=%zz"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let logs = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+    assert!(logs.contains("Malformed query string"), "{logs}");
+    assert!(logs.contains("webRTC"), "{logs}");
+    assert!(!logs.contains(&token), "{logs}");
+}